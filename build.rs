@@ -1,4 +1,10 @@
 extern crate walkdir;
+#[macro_use]
+extern crate failure;
+
+#[path = "src/archive.rs"]
+#[allow(dead_code)]
+mod archive;
 
 use std::env;
 use std::fs::{self, DirBuilder};
@@ -15,10 +21,15 @@ fn main() {
         .expect("failed to find target dir")
         .join(env::var("PROFILE").unwrap());
 
-    copy(
-        &manifest_dir.join("res"),
-        &executable_path.join("res"),
-    );
+    // release builds ship a single archive, dev builds keep loose files so they can be edited
+    if env::var("PROFILE").unwrap() == "release" {
+        pack(&manifest_dir.join("res"), &executable_path.join("res.pak"));
+    } else {
+        copy(
+            &manifest_dir.join("res"),
+            &executable_path.join("res"),
+        );
+    }
 }
 
 fn locate_target_dir_from_output_dir(mut target_dir_search: &Path) -> Option<&Path> {
@@ -58,3 +69,29 @@ fn copy(from: &Path, to: &Path) {
         }
     }
 }
+
+fn pack(from: &Path, to: &Path) {
+    let mut writer = archive::ArchiveWriter::new();
+
+    for entry in WalkDir::new(from) {
+        let entry = entry.unwrap();
+
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        if let Ok(rel_path) = entry.path().strip_prefix(from) {
+            // resource names always use '/', see resources::resource_name_to_path
+            let name = rel_path
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                .collect::<Vec<String>>()
+                .join("/");
+            let data = fs::read(entry.path()).expect("failed to read resource");
+            let compression = archive::default_compression(&name);
+            writer.add(&name, data, compression);
+        }
+    }
+
+    writer.write_to(to).expect("failed to write resource archive");
+}
@@ -0,0 +1,235 @@
+//! The subset of GL the engine calls, as a trait so that `Gl` can be backed by either the
+//! loaded driver functions or a `recording::RecordingGl`.
+//!
+//! Anything not listed here is not reachable through `Gl`; add it to `gl_api!` (and to the
+//! recording backend) before using it.
+
+use crate::bindings;
+use crate::types::*;
+use std::os::raw::c_void;
+
+macro_rules! gl_api {
+    ($(fn $name:ident($($arg:ident: $ty:ty),*) $(-> $ret:ty)?;)*) => {
+        #[allow(non_snake_case)]
+        pub trait GlApi {
+            $(unsafe fn $name(&self, $($arg: $ty),*) $(-> $ret)?;)*
+        }
+
+        #[allow(non_snake_case)]
+        impl GlApi for bindings::Gl {
+            $(
+                #[inline]
+                unsafe fn $name(&self, $($arg: $ty),*) $(-> $ret)? {
+                    bindings::Gl::$name(self, $($arg),*)
+                }
+            )*
+        }
+    };
+}
+
+gl_api! {
+    fn ActiveTexture(texture: GLenum);
+    fn AttachShader(program: GLuint, shader: GLuint);
+    fn BindBuffer(target: GLenum, buffer: GLuint);
+    fn BindBufferBase(target: GLenum, index: GLuint, buffer: GLuint);
+    fn BindFramebuffer(target: GLenum, framebuffer: GLuint);
+    fn BindRenderbuffer(target: GLenum, renderbuffer: GLuint);
+    fn BindTexture(target: GLenum, texture: GLuint);
+    fn BindVertexArray(array: GLuint);
+    fn BlendFunc(sfactor: GLenum, dfactor: GLenum);
+    fn BufferData(target: GLenum, size: GLsizeiptr, data: *const c_void, usage: GLenum);
+    fn CheckFramebufferStatus(target: GLenum) -> GLenum;
+    fn Clear(mask: GLbitfield);
+    fn ClearColor(red: GLfloat, green: GLfloat, blue: GLfloat, alpha: GLfloat);
+    fn CompileShader(shader: GLuint);
+    fn CreateProgram() -> GLuint;
+    fn CreateShader(type_: GLenum) -> GLuint;
+    fn DeleteBuffers(n: GLsizei, buffers: *const GLuint);
+    fn DeleteFramebuffers(n: GLsizei, framebuffers: *const GLuint);
+    fn DeleteProgram(program: GLuint);
+    fn DeleteRenderbuffers(n: GLsizei, renderbuffers: *const GLuint);
+    fn DeleteShader(shader: GLuint);
+    fn DeleteTextures(n: GLsizei, textures: *const GLuint);
+    fn DeleteVertexArrays(n: GLsizei, arrays: *const GLuint);
+    fn DetachShader(program: GLuint, shader: GLuint);
+    fn Disable(cap: GLenum);
+    fn DrawElements(mode: GLenum, count: GLsizei, type_: GLenum, indices: *const c_void);
+    fn Enable(cap: GLenum);
+    fn EnableVertexAttribArray(index: GLuint);
+    fn Finish();
+    fn FramebufferRenderbuffer(
+        target: GLenum,
+        attachment: GLenum,
+        renderbuffertarget: GLenum,
+        renderbuffer: GLuint
+    );
+    fn GenBuffers(n: GLsizei, buffers: *mut GLuint);
+    fn GenFramebuffers(n: GLsizei, framebuffers: *mut GLuint);
+    fn GenRenderbuffers(n: GLsizei, renderbuffers: *mut GLuint);
+    fn GenTextures(n: GLsizei, textures: *mut GLuint);
+    fn GenVertexArrays(n: GLsizei, arrays: *mut GLuint);
+    fn GenerateMipmap(target: GLenum);
+    fn GetActiveAttrib(
+        program: GLuint,
+        index: GLuint,
+        bufSize: GLsizei,
+        length: *mut GLsizei,
+        size: *mut GLint,
+        type_: *mut GLenum,
+        name: *mut GLchar
+    );
+    fn GetActiveUniform(
+        program: GLuint,
+        index: GLuint,
+        bufSize: GLsizei,
+        length: *mut GLsizei,
+        size: *mut GLint,
+        type_: *mut GLenum,
+        name: *mut GLchar
+    );
+    fn GetAttribLocation(program: GLuint, name: *const GLchar) -> GLint;
+    fn GetFloatv(pname: GLenum, data: *mut GLfloat);
+    fn GetIntegerv(pname: GLenum, data: *mut GLint);
+    fn GetProgramInfoLog(
+        program: GLuint,
+        bufSize: GLsizei,
+        length: *mut GLsizei,
+        infoLog: *mut GLchar
+    );
+    fn GetProgramiv(program: GLuint, pname: GLenum, params: *mut GLint);
+    fn GetShaderInfoLog(
+        shader: GLuint,
+        bufSize: GLsizei,
+        length: *mut GLsizei,
+        infoLog: *mut GLchar
+    );
+    fn GetShaderiv(shader: GLuint, pname: GLenum, params: *mut GLint);
+    fn GetUniformBlockIndex(program: GLuint, uniformBlockName: *const GLchar) -> GLuint;
+    fn GetUniformLocation(program: GLuint, name: *const GLchar) -> GLint;
+    fn LinkProgram(program: GLuint);
+    fn MapBufferRange(
+        target: GLenum,
+        offset: GLintptr,
+        length: GLsizeiptr,
+        access: GLbitfield
+    ) -> *mut c_void;
+    fn PixelStorei(pname: GLenum, param: GLint);
+    fn ReadPixels(
+        x: GLint,
+        y: GLint,
+        width: GLsizei,
+        height: GLsizei,
+        format: GLenum,
+        type_: GLenum,
+        pixels: *mut c_void
+    );
+    fn RenderbufferStorage(target: GLenum, internalformat: GLenum, width: GLsizei, height: GLsizei);
+    fn Scissor(x: GLint, y: GLint, width: GLsizei, height: GLsizei);
+    fn ShaderSource(
+        shader: GLuint,
+        count: GLsizei,
+        string: *const *const GLchar,
+        length: *const GLint
+    );
+    fn TexImage2D(
+        target: GLenum,
+        level: GLint,
+        internalformat: GLint,
+        width: GLsizei,
+        height: GLsizei,
+        border: GLint,
+        format: GLenum,
+        type_: GLenum,
+        pixels: *const c_void
+    );
+    fn TexImage3D(
+        target: GLenum,
+        level: GLint,
+        internalformat: GLint,
+        width: GLsizei,
+        height: GLsizei,
+        depth: GLsizei,
+        border: GLint,
+        format: GLenum,
+        type_: GLenum,
+        pixels: *const c_void
+    );
+    fn TexParameterf(target: GLenum, pname: GLenum, param: GLfloat);
+    fn TexParameteri(target: GLenum, pname: GLenum, param: GLint);
+    fn TexSubImage2D(
+        target: GLenum,
+        level: GLint,
+        xoffset: GLint,
+        yoffset: GLint,
+        width: GLsizei,
+        height: GLsizei,
+        format: GLenum,
+        type_: GLenum,
+        pixels: *const c_void
+    );
+    fn TexSubImage3D(
+        target: GLenum,
+        level: GLint,
+        xoffset: GLint,
+        yoffset: GLint,
+        zoffset: GLint,
+        width: GLsizei,
+        height: GLsizei,
+        depth: GLsizei,
+        format: GLenum,
+        type_: GLenum,
+        pixels: *const c_void
+    );
+    fn Uniform1fv(location: GLint, count: GLsizei, value: *const GLfloat);
+    fn Uniform1i(location: GLint, v0: GLint);
+    fn Uniform1iv(location: GLint, count: GLsizei, value: *const GLint);
+    fn Uniform1uiv(location: GLint, count: GLsizei, value: *const GLuint);
+    fn Uniform2fv(location: GLint, count: GLsizei, value: *const GLfloat);
+    fn Uniform3f(location: GLint, v0: GLfloat, v1: GLfloat, v2: GLfloat);
+    fn Uniform3fv(location: GLint, count: GLsizei, value: *const GLfloat);
+    fn Uniform4fv(location: GLint, count: GLsizei, value: *const GLfloat);
+    fn UniformBlockBinding(program: GLuint, uniformBlockIndex: GLuint, uniformBlockBinding: GLuint);
+    fn UniformMatrix2fv(
+        location: GLint,
+        count: GLsizei,
+        transpose: GLboolean,
+        value: *const GLfloat
+    );
+    fn UniformMatrix3fv(
+        location: GLint,
+        count: GLsizei,
+        transpose: GLboolean,
+        value: *const GLfloat
+    );
+    fn UniformMatrix4fv(
+        location: GLint,
+        count: GLsizei,
+        transpose: GLboolean,
+        value: *const GLfloat
+    );
+    fn UnmapBuffer(target: GLenum) -> GLboolean;
+    fn UseProgram(program: GLuint);
+    fn VertexAttribIPointer(
+        index: GLuint,
+        size: GLint,
+        type_: GLenum,
+        stride: GLsizei,
+        pointer: *const c_void
+    );
+    fn VertexAttribLPointer(
+        index: GLuint,
+        size: GLint,
+        type_: GLenum,
+        stride: GLsizei,
+        pointer: *const c_void
+    );
+    fn VertexAttribPointer(
+        index: GLuint,
+        size: GLint,
+        type_: GLenum,
+        normalized: GLboolean,
+        stride: GLsizei,
+        pointer: *const c_void
+    );
+    fn Viewport(x: GLint, y: GLint, width: GLsizei, height: GLsizei);
+}
@@ -16891,15 +16891,23 @@ mod bindings {
     unsafe impl __gl_imports::Send for Gl {}
 }
 
+mod api;
+pub mod recording;
+
 use std::ops::Deref;
 use std::rc::Rc;
 
+pub use crate::api::GlApi;
 pub use crate::bindings::Gl as InnerGl;
 pub use crate::bindings::*;
 
+// GL_EXT_texture_filter_anisotropic, core only since 4.6 which the bindings predate
+pub const TEXTURE_MAX_ANISOTROPY: types::GLenum = 0x84FE;
+pub const MAX_TEXTURE_MAX_ANISOTROPY: types::GLenum = 0x84FF;
+
 #[derive(Clone)]
 pub struct Gl {
-    inner: Rc<bindings::Gl>,
+    inner: Rc<dyn GlApi>,
 }
 
 impl Gl {
@@ -16911,12 +16919,17 @@ impl Gl {
             inner: Rc::new(bindings::Gl::load_with(loadfn)),
         }
     }
+
+    /// Uses any `GlApi` implementation instead of the driver, e.g. a `recording::RecordingGl`.
+    pub fn from_api(api: Rc<dyn GlApi>) -> Gl {
+        Gl { inner: api }
+    }
 }
 
 impl Deref for Gl {
-    type Target = bindings::Gl;
+    type Target = dyn GlApi;
 
-    fn deref(&self) -> &bindings::Gl {
-        &self.inner
+    fn deref(&self) -> &Self::Target {
+        &*self.inner
     }
 }
@@ -0,0 +1,667 @@
+//! A `GlApi` backend that never touches a driver. Every call is logged with its arguments,
+//! object names are handed out from a counter and tracked until deleted, so tests can check
+//! call sequences and that every `Gen*`/`Create*` is paired with a `Delete*`.
+//!
+//! ```ignore
+//! let recording = Rc::new(RecordingGl::new());
+//! let gl = gl::Gl::from_api(recording.clone());
+//! // ... build and drop render objects with `gl` ...
+//! assert!(recording.live_objects().is_empty());
+//! ```
+
+use crate::api::GlApi;
+use crate::types::*;
+use crate::*;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::ffi::CStr;
+use std::os::raw::c_void;
+
+/// One logged call, arguments formatted with `RecordArg`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Call {
+    pub name: &'static str,
+    pub args: Vec<String>,
+}
+
+/// How an argument shows up in `Call::args`. Pointers are reduced to `null` / `ptr`
+/// so recordings stay comparable between runs.
+pub trait RecordArg {
+    fn record(&self) -> String;
+}
+
+macro_rules! record_arg_debug {
+    ($($ty:ty),*) => {
+        $(impl RecordArg for $ty {
+            fn record(&self) -> String {
+                format!("{:?}", self)
+            }
+        })*
+    };
+}
+
+record_arg_debug!(u8, i32, u32, f32, isize);
+
+impl<T> RecordArg for *const T {
+    fn record(&self) -> String {
+        if self.is_null() { "null" } else { "ptr" }.into()
+    }
+}
+
+impl<T> RecordArg for *mut T {
+    fn record(&self) -> String {
+        if self.is_null() { "null" } else { "ptr" }.into()
+    }
+}
+
+#[derive(Clone, Debug)]
+struct ActiveVariable {
+    name: String,
+    gl_type: GLenum,
+    size: GLint,
+}
+
+#[derive(Default)]
+struct State {
+    calls: Vec<Call>,
+    next_name: GLuint,
+    live: BTreeMap<GLuint, &'static str>,
+    invalid_deletes: Vec<(&'static str, GLuint)>,
+    uniforms: Vec<ActiveVariable>,
+    attributes: Vec<ActiveVariable>,
+}
+
+#[derive(Default)]
+pub struct RecordingGl {
+    state: RefCell<State>,
+}
+
+impl RecordingGl {
+    pub fn new() -> RecordingGl {
+        RecordingGl::default()
+    }
+
+    /// Every program reports this uniform as active, at a location equal to its declaration index.
+    pub fn with_active_uniform(self, name: &str, gl_type: GLenum, size: GLint) -> Self {
+        self.state.borrow_mut().uniforms.push(ActiveVariable {
+            name: name.into(),
+            gl_type,
+            size,
+        });
+        self
+    }
+
+    /// Every program reports this vertex attribute as active, at a location equal to its declaration index.
+    pub fn with_active_attribute(self, name: &str, gl_type: GLenum) -> Self {
+        self.state.borrow_mut().attributes.push(ActiveVariable {
+            name: name.into(),
+            gl_type,
+            size: 1,
+        });
+        self
+    }
+
+    pub fn calls(&self) -> Vec<Call> {
+        self.state.borrow().calls.clone()
+    }
+
+    pub fn call_names(&self) -> Vec<&'static str> {
+        self.state.borrow().calls.iter().map(|c| c.name).collect()
+    }
+
+    pub fn clear_calls(&self) {
+        self.state.borrow_mut().calls.clear();
+    }
+
+    /// Objects that were generated or created and not deleted yet, as (kind, name).
+    pub fn live_objects(&self) -> Vec<(&'static str, GLuint)> {
+        self.state
+            .borrow()
+            .live
+            .iter()
+            .map(|(name, kind)| (*kind, *name))
+            .collect()
+    }
+
+    /// Deletes of names that were never generated, deleted twice or deleted as the wrong kind.
+    pub fn invalid_deletes(&self) -> Vec<(&'static str, GLuint)> {
+        self.state.borrow().invalid_deletes.clone()
+    }
+
+    fn record(&self, name: &'static str, args: Vec<String>) {
+        self.state.borrow_mut().calls.push(Call { name, args });
+    }
+
+    fn allocate(&self, kind: &'static str) -> GLuint {
+        let mut state = self.state.borrow_mut();
+        state.next_name += 1;
+        let name = state.next_name;
+        state.live.insert(name, kind);
+        name
+    }
+
+    unsafe fn gen(&self, call: &'static str, kind: &'static str, n: GLsizei, names: *mut GLuint) {
+        for i in 0..n.max(0) as usize {
+            *names.add(i) = self.allocate(kind);
+        }
+        let generated = (0..n.max(0) as usize).map(|i| *names.add(i)).collect::<Vec<_>>();
+        self.record(call, vec![n.record(), format!("{:?}", generated)]);
+    }
+
+    fn delete_one(&self, kind: &'static str, name: GLuint) {
+        // deleting 0 is silently ignored by GL
+        if name == 0 {
+            return;
+        }
+        let mut state = self.state.borrow_mut();
+        match state.live.get(&name) {
+            Some(live_kind) if *live_kind == kind => {
+                state.live.remove(&name);
+            }
+            _ => state.invalid_deletes.push((kind, name)),
+        }
+    }
+
+    unsafe fn delete(
+        &self,
+        call: &'static str,
+        kind: &'static str,
+        n: GLsizei,
+        names: *const GLuint,
+    ) {
+        let deleted = (0..n.max(0) as usize).map(|i| *names.add(i)).collect::<Vec<_>>();
+        for name in &deleted {
+            self.delete_one(kind, *name);
+        }
+        self.record(call, vec![n.record(), format!("{:?}", deleted)]);
+    }
+
+    fn variables(&self, list: ProgramList) -> Vec<ActiveVariable> {
+        let state = self.state.borrow();
+        match list {
+            ProgramList::Uniforms => state.uniforms.clone(),
+            ProgramList::Attributes => state.attributes.clone(),
+        }
+    }
+
+    unsafe fn get_active(
+        &self,
+        list: ProgramList,
+        index: GLuint,
+        buf_size: GLsizei,
+        length: *mut GLsizei,
+        size: *mut GLint,
+        type_: *mut GLenum,
+        name: *mut GLchar,
+    ) {
+        let variables = self.variables(list);
+        let variable = match variables.get(index as usize) {
+            Some(variable) => variable,
+            None => return,
+        };
+
+        let bytes = variable.name.as_bytes();
+        let len = bytes.len().min((buf_size.max(1) - 1) as usize);
+        for (i, b) in bytes[..len].iter().enumerate() {
+            *name.add(i) = *b as GLchar;
+        }
+        *name.add(len) = 0;
+        if !length.is_null() {
+            *length = len as GLsizei;
+        }
+        *size = variable.size;
+        *type_ = variable.gl_type;
+    }
+
+    unsafe fn location(&self, list: ProgramList, name: *const GLchar) -> GLint {
+        let name = CStr::from_ptr(name).to_string_lossy();
+        self.variables(list)
+            .iter()
+            .position(|v| v.name == name || v.name == format!("{}[0]", name))
+            .map(|i| i as GLint)
+            .unwrap_or(-1)
+    }
+}
+
+#[derive(Copy, Clone)]
+enum ProgramList {
+    Uniforms,
+    Attributes,
+}
+
+/// Calls with no outputs, only logged.
+macro_rules! recorded {
+    ($(fn $name:ident($($arg:ident: $ty:ty),*);)*) => {
+        $(
+            unsafe fn $name(&self, $($arg: $ty),*) {
+                self.record(stringify!($name), vec![$($arg.record()),*]);
+            }
+        )*
+    };
+}
+
+#[allow(non_snake_case)]
+impl GlApi for RecordingGl {
+    recorded! {
+        fn ActiveTexture(texture: GLenum);
+        fn AttachShader(program: GLuint, shader: GLuint);
+        fn BindBuffer(target: GLenum, buffer: GLuint);
+        fn BindBufferBase(target: GLenum, index: GLuint, buffer: GLuint);
+        fn BindFramebuffer(target: GLenum, framebuffer: GLuint);
+        fn BindRenderbuffer(target: GLenum, renderbuffer: GLuint);
+        fn BindTexture(target: GLenum, texture: GLuint);
+        fn BindVertexArray(array: GLuint);
+        fn BlendFunc(sfactor: GLenum, dfactor: GLenum);
+        fn BufferData(target: GLenum, size: GLsizeiptr, data: *const c_void, usage: GLenum);
+        fn Clear(mask: GLbitfield);
+        fn ClearColor(red: GLfloat, green: GLfloat, blue: GLfloat, alpha: GLfloat);
+        fn CompileShader(shader: GLuint);
+        fn DetachShader(program: GLuint, shader: GLuint);
+        fn Disable(cap: GLenum);
+        fn DrawElements(mode: GLenum, count: GLsizei, type_: GLenum, indices: *const c_void);
+        fn Enable(cap: GLenum);
+        fn EnableVertexAttribArray(index: GLuint);
+        fn Finish();
+        fn FramebufferRenderbuffer(
+            target: GLenum,
+            attachment: GLenum,
+            renderbuffertarget: GLenum,
+            renderbuffer: GLuint
+        );
+        fn GenerateMipmap(target: GLenum);
+        fn LinkProgram(program: GLuint);
+        fn PixelStorei(pname: GLenum, param: GLint);
+        fn RenderbufferStorage(
+            target: GLenum,
+            internalformat: GLenum,
+            width: GLsizei,
+            height: GLsizei
+        );
+        fn Scissor(x: GLint, y: GLint, width: GLsizei, height: GLsizei);
+        fn TexImage2D(
+            target: GLenum,
+            level: GLint,
+            internalformat: GLint,
+            width: GLsizei,
+            height: GLsizei,
+            border: GLint,
+            format: GLenum,
+            type_: GLenum,
+            pixels: *const c_void
+        );
+        fn TexImage3D(
+            target: GLenum,
+            level: GLint,
+            internalformat: GLint,
+            width: GLsizei,
+            height: GLsizei,
+            depth: GLsizei,
+            border: GLint,
+            format: GLenum,
+            type_: GLenum,
+            pixels: *const c_void
+        );
+        fn TexParameterf(target: GLenum, pname: GLenum, param: GLfloat);
+        fn TexParameteri(target: GLenum, pname: GLenum, param: GLint);
+        fn TexSubImage2D(
+            target: GLenum,
+            level: GLint,
+            xoffset: GLint,
+            yoffset: GLint,
+            width: GLsizei,
+            height: GLsizei,
+            format: GLenum,
+            type_: GLenum,
+            pixels: *const c_void
+        );
+        fn TexSubImage3D(
+            target: GLenum,
+            level: GLint,
+            xoffset: GLint,
+            yoffset: GLint,
+            zoffset: GLint,
+            width: GLsizei,
+            height: GLsizei,
+            depth: GLsizei,
+            format: GLenum,
+            type_: GLenum,
+            pixels: *const c_void
+        );
+        fn Uniform1fv(location: GLint, count: GLsizei, value: *const GLfloat);
+        fn Uniform1i(location: GLint, v0: GLint);
+        fn Uniform1iv(location: GLint, count: GLsizei, value: *const GLint);
+        fn Uniform1uiv(location: GLint, count: GLsizei, value: *const GLuint);
+        fn Uniform2fv(location: GLint, count: GLsizei, value: *const GLfloat);
+        fn Uniform3f(location: GLint, v0: GLfloat, v1: GLfloat, v2: GLfloat);
+        fn Uniform3fv(location: GLint, count: GLsizei, value: *const GLfloat);
+        fn Uniform4fv(location: GLint, count: GLsizei, value: *const GLfloat);
+        fn UniformBlockBinding(
+            program: GLuint,
+            uniformBlockIndex: GLuint,
+            uniformBlockBinding: GLuint
+        );
+        fn UniformMatrix2fv(
+            location: GLint,
+            count: GLsizei,
+            transpose: GLboolean,
+            value: *const GLfloat
+        );
+        fn UniformMatrix3fv(
+            location: GLint,
+            count: GLsizei,
+            transpose: GLboolean,
+            value: *const GLfloat
+        );
+        fn UniformMatrix4fv(
+            location: GLint,
+            count: GLsizei,
+            transpose: GLboolean,
+            value: *const GLfloat
+        );
+        fn UseProgram(program: GLuint);
+        fn VertexAttribIPointer(
+            index: GLuint,
+            size: GLint,
+            type_: GLenum,
+            stride: GLsizei,
+            pointer: *const c_void
+        );
+        fn VertexAttribLPointer(
+            index: GLuint,
+            size: GLint,
+            type_: GLenum,
+            stride: GLsizei,
+            pointer: *const c_void
+        );
+        fn VertexAttribPointer(
+            index: GLuint,
+            size: GLint,
+            type_: GLenum,
+            normalized: GLboolean,
+            stride: GLsizei,
+            pointer: *const c_void
+        );
+        fn Viewport(x: GLint, y: GLint, width: GLsizei, height: GLsizei);
+    }
+
+    unsafe fn GenBuffers(&self, n: GLsizei, buffers: *mut GLuint) {
+        self.gen("GenBuffers", "buffer", n, buffers);
+    }
+
+    unsafe fn GenFramebuffers(&self, n: GLsizei, framebuffers: *mut GLuint) {
+        self.gen("GenFramebuffers", "framebuffer", n, framebuffers);
+    }
+
+    unsafe fn GenRenderbuffers(&self, n: GLsizei, renderbuffers: *mut GLuint) {
+        self.gen("GenRenderbuffers", "renderbuffer", n, renderbuffers);
+    }
+
+    unsafe fn GenTextures(&self, n: GLsizei, textures: *mut GLuint) {
+        self.gen("GenTextures", "texture", n, textures);
+    }
+
+    unsafe fn GenVertexArrays(&self, n: GLsizei, arrays: *mut GLuint) {
+        self.gen("GenVertexArrays", "vertex array", n, arrays);
+    }
+
+    unsafe fn DeleteBuffers(&self, n: GLsizei, buffers: *const GLuint) {
+        self.delete("DeleteBuffers", "buffer", n, buffers);
+    }
+
+    unsafe fn DeleteFramebuffers(&self, n: GLsizei, framebuffers: *const GLuint) {
+        self.delete("DeleteFramebuffers", "framebuffer", n, framebuffers);
+    }
+
+    unsafe fn DeleteRenderbuffers(&self, n: GLsizei, renderbuffers: *const GLuint) {
+        self.delete("DeleteRenderbuffers", "renderbuffer", n, renderbuffers);
+    }
+
+    unsafe fn DeleteTextures(&self, n: GLsizei, textures: *const GLuint) {
+        self.delete("DeleteTextures", "texture", n, textures);
+    }
+
+    unsafe fn DeleteVertexArrays(&self, n: GLsizei, arrays: *const GLuint) {
+        self.delete("DeleteVertexArrays", "vertex array", n, arrays);
+    }
+
+    unsafe fn CreateProgram(&self) -> GLuint {
+        let program = self.allocate("program");
+        self.record("CreateProgram", vec![program.record()]);
+        program
+    }
+
+    unsafe fn CreateShader(&self, type_: GLenum) -> GLuint {
+        let shader = self.allocate("shader");
+        self.record("CreateShader", vec![type_.record(), shader.record()]);
+        shader
+    }
+
+    unsafe fn DeleteProgram(&self, program: GLuint) {
+        self.delete_one("program", program);
+        self.record("DeleteProgram", vec![program.record()]);
+    }
+
+    unsafe fn DeleteShader(&self, shader: GLuint) {
+        self.delete_one("shader", shader);
+        self.record("DeleteShader", vec![shader.record()]);
+    }
+
+    /// The source text itself is recorded, concatenated.
+    unsafe fn ShaderSource(
+        &self,
+        shader: GLuint,
+        count: GLsizei,
+        string: *const *const GLchar,
+        length: *const GLint,
+    ) {
+        let mut source = String::new();
+        for i in 0..count.max(0) as usize {
+            let part = *string.add(i);
+            if length.is_null() || *length.add(i) < 0 {
+                source.push_str(&CStr::from_ptr(part).to_string_lossy());
+            } else {
+                let bytes = std::slice::from_raw_parts(part as *const u8, *length.add(i) as usize);
+                source.push_str(&String::from_utf8_lossy(bytes));
+            }
+        }
+        self.record("ShaderSource", vec![shader.record(), count.record(), source]);
+    }
+
+    unsafe fn CheckFramebufferStatus(&self, target: GLenum) -> GLenum {
+        self.record("CheckFramebufferStatus", vec![target.record()]);
+        FRAMEBUFFER_COMPLETE
+    }
+
+    /// Always fails, nothing can be written through a mapping without a driver.
+    unsafe fn MapBufferRange(
+        &self,
+        target: GLenum,
+        offset: GLintptr,
+        length: GLsizeiptr,
+        access: GLbitfield,
+    ) -> *mut c_void {
+        self.record(
+            "MapBufferRange",
+            vec![target.record(), offset.record(), length.record(), access.record()],
+        );
+        std::ptr::null_mut()
+    }
+
+    unsafe fn UnmapBuffer(&self, target: GLenum) -> GLboolean {
+        self.record("UnmapBuffer", vec![target.record()]);
+        TRUE
+    }
+
+    /// Reports 16x anisotropic filtering, everything else is 0.
+    unsafe fn GetFloatv(&self, pname: GLenum, data: *mut GLfloat) {
+        self.record("GetFloatv", vec![pname.record()]);
+        *data = match pname {
+            MAX_TEXTURE_MAX_ANISOTROPY => 16.0,
+            _ => 0.0,
+        };
+    }
+
+    /// Reports 256 array texture layers, everything else is 0.
+    unsafe fn GetIntegerv(&self, pname: GLenum, data: *mut GLint) {
+        self.record("GetIntegerv", vec![pname.record()]);
+        *data = match pname {
+            MAX_ARRAY_TEXTURE_LAYERS => 256,
+            _ => 0,
+        };
+    }
+
+    /// Shaders always compile.
+    unsafe fn GetShaderiv(&self, shader: GLuint, pname: GLenum, params: *mut GLint) {
+        self.record("GetShaderiv", vec![shader.record(), pname.record()]);
+        *params = match pname {
+            COMPILE_STATUS => TRUE as GLint,
+            _ => 0,
+        };
+    }
+
+    /// Programs always link and report the uniforms/attributes given to `with_active_*`.
+    unsafe fn GetProgramiv(&self, program: GLuint, pname: GLenum, params: *mut GLint) {
+        self.record("GetProgramiv", vec![program.record(), pname.record()]);
+        let state = self.state.borrow();
+        let max_len = |vars: &[ActiveVariable]| {
+            vars.iter().map(|v| v.name.len() as GLint + 1).max().unwrap_or(0)
+        };
+        *params = match pname {
+            LINK_STATUS => TRUE as GLint,
+            ACTIVE_UNIFORMS => state.uniforms.len() as GLint,
+            ACTIVE_UNIFORM_MAX_LENGTH => max_len(&state.uniforms),
+            ACTIVE_ATTRIBUTES => state.attributes.len() as GLint,
+            ACTIVE_ATTRIBUTE_MAX_LENGTH => max_len(&state.attributes),
+            _ => 0,
+        };
+    }
+
+    unsafe fn GetShaderInfoLog(
+        &self,
+        shader: GLuint,
+        bufSize: GLsizei,
+        length: *mut GLsizei,
+        infoLog: *mut GLchar,
+    ) {
+        self.record("GetShaderInfoLog", vec![shader.record(), bufSize.record()]);
+        if !length.is_null() {
+            *length = 0;
+        }
+        if bufSize > 0 {
+            *infoLog = 0;
+        }
+    }
+
+    unsafe fn GetProgramInfoLog(
+        &self,
+        program: GLuint,
+        bufSize: GLsizei,
+        length: *mut GLsizei,
+        infoLog: *mut GLchar,
+    ) {
+        self.record("GetProgramInfoLog", vec![program.record(), bufSize.record()]);
+        if !length.is_null() {
+            *length = 0;
+        }
+        if bufSize > 0 {
+            *infoLog = 0;
+        }
+    }
+
+    unsafe fn GetActiveUniform(
+        &self,
+        program: GLuint,
+        index: GLuint,
+        bufSize: GLsizei,
+        length: *mut GLsizei,
+        size: *mut GLint,
+        type_: *mut GLenum,
+        name: *mut GLchar,
+    ) {
+        self.record("GetActiveUniform", vec![program.record(), index.record()]);
+        self.get_active(ProgramList::Uniforms, index, bufSize, length, size, type_, name);
+    }
+
+    unsafe fn GetActiveAttrib(
+        &self,
+        program: GLuint,
+        index: GLuint,
+        bufSize: GLsizei,
+        length: *mut GLsizei,
+        size: *mut GLint,
+        type_: *mut GLenum,
+        name: *mut GLchar,
+    ) {
+        self.record("GetActiveAttrib", vec![program.record(), index.record()]);
+        self.get_active(ProgramList::Attributes, index, bufSize, length, size, type_, name);
+    }
+
+    unsafe fn GetUniformLocation(&self, program: GLuint, name: *const GLchar) -> GLint {
+        let location = self.location(ProgramList::Uniforms, name);
+        self.record(
+            "GetUniformLocation",
+            vec![program.record(), CStr::from_ptr(name).to_string_lossy().into_owned()],
+        );
+        location
+    }
+
+    unsafe fn GetAttribLocation(&self, program: GLuint, name: *const GLchar) -> GLint {
+        let location = self.location(ProgramList::Attributes, name);
+        self.record(
+            "GetAttribLocation",
+            vec![program.record(), CStr::from_ptr(name).to_string_lossy().into_owned()],
+        );
+        location
+    }
+
+    /// Every block exists, at index 0.
+    unsafe fn GetUniformBlockIndex(
+        &self,
+        program: GLuint,
+        uniformBlockName: *const GLchar,
+    ) -> GLuint {
+        self.record(
+            "GetUniformBlockIndex",
+            vec![
+                program.record(),
+                CStr::from_ptr(uniformBlockName).to_string_lossy().into_owned(),
+            ],
+        );
+        0
+    }
+
+    /// Reads back transparent black.
+    unsafe fn ReadPixels(
+        &self,
+        x: GLint,
+        y: GLint,
+        width: GLsizei,
+        height: GLsizei,
+        format: GLenum,
+        type_: GLenum,
+        pixels: *mut c_void,
+    ) {
+        self.record(
+            "ReadPixels",
+            vec![
+                x.record(),
+                y.record(),
+                width.record(),
+                height.record(),
+                format.record(),
+                type_.record(),
+            ],
+        );
+        let channels = match format {
+            RED => 1,
+            RG => 2,
+            RGB => 3,
+            _ => 4,
+        };
+        std::ptr::write_bytes(
+            pixels as *mut u8,
+            0,
+            (width.max(0) * height.max(0) * channels) as usize,
+        );
+    }
+}
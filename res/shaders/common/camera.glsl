@@ -0,0 +1,6 @@
+#pragma once
+
+layout (std140) uniform Camera {
+    mat4 Projection;
+    mat4 View;
+};
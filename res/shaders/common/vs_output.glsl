@@ -0,0 +1,4 @@
+#pragma once
+
+// shared between the vertex and fragment stage: `out VS_OUTPUT_BLOCK OUT;` / `in VS_OUTPUT_BLOCK IN;`
+#define VS_OUTPUT_BLOCK VS_OUTPUT { vec2 tc; }
@@ -0,0 +1,15 @@
+#version 330 core
+
+#include "common/vs_output.glsl"
+
+uniform sampler2D TexFace;
+uniform vec4 Tint;
+
+in VS_OUTPUT_BLOCK IN;
+
+out vec4 Color;
+
+void main()
+{
+    Color = texture(TexFace, IN.tc) * Tint;
+}
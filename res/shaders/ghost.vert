@@ -0,0 +1,17 @@
+#version 330 core
+
+#include "common/camera.glsl"
+#include "common/vs_output.glsl"
+
+layout (location = 0) in vec3 Position;
+layout (location = 1) in vec2 tc;
+
+uniform mat4 Model;
+
+out VS_OUTPUT_BLOCK OUT;
+
+void main()
+{
+    gl_Position = Projection * View * Model * vec4(Position, 1.0);
+    OUT.tc = tc;
+}
@@ -0,0 +1,22 @@
+#version 330 core
+
+#include "common/vs_output.glsl"
+
+// a filled circle with a stronger rim, drawn on a quad scaled to the range diameter
+uniform vec4 Tint;
+// rim width as a fraction of the radius
+uniform float Rim;
+
+in VS_OUTPUT_BLOCK IN;
+
+out vec4 Color;
+
+void main()
+{
+    float distance = length(IN.tc - vec2(0.5)) * 2.0;
+    if (distance > 1.0) {
+        discard;
+    }
+    float alpha = distance > 1.0 - Rim ? Tint.a : Tint.a * 0.3;
+    Color = vec4(Tint.rgb, alpha);
+}
@@ -0,0 +1,20 @@
+#version 330 core
+
+uniform sampler2D TexFace;
+// a texture array, e.g. the variants of one pathogen, sampled at each sprite's layer
+uniform sampler2DArray TexLayers;
+uniform bool Layered;
+// the texture is a single channel coverage mask, e.g. the glyph cache
+uniform bool Mask;
+
+in vec2 tc;
+in vec4 tint;
+flat in float layer;
+
+out vec4 Color;
+
+void main()
+{
+    vec4 texel = Layered ? texture(TexLayers, vec3(tc, layer)) : texture(TexFace, tc);
+    Color = Mask ? vec4(tint.rgb, tint.a * texel.r) : texel * tint;
+}
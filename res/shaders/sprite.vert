@@ -0,0 +1,22 @@
+#version 330 core
+
+// screen space sprites, see render_gl::SpriteBatch
+layout (location = 0) in vec2 Position;
+layout (location = 1) in vec2 Uv;
+layout (location = 2) in vec4 Tint;
+// layer of TexLayers, only read while Layered
+layout (location = 3) in float Layer;
+
+uniform mat4 Projection;
+
+out vec2 tc;
+out vec4 tint;
+flat out float layer;
+
+void main()
+{
+    gl_Position = Projection * vec4(Position, 0.0, 1.0);
+    tc = Uv;
+    tint = Tint;
+    layer = Layer;
+}
@@ -1,10 +1,10 @@
 #version 330 core
 
+#include "common/vs_output.glsl"
+
 uniform sampler2D TexFace;
 
-in VS_OUTPUT {
-    vec2 tc;
-} IN;
+in VS_OUTPUT_BLOCK IN;
 
 out vec4 Color;
 
@@ -1,20 +1,17 @@
 #version 330 core
 
+#include "common/camera.glsl"
+#include "common/vs_output.glsl"
+
 layout (location = 0) in vec3 Position;
 layout (location = 1) in vec2 tc;
 
 uniform mat4 Model;
-uniform mat4 View;
-uniform mat4 Projection;
 
-out VS_OUTPUT {
-    vec2 tc;
-} OUT;
+out VS_OUTPUT_BLOCK OUT;
 
 void main()
 {
-    // gl_Position = Projection * View * vec4(Position, 1.0);
-    gl_Position = Projection * Model * vec4(Position, 1.0);
-    // gl_Position = Projection * View * vec4(Position, 1.0) * Model;
+    gl_Position = Projection * View * Model * vec4(Position, 1.0);
     OUT.tc = tc;
 }
\ No newline at end of file
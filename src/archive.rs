@@ -0,0 +1,373 @@
+//! Single-file resource archive (`.pak`), written by `build.rs` for release builds.
+//!
+//! Layout, all integers little endian:
+//!
+//! ```text
+//! header      magic "PJPK", version u16, flags u16, entry count u32, toc offset u64
+//! data        entry payloads, back to back
+//! toc         per entry: name length u16, name (utf-8, '/' separated),
+//!             offset u64, stored size u64, size u64, compression u8, crc32 u32
+//! ```
+//!
+//! The crc32 is over the uncompressed bytes, so it also catches a bad inflate.
+//! This file is shared with `build.rs` and may only depend on std, failure, flate2 and crc32fast.
+
+use crc32fast;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+const MAGIC: &[u8; 4] = b"PJPK";
+const VERSION: u16 = 1;
+const HEADER_LEN: u64 = 20;
+/// Deflate can't shrink data by more than this factor.
+const MAX_DEFLATE_RATIO: u64 = 1032;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "I/O error")]
+    Io(#[cause] io::Error),
+    #[fail(display = "Not a resource archive")]
+    BadMagic,
+    #[fail(display = "Unsupported archive version {}", version)]
+    UnsupportedVersion { version: u16 },
+    #[fail(display = "Archive table of contents is corrupt")]
+    CorruptToc,
+    #[fail(display = "Unknown compression {} for archive entry {}", compression, name)]
+    UnknownCompression { name: String, compression: u8 },
+    #[fail(display = "Checksum mismatch for archive entry {}", name)]
+    ChecksumMismatch { name: String },
+}
+
+impl From<io::Error> for Error {
+    fn from(other: io::Error) -> Self {
+        Error::Io(other)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Compression {
+    None = 0,
+    Deflate = 1,
+}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    offset: u64,
+    stored_size: u64,
+    size: u64,
+    compression: u8,
+    crc32: u32,
+}
+
+impl Entry {
+    /// Whether the payload lies between the header and the table of contents at
+    /// `toc_offset`, and `size` is what the stored bytes could inflate to.
+    fn is_plausible(&self, toc_offset: u64) -> bool {
+        let in_data = self.offset >= HEADER_LEN
+            && self
+                .offset
+                .checked_add(self.stored_size)
+                .map_or(false, |end| end <= toc_offset);
+        let size_fits = if self.compression == Compression::None as u8 {
+            self.size == self.stored_size
+        } else {
+            self.size <= self.stored_size.saturating_mul(MAX_DEFLATE_RATIO)
+        };
+
+        in_data && size_fits
+    }
+}
+
+/// A read-only archive opened from disk. Entries are read on demand.
+pub struct Archive {
+    file: Mutex<fs::File>,
+    entries: BTreeMap<String, Entry>,
+}
+
+impl Archive {
+    pub fn open(path: &Path) -> Result<Archive, Error> {
+        let mut file = fs::File::open(path)?;
+
+        let mut header = [0u8; HEADER_LEN as usize];
+        file.read_exact(&mut header)?;
+        if &header[0..4] != MAGIC {
+            return Err(Error::BadMagic);
+        }
+        let version = u16::from_le_bytes([header[4], header[5]]);
+        if version != VERSION {
+            return Err(Error::UnsupportedVersion { version });
+        }
+        let count = read_u32(&header[8..12]);
+        let toc_offset = read_u64(&header[12..20]);
+        if toc_offset < HEADER_LEN || toc_offset > file.metadata()?.len() {
+            return Err(Error::CorruptToc);
+        }
+
+        file.seek(SeekFrom::Start(toc_offset))?;
+        let mut toc = Vec::new();
+        file.read_to_end(&mut toc)?;
+
+        let mut entries = BTreeMap::new();
+        let mut cursor = &toc[..];
+        for _ in 0..count {
+            let (name, entry) = read_entry(&mut cursor).ok_or(Error::CorruptToc)?;
+            // the sizes are trusted from here on, a bad one would read past the data
+            if !entry.is_plausible(toc_offset) {
+                return Err(Error::CorruptToc);
+            }
+            entries.insert(name, entry);
+        }
+
+        Ok(Archive {
+            file: Mutex::new(file),
+            entries,
+        })
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.contains_key(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+
+    /// The uncompressed bytes of entry `name`, `None` if there is no such entry.
+    pub fn read(&self, name: &str) -> Option<Result<Vec<u8>, Error>> {
+        let entry = self.entries.get(name)?;
+        Some(self.read_entry(name, entry))
+    }
+
+    fn read_entry(&self, name: &str, entry: &Entry) -> Result<Vec<u8>, Error> {
+        let mut stored = vec![0u8; entry.stored_size as usize];
+        {
+            let mut file = self.file.lock().expect("expected archive file lock");
+            file.seek(SeekFrom::Start(entry.offset))?;
+            file.read_exact(&mut stored)?;
+        }
+
+        let data = match entry.compression {
+            c if c == Compression::None as u8 => stored,
+            c if c == Compression::Deflate as u8 => {
+                let mut data = Vec::with_capacity(entry.size as usize);
+                DeflateDecoder::new(&stored[..]).read_to_end(&mut data)?;
+                data
+            }
+            compression => {
+                return Err(Error::UnknownCompression {
+                    name: name.into(),
+                    compression,
+                })
+            }
+        };
+
+        if data.len() as u64 != entry.size || crc32fast::hash(&data) != entry.crc32 {
+            return Err(Error::ChecksumMismatch { name: name.into() });
+        }
+
+        Ok(data)
+    }
+}
+
+/// Collects entries in memory and writes them out as one archive.
+#[derive(Default)]
+pub struct ArchiveWriter {
+    entries: BTreeMap<String, (Compression, Vec<u8>)>,
+}
+
+impl ArchiveWriter {
+    pub fn new() -> ArchiveWriter {
+        ArchiveWriter::default()
+    }
+
+    /// Adds `data` as `name`. Compressed entries that don't get smaller are stored as is.
+    pub fn add(&mut self, name: &str, data: Vec<u8>, compression: Compression) {
+        self.entries.insert(name.into(), (compression, data));
+    }
+
+    pub fn write_to(&self, path: &Path) -> Result<(), Error> {
+        let mut file = io::BufWriter::new(fs::File::create(path)?);
+        file.write_all(&[0u8; HEADER_LEN as usize])?;
+
+        let mut toc = Vec::new();
+        let mut offset = HEADER_LEN;
+
+        for (name, (compression, data)) in &self.entries {
+            let (compression, stored) = match compression {
+                Compression::Deflate => {
+                    let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::best());
+                    encoder.write_all(data)?;
+                    let compressed = encoder.finish()?;
+                    if compressed.len() < data.len() {
+                        (Compression::Deflate, compressed)
+                    } else {
+                        (Compression::None, data.clone())
+                    }
+                }
+                Compression::None => (Compression::None, data.clone()),
+            };
+
+            file.write_all(&stored)?;
+
+            toc.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            toc.extend_from_slice(name.as_bytes());
+            toc.extend_from_slice(&offset.to_le_bytes());
+            toc.extend_from_slice(&(stored.len() as u64).to_le_bytes());
+            toc.extend_from_slice(&(data.len() as u64).to_le_bytes());
+            toc.push(compression as u8);
+            toc.extend_from_slice(&crc32fast::hash(data).to_le_bytes());
+
+            offset += stored.len() as u64;
+        }
+
+        file.write_all(&toc)?;
+
+        let mut header = Vec::with_capacity(HEADER_LEN as usize);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&VERSION.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        header.extend_from_slice(&offset.to_le_bytes());
+
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&header)?;
+        file.flush()?;
+
+        Ok(())
+    }
+}
+
+/// Formats that are already compressed gain nothing from deflate.
+pub fn default_compression(name: &str) -> Compression {
+    const PRECOMPRESSED: [&str; 5] = [".png", ".jpg", ".ogg", ".mp3", ".pak"];
+
+    if PRECOMPRESSED.iter().any(|ext| name.ends_with(ext)) {
+        Compression::None
+    } else {
+        Compression::Deflate
+    }
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    let mut buf = [0u8; 4];
+    buf.copy_from_slice(&bytes[..4]);
+    u32::from_le_bytes(buf)
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(&bytes[..8]);
+    u64::from_le_bytes(buf)
+}
+
+fn take<'a>(cursor: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if cursor.len() < len {
+        return None;
+    }
+    let (head, tail) = cursor.split_at(len);
+    *cursor = tail;
+    Some(head)
+}
+
+fn read_entry(cursor: &mut &[u8]) -> Option<(String, Entry)> {
+    let name_len = take(cursor, 2)?;
+    let name_len = u16::from_le_bytes([name_len[0], name_len[1]]) as usize;
+    let name = String::from_utf8(take(cursor, name_len)?.to_vec()).ok()?;
+
+    let entry = Entry {
+        offset: read_u64(take(cursor, 8)?),
+        stored_size: read_u64(take(cursor, 8)?),
+        size: read_u64(take(cursor, 8)?),
+        compression: take(cursor, 1)?[0],
+        crc32: read_u32(take(cursor, 4)?),
+    };
+
+    Some((name, entry))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn write_archive(name: &str) -> std::path::PathBuf {
+        let path = env::temp_dir().join(format!("tow_def_game_archive_{}.pak", name));
+        let mut writer = ArchiveWriter::new();
+        writer.add("plain.txt", b"stored as is".to_vec(), Compression::None);
+        writer.add("packed.txt", vec![b'a'; 4096], Compression::Deflate);
+        writer.write_to(&path).unwrap();
+        path
+    }
+
+    #[test]
+    fn entries_round_trip() {
+        let path = write_archive("round_trip");
+        let archive = Archive::open(&path).unwrap();
+
+        assert_eq!(
+            archive.names().collect::<Vec<_>>(),
+            ["packed.txt", "plain.txt"]
+        );
+        assert_eq!(archive.read("plain.txt").unwrap().unwrap(), b"stored as is");
+        assert_eq!(
+            archive.read("packed.txt").unwrap().unwrap(),
+            vec![b'a'; 4096]
+        );
+        assert!(archive.read("missing.txt").is_none());
+        // deflate got the repeated bytes smaller
+        assert!(fs::metadata(&path).unwrap().len() < 4096);
+    }
+
+    #[test]
+    fn changed_payload_fails_the_checksum() {
+        let path = write_archive("checksum");
+        let mut bytes = fs::read(&path).unwrap();
+        let at = bytes
+            .windows(12)
+            .position(|w| w == b"stored as is")
+            .unwrap();
+        bytes[at] = b'S';
+        fs::write(&path, bytes).unwrap();
+
+        let archive = Archive::open(&path).unwrap();
+        match archive.read("plain.txt").unwrap() {
+            Err(Error::ChecksumMismatch { name }) => assert_eq!(name, "plain.txt"),
+            other => panic!("expected a checksum mismatch, got {:?}", other),
+        }
+        assert!(archive.read("packed.txt").unwrap().is_ok());
+    }
+
+    #[test]
+    fn truncated_toc_is_corrupt() {
+        let path = write_archive("truncated");
+        let mut bytes = fs::read(&path).unwrap();
+        let len = bytes.len();
+        bytes.truncate(len - 3);
+        fs::write(&path, bytes).unwrap();
+
+        match Archive::open(&path) {
+            Err(Error::CorruptToc) => {}
+            other => panic!("expected a corrupt toc, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn entry_past_the_data_is_corrupt() {
+        let path = write_archive("past_data");
+        let mut bytes = fs::read(&path).unwrap();
+        // the last entry's stored size sits 21 bytes before the end of the toc
+        let at = bytes.len() - 21;
+        bytes[at..at + 8].copy_from_slice(&u64::max_value().to_le_bytes());
+        fs::write(&path, bytes).unwrap();
+
+        match Archive::open(&path) {
+            Err(Error::CorruptToc) => {}
+            other => panic!("expected a corrupt toc, got {:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,371 @@
+use crate::font::Font;
+use crate::level::Level;
+use crate::loader::Loader;
+use crate::render_gl::{Program, Texture};
+use crate::resources::{self, Resources};
+use failure::{self, ResultExt};
+use gl;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::rc::{Rc, Weak};
+use std::sync::Arc;
+use toml;
+
+/// Something `Assets` can load by resource name.
+pub trait Asset: Sized + 'static {
+    fn load(gl: &gl::Gl, res: &Resources, name: &str) -> Result<Self, failure::Error>;
+}
+
+impl Asset for Texture {
+    /// RGBA when the image has an alpha channel, RGB otherwise.
+    fn load(gl: &gl::Gl, res: &Resources, name: &str) -> Result<Texture, failure::Error> {
+        match Texture::from_res_rgba(name).load(gl, res) {
+            Err(e) => match e.downcast::<resources::Error>() {
+                Ok(resources::Error::ImageIsNotRgba { .. }) => {
+                    Texture::from_res_rgb(name).load(gl, res)
+                }
+                Ok(e) => Err(e.into()),
+                Err(e) => Err(e),
+            },
+            texture => texture,
+        }
+    }
+}
+
+impl Asset for Program {
+    fn load(gl: &gl::Gl, res: &Resources, name: &str) -> Result<Program, failure::Error> {
+        Ok(Program::from_res(gl, res, name)?)
+    }
+}
+
+impl Asset for Font {
+    fn load(_gl: &gl::Gl, res: &Resources, name: &str) -> Result<Font, failure::Error> {
+        Ok(Font::from_res(res, name)?)
+    }
+}
+
+impl Asset for Level {
+    fn load(_gl: &gl::Gl, res: &Resources, name: &str) -> Result<Level, failure::Error> {
+        Ok(Level::from_res(res, name)?)
+    }
+}
+
+struct Shared<T> {
+    name: String,
+    asset: T,
+}
+
+/// A shared, reference counted asset. The asset is unloaded when the last handle is dropped.
+pub struct Handle<T> {
+    shared: Rc<Shared<T>>,
+}
+
+impl<T> Handle<T> {
+    pub fn name(&self) -> &str {
+        &self.shared.name
+    }
+
+    pub fn ptr_eq(a: &Handle<T>, b: &Handle<T>) -> bool {
+        Rc::ptr_eq(&a.shared, &b.shared)
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Handle {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Deref for Handle<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.shared.asset
+    }
+}
+
+type Cache<T> = HashMap<String, Weak<Shared<T>>>;
+
+trait AnyCache {
+    fn collect_garbage(&mut self);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> AnyCache for Cache<T> {
+    fn collect_garbage(&mut self) {
+        self.retain(|_, weak| weak.strong_count() > 0);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Typed asset cache on top of `Resources`. Loading the same name twice returns the same
+/// asset for as long as a handle to it is alive.
+pub struct Assets {
+    gl: gl::Gl,
+    res: Arc<Resources>,
+    caches: HashMap<TypeId, Box<dyn AnyCache>>,
+}
+
+impl Assets {
+    pub fn new(gl: &gl::Gl, res: Resources) -> Assets {
+        Assets {
+            gl: gl.clone(),
+            res: Arc::new(res),
+            caches: HashMap::new(),
+        }
+    }
+
+    pub fn res(&self) -> &Resources {
+        &self.res
+    }
+
+    pub fn load<T: Asset>(&mut self, name: &str) -> Result<Handle<T>, failure::Error> {
+        if let Some(handle) = self.get(name) {
+            return Ok(handle);
+        }
+
+        let asset = T::load(&self.gl, &self.res, name)
+            .with_context(|_| format!("Failed to load asset {}", name))?;
+
+        Ok(self.insert(name, asset))
+    }
+
+    /// The already loaded asset `name`, if any handle to it is still alive.
+    pub fn get<T: Asset>(&self, name: &str) -> Option<Handle<T>> {
+        self.caches
+            .get(&TypeId::of::<T>())
+            .and_then(|cache| cache.as_any().downcast_ref::<Cache<T>>())
+            .and_then(|cache| cache.get(name))
+            .and_then(|weak| weak.upgrade())
+            .map(|shared| Handle { shared })
+    }
+
+    /// Registers an asset that was loaded elsewhere, e.g. by a background loader.
+    pub fn insert<T: Asset>(&mut self, name: &str, asset: T) -> Handle<T> {
+        let shared = Rc::new(Shared {
+            name: name.into(),
+            asset,
+        });
+
+        self.cache_mut::<T>()
+            .insert(name.into(), Rc::downgrade(&shared));
+
+        Handle { shared }
+    }
+
+    /// Forgets cache entries whose assets were already unloaded.
+    pub fn collect_garbage(&mut self) {
+        for cache in self.caches.values_mut() {
+            cache.collect_garbage();
+        }
+    }
+
+    /// Reads a manifest listing assets to load up front, see `res/manifest.toml`.
+    pub fn preload(&self, manifest_name: &str) -> Result<Preload, failure::Error> {
+        let mut pending = self.read_manifest(manifest_name)?;
+        pending.reverse();
+
+        Ok(Preload {
+            total: pending.len(),
+            pending,
+            loaded: LoadedAssets::default(),
+        })
+    }
+
+    /// Like `preload`, but decodes on `workers` background threads. Drive the returned
+    /// loader with `Loader::poll` once per frame.
+    pub fn preload_in_background(
+        &self,
+        manifest_name: &str,
+        workers: usize,
+    ) -> Result<Loader, failure::Error> {
+        let pending = self.read_manifest(manifest_name)?;
+
+        Ok(Loader::start(self.res.clone(), pending, workers))
+    }
+
+    pub(crate) fn gl(&self) -> &gl::Gl {
+        &self.gl
+    }
+
+    fn read_manifest(
+        &self,
+        manifest_name: &str,
+    ) -> Result<Vec<(AssetKind, String)>, failure::Error> {
+        let text = self
+            .res
+            .load_string(manifest_name)
+            .with_context(|_| format!("Failed to load manifest {}", manifest_name))?;
+        let manifest: Manifest = toml::from_str(&text)
+            .with_context(|_| format!("Failed to parse manifest {}", manifest_name))?;
+
+        let mut pending = Vec::new();
+        pending.extend(
+            manifest
+                .textures
+                .into_iter()
+                .map(|n| (AssetKind::Texture, n)),
+        );
+        pending.extend(
+            manifest
+                .programs
+                .into_iter()
+                .map(|n| (AssetKind::Program, n)),
+        );
+        pending.extend(manifest.fonts.into_iter().map(|n| (AssetKind::Font, n)));
+        pending.extend(manifest.levels.into_iter().map(|n| (AssetKind::Level, n)));
+
+        Ok(pending)
+    }
+
+    fn cache_mut<T: Asset>(&mut self) -> &mut Cache<T> {
+        self.caches
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(Cache::<T>::new()))
+            .as_any_mut()
+            .downcast_mut::<Cache<T>>()
+            .expect("expected asset cache to match its type id")
+    }
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    textures: Vec<String>,
+    #[serde(default)]
+    programs: Vec<String>,
+    #[serde(default)]
+    fonts: Vec<String>,
+    #[serde(default)]
+    levels: Vec<String>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AssetKind {
+    Texture,
+    Program,
+    Font,
+    Level,
+}
+
+/// Handles kept alive by a finished `Preload`.
+#[derive(Default)]
+pub struct LoadedAssets {
+    pub textures: Vec<Handle<Texture>>,
+    pub programs: Vec<Handle<Program>>,
+    pub fonts: Vec<Handle<Font>>,
+    pub levels: Vec<Handle<Level>>,
+}
+
+/// Manifest loading spread over several frames so a loading screen can keep drawing.
+pub struct Preload {
+    pending: Vec<(AssetKind, String)>,
+    total: usize,
+    loaded: LoadedAssets,
+}
+
+impl Preload {
+    /// Loads the next asset of the manifest, returns false once everything is loaded.
+    pub fn step(&mut self, assets: &mut Assets) -> Result<bool, failure::Error> {
+        let (kind, name) = match self.pending.pop() {
+            Some(next) => next,
+            None => return Ok(false),
+        };
+
+        match kind {
+            AssetKind::Texture => self.loaded.textures.push(assets.load(&name)?),
+            AssetKind::Program => self.loaded.programs.push(assets.load(&name)?),
+            AssetKind::Font => self.loaded.fonts.push(assets.load(&name)?),
+            AssetKind::Level => self.loaded.levels.push(assets.load(&name)?),
+        }
+
+        Ok(!self.pending.is_empty())
+    }
+
+    /// Fraction of the manifest loaded, from 0.0 to 1.0.
+    pub fn progress(&self) -> f32 {
+        if self.total == 0 {
+            return 1.0;
+        }
+        (self.total - self.pending.len()) as f32 / self.total as f32
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    pub fn finish(self) -> LoadedAssets {
+        self.loaded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::MemorySource;
+    use gl::recording::RecordingGl;
+    use image::png::PNGEncoder;
+    use image::ColorType;
+
+    fn assets(recording: &Rc<RecordingGl>) -> Assets {
+        let mut png = Vec::new();
+        PNGEncoder::new(&mut png)
+            .encode(&[255; 16], 2, 2, ColorType::RGBA(8))
+            .unwrap();
+        let res = Resources::new().with_source(0, MemorySource::new().with("tower.png", png));
+        Assets::new(&gl::Gl::from_api(recording.clone()), res)
+    }
+
+    fn live_textures(recording: &RecordingGl) -> usize {
+        recording
+            .live_objects()
+            .iter()
+            .filter(|(kind, _)| *kind == "texture")
+            .count()
+    }
+
+    #[test]
+    fn loading_a_name_twice_shares_the_asset() {
+        let recording = Rc::new(RecordingGl::new());
+        let mut assets = assets(&recording);
+
+        let first = assets.load::<Texture>("tower.png").unwrap();
+        let second = assets.load::<Texture>("tower.png").unwrap();
+
+        assert!(Handle::ptr_eq(&first, &second));
+        assert_eq!(second.name(), "tower.png");
+        assert_eq!(live_textures(&recording), 1);
+    }
+
+    #[test]
+    fn asset_unloads_with_its_last_handle() {
+        let recording = Rc::new(RecordingGl::new());
+        let mut assets = assets(&recording);
+
+        let first = assets.load::<Texture>("tower.png").unwrap();
+        let second = first.clone();
+        drop(first);
+        assert_eq!(live_textures(&recording), 1);
+        assert!(assets.get::<Texture>("tower.png").is_some());
+
+        drop(second);
+        assert_eq!(live_textures(&recording), 0);
+        assert!(assets.get::<Texture>("tower.png").is_none());
+
+        // loading again after the unload makes a new texture
+        let _again = assets.load::<Texture>("tower.png").unwrap();
+        assert_eq!(live_textures(&recording), 1);
+        assert!(recording.invalid_deletes().is_empty());
+    }
+}
@@ -0,0 +1,303 @@
+use crate::audio::spatial::StereoGain;
+use crate::audio::{Group, Sound};
+use std::sync::Arc;
+
+/// Gains of the volume groups, from 0.0 to 1.0. `master` scales both others.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Volumes {
+    pub master: f32,
+    pub music: f32,
+    pub sfx: f32,
+}
+
+impl Default for Volumes {
+    fn default() -> Volumes {
+        Volumes {
+            master: 1.0,
+            music: 1.0,
+            sfx: 1.0,
+        }
+    }
+}
+
+impl Volumes {
+    pub fn get(&self, group: Group) -> f32 {
+        match group {
+            Group::Music => self.music,
+            Group::Sfx => self.sfx,
+        }
+    }
+}
+
+struct Voice {
+    sound: Sound,
+    /// frame to play next
+    position: usize,
+    looping: bool,
+    stereo: StereoGain,
+    /// fade multiplier and its change per frame, the voice ends once a fade out reaches 0
+    fade: f32,
+    fade_step: f32,
+    /// when the voice started, to find the oldest one
+    serial: u64,
+}
+
+impl Voice {
+    fn is_fading_out(&self) -> bool {
+        self.fade_step < 0.0
+    }
+}
+
+/// Adds up playing voices into interleaved stereo `f32` samples. Runs on the audio thread
+/// behind `Audio`, or anywhere samples are wanted without a device.
+pub struct Mixer {
+    sample_rate: u32,
+    volumes: Volumes,
+    voices: Vec<Voice>,
+    serial: u64,
+}
+
+impl Mixer {
+    pub fn new(sample_rate: u32) -> Mixer {
+        Mixer {
+            sample_rate,
+            volumes: Volumes::default(),
+            voices: Vec::new(),
+            serial: 0,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn volumes(&self) -> Volumes {
+        self.volumes
+    }
+
+    pub fn set_volumes(&mut self, volumes: Volumes) {
+        self.volumes = volumes;
+    }
+
+    /// Voices playing right now, music included.
+    pub fn voice_count(&self) -> usize {
+        self.voices.len()
+    }
+
+    /// Starts a one-shot voice. When `sound` already plays `max_voices` times, its oldest
+    /// voice is cut off.
+    pub fn play(&mut self, sound: &Sound) {
+        self.play_panned(sound, StereoGain::CENTER);
+    }
+
+    /// Like `play` with separate gains per channel, see `spatial::spatialize`. Inaudible
+    /// sounds don't take a voice.
+    pub fn play_panned(&mut self, sound: &Sound, stereo: StereoGain) {
+        if sound.max_voices == 0 || (stereo.left <= 0.0 && stereo.right <= 0.0) {
+            return;
+        }
+        let playing = self
+            .voices
+            .iter()
+            .filter(|v| Arc::ptr_eq(&v.sound.clip, &sound.clip))
+            .count();
+        if playing >= sound.max_voices {
+            if let Some(oldest) = self
+                .voices
+                .iter()
+                .enumerate()
+                .filter(|(_, v)| Arc::ptr_eq(&v.sound.clip, &sound.clip))
+                .min_by_key(|(_, v)| v.serial)
+                .map(|(i, _)| i)
+            {
+                self.voices.remove(oldest);
+            }
+        }
+
+        self.start(sound, false, stereo, 1.0, 0.0);
+    }
+
+    /// Loops `sound` as the music, crossfading from the current music over `fade` seconds.
+    /// Playing the music that already plays does nothing.
+    pub fn play_music(&mut self, sound: &Sound, fade: f32) {
+        let already_playing = self.voices.iter().any(|v| {
+            v.sound.group == Group::Music
+                && !v.is_fading_out()
+                && Arc::ptr_eq(&v.sound.clip, &sound.clip)
+        });
+        if already_playing {
+            return;
+        }
+
+        self.stop_music(fade);
+        if fade > 0.0 {
+            let step = self.fade_step(fade);
+            self.start(sound, true, StereoGain::CENTER, 0.0, step);
+        } else {
+            self.start(sound, true, StereoGain::CENTER, 1.0, 0.0);
+        }
+    }
+
+    /// Fades the music out over `fade` seconds, or cuts it off with 0.
+    pub fn stop_music(&mut self, fade: f32) {
+        if fade <= 0.0 {
+            self.voices.retain(|v| v.sound.group != Group::Music);
+            return;
+        }
+
+        let step = self.fade_step(fade);
+        for voice in &mut self.voices {
+            if voice.sound.group == Group::Music {
+                voice.fade_step = -step;
+            }
+        }
+    }
+
+    pub fn stop_all(&mut self) {
+        self.voices.clear();
+    }
+
+    /// Overwrites `out`, interleaved stereo, with the next samples of every voice.
+    pub fn mix(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            *sample = 0.0;
+        }
+
+        let volumes = self.volumes;
+        for voice in &mut self.voices {
+            let gain = voice.sound.volume * volumes.master * volumes.get(voice.sound.group);
+            let frames = &voice.sound.clip.frames;
+            let frame_count = frames.len() / 2;
+
+            for out in out.chunks_exact_mut(2) {
+                if voice.position >= frame_count {
+                    if voice.looping && frame_count > 0 {
+                        voice.position = 0;
+                    } else {
+                        break;
+                    }
+                }
+
+                let g = gain * voice.fade;
+                out[0] += frames[voice.position * 2] * g * voice.stereo.left;
+                out[1] += frames[voice.position * 2 + 1] * g * voice.stereo.right;
+                voice.position += 1;
+
+                voice.fade = (voice.fade + voice.fade_step).max(0.0).min(1.0);
+                if voice.fade == 0.0 && voice.is_fading_out() {
+                    break;
+                }
+            }
+        }
+
+        self.voices.retain(|v| {
+            let faded_out = v.fade == 0.0 && v.is_fading_out();
+            let finished = !v.looping && v.position >= v.sound.clip.frames.len() / 2;
+            !faded_out && !finished
+        });
+
+        for sample in out.iter_mut() {
+            *sample = sample.max(-1.0).min(1.0);
+        }
+    }
+
+    fn start(
+        &mut self,
+        sound: &Sound,
+        looping: bool,
+        stereo: StereoGain,
+        fade: f32,
+        fade_step: f32,
+    ) {
+        self.serial += 1;
+        self.voices.push(Voice {
+            sound: sound.clone(),
+            position: 0,
+            looping,
+            stereo,
+            fade,
+            fade_step,
+            serial: self.serial,
+        });
+    }
+
+    fn fade_step(&self, seconds: f32) -> f32 {
+        1.0 / (seconds * self.sample_rate as f32).max(1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::spatial::{spatialize, Falloff, Listener};
+    use crate::audio::Clip;
+    use nalgebra as na;
+
+    const FRAMES: usize = 64;
+
+    /// A clip holding 0.5 on both channels.
+    fn constant(max_voices: usize) -> Sound {
+        let clip = Clip {
+            frames: vec![0.5; FRAMES * 2],
+        };
+        Sound::new(clip, Group::Sfx, 1.0, max_voices)
+    }
+
+    fn listener() -> Listener {
+        Listener::new(na::Point2::new(0.0, 0.0), na::Vector2::new(10.0, 10.0))
+    }
+
+    #[test]
+    fn source_left_of_the_view_is_louder_on_the_left() {
+        let mut mixer = Mixer::new(44100);
+        let sound = constant(4);
+        let falloff = Falloff::default();
+
+        mixer.play_panned(
+            &sound,
+            spatialize(&listener(), &na::Point2::new(-8.0, 0.0), &falloff),
+        );
+        let mut out = vec![0.0; FRAMES * 2];
+        mixer.mix(&mut out);
+
+        for frame in out.chunks_exact(2) {
+            assert_eq!(frame[0], 0.5);
+            assert!(frame[1] < frame[0] && frame[1] > 0.0);
+        }
+        assert_eq!(
+            mixer.voice_count(),
+            0,
+            "a finished one-shot frees its voice"
+        );
+    }
+
+    #[test]
+    fn sound_spatialized_to_silence_takes_no_voice() {
+        let mut mixer = Mixer::new(44100);
+        let sound = constant(4);
+        let falloff = Falloff::default();
+        let far = na::Point2::new(10.0 * falloff.outer + 1.0, 0.0);
+
+        mixer.play_panned(&sound, spatialize(&listener(), &far, &falloff));
+        assert_eq!(mixer.voice_count(), 0);
+
+        let mut out = vec![1.0; FRAMES * 2];
+        mixer.mix(&mut out);
+        assert!(out.iter().all(|sample| *sample == 0.0));
+    }
+
+    #[test]
+    fn oldest_voice_is_cut_off_past_max_voices() {
+        let mut mixer = Mixer::new(44100);
+        let sound = constant(2);
+
+        for _ in 0..3 {
+            mixer.play(&sound);
+        }
+        assert_eq!(mixer.voice_count(), 2);
+
+        let mut out = vec![0.0; 2];
+        mixer.mix(&mut out);
+        assert_eq!(out, vec![1.0, 1.0]);
+    }
+}
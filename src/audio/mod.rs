@@ -0,0 +1,295 @@
+//! Sound effects and music, mixed in software and played through an SDL audio device.
+//!
+//! Clips are WAV files listed in `res/audio/sounds.toml`, decoded once at the device's
+//! sample rate. Without an audio device, e.g. on CI, `Audio` keeps working silently.
+
+mod mixer;
+pub mod spatial;
+mod wav;
+
+pub use self::{
+    mixer::{Mixer, Volumes},
+    spatial::{Falloff, Listener, StereoGain},
+};
+
+use crate::resources::{self, Resources};
+use nalgebra as na;
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::sync::Arc;
+use toml;
+
+/// Sample rate asked of the device, and used without one.
+const SAMPLE_RATE: i32 = 48_000;
+/// Frames per device callback, small enough for effects to feel immediate.
+const BUFFER_FRAMES: u16 = 1024;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to load sound {}", name)]
+    ResourceLoad {
+        name: String,
+        #[cause]
+        inner: resources::Error,
+    },
+    #[fail(display = "Failed to parse sounds {}", name)]
+    Parse {
+        name: String,
+        #[cause]
+        inner: toml::de::Error,
+    },
+    #[fail(display = "Failed to decode sound {}: {}", name, message)]
+    Decode { name: String, message: String },
+}
+
+/// Which volume a sound follows, besides the master volume.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Deserialize)]
+pub enum Group {
+    Music,
+    Sfx,
+}
+
+impl Default for Group {
+    fn default() -> Group {
+        Group::Sfx
+    }
+}
+
+/// Decoded interleaved stereo samples at the mixer's sample rate.
+pub struct Clip {
+    frames: Vec<f32>,
+}
+
+impl Clip {
+    /// Decodes WAV `bytes` and resamples them to `sample_rate`.
+    pub fn from_wav(name: &str, bytes: &[u8], sample_rate: u32) -> Result<Clip, Error> {
+        let samples = wav::decode(bytes).map_err(|message| Error::Decode {
+            name: name.into(),
+            message,
+        })?;
+
+        Ok(Clip {
+            frames: samples.resampled(sample_rate).frames,
+        })
+    }
+
+    /// Seconds at `sample_rate`.
+    pub fn duration(&self, sample_rate: u32) -> f32 {
+        (self.frames.len() / 2) as f32 / sample_rate as f32
+    }
+}
+
+/// A clip with how it should be played. Cheap to clone.
+#[derive(Clone)]
+pub struct Sound {
+    clip: Arc<Clip>,
+    group: Group,
+    volume: f32,
+    /// voices of this sound allowed at once
+    max_voices: usize,
+}
+
+impl Sound {
+    pub fn new(clip: Clip, group: Group, volume: f32, max_voices: usize) -> Sound {
+        Sound {
+            clip: Arc::new(clip),
+            group,
+            volume,
+            max_voices,
+        }
+    }
+
+    pub fn group(&self) -> Group {
+        self.group
+    }
+}
+
+fn default_volume() -> f32 {
+    1.0
+}
+
+fn default_max_voices() -> usize {
+    4
+}
+
+#[derive(Deserialize)]
+struct SoundDef {
+    file: String,
+    #[serde(default)]
+    group: Group,
+    #[serde(default = "default_volume")]
+    volume: f32,
+    #[serde(default = "default_max_voices")]
+    max_voices: usize,
+}
+
+#[derive(Deserialize)]
+struct SoundsFile {
+    #[serde(default)]
+    falloff: Falloff,
+    sounds: BTreeMap<String, SoundDef>,
+}
+
+/// Every sound of `res/audio/sounds.toml`, by id.
+pub struct SoundBank {
+    sounds: HashMap<String, Sound>,
+    falloff: Falloff,
+}
+
+impl SoundBank {
+    pub fn from_res(res: &Resources, name: &str, sample_rate: u32) -> Result<SoundBank, Error> {
+        let text = res.load_string(name).map_err(|e| Error::ResourceLoad {
+            name: name.into(),
+            inner: e,
+        })?;
+        let file: SoundsFile = toml::from_str(&text).map_err(|e| Error::Parse {
+            name: name.into(),
+            inner: e,
+        })?;
+
+        let mut sounds = HashMap::new();
+        for (id, def) in file.sounds {
+            let bytes = res.load_bytes(&def.file).map_err(|e| Error::ResourceLoad {
+                name: def.file.clone(),
+                inner: e,
+            })?;
+            let clip = Clip::from_wav(&def.file, &bytes, sample_rate)?;
+            sounds.insert(id, Sound::new(clip, def.group, def.volume, def.max_voices));
+        }
+
+        Ok(SoundBank {
+            sounds,
+            falloff: file.falloff,
+        })
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Sound> {
+        self.sounds.get(id)
+    }
+
+    /// How positioned sounds fade, for `Audio::set_falloff`.
+    pub fn falloff(&self) -> Falloff {
+        self.falloff
+    }
+}
+
+struct MixerCallback {
+    mixer: Mixer,
+}
+
+impl AudioCallback for MixerCallback {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        self.mixer.mix(out);
+    }
+}
+
+enum Output {
+    Device(AudioDevice<MixerCallback>),
+    /// no device: sounds are accepted but never heard
+    Null(Mixer),
+}
+
+pub struct Audio {
+    output: Output,
+    listener: Listener,
+    falloff: Falloff,
+}
+
+impl Audio {
+    /// Opens the default playback device, or falls back to `Audio::null` with a warning.
+    pub fn new(sdl: &sdl2::Sdl) -> Audio {
+        match Audio::open(sdl) {
+            Ok(audio) => audio,
+            Err(e) => {
+                println!("No audio device, continuing without sound: {}", e);
+                Audio::null()
+            }
+        }
+    }
+
+    pub fn null() -> Audio {
+        Audio {
+            output: Output::Null(Mixer::new(SAMPLE_RATE as u32)),
+            listener: Listener::default(),
+            falloff: Falloff::default(),
+        }
+    }
+
+    fn open(sdl: &sdl2::Sdl) -> Result<Audio, String> {
+        let subsystem = sdl.audio()?;
+        let desired = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE),
+            channels: Some(2),
+            samples: Some(BUFFER_FRAMES),
+        };
+        let device = subsystem.open_playback(None, &desired, |spec| MixerCallback {
+            mixer: Mixer::new(spec.freq as u32),
+        })?;
+        device.resume();
+
+        Ok(Audio {
+            output: Output::Device(device),
+            listener: Listener::default(),
+            falloff: Falloff::default(),
+        })
+    }
+
+    pub fn is_null(&self) -> bool {
+        match self.output {
+            Output::Device(_) => false,
+            Output::Null(_) => true,
+        }
+    }
+
+    /// The rate clips have to be decoded at, see `SoundBank::from_res`.
+    pub fn sample_rate(&mut self) -> u32 {
+        self.with_mixer(|mixer| mixer.sample_rate())
+    }
+
+    /// Runs `f` with the audio thread paused.
+    pub fn with_mixer<R>(&mut self, f: impl FnOnce(&mut Mixer) -> R) -> R {
+        match &mut self.output {
+            Output::Device(device) => f(&mut device.lock().mixer),
+            Output::Null(mixer) => f(mixer),
+        }
+    }
+
+    pub fn play(&mut self, sound: &Sound) {
+        self.with_mixer(|mixer| mixer.play(sound));
+    }
+
+    /// Plays `sound` panned and attenuated by where `position` is relative to the listener.
+    pub fn play_at(&mut self, sound: &Sound, position: &na::Point2<f32>) {
+        let stereo = spatial::spatialize(&self.listener, position, &self.falloff);
+        self.with_mixer(|mixer| mixer.play_panned(sound, stereo));
+    }
+
+    /// Follows the camera, call when it moves or zooms.
+    pub fn set_listener(&mut self, listener: Listener) {
+        self.listener = listener;
+    }
+
+    pub fn set_falloff(&mut self, falloff: Falloff) {
+        self.falloff = falloff;
+    }
+
+    /// Crossfades to `sound` over `fade` seconds.
+    pub fn play_music(&mut self, sound: &Sound, fade: f32) {
+        self.with_mixer(|mixer| mixer.play_music(sound, fade));
+    }
+
+    pub fn stop_music(&mut self, fade: f32) {
+        self.with_mixer(|mixer| mixer.stop_music(fade));
+    }
+
+    pub fn volumes(&mut self) -> Volumes {
+        self.with_mixer(|mixer| mixer.volumes())
+    }
+
+    pub fn set_volumes(&mut self, volumes: Volumes) {
+        self.with_mixer(|mixer| mixer.set_volumes(volumes));
+    }
+}
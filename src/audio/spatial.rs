@@ -0,0 +1,183 @@
+//! Panning and distance attenuation of one-shot sounds relative to the camera's view.
+//!
+//! Distances are measured in half view widths from the camera target, so zooming out lets
+//! the player hear more of the map, like they see more of it.
+
+use crate::camera::Camera;
+use nalgebra as na;
+
+/// Where the player listens from: the part of the world the camera shows.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Listener {
+    pub center: na::Point2<f32>,
+    /// half the view's width and height in world units
+    pub half_extents: na::Vector2<f32>,
+}
+
+impl Listener {
+    pub fn new(center: na::Point2<f32>, half_extents: na::Vector2<f32>) -> Listener {
+        Listener {
+            center,
+            half_extents,
+        }
+    }
+
+    pub fn from_camera(camera: &Camera) -> Listener {
+        let (center, half_extents) = camera.view_bounds();
+        Listener::new(center, half_extents)
+    }
+}
+
+impl Default for Listener {
+    fn default() -> Listener {
+        Listener::new(na::Point2::origin(), na::Vector2::new(1.0, 1.0))
+    }
+}
+
+fn default_inner() -> f32 {
+    1.0
+}
+
+fn default_outer() -> f32 {
+    3.0
+}
+
+fn default_rolloff() -> f32 {
+    1.0
+}
+
+fn default_stereo_width() -> f32 {
+    0.8
+}
+
+/// How loud and how far to the side a positioned sound plays, see `res/audio/sounds.toml`.
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+pub struct Falloff {
+    /// sounds closer than this, in half view widths, play at full volume
+    #[serde(default = "default_inner")]
+    pub inner: f32,
+    /// sounds farther than this are silent
+    #[serde(default = "default_outer")]
+    pub outer: f32,
+    /// curve between `inner` and `outer`: 1.0 is linear, higher drops off faster
+    #[serde(default = "default_rolloff")]
+    pub rolloff: f32,
+    /// pan of a sound at the edge of the view, 1.0 is all the way to one side
+    #[serde(default = "default_stereo_width")]
+    pub stereo_width: f32,
+}
+
+impl Default for Falloff {
+    fn default() -> Falloff {
+        Falloff {
+            inner: default_inner(),
+            outer: default_outer(),
+            rolloff: default_rolloff(),
+            stereo_width: default_stereo_width(),
+        }
+    }
+}
+
+/// Per channel gains of a voice.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct StereoGain {
+    pub left: f32,
+    pub right: f32,
+}
+
+impl StereoGain {
+    /// Both channels at full volume, for music and sounds without a position.
+    pub const CENTER: StereoGain = StereoGain {
+        left: 1.0,
+        right: 1.0,
+    };
+
+    /// Balance panning from -1.0 (left) to 1.0 (right): the far channel is turned down, so
+    /// a centered sound is as loud as one without a position.
+    pub fn panned(pan: f32, gain: f32) -> StereoGain {
+        let pan = pan.max(-1.0).min(1.0);
+        StereoGain {
+            left: gain * (1.0 - pan).min(1.0),
+            right: gain * (1.0 + pan).min(1.0),
+        }
+    }
+}
+
+/// Distance attenuation from 1.0 to 0.0 for `distance` in half view widths.
+pub fn attenuation(distance: f32, falloff: &Falloff) -> f32 {
+    if distance <= falloff.inner {
+        return 1.0;
+    }
+    if distance >= falloff.outer || falloff.outer <= falloff.inner {
+        return 0.0;
+    }
+
+    let t = (distance - falloff.inner) / (falloff.outer - falloff.inner);
+    (1.0 - t).powf(falloff.rolloff.max(0.0))
+}
+
+/// Pan from -1.0 to 1.0 of a sound at `position`.
+pub fn pan(listener: &Listener, position: &na::Point2<f32>, falloff: &Falloff) -> f32 {
+    if listener.half_extents.x <= 0.0 {
+        return 0.0;
+    }
+
+    let dx = (position.x - listener.center.x) / listener.half_extents.x;
+    (dx * falloff.stereo_width).max(-1.0).min(1.0)
+}
+
+/// Channel gains of a sound at `position`, as heard by `listener`.
+pub fn spatialize(
+    listener: &Listener,
+    position: &na::Point2<f32>,
+    falloff: &Falloff,
+) -> StereoGain {
+    if listener.half_extents.x <= 0.0 {
+        return StereoGain::CENTER;
+    }
+
+    let distance = (position - listener.center).norm() / listener.half_extents.x;
+    StereoGain::panned(
+        pan(listener, position, falloff),
+        attenuation(distance, falloff),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attenuation_is_full_inside_inner_and_silent_past_outer() {
+        let falloff = Falloff::default();
+        assert_eq!(attenuation(0.0, &falloff), 1.0);
+        assert_eq!(attenuation(falloff.inner, &falloff), 1.0);
+        assert_eq!(attenuation(falloff.outer, &falloff), 0.0);
+        assert_eq!(attenuation(falloff.outer + 10.0, &falloff), 0.0);
+
+        let halfway = (falloff.inner + falloff.outer) / 2.0;
+        assert!((attenuation(halfway, &falloff) - 0.5).abs() < 1e-6);
+        let steep = Falloff {
+            rolloff: 2.0,
+            ..falloff
+        };
+        assert!((attenuation(halfway, &steep) - 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn sounds_left_of_the_view_pan_left() {
+        let listener = Listener::new(na::Point2::new(10.0, 5.0), na::Vector2::new(4.0, 3.0));
+        let falloff = Falloff::default();
+
+        let left = spatialize(&listener, &na::Point2::new(6.0, 5.0), &falloff);
+        assert!(left.left > left.right);
+        assert_eq!(left.left, 1.0);
+        assert!((left.right - (1.0 - falloff.stereo_width)).abs() < 1e-6);
+
+        let centered = spatialize(&listener, &na::Point2::new(10.0, 6.0), &falloff);
+        assert_eq!(centered, StereoGain::CENTER);
+
+        let far = spatialize(&listener, &na::Point2::new(30.0, 5.0), &falloff);
+        assert_eq!(far, StereoGain::panned(1.0, 0.0));
+    }
+}
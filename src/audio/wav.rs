@@ -0,0 +1,247 @@
+//! Just enough of RIFF WAVE to read the game's clips: 8 and 16 bit integer or 32 bit float
+//! PCM, mono or stereo.
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_FLOAT: u16 = 3;
+
+/// Interleaved stereo samples from -1.0 to 1.0.
+pub struct Samples {
+    pub sample_rate: u32,
+    pub frames: Vec<f32>,
+}
+
+impl Samples {
+    pub fn frame_count(&self) -> usize {
+        self.frames.len() / 2
+    }
+
+    /// Linearly interpolated to `sample_rate`, good enough for short effects.
+    pub fn resampled(self, sample_rate: u32) -> Samples {
+        if self.sample_rate == sample_rate || self.frames.is_empty() {
+            return Samples {
+                sample_rate,
+                frames: self.frames,
+            };
+        }
+
+        let source = self.frame_count();
+        let ratio = f64::from(self.sample_rate) / f64::from(sample_rate);
+        let count = (source as f64 / ratio).floor() as usize;
+        let mut frames = Vec::with_capacity(count * 2);
+
+        for i in 0..count {
+            let position = i as f64 * ratio;
+            let a = (position as usize).min(source - 1);
+            let b = (a + 1).min(source - 1);
+            let t = (position - a as f64) as f32;
+            for channel in 0..2 {
+                let from = self.frames[a * 2 + channel];
+                let to = self.frames[b * 2 + channel];
+                frames.push(from + (to - from) * t);
+            }
+        }
+
+        Samples {
+            sample_rate,
+            frames,
+        }
+    }
+}
+
+/// Decodes a WAV file into stereo samples, mono is played on both channels.
+pub fn decode(bytes: &[u8]) -> Result<Samples, String> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err("not a RIFF WAVE file".into());
+    }
+
+    let mut format = None;
+    let mut data = None;
+    let mut offset = 12;
+    while offset + 8 <= bytes.len() {
+        let id = &bytes[offset..offset + 4];
+        let len = read_u32(bytes, offset + 4) as usize;
+        let start = offset + 8;
+        let end = start
+            .checked_add(len)
+            .filter(|end| *end <= bytes.len())
+            .ok_or_else(|| format!("chunk {:?} runs past the end", String::from_utf8_lossy(id)))?;
+
+        match id {
+            b"fmt " if len >= 16 => {
+                format = Some((
+                    read_u16(bytes, start),
+                    read_u16(bytes, start + 2),
+                    read_u32(bytes, start + 4),
+                    read_u16(bytes, start + 12),
+                    read_u16(bytes, start + 14),
+                ))
+            }
+            b"data" => data = Some(&bytes[start..end]),
+            _ => {}
+        }
+        // chunks are padded to even sizes
+        offset = end + (len & 1);
+    }
+
+    let (tag, channels, sample_rate, block_align, bits) = format.ok_or("missing fmt chunk")?;
+    let data = data.ok_or("missing data chunk")?;
+    if channels != 1 && channels != 2 {
+        return Err(format!("{} channels, expected 1 or 2", channels));
+    }
+    if sample_rate == 0 {
+        return Err("sample rate of 0".into());
+    }
+    match (tag, bits) {
+        (FORMAT_PCM, 8) | (FORMAT_PCM, 16) | (FORMAT_FLOAT, 32) => {}
+        _ => return Err(format!("unsupported format {} with {} bits", tag, bits)),
+    }
+    let frame_size = channels as usize * bits as usize / 8;
+    if block_align as usize != frame_size {
+        return Err(format!(
+            "block align of {} bytes, expected {} for {} channels of {} bits",
+            block_align, frame_size, channels, bits
+        ));
+    }
+    if data.len() % frame_size != 0 {
+        return Err(format!(
+            "{} bytes of data isn't a whole number of {} byte frames",
+            data.len(),
+            frame_size
+        ));
+    }
+
+    let samples: Vec<f32> = match (tag, bits) {
+        (FORMAT_PCM, 8) => data
+            .iter()
+            .map(|s| (f32::from(*s) - 128.0) / 128.0)
+            .collect(),
+        (FORMAT_PCM, 16) => data
+            .chunks_exact(2)
+            .map(|s| f32::from(i16::from_le_bytes([s[0], s[1]])) / 32768.0)
+            .collect(),
+        (FORMAT_FLOAT, 32) => data
+            .chunks_exact(4)
+            .map(|s| f32::from_bits(u32::from_le_bytes([s[0], s[1], s[2], s[3]])))
+            .collect(),
+        _ => unreachable!("checked above"),
+    };
+
+    let frames = if channels == 1 {
+        samples.iter().flat_map(|s| vec![*s, *s]).collect()
+    } else {
+        samples
+    };
+
+    Ok(Samples {
+        sample_rate,
+        frames,
+    })
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A WAV file with a 16 byte fmt chunk.
+    fn wav(tag: u16, channels: u16, sample_rate: u32, bits: u16, data: &[u8]) -> Vec<u8> {
+        let block_align = channels * bits / 8;
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(4 + 24 + 8 + data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(b"WAVE");
+        bytes.extend_from_slice(b"fmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        bytes.extend_from_slice(&tag.to_le_bytes());
+        bytes.extend_from_slice(&channels.to_le_bytes());
+        bytes.extend_from_slice(&sample_rate.to_le_bytes());
+        bytes.extend_from_slice(&(sample_rate * u32::from(block_align)).to_le_bytes());
+        bytes.extend_from_slice(&block_align.to_le_bytes());
+        bytes.extend_from_slice(&bits.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn decodes_8_bit_mono_onto_both_channels() {
+        let samples = decode(&wav(FORMAT_PCM, 1, 22050, 8, &[128, 255, 0])).unwrap();
+        assert_eq!(samples.sample_rate, 22050);
+        assert_eq!(
+            samples.frames,
+            vec![0.0, 0.0, 127.0 / 128.0, 127.0 / 128.0, -1.0, -1.0]
+        );
+    }
+
+    #[test]
+    fn decodes_16_bit_stereo() {
+        let mut data = Vec::new();
+        for sample in &[0i16, -32768, 16384, 32767] {
+            data.extend_from_slice(&sample.to_le_bytes());
+        }
+        let samples = decode(&wav(FORMAT_PCM, 2, 44100, 16, &data)).unwrap();
+        assert_eq!(samples.frame_count(), 2);
+        assert_eq!(samples.frames, vec![0.0, -1.0, 0.5, 32767.0 / 32768.0]);
+    }
+
+    #[test]
+    fn decodes_float_mono_onto_both_channels() {
+        let mut data = Vec::new();
+        for sample in &[0.25f32, -0.75] {
+            data.extend_from_slice(&sample.to_bits().to_le_bytes());
+        }
+        let samples = decode(&wav(FORMAT_FLOAT, 1, 48000, 32, &data)).unwrap();
+        assert_eq!(samples.frames, vec![0.25, 0.25, -0.75, -0.75]);
+    }
+
+    #[test]
+    fn rejects_a_sample_rate_of_0() {
+        assert!(decode(&wav(FORMAT_PCM, 1, 0, 16, &[0, 0])).is_err());
+    }
+
+    #[test]
+    fn rejects_a_block_align_that_doesnt_match_channels_and_bits() {
+        let mut bytes = wav(FORMAT_PCM, 2, 44100, 16, &[0; 8]);
+        // block align of the fmt chunk, 2 bytes instead of 4
+        bytes[32..34].copy_from_slice(&2u16.to_le_bytes());
+        assert!(decode(&bytes).is_err());
+    }
+
+    #[test]
+    fn rejects_data_that_ends_mid_frame() {
+        assert!(decode(&wav(FORMAT_PCM, 2, 44100, 16, &[0; 6])).is_err());
+        assert!(decode(&wav(FORMAT_FLOAT, 1, 44100, 32, &[0; 6])).is_err());
+    }
+
+    #[test]
+    fn rejects_unsupported_formats() {
+        assert!(decode(&wav(FORMAT_PCM, 1, 44100, 24, &[0; 3])).is_err());
+        assert!(decode(&wav(FORMAT_FLOAT, 1, 44100, 64, &[0; 8])).is_err());
+        assert!(decode(&wav(FORMAT_PCM, 3, 44100, 16, &[0; 6])).is_err());
+    }
+
+    #[test]
+    fn resampling_keeps_the_duration() {
+        let samples = Samples {
+            sample_rate: 22050,
+            frames: [0.0, 0.0, 1.0, 1.0].repeat(50),
+        };
+        let resampled = samples.resampled(44100);
+        assert_eq!(resampled.sample_rate, 44100);
+        assert_eq!(resampled.frame_count(), 200);
+        assert_eq!(&resampled.frames[0..4], &[0.0, 0.0, 0.5, 0.5]);
+    }
+}
@@ -1,19 +1,141 @@
+use crate::render_gl::{buffer, Std140Writer, Viewport};
+use gl;
 use nalgebra as na;
 
+/// World units the camera shows at least, more along the longer side when the viewport's
+/// aspect ratio differs.
+pub const VIEW_SIZE: (f32, f32) = (16.0, 9.0);
+
+/// How far the camera zooms out and in, as multiples of `VIEW_SIZE`.
+pub const ZOOM_RANGE: (f32, f32) = (0.5, 2.0);
+
 pub struct Camera {
     pub target: na::Point3<f32>,
+    aspect: f32,
+    zoom: f32,
     projection: na::Orthographic3<f32>,
 }
 
 impl Camera {
     pub fn new() -> Camera {
+        let aspect = VIEW_SIZE.0 / VIEW_SIZE.1;
         Camera {
             target: na::Point3::origin(),
-            projection: na::geometry::Orthographic3::new(-8.0, 8.0, -4.5, 4.5, -1.0, 1.0),
+            aspect,
+            zoom: 1.0,
+            projection: Camera::fit(aspect, 1.0),
+        }
+    }
+
+    /// Fits the projection to a viewport `aspect` times as wide as it is high, call after
+    /// the window is resized or switches modes.
+    pub fn set_aspect(&mut self, aspect: f32) {
+        // a minimized window has no height
+        if aspect.is_finite() && aspect > 0.0 {
+            self.aspect = aspect;
+            self.projection = Camera::fit(aspect, self.zoom);
+        }
+    }
+
+    /// Multiples of `VIEW_SIZE` the camera shows.
+    pub fn zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    /// Shows `factor` times as much of the world, within `ZOOM_RANGE`.
+    pub fn zoom_by(&mut self, factor: f32) {
+        let zoom = self.zoom * factor;
+        if zoom.is_finite() {
+            self.zoom = zoom.max(ZOOM_RANGE.0).min(ZOOM_RANGE.1);
+            self.projection = Camera::fit(self.aspect, self.zoom);
+        }
+    }
+
+    fn fit(aspect: f32, zoom: f32) -> na::Orthographic3<f32> {
+        let (mut half_w, mut half_h) = (VIEW_SIZE.0 * zoom / 2.0, VIEW_SIZE.1 * zoom / 2.0);
+        if aspect > half_w / half_h {
+            half_w = half_h * aspect;
+        } else {
+            half_h = half_w / aspect;
         }
+        na::Orthographic3::new(-half_w, half_w, -half_h, half_h, -1.0, 1.0)
     }
 
     pub fn get_p_matrix(&self) -> na::Matrix4<f32> {
         self.projection.into_inner()
     }
+
+    pub fn get_v_matrix(&self) -> na::Matrix4<f32> {
+        na::Translation3::from(-self.target.coords).to_homogeneous()
+    }
+
+    /// Center and half extents in world units of what the camera shows.
+    pub fn view_bounds(&self) -> (na::Point2<f32>, na::Vector2<f32>) {
+        let center = na::Point2::new(
+            self.target.x + (self.projection.left() + self.projection.right()) / 2.0,
+            self.target.y + (self.projection.bottom() + self.projection.top()) / 2.0,
+        );
+        let half_extents = na::Vector2::new(
+            (self.projection.right() - self.projection.left()) / 2.0,
+            (self.projection.top() - self.projection.bottom()) / 2.0,
+        );
+
+        (center, half_extents)
+    }
+
+    /// Unprojects a position in window coordinates (origin top left, as SDL reports it)
+    /// onto the z = 0 plane.
+    pub fn screen_to_world(
+        &self,
+        screen: &na::Point2<f32>,
+        viewport: &Viewport,
+    ) -> na::Point2<f32> {
+        let pixel = screen * viewport.pixel_ratio;
+        let ndc = na::Point3::new(
+            (pixel.x - viewport.x as f32) / viewport.w as f32 * 2.0 - 1.0,
+            1.0 - (pixel.y - viewport.y as f32) / viewport.h as f32 * 2.0,
+            0.0,
+        );
+        let view_projection = self.get_p_matrix() * self.get_v_matrix();
+        let world = view_projection
+            .try_inverse()
+            .expect("expected camera matrices to be invertible")
+            .transform_point(&ndc);
+
+        na::Point2::new(world.x, world.y)
+    }
+}
+
+/// The `Camera` uniform block from `shaders/common/camera.glsl`, shared by every program
+/// that binds it with `Program::bind_uniform_block("Camera", CameraUniforms::BINDING)`.
+pub struct CameraUniforms {
+    ubo: buffer::UniformBuffer,
+    block: Std140Writer,
+}
+
+impl CameraUniforms {
+    pub const BINDING: u32 = 0;
+
+    pub fn new(gl: &gl::Gl) -> CameraUniforms {
+        let ubo = buffer::UniformBuffer::new(gl);
+        ubo.bind();
+        ubo.dynamic_draw_data_null::<na::Matrix4<f32>>(2);
+        ubo.unbind();
+        ubo.bind_base(CameraUniforms::BINDING);
+
+        CameraUniforms {
+            ubo,
+            block: Std140Writer::new(),
+        }
+    }
+
+    /// Uploads the camera matrices, once per frame before anything is drawn.
+    pub fn update(&mut self, cam: &Camera) {
+        self.block.clear();
+        self.block.push(&cam.get_p_matrix()).push(&cam.get_v_matrix());
+
+        self.ubo.bind();
+        self.ubo.dynamic_draw_data(self.block.as_bytes());
+        self.ubo.unbind();
+    }
 }
@@ -0,0 +1,159 @@
+//! The order levels are played in, what earns their stars and the achievements there are,
+//! see `res/campaign.toml`. What a player has reached of it is kept in `profile`.
+
+use crate::resources::{self, Resources};
+use toml;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to load campaign {}", name)]
+    ResourceLoad {
+        name: String,
+        #[cause]
+        inner: resources::Error,
+    },
+    #[fail(display = "Failed to parse campaign {}", name)]
+    Parse {
+        name: String,
+        #[cause]
+        inner: toml::de::Error,
+    },
+    #[fail(display = "Campaign {} has no levels", name)]
+    NoLevels { name: String },
+    #[fail(display = "{} is listed twice in campaign {}", id, name)]
+    Duplicate { name: String, id: String },
+}
+
+/// A level of the campaign. Winning it unlocks the next one.
+#[derive(Clone, Debug, Deserialize)]
+pub struct CampaignLevel {
+    /// resource name of the level, e.g. `levels/arm.toml`
+    pub level: String,
+    /// fraction of the heart's health left needed for each star, lowest first
+    #[serde(default = "default_stars")]
+    pub stars: Vec<f32>,
+}
+
+fn default_stars() -> Vec<f32> {
+    vec![0.0, 0.5, 1.0]
+}
+
+impl CampaignLevel {
+    /// Stars for winning with `health` of `max` heart health left.
+    pub fn stars_for(&self, health: u32, max: u32) -> u32 {
+        let left = if max > 0 {
+            health as f32 / max as f32
+        } else {
+            0.0
+        };
+        self.stars.iter().filter(|needed| left >= **needed).count() as u32
+    }
+}
+
+/// What an achievement asks for, counted over every match the player finished.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type")]
+pub enum Goal {
+    /// `count` pathogens killed, of kind `pathogen` if given
+    Kills {
+        #[serde(default)]
+        pathogen: Option<String>,
+        count: u64,
+    },
+    /// `count` towers built, of kind `tower` if given
+    TowersBuilt {
+        #[serde(default)]
+        tower: Option<String>,
+        count: u64,
+    },
+    LevelsWon {
+        count: u64,
+    },
+    /// best stars of every level added up
+    Stars {
+        count: u32,
+    },
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct AchievementDef {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub goal: Goal,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Campaign {
+    levels: Vec<CampaignLevel>,
+    #[serde(default)]
+    achievements: Vec<AchievementDef>,
+}
+
+impl Campaign {
+    pub fn from_res(res: &Resources, name: &str) -> Result<Campaign, Error> {
+        let text = res.load_string(name).map_err(|e| Error::ResourceLoad {
+            name: name.into(),
+            inner: e,
+        })?;
+
+        Campaign::from_toml_str(name, &text)
+    }
+
+    pub fn from_toml_str(name: &str, text: &str) -> Result<Campaign, Error> {
+        let campaign: Campaign = toml::from_str(text).map_err(|e| Error::Parse {
+            name: name.into(),
+            inner: e,
+        })?;
+
+        if campaign.levels.is_empty() {
+            return Err(Error::NoLevels { name: name.into() });
+        }
+        let duplicate = |id: &str| Error::Duplicate {
+            name: name.into(),
+            id: id.into(),
+        };
+        for (i, level) in campaign.levels.iter().enumerate() {
+            if campaign.levels[..i].iter().any(|l| l.level == level.level) {
+                return Err(duplicate(&level.level));
+            }
+        }
+        for (i, achievement) in campaign.achievements.iter().enumerate() {
+            if campaign.achievements[..i]
+                .iter()
+                .any(|a| a.id == achievement.id)
+            {
+                return Err(duplicate(&achievement.id));
+            }
+        }
+
+        Ok(campaign)
+    }
+
+    /// The level a new player starts with.
+    pub fn first(&self) -> &CampaignLevel {
+        &self.levels[0]
+    }
+
+    pub fn get(&self, level: &str) -> Option<&CampaignLevel> {
+        self.levels.iter().find(|l| l.level == level)
+    }
+
+    /// The level winning `level` unlocks, if any.
+    pub fn next_after(&self, level: &str) -> Option<&CampaignLevel> {
+        let index = self.levels.iter().position(|l| l.level == level)?;
+        self.levels.get(index + 1)
+    }
+
+    pub fn levels(&self) -> impl Iterator<Item = &CampaignLevel> {
+        self.levels.iter()
+    }
+
+    pub fn achievements(&self) -> impl Iterator<Item = &AchievementDef> {
+        self.achievements.iter()
+    }
+
+    pub fn achievement(&self, id: &str) -> Option<&AchievementDef> {
+        self.achievements.iter().find(|a| a.id == id)
+    }
+}
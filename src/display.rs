@@ -0,0 +1,154 @@
+//! The displays connected, and switching the window between windowed, borderless and
+//! exclusive fullscreen on one of them while the game runs.
+//!
+//! A switch changes the window's size without the player resizing it, so everything sized
+//! to the window has to follow, the same as after a `WindowEvent::SizeChanged`.
+
+use crate::settings::{self, Vsync, WindowMode};
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::video::{DisplayMode, FullscreenType, SwapInterval, Window, WindowPos};
+use sdl2::VideoSubsystem;
+use std::fmt;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to query display {}: {}", index, message)]
+    Query { index: i32, message: String },
+    #[fail(display = "Failed to switch the window to {:?}: {}", mode, message)]
+    Switch { mode: WindowMode, message: String },
+}
+
+/// A mode exclusive fullscreen can switch a display to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Mode {
+    pub width: u32,
+    pub height: u32,
+    pub refresh_rate: u32,
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.refresh_rate {
+            0 => write!(f, "{}x{}", self.width, self.height),
+            rate => write!(f, "{}x{} @ {} Hz", self.width, self.height, rate),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DisplayInfo {
+    pub name: String,
+    /// largest and fastest first, as SDL lists them
+    pub modes: Vec<Mode>,
+}
+
+/// Every connected display, in the order `settings::Display::monitor` counts them. A
+/// display that fails to report its name or modes is still listed, without them.
+pub fn displays(video: &VideoSubsystem) -> Vec<DisplayInfo> {
+    let count = video.num_video_displays().unwrap_or(0);
+    (0..count)
+        .map(|index| {
+            let name = video
+                .display_name(index)
+                .unwrap_or_else(|_| format!("Display {}", index + 1));
+            let mut modes: Vec<Mode> = Vec::new();
+            for mode_index in 0..video.num_display_modes(index).unwrap_or(0) {
+                let mode = match video.display_mode(index, mode_index) {
+                    Ok(mode) => Mode {
+                        width: mode.w as u32,
+                        height: mode.h as u32,
+                        refresh_rate: mode.refresh_rate as u32,
+                    },
+                    Err(_) => continue,
+                };
+                // the same mode is listed once per pixel format
+                if !modes.contains(&mode) {
+                    modes.push(mode);
+                }
+            }
+            DisplayInfo { name, modes }
+        })
+        .collect()
+}
+
+/// Moves `window` to the display, mode and size in `settings`. A display that is no longer
+/// connected falls back to the first one, and a fullscreen mode it lacks to the closest it
+/// has.
+pub fn apply(
+    window: &mut Window,
+    video: &VideoSubsystem,
+    settings: &settings::Display,
+) -> Result<(), Error> {
+    let count = video.num_video_displays().unwrap_or(0);
+    let index = if (settings.monitor as i32) < count {
+        settings.monitor as i32
+    } else {
+        0
+    };
+    let query = |message| Error::Query { index, message };
+    let switch = |message| Error::Switch {
+        mode: settings.mode,
+        message,
+    };
+    let bounds = video.display_bounds(index).map_err(query)?;
+
+    // the window only moves to another display and changes size while windowed
+    window.set_fullscreen(FullscreenType::Off).map_err(switch)?;
+    match settings.mode {
+        WindowMode::Windowed => {
+            let width = settings.width.min(bounds.width());
+            let height = settings.height.min(bounds.height());
+            window
+                .set_size(width, height)
+                .map_err(|e| switch(e.to_string()))?;
+            window.set_position(
+                WindowPos::Positioned(bounds.x() + (bounds.width() - width) as i32 / 2),
+                WindowPos::Positioned(bounds.y() + (bounds.height() - height) as i32 / 2),
+            );
+        }
+        WindowMode::Borderless => {
+            window.set_position(
+                WindowPos::Positioned(bounds.x()),
+                WindowPos::Positioned(bounds.y()),
+            );
+            window
+                .set_fullscreen(FullscreenType::Desktop)
+                .map_err(switch)?;
+        }
+        WindowMode::Fullscreen => {
+            let wanted = DisplayMode::new(
+                PixelFormatEnum::Unknown,
+                settings.width as i32,
+                settings.height as i32,
+                settings.refresh_rate as i32,
+            );
+            let mode = video.closest_display_mode(index, &wanted).map_err(query)?;
+            window.set_position(
+                WindowPos::Positioned(bounds.x()),
+                WindowPos::Positioned(bounds.y()),
+            );
+            window.set_display_mode(mode).map_err(switch)?;
+            window
+                .set_fullscreen(FullscreenType::True)
+                .map_err(switch)?;
+        }
+    }
+    Ok(())
+}
+
+/// Sets the swap interval of the current GL context and returns the vsync now in effect,
+/// drivers without adaptive vsync get plain vsync instead.
+pub fn set_vsync(video: &VideoSubsystem, vsync: Vsync) -> Result<Vsync, String> {
+    let interval = match vsync {
+        Vsync::Off => SwapInterval::Immediate,
+        Vsync::On => SwapInterval::VSync,
+        Vsync::Adaptive => SwapInterval::LateSwapTearing,
+    };
+    match video.gl_set_swap_interval(interval) {
+        Ok(()) => Ok(vsync),
+        Err(_) if vsync == Vsync::Adaptive => video
+            .gl_set_swap_interval(SwapInterval::VSync)
+            .map(|_| Vsync::On),
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,88 @@
+use crate::resources::{self, Resources};
+use rusttype;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to load font {}", name)]
+    ResourceLoad {
+        name: String,
+        #[cause]
+        inner: resources::Error,
+    },
+    #[fail(display = "Failed to parse font {}", name)]
+    Parse {
+        name: String,
+        #[cause]
+        inner: rusttype::Error,
+    },
+}
+
+/// A TrueType font, parsed once and shared through `Handle<Font>`.
+pub struct Font {
+    font: rusttype::Font<'static>,
+}
+
+impl Font {
+    pub fn from_res(res: &Resources, name: &str) -> Result<Font, Error> {
+        let bytes = res.load_bytes(name).map_err(|e| Error::ResourceLoad {
+            name: name.into(),
+            inner: e,
+        })?;
+
+        Font::from_bytes(name, bytes)
+    }
+
+    pub fn from_bytes(name: &str, bytes: Vec<u8>) -> Result<Font, Error> {
+        let font = rusttype::Font::from_bytes(bytes).map_err(|e| Error::Parse {
+            name: name.into(),
+            inner: e,
+        })?;
+
+        Ok(Font { font })
+    }
+
+    pub fn rusttype(&self) -> &rusttype::Font<'static> {
+        &self.font
+    }
+
+    /// Ascent and line height in pixels at `size` pixels per em.
+    pub fn line_metrics(&self, size: f32) -> (f32, f32) {
+        let metrics = self.font.v_metrics(rusttype::Scale::uniform(size));
+        (
+            metrics.ascent,
+            metrics.ascent - metrics.descent + metrics.line_gap,
+        )
+    }
+
+    /// The glyphs of `text` on a single line with their pen x positions, kerning applied.
+    pub fn layout_line(&self, text: &str, size: f32) -> Vec<(rusttype::ScaledGlyph<'_>, f32)> {
+        let scale = rusttype::Scale::uniform(size);
+        let mut glyphs = Vec::with_capacity(text.len());
+        let mut x = 0.0;
+        let mut previous = None;
+
+        for c in text.chars() {
+            let glyph = self.font.glyph(c).scaled(scale);
+            if let Some(previous) = previous {
+                x += self.font.pair_kerning(scale, previous, glyph.id());
+            }
+            previous = Some(glyph.id());
+            let advance = glyph.h_metrics().advance_width;
+            glyphs.push((glyph, x));
+            x += advance;
+        }
+
+        glyphs
+    }
+
+    /// Width and line height in pixels of `text` on a single line.
+    pub fn measure(&self, text: &str, size: f32) -> (f32, f32) {
+        let width = self
+            .layout_line(text, size)
+            .last()
+            .map(|(glyph, x)| x + glyph.h_metrics().advance_width)
+            .unwrap_or(0.0);
+
+        (width, self.line_metrics(size).1)
+    }
+}
@@ -0,0 +1,890 @@
+//! A match: gold, Joe's heart, the wave schedule, game speed and the simulation of towers
+//! and pathogens. Changes are announced as `GameEvent`s, so views like the HUD only update
+//! when something happened.
+//!
+//! The match advances in fixed ticks and never looks at the clock, so it runs the same with
+//! or without a window, see `headless`. `Game::to_save` and `Game::from_save` pause a match
+//! into a `save::SaveGame` and pick it up again.
+
+use crate::level::{Level, TileCoord, Wave};
+use crate::pathing::FlowField;
+use crate::pathogen::PathogenCatalog;
+use crate::placement::{self, Validity};
+use crate::rng::{Rng, RngService};
+use crate::save::{self, SaveGame, SavedEnemy, SavedProjectile, SavedSpawn, SavedTower};
+use crate::sim::{Enemy, PendingSpawn, Projectile, World, TICK};
+use crate::tower::{PlacedTower, TowerCatalog, Towers};
+use nalgebra as na;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Level {} sends unknown pathogen {}", level, pathogen)]
+    UnknownPathogen { level: String, pathogen: String },
+}
+
+/// Game speeds the player can pick, as multiples of real time.
+pub const SPEEDS: [u32; 3] = [1, 2, 3];
+/// Score for every point of heart health left when the level is won.
+pub const HEART_BONUS: u32 = 100;
+/// Share of a tower's cost paid back when it is sold.
+pub const SELL_REFUND_PERCENT: u32 = 50;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GameEvent {
+    GoldChanged {
+        gold: u32,
+    },
+    ScoreChanged {
+        score: u32,
+    },
+    HeartHealthChanged {
+        health: u32,
+        max: u32,
+    },
+    /// a pathogen reached the heart
+    HeartHit {
+        damage: u32,
+    },
+    /// `wave` counts from 1, `preview` lists how many of each pathogen it brings
+    WaveScheduled {
+        wave: usize,
+        total: usize,
+        preview: Vec<(String, u32)>,
+    },
+    /// whole seconds until the scheduled wave, sent each time it ticks down
+    WaveCountdown {
+        seconds: u32,
+    },
+    WaveStarted {
+        wave: usize,
+        total: usize,
+    },
+    AllWavesSent,
+    SpeedChanged {
+        speed: u32,
+    },
+    PausedChanged {
+        paused: bool,
+    },
+    HeartDestroyed,
+    TowerPlaced {
+        tile: TileCoord,
+        tower: String,
+    },
+    TowerSold {
+        tile: TileCoord,
+        tower: String,
+        refund: u32,
+    },
+    TowerFired {
+        tile: TileCoord,
+    },
+    PathogenSpawned {
+        pathogen: String,
+    },
+    /// `tower` is the tile of the tower whose shot killed it
+    PathogenKilled {
+        pathogen: String,
+        tower: TileCoord,
+        position: na::Point2<f32>,
+        reward: u32,
+    },
+    /// every wave was sent and beaten with the heart still beating
+    LevelWon,
+}
+
+/// Something the player asked for, from a key binding or a HUD button.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "args")]
+pub enum Command {
+    TogglePause,
+    SetSpeed(u32),
+    /// the next entry of `SPEEDS`, wrapping around
+    CycleSpeed,
+    SendNextWave,
+    /// `tower` comes first, TOML needs plain values before tables like `tile`
+    PlaceTower {
+        tower: String,
+        tile: TileCoord,
+    },
+    SellTower {
+        tile: TileCoord,
+    },
+}
+
+impl Command {
+    /// False for commands that only change how fast the match is watched, replays leave
+    /// those out.
+    pub fn affects_simulation(&self) -> bool {
+        match self {
+            Command::TogglePause | Command::SetSpeed(_) | Command::CycleSpeed => false,
+            Command::SendNextWave | Command::PlaceTower { .. } | Command::SellTower { .. } => true,
+        }
+    }
+}
+
+/// Counts down to each wave of a level in turn.
+pub struct WaveScheduler {
+    waves: Vec<Wave>,
+    next: usize,
+    countdown: f32,
+}
+
+impl WaveScheduler {
+    pub fn new(waves: Vec<Wave>) -> WaveScheduler {
+        let countdown = waves.first().map(|w| w.delay).unwrap_or(0.0);
+        WaveScheduler {
+            waves,
+            next: 0,
+            countdown,
+        }
+    }
+
+    /// Picks up a schedule that already started `started` waves, with `countdown` seconds
+    /// left until the next one.
+    pub fn resume(waves: Vec<Wave>, started: usize, countdown: f32) -> WaveScheduler {
+        WaveScheduler {
+            next: started.min(waves.len()),
+            waves,
+            countdown,
+        }
+    }
+
+    /// Waves started so far.
+    pub fn started(&self) -> usize {
+        self.next
+    }
+
+    pub fn total(&self) -> usize {
+        self.waves.len()
+    }
+
+    /// Seconds until the next wave, `None` once all are sent.
+    pub fn countdown(&self) -> Option<f32> {
+        if self.next < self.waves.len() {
+            Some(self.countdown)
+        } else {
+            None
+        }
+    }
+
+    /// Counts down `dt` seconds, returns the wave that started if the countdown ran out.
+    pub fn update(&mut self, dt: f32, events: &mut Vec<GameEvent>) -> Option<&Wave> {
+        if self.next >= self.waves.len() {
+            return None;
+        }
+
+        let seconds = self.countdown.ceil();
+        self.countdown -= dt;
+        if self.countdown <= 0.0 {
+            return self.start_next(events);
+        } else if self.countdown.ceil() < seconds {
+            events.push(GameEvent::WaveCountdown {
+                seconds: self.countdown.ceil() as u32,
+            });
+        }
+        None
+    }
+
+    /// Starts the next wave now instead of waiting out the countdown, returns it.
+    pub fn start_next(&mut self, events: &mut Vec<GameEvent>) -> Option<&Wave> {
+        if self.next >= self.waves.len() {
+            return None;
+        }
+
+        self.next += 1;
+        events.push(GameEvent::WaveStarted {
+            wave: self.next,
+            total: self.waves.len(),
+        });
+
+        match self.waves.get(self.next) {
+            Some(wave) => {
+                self.countdown = wave.delay;
+                self.announce(events);
+            }
+            None => events.push(GameEvent::AllWavesSent),
+        }
+        self.waves.get(self.next - 1)
+    }
+
+    /// Events describing the upcoming wave, if any.
+    fn announce(&self, events: &mut Vec<GameEvent>) {
+        let wave = match self.waves.get(self.next) {
+            Some(wave) => wave,
+            None => return,
+        };
+
+        let mut preview: Vec<(String, u32)> = Vec::new();
+        for group in &wave.groups {
+            match preview.iter_mut().find(|(p, _)| *p == group.pathogen) {
+                Some((_, count)) => *count += group.count,
+                None => preview.push((group.pathogen.clone(), group.count)),
+            }
+        }
+
+        events.push(GameEvent::WaveScheduled {
+            wave: self.next + 1,
+            total: self.waves.len(),
+            preview,
+        });
+        events.push(GameEvent::WaveCountdown {
+            seconds: self.countdown.ceil() as u32,
+        });
+    }
+}
+
+pub struct Game {
+    gold: u32,
+    score: u32,
+    heart_health: u32,
+    max_heart_health: u32,
+    waves: WaveScheduler,
+    speed: u32,
+    paused: bool,
+    won: bool,
+    ticks: u64,
+    rng: RngService,
+    catalog: TowerCatalog,
+    pathogens: PathogenCatalog,
+    towers: Towers,
+    world: World,
+    events: Vec<GameEvent>,
+}
+
+impl Game {
+    /// The first events describe the starting state, so views need no other setup. The
+    /// same `seed` and commands always play out the same match.
+    pub fn new(
+        level: &Level,
+        catalog: TowerCatalog,
+        pathogens: PathogenCatalog,
+        seed: u64,
+    ) -> Result<Game, Error> {
+        for group in level.waves.iter().flat_map(|w| w.groups.iter()) {
+            if pathogens.get(&group.pathogen).is_none() {
+                return Err(Error::UnknownPathogen {
+                    level: level.id.clone(),
+                    pathogen: group.pathogen.clone(),
+                });
+            }
+        }
+
+        let mut game = Game {
+            gold: level.starting_gold,
+            score: 0,
+            heart_health: level.heart_health,
+            max_heart_health: level.heart_health,
+            waves: WaveScheduler::new(level.waves.clone()),
+            speed: SPEEDS[0],
+            paused: false,
+            won: false,
+            ticks: 0,
+            rng: RngService::new(seed),
+            catalog,
+            pathogens,
+            towers: Towers::new(),
+            world: World::new(level),
+            events: Vec::new(),
+        };
+
+        game.announce_state();
+        Ok(game)
+    }
+
+    /// Continues the match `save` was taken from, on `level`. Everything in the save is
+    /// checked against the level and catalogs first. Like `new`, the first events describe
+    /// the state, with the waves already sent announced as started.
+    pub fn from_save(
+        level: &Level,
+        catalog: TowerCatalog,
+        pathogens: PathogenCatalog,
+        save: SaveGame,
+    ) -> Result<Game, save::Error> {
+        if save.level != level.id {
+            return Err(save::Error::WrongLevel {
+                saved: save.level,
+                level: level.id.clone(),
+            });
+        }
+        let invalid = |message: String| save::Error::Invalid { message };
+        let tile_of = |tile: TileCoord, what: &str| {
+            level.tile(tile).ok_or_else(|| {
+                invalid(format!(
+                    "{} at ({}, {}) is off the map",
+                    what, tile.col, tile.row
+                ))
+            })
+        };
+
+        if save.waves_started > level.waves.len() {
+            return Err(invalid(format!(
+                "{} waves started, the level has {}",
+                save.waves_started,
+                level.waves.len()
+            )));
+        }
+        if !SPEEDS.contains(&save.speed) {
+            return Err(invalid(format!("unknown game speed {}", save.speed)));
+        }
+
+        let mut towers = Towers::new();
+        for saved in save.towers {
+            if catalog.get(&saved.tower).is_none() {
+                return Err(invalid(format!("unknown tower {}", saved.tower)));
+            }
+            if !tile_of(saved.tile, "tower")?.is_buildable() || towers.is_occupied(saved.tile) {
+                return Err(invalid(format!(
+                    "tower can't stand at ({}, {})",
+                    saved.tile.col, saved.tile.row
+                )));
+            }
+            towers.place(
+                saved.tile,
+                PlacedTower {
+                    tower: saved.tower,
+                    cost: saved.cost,
+                    cooldown: saved.cooldown,
+                    kills: saved.kills,
+                },
+            );
+        }
+
+        let mut world = World::new(level);
+        for saved in save.enemies {
+            let def = pathogens
+                .get(&saved.pathogen)
+                .ok_or_else(|| invalid(format!("unknown pathogen {}", saved.pathogen)))?;
+            tile_of(saved.tile, "pathogen")?;
+            if let Some(target) = saved.target {
+                tile_of(target, "pathogen target")?;
+            }
+            world.enemies.push(Enemy {
+                id: saved.id,
+                pathogen: saved.pathogen,
+                health: saved.health,
+                max_health: def.health,
+                speed: def.speed * level.tile_size,
+                reward: def.reward,
+                damage: def.damage,
+                position: na::Point2::new(saved.position[0], saved.position[1]),
+                tile: saved.tile,
+                target: saved.target,
+            });
+        }
+        for saved in save.projectiles {
+            world.projectiles.push(Projectile {
+                tower: saved.tower,
+                target: saved.target,
+                position: na::Point2::new(saved.position[0], saved.position[1]),
+                speed: saved.speed,
+                damage: saved.damage,
+            });
+        }
+        for saved in save.spawns {
+            if pathogens.get(&saved.pathogen).is_none() {
+                return Err(invalid(format!("unknown pathogen {}", saved.pathogen)));
+            }
+            world.spawns.push(PendingSpawn {
+                tick: saved.tick,
+                pathogen: saved.pathogen,
+            });
+        }
+        world.spawns.sort_by_key(|s| s.tick);
+        world.next_enemy_id = save.next_enemy_id;
+        world.spawn_counter = save.spawn_counter;
+
+        let flow = FlowField::new(level, |coord| towers.is_occupied(coord));
+        world.set_flow(level, flow);
+
+        let mut game = Game {
+            gold: save.gold,
+            score: save.score,
+            heart_health: save.heart_health.min(level.heart_health),
+            max_heart_health: level.heart_health,
+            waves: WaveScheduler::resume(
+                level.waves.clone(),
+                save.waves_started,
+                save.wave_countdown,
+            ),
+            speed: save.speed,
+            paused: false,
+            won: false,
+            ticks: save.ticks,
+            rng: save.rng,
+            catalog,
+            pathogens,
+            towers,
+            world,
+            events: Vec::new(),
+        };
+        game.announce_state();
+        Ok(game)
+    }
+
+    /// Everything needed to continue this match later, see `from_save`.
+    pub fn to_save(&self, level: &Level) -> SaveGame {
+        let point = |p: &na::Point2<f32>| [p.x, p.y];
+
+        SaveGame {
+            version: save::VERSION,
+            game_version: env!("CARGO_PKG_VERSION").into(),
+            level: level.id.clone(),
+            ticks: self.ticks,
+            gold: self.gold,
+            heart_health: self.heart_health,
+            score: self.score,
+            speed: self.speed,
+            waves_started: self.waves.started(),
+            wave_countdown: self.waves.countdown().unwrap_or(0.0),
+            next_enemy_id: self.world.next_enemy_id,
+            spawn_counter: self.world.spawn_counter,
+            rng: self.rng.clone(),
+            towers: self
+                .towers
+                .iter()
+                .map(|(tile, tower)| SavedTower {
+                    tower: tower.tower.clone(),
+                    cost: tower.cost,
+                    cooldown: tower.cooldown,
+                    kills: tower.kills,
+                    tile,
+                })
+                .collect(),
+            enemies: self
+                .world
+                .enemies
+                .iter()
+                .map(|enemy| SavedEnemy {
+                    id: enemy.id,
+                    pathogen: enemy.pathogen.clone(),
+                    health: enemy.health,
+                    position: point(&enemy.position),
+                    tile: enemy.tile,
+                    target: enemy.target,
+                })
+                .collect(),
+            projectiles: self
+                .world
+                .projectiles
+                .iter()
+                .map(|projectile| SavedProjectile {
+                    target: projectile.target,
+                    position: point(&projectile.position),
+                    speed: projectile.speed,
+                    damage: projectile.damage,
+                    tower: projectile.tower,
+                })
+                .collect(),
+            spawns: self
+                .world
+                .spawns
+                .iter()
+                .map(|spawn| SavedSpawn {
+                    tick: spawn.tick,
+                    pathogen: spawn.pathogen.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Events describing the whole state, for views that start watching.
+    fn announce_state(&mut self) {
+        self.events.push(GameEvent::GoldChanged { gold: self.gold });
+        self.events
+            .push(GameEvent::ScoreChanged { score: self.score });
+        self.events.push(GameEvent::HeartHealthChanged {
+            health: self.heart_health,
+            max: self.max_heart_health,
+        });
+        self.events
+            .push(GameEvent::SpeedChanged { speed: self.speed });
+        self.events.push(GameEvent::PausedChanged {
+            paused: self.paused,
+        });
+        if self.waves.started() > 0 {
+            self.events.push(GameEvent::WaveStarted {
+                wave: self.waves.started(),
+                total: self.waves.total(),
+            });
+        }
+        if self.waves.countdown().is_some() {
+            self.waves.announce(&mut self.events);
+        } else {
+            self.events.push(GameEvent::AllWavesSent);
+        }
+    }
+
+    pub fn gold(&self) -> u32 {
+        self.gold
+    }
+
+    pub fn set_gold(&mut self, gold: u32) {
+        if gold != self.gold {
+            self.gold = gold;
+            self.events.push(GameEvent::GoldChanged { gold });
+        }
+    }
+
+    pub fn score(&self) -> u32 {
+        self.score
+    }
+
+    fn add_score(&mut self, points: u32) {
+        if points > 0 {
+            self.score += points;
+            self.events
+                .push(GameEvent::ScoreChanged { score: self.score });
+        }
+    }
+
+    pub fn heart_health(&self) -> u32 {
+        self.heart_health
+    }
+
+    pub fn max_heart_health(&self) -> u32 {
+        self.max_heart_health
+    }
+
+    /// Health lost to a pathogen reaching the heart.
+    pub fn damage_heart(&mut self, amount: u32) {
+        if self.heart_health == 0 || amount == 0 {
+            return;
+        }
+
+        let damage = amount.min(self.heart_health);
+        self.heart_health -= damage;
+        self.events.push(GameEvent::HeartHit { damage });
+        self.events.push(GameEvent::HeartHealthChanged {
+            health: self.heart_health,
+            max: self.max_heart_health,
+        });
+        if self.heart_health == 0 {
+            self.events.push(GameEvent::HeartDestroyed);
+        }
+    }
+
+    pub fn waves(&self) -> &WaveScheduler {
+        &self.waves
+    }
+
+    pub fn speed(&self) -> u32 {
+        self.speed
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Simulation ticks run so far.
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    pub fn is_won(&self) -> bool {
+        self.won
+    }
+
+    /// True once the heart gave out or the level is won, nothing moves after that.
+    pub fn is_over(&self) -> bool {
+        self.heart_health == 0 || self.won
+    }
+
+    pub fn rng(&self) -> &RngService {
+        &self.rng
+    }
+
+    /// Randomness for effects that don't change the match, see `rng::COSMETIC`.
+    pub fn cosmetic_rng(&mut self) -> &mut Rng {
+        self.rng.cosmetic()
+    }
+
+    pub fn catalog(&self) -> &TowerCatalog {
+        &self.catalog
+    }
+
+    pub fn pathogens(&self) -> &PathogenCatalog {
+        &self.pathogens
+    }
+
+    pub fn towers(&self) -> &Towers {
+        &self.towers
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    pub fn apply(&mut self, level: &Level, command: Command) {
+        match command {
+            Command::TogglePause => {
+                self.paused = !self.paused;
+                self.events.push(GameEvent::PausedChanged {
+                    paused: self.paused,
+                });
+            }
+            Command::SetSpeed(speed) => {
+                if speed != self.speed && SPEEDS.contains(&speed) {
+                    self.speed = speed;
+                    self.events.push(GameEvent::SpeedChanged { speed });
+                }
+            }
+            Command::CycleSpeed => {
+                let i = SPEEDS.iter().position(|s| *s == self.speed).unwrap_or(0);
+                self.apply(level, Command::SetSpeed(SPEEDS[(i + 1) % SPEEDS.len()]));
+            }
+            Command::SendNextWave => {
+                if let Some(wave) = self.waves.start_next(&mut self.events) {
+                    self.world.queue_wave(wave, self.ticks, self.rng.gameplay());
+                }
+            }
+            Command::PlaceTower { tile, tower } => {
+                let _ = self.place_tower(level, tile, &tower);
+            }
+            Command::SellTower { tile } => {
+                let _ = self.sell_tower(level, tile);
+            }
+        }
+    }
+
+    /// Builds `tower` on `tile` and pays for it, unless `placement::check_tile` objects.
+    pub fn place_tower(
+        &mut self,
+        level: &Level,
+        tile: TileCoord,
+        tower: &str,
+    ) -> Result<(), Validity> {
+        let def = self.catalog.get(tower).ok_or(Validity::UnknownTower)?;
+        let flow =
+            placement::flow_with_tower(level, &self.towers, &self.world, self.gold, def, tile)?;
+
+        let placed = PlacedTower::new(def);
+        self.set_gold(self.gold - placed.cost);
+        self.towers.place(tile, placed);
+        self.world.set_flow(level, flow);
+        self.events.push(GameEvent::TowerPlaced {
+            tile,
+            tower: tower.into(),
+        });
+        Ok(())
+    }
+
+    /// Tears down the tower on `tile` and refunds `SELL_REFUND_PERCENT` of what was paid,
+    /// returns the refund or `None` when there is no tower.
+    pub fn sell_tower(&mut self, level: &Level, tile: TileCoord) -> Option<u32> {
+        let sold = self.towers.remove(tile)?;
+        let refund = sold.cost * SELL_REFUND_PERCENT / 100;
+        self.set_gold(self.gold + refund);
+        let towers = &self.towers;
+        let flow = FlowField::new(level, |coord| towers.is_occupied(coord));
+        self.world.set_flow(level, flow);
+        self.events.push(GameEvent::TowerSold {
+            tile,
+            tower: sold.tower,
+            refund,
+        });
+        Some(refund)
+    }
+
+    /// Runs one simulation step of `sim::TICK` seconds, regardless of speed and pause.
+    pub fn tick(&mut self, level: &Level) {
+        if self.is_over() {
+            return;
+        }
+        self.ticks += 1;
+
+        if let Some(wave) = self.waves.update(TICK, &mut self.events) {
+            self.world.queue_wave(wave, self.ticks, self.rng.gameplay());
+        }
+
+        for id in self.world.spawn_due(level, &self.pathogens, self.ticks) {
+            if let Some(enemy) = self.world.enemies.iter().find(|e| e.id == id) {
+                self.events.push(GameEvent::PathogenSpawned {
+                    pathogen: enemy.pathogen.clone(),
+                });
+            }
+        }
+
+        for enemy in self.world.move_enemies(level) {
+            self.damage_heart(enemy.damage);
+        }
+        if self.heart_health == 0 {
+            return;
+        }
+
+        for tile in
+            self.world
+                .fire_towers(level, &self.catalog, &mut self.towers, self.rng.gameplay())
+        {
+            self.events.push(GameEvent::TowerFired { tile });
+        }
+
+        for hit in self.world.move_projectiles() {
+            let enemy = match self.world.damage_enemy(hit.enemy, hit.damage) {
+                Some(enemy) => enemy,
+                None => continue,
+            };
+            if let Some(tower) = self.towers.get_mut(hit.tower) {
+                tower.kills += 1;
+            }
+            self.set_gold(self.gold + enemy.reward);
+            self.add_score(enemy.reward);
+            self.events.push(GameEvent::PathogenKilled {
+                pathogen: enemy.pathogen,
+                tower: hit.tower,
+                position: enemy.position,
+                reward: enemy.reward,
+            });
+        }
+
+        if self.waves.countdown().is_none() && self.world.is_clear() {
+            self.won = true;
+            self.add_score(self.heart_health * HEART_BONUS);
+            self.events.push(GameEvent::LevelWon);
+        }
+    }
+
+    /// Ticks to run this frame: the game speed, none while paused.
+    pub fn frame_ticks(&self) -> u32 {
+        if self.paused {
+            0
+        } else {
+            self.speed
+        }
+    }
+
+    /// Advances one frame, see `frame_ticks`.
+    pub fn update(&mut self, level: &Level) {
+        for _ in 0..self.frame_ticks() {
+            self.tick(level);
+        }
+    }
+
+    /// Everything that happened since the last call, oldest first.
+    pub fn drain_events(&mut self) -> Vec<GameEvent> {
+        std::mem::replace(&mut self.events, Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Waves {
+        waves: Vec<Wave>,
+    }
+
+    fn waves(text: &str) -> Vec<Wave> {
+        toml::from_str::<Waves>(text).unwrap().waves
+    }
+
+    fn countdowns(events: &[GameEvent]) -> Vec<u32> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                GameEvent::WaveCountdown { seconds } => Some(*seconds),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn countdown_is_sent_once_per_whole_second() {
+        let mut waves = WaveScheduler::new(waves(
+            r#"
+                [[waves]]
+                delay = 3.0
+                groups = [{ pathogen = "virus", count = 1 }]
+            "#,
+        ));
+        let mut events = Vec::new();
+
+        for _ in 0..150 {
+            assert!(waves.update(TICK, &mut events).is_none());
+        }
+        assert_eq!(countdowns(&events), [2, 1]);
+        assert_eq!(events.len(), 2);
+
+        events.clear();
+        let mut started = 0;
+        for _ in 0..60 {
+            if waves.update(TICK, &mut events).is_some() {
+                started += 1;
+            }
+        }
+        assert_eq!(started, 1);
+        assert_eq!(
+            events,
+            [
+                GameEvent::WaveStarted { wave: 1, total: 1 },
+                GameEvent::AllWavesSent,
+            ]
+        );
+        assert_eq!(waves.countdown(), None);
+    }
+
+    #[test]
+    fn next_wave_is_previewed_by_pathogen() {
+        let mut waves = WaveScheduler::new(waves(
+            r#"
+                [[waves]]
+                delay = 1.0
+                groups = [{ pathogen = "virus", count = 1 }]
+
+                [[waves]]
+                delay = 4.5
+                groups = [
+                    { pathogen = "virus", count = 3 },
+                    { pathogen = "bacteria", count = 2 },
+                    { pathogen = "virus", count = 4, delay = 2.0 },
+                ]
+            "#,
+        ));
+        let mut events = Vec::new();
+
+        waves.start_next(&mut events);
+        assert_eq!(
+            events,
+            [
+                GameEvent::WaveStarted { wave: 1, total: 2 },
+                GameEvent::WaveScheduled {
+                    wave: 2,
+                    total: 2,
+                    preview: vec![("virus".into(), 7), ("bacteria".into(), 2)],
+                },
+                GameEvent::WaveCountdown { seconds: 5 },
+            ]
+        );
+    }
+
+    #[test]
+    fn start_next_skips_the_countdown() {
+        let mut waves = WaveScheduler::new(waves(
+            r#"
+                [[waves]]
+                delay = 30.0
+                groups = [{ pathogen = "virus", count = 1 }]
+
+                [[waves]]
+                delay = 20.0
+                groups = [{ pathogen = "bacteria", count = 1 }]
+            "#,
+        ));
+        let mut events = Vec::new();
+
+        let wave = waves.start_next(&mut events).unwrap();
+        assert_eq!(wave.groups[0].pathogen, "virus");
+        assert_eq!((waves.started(), waves.countdown()), (1, Some(20.0)));
+
+        let wave = waves.start_next(&mut events).unwrap();
+        assert_eq!(wave.groups[0].pathogen, "bacteria");
+        assert_eq!(events.last(), Some(&GameEvent::AllWavesSent));
+
+        events.clear();
+        assert!(waves.start_next(&mut events).is_none());
+        assert!(waves.update(TICK, &mut events).is_none());
+        assert!(events.is_empty());
+        assert_eq!(waves.started(), 2);
+    }
+}
@@ -0,0 +1,536 @@
+//! Runs a match without a window or GL context, for balancing and regression checks.
+//!
+//! Towers are placed from a `BuildScript` at fixed ticks and the match runs until the
+//! heart gives out, the level is won or the tick limit is reached. Nothing depends on the
+//! clock, so the same level, catalogs and script always give the same `Outcome`.
+
+use crate::game::{self, Command, Game};
+use crate::level::{self, Level, TileCoord};
+use crate::pathogen::{self, PathogenCatalog};
+use crate::placement::Validity;
+use crate::replay::{self, Divergence, Playback, Recorder, Replay};
+use crate::resources::{self, Resources};
+use crate::sim::TICKS_PER_SECOND;
+use crate::tower::{self, TowerCatalog};
+use std::fmt;
+use std::path::PathBuf;
+use toml;
+
+/// Seed of matches that don't name one.
+pub const DEFAULT_SEED: u64 = 1;
+/// Ticks simulated before giving up on a match that doesn't end, half an hour of play.
+pub const DEFAULT_MAX_TICKS: u64 = 30 * 60 * TICKS_PER_SECOND as u64;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to load build script {}", name)]
+    ResourceLoad {
+        name: String,
+        #[cause]
+        inner: resources::Error,
+    },
+    #[fail(display = "Failed to parse build script {}", name)]
+    Parse {
+        name: String,
+        #[cause]
+        inner: toml::de::Error,
+    },
+    #[fail(display = "Failed to load level")]
+    Level {
+        #[cause]
+        inner: level::Error,
+    },
+    #[fail(display = "Failed to load towers")]
+    Towers {
+        #[cause]
+        inner: tower::Error,
+    },
+    #[fail(display = "Failed to load pathogens")]
+    Pathogens {
+        #[cause]
+        inner: pathogen::Error,
+    },
+    #[fail(display = "Failed to load replay")]
+    Replay {
+        #[cause]
+        inner: replay::Error,
+    },
+    #[fail(display = "Failed to start the match")]
+    Game {
+        #[cause]
+        inner: game::Error,
+    },
+}
+
+/// One tower of a build, placed once the match reaches `tick`.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct BuildStep {
+    pub tick: u64,
+    pub tower: String,
+    pub col: usize,
+    pub row: usize,
+}
+
+/// Towers to place during a headless match, see `res/builds/arm_basic.toml`.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct BuildScript {
+    #[serde(default)]
+    pub towers: Vec<BuildStep>,
+}
+
+impl BuildScript {
+    pub fn from_res(res: &Resources, name: &str) -> Result<BuildScript, Error> {
+        let text = res.load_string(name).map_err(|e| Error::ResourceLoad {
+            name: name.into(),
+            inner: e,
+        })?;
+
+        BuildScript::from_toml_str(name, &text)
+    }
+
+    /// Steps are kept in tick order, steps of the same tick in the order they are listed.
+    pub fn from_toml_str(name: &str, text: &str) -> Result<BuildScript, Error> {
+        let mut script: BuildScript = toml::from_str(text).map_err(|e| Error::Parse {
+            name: name.into(),
+            inner: e,
+        })?;
+        script.towers.sort_by_key(|step| step.tick);
+        Ok(script)
+    }
+}
+
+/// What a level and a build script are run with. With a `replay`, its level, seed and
+/// commands are used instead.
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub level: String,
+    pub build: Option<String>,
+    pub seed: u64,
+    pub max_ticks: u64,
+    pub replay: Option<PathBuf>,
+}
+
+impl Config {
+    pub fn new(level: &str) -> Config {
+        Config {
+            level: level.into(),
+            build: None,
+            seed: DEFAULT_SEED,
+            max_ticks: DEFAULT_MAX_TICKS,
+            replay: None,
+        }
+    }
+}
+
+/// How playback of a replay compared with the recording.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PlaybackCheck {
+    /// checksums compared
+    pub checked: usize,
+    pub divergence: Option<Divergence>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TowerReport {
+    pub tile: TileCoord,
+    pub tower: String,
+    pub kills: u32,
+}
+
+/// How a headless match ended.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Outcome {
+    pub level: String,
+    pub seed: u64,
+    /// the level was won with the heart still beating
+    pub survived: bool,
+    /// false if the tick limit ran out first
+    pub finished: bool,
+    pub heart_health: u32,
+    pub max_heart_health: u32,
+    pub gold: u32,
+    pub ticks: u64,
+    pub waves_started: usize,
+    pub waves_total: usize,
+    /// every tower built, ordered by tile
+    pub towers: Vec<TowerReport>,
+    /// build steps the game turned down, and why
+    pub rejected: Vec<(BuildStep, Validity)>,
+    /// set when a replay was played back
+    pub playback: Option<PlaybackCheck>,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let result = match (self.finished, self.survived) {
+            (false, _) => "undecided (tick limit reached)",
+            (true, true) => "survived",
+            (true, false) => "heart destroyed",
+        };
+        writeln!(f, "Level: {}", self.level)?;
+        writeln!(f, "Seed: {}", self.seed)?;
+        writeln!(f, "Result: {}", result)?;
+        writeln!(f, "Heart: {}/{}", self.heart_health, self.max_heart_health)?;
+        writeln!(f, "Gold: {}", self.gold)?;
+        writeln!(f, "Waves: {}/{}", self.waves_started, self.waves_total)?;
+        writeln!(
+            f,
+            "Ticks: {} ({:.1}s)",
+            self.ticks,
+            self.ticks as f32 / TICKS_PER_SECOND as f32
+        )?;
+        writeln!(f, "Kills per tower:")?;
+        for tower in &self.towers {
+            writeln!(
+                f,
+                "  {} at ({}, {}): {}",
+                tower.tower, tower.tile.col, tower.tile.row, tower.kills
+            )?;
+        }
+        for (step, validity) in &self.rejected {
+            writeln!(
+                f,
+                "Rejected {} at ({}, {}) on tick {}: {:?}",
+                step.tower, step.col, step.row, step.tick, validity
+            )?;
+        }
+        match self.playback {
+            Some(PlaybackCheck {
+                divergence: Some(divergence),
+                ..
+            }) => writeln!(f, "Replay: {}", divergence)?,
+            Some(PlaybackCheck { checked, .. }) => {
+                writeln!(f, "Replay: all {} checksums matched", checked)?
+            }
+            None => {}
+        }
+        Ok(())
+    }
+}
+
+/// Plays `level` with `build` for at most `max_ticks` ticks.
+pub fn simulate(
+    level: &Level,
+    catalog: TowerCatalog,
+    pathogens: PathogenCatalog,
+    build: &BuildScript,
+    seed: u64,
+    max_ticks: u64,
+) -> Result<Outcome, game::Error> {
+    play(level, catalog, pathogens, build, seed, max_ticks, None)
+}
+
+/// Like `simulate` with the seed of `recorder`, which records the match.
+pub fn record(
+    level: &Level,
+    catalog: TowerCatalog,
+    pathogens: PathogenCatalog,
+    build: &BuildScript,
+    recorder: &mut Recorder,
+    max_ticks: u64,
+) -> Result<Outcome, game::Error> {
+    let seed = recorder.replay().seed;
+    play(
+        level,
+        catalog,
+        pathogens,
+        build,
+        seed,
+        max_ticks,
+        Some(recorder),
+    )
+}
+
+fn play(
+    level: &Level,
+    catalog: TowerCatalog,
+    pathogens: PathogenCatalog,
+    build: &BuildScript,
+    seed: u64,
+    max_ticks: u64,
+    mut recorder: Option<&mut Recorder>,
+) -> Result<Outcome, game::Error> {
+    let mut game = Game::new(level, catalog, pathogens, seed)?;
+    let mut steps = build.towers.iter().peekable();
+    let mut rejected = Vec::new();
+
+    while !game.is_over() && game.ticks() < max_ticks {
+        while let Some(step) = steps.peek() {
+            if step.tick > game.ticks() {
+                break;
+            }
+            let tile = TileCoord::new(step.col, step.row);
+            if let Some(recorder) = recorder.as_mut() {
+                let command = Command::PlaceTower {
+                    tower: step.tower.clone(),
+                    tile,
+                };
+                recorder.record(&game, &command);
+            }
+            if let Err(validity) = game.place_tower(level, tile, &step.tower) {
+                rejected.push(((*step).clone(), validity));
+            }
+            steps.next();
+        }
+
+        game.tick(level);
+        if let Some(recorder) = recorder.as_mut() {
+            recorder.after_tick(&game);
+        }
+        // nobody listens, don't let them pile up
+        game.drain_events();
+    }
+
+    Ok(outcome(level, &game, rejected))
+}
+
+/// Plays back `replay` on `level` for at most `max_ticks` ticks.
+pub fn play_back(
+    level: &Level,
+    catalog: TowerCatalog,
+    pathogens: PathogenCatalog,
+    replay: Replay,
+    max_ticks: u64,
+) -> Result<Outcome, game::Error> {
+    let mut game = Game::new(level, catalog, pathogens, replay.seed)?;
+    let mut playback = Playback::new(replay);
+
+    while !game.is_over() && game.ticks() < max_ticks && !playback.is_finished(&game) {
+        playback.before_tick(&mut game, level);
+        game.tick(level);
+        playback.after_tick(&game);
+        game.drain_events();
+    }
+
+    let mut outcome = outcome(level, &game, Vec::new());
+    outcome.playback = Some(PlaybackCheck {
+        checked: playback.checked(),
+        divergence: playback.divergence(),
+    });
+    Ok(outcome)
+}
+
+fn outcome(level: &Level, game: &Game, rejected: Vec<(BuildStep, Validity)>) -> Outcome {
+    Outcome {
+        level: level.name.clone(),
+        seed: game.rng().seed(),
+        survived: game.is_won(),
+        finished: game.is_over(),
+        heart_health: game.heart_health(),
+        max_heart_health: game.max_heart_health(),
+        gold: game.gold(),
+        ticks: game.ticks(),
+        waves_started: game.waves().started(),
+        waves_total: game.waves().total(),
+        towers: game
+            .towers()
+            .iter()
+            .map(|(tile, placed)| TowerReport {
+                tile,
+                tower: placed.tower.clone(),
+                kills: placed.kills,
+            })
+            .collect(),
+        rejected,
+        playback: None,
+    }
+}
+
+/// Loads everything `config` names from `res` and plays the match.
+pub fn run(res: &Resources, config: &Config) -> Result<Outcome, Error> {
+    let replay = match &config.replay {
+        Some(path) => Some(Replay::load(path).map_err(|e| Error::Replay { inner: e })?),
+        None => None,
+    };
+    let level_name = replay.as_ref().map_or(&config.level, |r| &r.level);
+    let level = Level::from_res(res, level_name).map_err(|e| Error::Level { inner: e })?;
+    let catalog =
+        TowerCatalog::from_res(res, "towers.toml").map_err(|e| Error::Towers { inner: e })?;
+    let pathogens = PathogenCatalog::from_res(res, "pathogens.toml")
+        .map_err(|e| Error::Pathogens { inner: e })?;
+    if let Some(replay) = replay {
+        if let Some(warning) = replay.version_warning() {
+            println!("{}", warning);
+        }
+        return play_back(&level, catalog, pathogens, replay, config.max_ticks)
+            .map_err(|e| Error::Game { inner: e });
+    }
+
+    let build = match &config.build {
+        Some(name) => BuildScript::from_res(res, name)?,
+        None => BuildScript::default(),
+    };
+
+    simulate(
+        &level,
+        catalog,
+        pathogens,
+        &build,
+        config.seed,
+        config.max_ticks,
+    )
+    .map_err(|e| Error::Game { inner: e })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    const LEVEL: &str = "levels/arm.toml";
+    const BUILD: &str = "builds/arm_basic.toml";
+
+    fn res() -> Resources {
+        Resources::from_directory(&Path::new(env!("CARGO_MANIFEST_DIR")).join("res"))
+    }
+
+    fn load(res: &Resources) -> (Level, TowerCatalog, PathogenCatalog, BuildScript) {
+        (
+            Level::from_res(res, LEVEL).unwrap(),
+            TowerCatalog::from_res(res, "towers.toml").unwrap(),
+            PathogenCatalog::from_res(res, "pathogens.toml").unwrap(),
+            BuildScript::from_res(res, BUILD).unwrap(),
+        )
+    }
+
+    /// Health of every enemy on every tick of a match played with `build`.
+    fn health_history(
+        level: &Level,
+        catalog: &TowerCatalog,
+        pathogens: &PathogenCatalog,
+        build: &BuildScript,
+        seed: u64,
+    ) -> Vec<Vec<u32>> {
+        let mut game = Game::new(level, catalog.clone(), pathogens.clone(), seed).unwrap();
+        let mut history = Vec::new();
+        while !game.is_over() && game.ticks() < DEFAULT_MAX_TICKS {
+            let now = game.ticks();
+            for step in build.towers.iter().filter(|s| s.tick == now) {
+                let tile = TileCoord::new(step.col, step.row);
+                let _ = game.place_tower(level, tile, &step.tower);
+            }
+            game.tick(level);
+            game.drain_events();
+            history.push(
+                game.world()
+                    .enemies
+                    .iter()
+                    .map(|enemy| enemy.health.to_bits())
+                    .collect(),
+            );
+        }
+        history
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_outcome() {
+        let res = res();
+        let (level, catalog, pathogens, build) = load(&res);
+        let run = || {
+            simulate(
+                &level,
+                catalog.clone(),
+                pathogens.clone(),
+                &build,
+                7,
+                DEFAULT_MAX_TICKS,
+            )
+            .unwrap()
+        };
+
+        let first = run();
+        assert!(first.finished);
+        assert_eq!(first, run());
+    }
+
+    #[test]
+    fn recorded_match_plays_back_without_diverging() {
+        let res = res();
+        let (level, catalog, pathogens, build) = load(&res);
+        let mut recorder = Recorder::new(LEVEL, 7);
+        let recorded = record(
+            &level,
+            catalog.clone(),
+            pathogens.clone(),
+            &build,
+            &mut recorder,
+            DEFAULT_MAX_TICKS,
+        )
+        .unwrap();
+        assert!(!recorder.replay().actions.is_empty());
+
+        // through TOML, like a replay file
+        let text = toml::to_string(recorder.replay()).unwrap();
+        let replay: Replay = toml::from_str(&text).unwrap();
+        let played = play_back(&level, catalog, pathogens, replay, DEFAULT_MAX_TICKS).unwrap();
+
+        let check = played.playback.unwrap();
+        assert_eq!(check.divergence, None);
+        assert_eq!(check.checked, recorder.replay().checksums.len());
+        assert!(check.checked > 0);
+        assert_eq!(
+            Outcome {
+                playback: None,
+                rejected: recorded.rejected.clone(),
+                ..played
+            },
+            recorded
+        );
+    }
+
+    #[test]
+    fn playback_reports_where_a_changed_replay_diverges() {
+        let res = res();
+        let (level, catalog, pathogens, build) = load(&res);
+        let mut recorder = Recorder::new(LEVEL, 7);
+        record(
+            &level,
+            catalog.clone(),
+            pathogens.clone(),
+            &build,
+            &mut recorder,
+            DEFAULT_MAX_TICKS,
+        )
+        .unwrap();
+
+        let mut replay = recorder.replay().clone();
+        replay.seed = 8;
+        let played = play_back(&level, catalog, pathogens, replay, DEFAULT_MAX_TICKS).unwrap();
+        assert!(played.playback.unwrap().divergence.is_some());
+    }
+
+    #[test]
+    fn seed_changes_spawn_jitter() {
+        let res = res();
+        let (level, catalog, pathogens, _) = load(&res);
+        let spawns = |seed| {
+            let mut game = Game::new(&level, catalog.clone(), pathogens.clone(), seed).unwrap();
+            // the second wave is the one with jitter
+            while game.waves().started() < 2 {
+                game.tick(&level);
+            }
+            game.world()
+                .spawns
+                .iter()
+                .map(|spawn| spawn.tick)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(spawns(1), spawns(1));
+        assert_ne!(spawns(1), spawns(2));
+    }
+
+    #[test]
+    fn seed_changes_critical_hits() {
+        let res = res();
+        let (mut level, catalog, pathogens, build) = load(&res);
+        // without jitter only critical hits draw from the seed
+        for group in level.waves.iter_mut().flat_map(|w| w.groups.iter_mut()) {
+            group.jitter = 0.0;
+        }
+
+        let history = |seed| health_history(&level, &catalog, &pathogens, &build, seed);
+        assert_eq!(history(1), history(1));
+        assert_ne!(history(1), history(2));
+    }
+}
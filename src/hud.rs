@@ -0,0 +1,151 @@
+//! The in-game HUD: Joe's heart, gold, score, waves and game speed.
+//!
+//! The HUD keeps its own copy of what it shows and only changes it in `handle_event`, the
+//! game is never queried while drawing.
+
+use crate::game::{Command, GameEvent, SPEEDS};
+use crate::ui::{Rect, Ui};
+
+const PANEL_WIDTH: f32 = 260.0;
+
+#[derive(Clone, Debug, Default)]
+pub struct Hud {
+    gold: u32,
+    score: u32,
+    health: u32,
+    max_health: u32,
+    wave: usize,
+    total_waves: usize,
+    /// `None` once every wave is sent
+    countdown: Option<u32>,
+    preview: String,
+    speed: u32,
+    paused: bool,
+    heart_destroyed: bool,
+    won: bool,
+}
+
+impl Hud {
+    pub fn new() -> Hud {
+        Hud::default()
+    }
+
+    pub fn handle_event(&mut self, event: &GameEvent) {
+        match event {
+            GameEvent::GoldChanged { gold } => self.gold = *gold,
+            GameEvent::ScoreChanged { score } => self.score = *score,
+            GameEvent::HeartHealthChanged { health, max } => {
+                self.health = *health;
+                self.max_health = *max;
+            }
+            GameEvent::WaveScheduled { total, preview, .. } => {
+                self.total_waves = *total;
+                self.preview = preview
+                    .iter()
+                    .map(|(pathogen, count)| format!("{} x{}", pathogen, count))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+            }
+            GameEvent::WaveCountdown { seconds } => self.countdown = Some(*seconds),
+            GameEvent::WaveStarted { wave, total } => {
+                self.wave = *wave;
+                self.total_waves = *total;
+            }
+            GameEvent::AllWavesSent => {
+                self.countdown = None;
+                self.preview.clear();
+            }
+            GameEvent::SpeedChanged { speed } => self.speed = *speed,
+            GameEvent::PausedChanged { paused } => self.paused = *paused,
+            GameEvent::HeartDestroyed => self.heart_destroyed = true,
+            GameEvent::LevelWon => self.won = true,
+            GameEvent::HeartHit { .. }
+            | GameEvent::TowerPlaced { .. }
+            | GameEvent::TowerSold { .. }
+            | GameEvent::TowerFired { .. }
+            | GameEvent::PathogenSpawned { .. }
+            | GameEvent::PathogenKilled { .. } => {}
+        }
+    }
+
+    /// Declares the HUD panel in the top left of `screen`, returns what its buttons asked for.
+    pub fn build(&self, ui: &mut Ui, screen: Rect) -> Vec<Command> {
+        let mut commands = Vec::new();
+        let height = (ui.button_height() + ui.theme().spacing) * 7.0 + ui.theme().padding * 2.0;
+        let rect = Rect::new(screen.x, screen.y, PANEL_WIDTH, height.min(screen.h));
+        let heart = ui.theme().colors.accent;
+
+        ui.panel("hud", rect, |ui| {
+            let fraction = if self.max_health > 0 {
+                self.health as f32 / self.max_health as f32
+            } else {
+                0.0
+            };
+            ui.progress_bar(
+                &format!("Heart {}/{}", self.health, self.max_health),
+                fraction,
+                heart,
+            );
+            ui.tooltip("Joe's heart, lost when it reaches zero");
+
+            ui.label(&format!("Gold: {}", self.gold));
+            ui.label(&format!("Score: {}", self.score));
+            ui.label(&format!("Wave {}/{}", self.wave, self.total_waves));
+            match self.countdown {
+                _ if self.heart_destroyed => ui.label("Joe's heart gave out"),
+                _ if self.won => ui.label("Joe is safe"),
+                Some(seconds) => {
+                    ui.label(&format!("Next wave in {}s", seconds));
+                    ui.tooltip(&self.preview);
+                }
+                None => ui.label("All waves sent"),
+            }
+
+            ui.row(|ui| {
+                if ui.button(if self.paused { "Resume" } else { "Pause" }) {
+                    commands.push(Command::TogglePause);
+                }
+                for speed in SPEEDS.iter() {
+                    if ui.button_enabled(&format!("{}x", speed), *speed != self.speed) {
+                        commands.push(Command::SetSpeed(*speed));
+                    }
+                }
+            });
+            if ui.button_enabled("Send next wave", self.countdown.is_some()) {
+                commands.push(Command::SendNextWave);
+            }
+            ui.tooltip(&self.preview);
+        });
+
+        commands
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracks_the_upcoming_wave() {
+        let mut hud = Hud::new();
+
+        hud.handle_event(&GameEvent::WaveScheduled {
+            wave: 2,
+            total: 3,
+            preview: vec![("virus".into(), 7), ("bacteria".into(), 2)],
+        });
+        hud.handle_event(&GameEvent::WaveCountdown { seconds: 5 });
+        assert_eq!(hud.total_waves, 3);
+        assert_eq!(hud.preview, "virus x7, bacteria x2");
+        assert_eq!(hud.countdown, Some(5));
+
+        hud.handle_event(&GameEvent::WaveCountdown { seconds: 4 });
+        hud.handle_event(&GameEvent::WaveStarted { wave: 3, total: 3 });
+        assert_eq!(hud.countdown, Some(4));
+        assert_eq!(hud.wave, 3);
+
+        hud.handle_event(&GameEvent::AllWavesSent);
+        assert_eq!(hud.countdown, None);
+        assert!(hud.preview.is_empty());
+    }
+}
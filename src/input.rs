@@ -0,0 +1,585 @@
+//! Maps raw SDL keyboard, mouse and game controller events to game actions.
+//!
+//! Feed every event to `Input::handle_event`, call `Input::begin_frame` before polling the
+//! next batch. Which inputs trigger which action is read from `res/input.toml`.
+
+use crate::camera::Camera;
+use crate::render_gl::Viewport;
+use crate::resources::{self, Resources};
+use nalgebra as na;
+use sdl2::controller::{Axis, Button, GameController};
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+use sdl2::GameControllerSubsystem;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use toml;
+
+/// Axis values below this are treated as a stick at rest.
+const AXIS_DEADZONE: f32 = 0.2;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to load input bindings {}", name)]
+    ResourceLoad {
+        name: String,
+        #[cause]
+        inner: resources::Error,
+    },
+    #[fail(display = "Failed to parse input bindings {}", name)]
+    Parse {
+        name: String,
+        #[cause]
+        inner: toml::de::Error,
+    },
+    #[fail(display = "Unknown input {:?} bound to {:?}", binding, action)]
+    UnknownBinding { action: Action, binding: String },
+    #[fail(display = "Unknown action {:?} in input bindings {}", action, name)]
+    UnknownAction { name: String, action: String },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Action {
+    PlaceTower,
+    SellTower,
+    NextWave,
+    Pause,
+    CameraPan,
+    Zoom,
+    SpeedUp,
+    Cancel,
+    Quit,
+    QuickSave,
+    QuickLoad,
+    Settings,
+    ToggleFullscreen,
+}
+
+impl Action {
+    /// The action called `name` in bindings files, e.g. `PlaceTower`.
+    pub fn from_name(name: &str) -> Option<Action> {
+        use serde::de::{value, Deserialize, IntoDeserializer};
+
+        // TOML can't read enum variants as table keys, so names go through serde here
+        let deserializer: value::StrDeserializer<value::Error> = name.into_deserializer();
+        Action::deserialize(deserializer).ok()
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// One physical input, written in the bindings file as `Key:<SDL key name>`,
+/// `Mouse:Left|Middle|Right|X1|X2`, `Wheel`, `Pad:<SDL button name>` or
+/// `Axis:<SDL axis name>`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(Keycode),
+    Mouse(MouseButton),
+    Wheel,
+    Button(Button),
+    Axis(Axis),
+}
+
+impl Binding {
+    pub fn parse(text: &str) -> Option<Binding> {
+        let mut parts = text.splitn(2, ':');
+        let kind = parts.next()?;
+        let name = parts.next().unwrap_or("");
+
+        match kind {
+            "Key" => Keycode::from_name(name).map(Binding::Key),
+            "Mouse" => match name {
+                "Left" => Some(MouseButton::Left),
+                "Middle" => Some(MouseButton::Middle),
+                "Right" => Some(MouseButton::Right),
+                "X1" => Some(MouseButton::X1),
+                "X2" => Some(MouseButton::X2),
+                _ => None,
+            }
+            .map(Binding::Mouse),
+            "Wheel" if name.is_empty() => Some(Binding::Wheel),
+            "Pad" => Button::from_string(name).map(Binding::Button),
+            "Axis" => Axis::from_string(name).map(Binding::Axis),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Binding::Key(keycode) => write!(f, "Key:{}", keycode.name()),
+            Binding::Mouse(button) => write!(f, "Mouse:{:?}", button),
+            Binding::Wheel => write!(f, "Wheel"),
+            Binding::Button(button) => write!(f, "Pad:{}", button.string()),
+            Binding::Axis(axis) => write!(f, "Axis:{}", axis.string()),
+        }
+    }
+}
+
+/// Actions are keyed by name, see `Action::from_name`.
+#[derive(Serialize, Deserialize)]
+struct BindingsFile {
+    bindings: BTreeMap<String, Vec<String>>,
+}
+
+/// Which bindings trigger which action. One binding may trigger several actions.
+#[derive(Clone, Debug, Default)]
+pub struct Bindings {
+    actions: BTreeMap<Action, Vec<Binding>>,
+}
+
+impl Bindings {
+    pub fn from_res(res: &Resources, name: &str) -> Result<Bindings, Error> {
+        let text = res.load_string(name).map_err(|e| Error::ResourceLoad {
+            name: name.into(),
+            inner: e,
+        })?;
+
+        Bindings::from_toml_str(name, &text)
+    }
+
+    pub fn from_toml_str(name: &str, text: &str) -> Result<Bindings, Error> {
+        let file: BindingsFile = toml::from_str(text).map_err(|e| Error::Parse {
+            name: name.into(),
+            inner: e,
+        })?;
+
+        let mut bindings = Bindings::default();
+        for (action_name, texts) in file.bindings {
+            let action = Action::from_name(&action_name).ok_or_else(|| Error::UnknownAction {
+                name: name.into(),
+                action: action_name.clone(),
+            })?;
+            for text in texts {
+                let binding = Binding::parse(&text).ok_or_else(|| Error::UnknownBinding {
+                    action,
+                    binding: text.clone(),
+                })?;
+                bindings.bind(action, binding);
+            }
+        }
+
+        Ok(bindings)
+    }
+
+    /// The bindings in the same format `from_toml_str` reads, for saving rebound keys.
+    pub fn to_toml_string(&self) -> String {
+        let file = BindingsFile {
+            bindings: self
+                .actions
+                .iter()
+                .map(|(action, bindings)| {
+                    (
+                        action.to_string(),
+                        bindings.iter().map(Binding::to_string).collect(),
+                    )
+                })
+                .collect(),
+        };
+
+        toml::to_string(&file).expect("expected bindings to serialize")
+    }
+
+    pub fn bind(&mut self, action: Action, binding: Binding) {
+        let bindings = self.actions.entry(action).or_insert_with(Vec::new);
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    pub fn unbind(&mut self, action: Action, binding: Binding) {
+        if let Some(bindings) = self.actions.get_mut(&action) {
+            bindings.retain(|b| *b != binding);
+        }
+    }
+
+    /// Drops all bindings of `action`, e.g. before assigning a new key.
+    pub fn clear(&mut self, action: Action) {
+        self.actions.remove(&action);
+    }
+
+    pub fn bindings(&self, action: Action) -> &[Binding] {
+        self.actions.get(&action).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    fn actions_for(&self, binding: Binding) -> impl Iterator<Item = Action> + '_ {
+        self.actions
+            .iter()
+            .filter(move |(_, bindings)| bindings.contains(&binding))
+            .map(|(action, _)| *action)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+struct ActionState {
+    held: bool,
+    pressed: bool,
+    released: bool,
+}
+
+pub struct Input {
+    bindings: Bindings,
+    controller_subsystem: Option<GameControllerSubsystem>,
+    controllers: Vec<GameController>,
+    down: HashSet<Binding>,
+    states: HashMap<Action, ActionState>,
+    axes: HashMap<Axis, f32>,
+    wheel: f32,
+    mouse_screen: na::Point2<f32>,
+    mouse_delta: na::Vector2<f32>,
+    mouse_world: na::Point2<f32>,
+}
+
+impl Input {
+    /// Without a controller subsystem, game controller events are ignored.
+    pub fn new(bindings: Bindings, controller_subsystem: Option<GameControllerSubsystem>) -> Input {
+        Input {
+            bindings,
+            controller_subsystem,
+            controllers: Vec::new(),
+            down: HashSet::new(),
+            states: HashMap::new(),
+            axes: HashMap::new(),
+            wheel: 0.0,
+            mouse_screen: na::Point2::origin(),
+            mouse_delta: na::Vector2::zeros(),
+            mouse_world: na::Point2::origin(),
+        }
+    }
+
+    pub fn bindings(&self) -> &Bindings {
+        &self.bindings
+    }
+
+    /// Rebinding releases everything currently held.
+    pub fn set_bindings(&mut self, bindings: Bindings) {
+        self.bindings = bindings;
+        self.down.clear();
+        self.states.clear();
+    }
+
+    /// Forgets last frame's presses, releases and relative motion.
+    pub fn begin_frame(&mut self) {
+        for state in self.states.values_mut() {
+            state.pressed = false;
+            state.released = false;
+        }
+        self.wheel = 0.0;
+        self.mouse_delta = na::Vector2::zeros();
+    }
+
+    pub fn handle_event(&mut self, event: &Event) {
+        match *event {
+            Event::KeyDown {
+                keycode: Some(keycode),
+                repeat: false,
+                ..
+            } => self.press(Binding::Key(keycode)),
+            Event::KeyUp {
+                keycode: Some(keycode),
+                ..
+            } => self.release(Binding::Key(keycode)),
+            Event::MouseButtonDown { mouse_btn, .. } => self.press(Binding::Mouse(mouse_btn)),
+            Event::MouseButtonUp { mouse_btn, .. } => self.release(Binding::Mouse(mouse_btn)),
+            Event::MouseMotion {
+                x, y, xrel, yrel, ..
+            } => {
+                self.mouse_screen = na::Point2::new(x as f32, y as f32);
+                self.mouse_delta += na::Vector2::new(xrel as f32, yrel as f32);
+            }
+            Event::MouseWheel { y, .. } => {
+                self.wheel += y as f32;
+                // a wheel tick is pressed and released within the frame
+                self.press(Binding::Wheel);
+                self.release(Binding::Wheel);
+            }
+            Event::ControllerButtonDown { button, .. } => self.press(Binding::Button(button)),
+            Event::ControllerButtonUp { button, .. } => self.release(Binding::Button(button)),
+            Event::ControllerAxisMotion { axis, value, .. } => {
+                let value = f32::from(value) / f32::from(i16::max_value());
+                let value = if value.abs() < AXIS_DEADZONE {
+                    0.0
+                } else {
+                    value
+                };
+                self.axes.insert(axis, value);
+                if value != 0.0 {
+                    self.press(Binding::Axis(axis));
+                } else {
+                    self.release(Binding::Axis(axis));
+                }
+            }
+            Event::ControllerDeviceAdded { which, .. } => {
+                if let Some(subsystem) = &self.controller_subsystem {
+                    match subsystem.open(which) {
+                        Ok(controller) => self.controllers.push(controller),
+                        Err(e) => println!("Failed to open game controller {}: {}", which, e),
+                    }
+                }
+            }
+            Event::ControllerDeviceRemoved { which, .. } => {
+                self.controllers.retain(|c| c.instance_id() != which);
+            }
+            _ => {}
+        }
+    }
+
+    /// Projects the mouse position into the world, after this frame's events are handled.
+    pub fn update_mouse_world(&mut self, camera: &Camera, viewport: &Viewport) {
+        self.mouse_world = camera.screen_to_world(&self.mouse_screen, viewport);
+    }
+
+    /// True only in the frame the action started.
+    pub fn pressed(&self, action: Action) -> bool {
+        self.state(action).pressed
+    }
+
+    pub fn held(&self, action: Action) -> bool {
+        self.state(action).held
+    }
+
+    /// True only in the frame the action ended.
+    pub fn released(&self, action: Action) -> bool {
+        self.state(action).released
+    }
+
+    /// Analog value of an action: wheel ticks this frame plus bound controller axes,
+    /// or 1.0 while a digital binding is held.
+    pub fn value(&self, action: Action) -> f32 {
+        let mut value = 0.0;
+        for binding in self.bindings.bindings(action) {
+            match binding {
+                Binding::Wheel => value += self.wheel,
+                Binding::Axis(axis) => value += self.axes.get(axis).cloned().unwrap_or(0.0),
+                _ => {}
+            }
+        }
+
+        if value == 0.0 && self.held(action) {
+            1.0
+        } else {
+            value
+        }
+    }
+
+    /// Mouse position in window pixels, origin at the top left.
+    pub fn mouse_screen(&self) -> na::Point2<f32> {
+        self.mouse_screen
+    }
+
+    /// Mouse motion in window pixels since `begin_frame`.
+    pub fn mouse_delta(&self) -> na::Vector2<f32> {
+        self.mouse_delta
+    }
+
+    /// Mouse position in world units, as of the last `update_mouse_world`.
+    pub fn mouse_world(&self) -> na::Point2<f32> {
+        self.mouse_world
+    }
+
+    fn state(&self, action: Action) -> ActionState {
+        self.states.get(&action).cloned().unwrap_or_default()
+    }
+
+    fn press(&mut self, binding: Binding) {
+        self.down.insert(binding);
+        for action in self.bindings.actions_for(binding) {
+            let state = self
+                .states
+                .entry(action)
+                .or_insert_with(ActionState::default);
+            if !state.held {
+                state.held = true;
+                state.pressed = true;
+            }
+        }
+    }
+
+    fn release(&mut self, binding: Binding) {
+        self.down.remove(&binding);
+        let down = &self.down;
+        for action in self.bindings.actions_for(binding) {
+            let still_held = self
+                .bindings
+                .bindings(action)
+                .iter()
+                .any(|b| down.contains(b));
+            let state = self
+                .states
+                .entry(action)
+                .or_insert_with(ActionState::default);
+            if state.held && !still_held {
+                state.held = false;
+                state.released = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sdl2::keyboard::Mod;
+    use sdl2::mouse::MouseWheelDirection;
+
+    const BINDINGS: &str = r#"
+        [bindings]
+        PlaceTower = ["Mouse:Left", "Key:P"]
+        Cancel = ["Mouse:Right", "Key:C"]
+        Zoom = ["Wheel"]
+    "#;
+
+    fn input() -> Input {
+        Input::new(Bindings::from_toml_str("test", BINDINGS).unwrap(), None)
+    }
+
+    fn key(keycode: Keycode, down: bool) -> Event {
+        if down {
+            Event::KeyDown {
+                timestamp: 0,
+                window_id: 0,
+                keycode: Some(keycode),
+                scancode: None,
+                keymod: Mod::empty(),
+                repeat: false,
+            }
+        } else {
+            Event::KeyUp {
+                timestamp: 0,
+                window_id: 0,
+                keycode: Some(keycode),
+                scancode: None,
+                keymod: Mod::empty(),
+                repeat: false,
+            }
+        }
+    }
+
+    fn mouse(mouse_btn: MouseButton, down: bool) -> Event {
+        if down {
+            Event::MouseButtonDown {
+                timestamp: 0,
+                window_id: 0,
+                which: 0,
+                mouse_btn,
+                clicks: 1,
+                x: 0,
+                y: 0,
+            }
+        } else {
+            Event::MouseButtonUp {
+                timestamp: 0,
+                window_id: 0,
+                which: 0,
+                mouse_btn,
+                clicks: 1,
+                x: 0,
+                y: 0,
+            }
+        }
+    }
+
+    #[test]
+    fn bindings_parse_from_toml() {
+        let bindings = Bindings::from_toml_str("test", BINDINGS).unwrap();
+
+        assert_eq!(
+            bindings.bindings(Action::PlaceTower),
+            &[Binding::Mouse(MouseButton::Left), Binding::Key(Keycode::P)]
+        );
+        assert_eq!(
+            bindings.bindings(Action::Cancel),
+            &[Binding::Mouse(MouseButton::Right), Binding::Key(Keycode::C)]
+        );
+        assert_eq!(bindings.bindings(Action::Zoom), &[Binding::Wheel]);
+        assert!(bindings.bindings(Action::Quit).is_empty());
+    }
+
+    #[test]
+    fn unknown_actions_and_bindings_are_errors() {
+        match Bindings::from_toml_str("test", "[bindings]\nDance = [\"Key:P\"]") {
+            Err(Error::UnknownAction { action, .. }) => assert_eq!(action, "Dance"),
+            _ => panic!("expected an unknown action"),
+        }
+        match Bindings::from_toml_str("test", "[bindings]\nQuit = [\"Mouse:Elbow\"]") {
+            Err(Error::UnknownBinding { action, binding }) => {
+                assert_eq!(action, Action::Quit);
+                assert_eq!(binding, "Mouse:Elbow");
+            }
+            _ => panic!("expected an unknown binding"),
+        }
+    }
+
+    #[test]
+    fn presses_and_releases_last_one_frame() {
+        let mut input = input();
+
+        input.begin_frame();
+        input.handle_event(&key(Keycode::P, true));
+        assert!(input.pressed(Action::PlaceTower));
+        assert!(input.held(Action::PlaceTower));
+        assert!(!input.released(Action::PlaceTower));
+
+        input.begin_frame();
+        assert!(!input.pressed(Action::PlaceTower));
+        assert!(input.held(Action::PlaceTower));
+
+        input.begin_frame();
+        input.handle_event(&key(Keycode::P, false));
+        assert!(!input.held(Action::PlaceTower));
+        assert!(input.released(Action::PlaceTower));
+
+        input.begin_frame();
+        assert!(!input.released(Action::PlaceTower));
+        assert!(!input.pressed(Action::Cancel));
+    }
+
+    #[test]
+    fn action_is_held_until_every_binding_is_released() {
+        let mut input = input();
+
+        input.begin_frame();
+        input.handle_event(&key(Keycode::P, true));
+        input.begin_frame();
+        // a second binding doesn't press the action again
+        input.handle_event(&mouse(MouseButton::Left, true));
+        assert!(!input.pressed(Action::PlaceTower));
+
+        input.begin_frame();
+        input.handle_event(&key(Keycode::P, false));
+        assert!(input.held(Action::PlaceTower));
+        assert!(!input.released(Action::PlaceTower));
+
+        input.begin_frame();
+        input.handle_event(&mouse(MouseButton::Left, false));
+        assert!(!input.held(Action::PlaceTower));
+        assert!(input.released(Action::PlaceTower));
+    }
+
+    #[test]
+    fn wheel_ticks_count_for_one_frame() {
+        let mut input = input();
+
+        input.begin_frame();
+        input.handle_event(&Event::MouseWheel {
+            timestamp: 0,
+            window_id: 0,
+            which: 0,
+            x: 0,
+            y: 2,
+            direction: MouseWheelDirection::Normal,
+        });
+        assert!(input.pressed(Action::Zoom));
+        assert!(!input.held(Action::Zoom));
+        assert_eq!(input.value(Action::Zoom), 2.0);
+
+        input.begin_frame();
+        assert_eq!(input.value(Action::Zoom), 0.0);
+    }
+}
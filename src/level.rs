@@ -0,0 +1,297 @@
+use crate::resources::{self, Resources};
+use nalgebra as na;
+use std::collections::{HashSet, VecDeque};
+use toml;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to load level {}", name)]
+    ResourceLoad {
+        name: String,
+        #[cause]
+        inner: resources::Error,
+    },
+    #[fail(display = "Failed to parse level {}", name)]
+    Parse {
+        name: String,
+        #[cause]
+        inner: toml::de::Error,
+    },
+    #[fail(display = "Invalid map in level {}: {}", name, message)]
+    InvalidMap { name: String, message: String },
+}
+
+/// One cell of the level grid, written as a single character in the level's `map`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Tile {
+    /// `.` towers can be built here and pathogens walk over it while it is free
+    Buildable,
+    /// `=` pathogens walk here, towers can't be built
+    Path,
+    /// `#` scenery, nothing goes here
+    Blocked,
+    /// `S` where pathogens enter
+    Spawn,
+    /// `H` Joe's heart, where pathogens are headed
+    Heart,
+}
+
+impl Tile {
+    fn from_char(c: char) -> Option<Tile> {
+        match c {
+            '.' => Some(Tile::Buildable),
+            '=' => Some(Tile::Path),
+            '#' => Some(Tile::Blocked),
+            'S' => Some(Tile::Spawn),
+            'H' => Some(Tile::Heart),
+            _ => None,
+        }
+    }
+
+    pub fn is_buildable(self) -> bool {
+        self == Tile::Buildable
+    }
+
+    pub fn is_walkable(self) -> bool {
+        self != Tile::Blocked
+    }
+}
+
+/// Column and row in the level grid, row 0 being the top line of the map.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct TileCoord {
+    pub col: usize,
+    pub row: usize,
+}
+
+impl TileCoord {
+    pub fn new(col: usize, row: usize) -> TileCoord {
+        TileCoord { col, row }
+    }
+}
+
+/// A run of identical pathogens within a wave.
+#[derive(Clone, Debug, Deserialize)]
+pub struct WaveGroup {
+    pub pathogen: String,
+    pub count: u32,
+    /// seconds between two spawns of this group
+    #[serde(default = "default_interval")]
+    pub interval: f32,
+    /// seconds after the wave starts before the first one spawns
+    #[serde(default)]
+    pub delay: f32,
+    /// up to this many seconds are randomly added to each spawn
+    #[serde(default)]
+    pub jitter: f32,
+}
+
+fn default_interval() -> f32 {
+    1.0
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Wave {
+    /// seconds of calm before this wave starts
+    #[serde(default)]
+    pub delay: f32,
+    pub groups: Vec<WaveGroup>,
+}
+
+#[derive(Deserialize)]
+struct LevelFile {
+    name: String,
+    starting_gold: u32,
+    heart_health: u32,
+    #[serde(default = "default_tile_size")]
+    tile_size: f32,
+    map: String,
+    #[serde(default)]
+    waves: Vec<Wave>,
+}
+
+fn default_tile_size() -> f32 {
+    1.0
+}
+
+pub struct Level {
+    pub id: String,
+    pub name: String,
+    pub starting_gold: u32,
+    pub heart_health: u32,
+    pub tile_size: f32,
+    pub waves: Vec<Wave>,
+    width: usize,
+    height: usize,
+    tiles: Vec<Tile>,
+    spawns: Vec<TileCoord>,
+    heart: TileCoord,
+}
+
+impl Level {
+    pub fn from_res(res: &Resources, name: &str) -> Result<Level, Error> {
+        let text = res.load_string(name).map_err(|e| Error::ResourceLoad {
+            name: name.into(),
+            inner: e,
+        })?;
+
+        Level::from_toml_str(name, &text)
+    }
+
+    pub fn from_toml_str(name: &str, text: &str) -> Result<Level, Error> {
+        let file: LevelFile = toml::from_str(text).map_err(|e| Error::Parse {
+            name: name.into(),
+            inner: e,
+        })?;
+
+        let invalid = |message: String| Error::InvalidMap {
+            name: name.into(),
+            message,
+        };
+
+        let rows = file
+            .map
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .collect::<Vec<&str>>();
+        let height = rows.len();
+        let width = rows.first().map(|r| r.chars().count()).unwrap_or(0);
+        if width == 0 {
+            return Err(invalid("map is empty".into()));
+        }
+
+        let mut tiles = Vec::with_capacity(width * height);
+        let mut spawns = Vec::new();
+        let mut hearts = Vec::new();
+
+        for (row, line) in rows.iter().enumerate() {
+            if line.chars().count() != width {
+                return Err(invalid(format!(
+                    "row {} is {} tiles wide, expected {}",
+                    row + 1,
+                    line.chars().count(),
+                    width
+                )));
+            }
+            for (col, c) in line.chars().enumerate() {
+                let tile = Tile::from_char(c)
+                    .ok_or_else(|| invalid(format!("unknown tile '{}' in row {}", c, row + 1)))?;
+                match tile {
+                    Tile::Spawn => spawns.push(TileCoord::new(col, row)),
+                    Tile::Heart => hearts.push(TileCoord::new(col, row)),
+                    _ => {}
+                }
+                tiles.push(tile);
+            }
+        }
+
+        if spawns.is_empty() {
+            return Err(invalid("no spawn tile 'S'".into()));
+        }
+        if hearts.len() != 1 {
+            return Err(invalid(format!(
+                "expected exactly one heart tile 'H', found {}",
+                hearts.len()
+            )));
+        }
+
+        Ok(Level {
+            id: name.into(),
+            name: file.name,
+            starting_gold: file.starting_gold,
+            heart_health: file.heart_health,
+            tile_size: file.tile_size,
+            waves: file.waves,
+            width,
+            height,
+            tiles,
+            spawns,
+            heart: hearts[0],
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn tile(&self, coord: TileCoord) -> Option<Tile> {
+        if coord.col >= self.width || coord.row >= self.height {
+            return None;
+        }
+        Some(self.tiles[coord.row * self.width + coord.col])
+    }
+
+    pub fn spawns(&self) -> &[TileCoord] {
+        &self.spawns
+    }
+
+    pub fn heart(&self) -> TileCoord {
+        self.heart
+    }
+
+    /// The up to four tiles sharing an edge with `coord`.
+    pub fn neighbours(&self, coord: TileCoord) -> impl Iterator<Item = TileCoord> {
+        let (width, height) = (self.width, self.height);
+        let candidates = [
+            (coord.col.wrapping_sub(1), coord.row),
+            (coord.col + 1, coord.row),
+            (coord.col, coord.row.wrapping_sub(1)),
+            (coord.col, coord.row + 1),
+        ];
+        candidates
+            .to_vec()
+            .into_iter()
+            .filter(move |&(col, row)| col < width && row < height)
+            .map(|(col, row)| TileCoord::new(col, row))
+    }
+
+    /// Whether pathogens can still walk from every spawn to the heart when the tiles
+    /// `blocked` returns true for can't be entered, e.g. because a tower stands there.
+    pub fn path_exists<F>(&self, blocked: F) -> bool
+    where
+        F: Fn(TileCoord) -> bool,
+    {
+        let walkable =
+            |coord: TileCoord| self.tile(coord).map_or(false, Tile::is_walkable) && !blocked(coord);
+
+        let mut reached = HashSet::new();
+        let mut open = VecDeque::new();
+        if walkable(self.heart) {
+            reached.insert(self.heart);
+            open.push_back(self.heart);
+        }
+        while let Some(coord) = open.pop_front() {
+            for next in self.neighbours(coord) {
+                if walkable(next) && reached.insert(next) {
+                    open.push_back(next);
+                }
+            }
+        }
+
+        self.spawns.iter().all(|spawn| reached.contains(spawn))
+    }
+
+    /// The grid is centered on the world origin.
+    pub fn tile_center(&self, coord: TileCoord) -> na::Point2<f32> {
+        na::Point2::new(
+            (coord.col as f32 - self.width as f32 / 2.0 + 0.5) * self.tile_size,
+            (self.height as f32 / 2.0 - coord.row as f32 - 0.5) * self.tile_size,
+        )
+    }
+
+    /// The tile under a world position, `None` outside the grid.
+    pub fn world_to_tile(&self, point: &na::Point2<f32>) -> Option<TileCoord> {
+        let col = (point.x / self.tile_size + self.width as f32 / 2.0).floor();
+        let row = (self.height as f32 / 2.0 - point.y / self.tile_size).floor();
+
+        if col < 0.0 || row < 0.0 || col >= self.width as f32 || row >= self.height as f32 {
+            return None;
+        }
+        Some(TileCoord::new(col as usize, row as usize))
+    }
+}
@@ -0,0 +1,52 @@
+//! Protect Joe: a tower defense game inside Joe's body.
+//!
+//! The game runs in a window from `main.rs`, and without one through `headless`, which
+//! needs nothing but the resources.
+
+#[macro_use]
+extern crate failure;
+#[macro_use]
+extern crate render_gl_derive;
+#[macro_use]
+extern crate serde_derive;
+
+pub mod archive;
+pub mod assets;
+pub mod audio;
+pub mod camera;
+pub mod campaign;
+pub mod debug;
+pub mod display;
+pub mod font;
+pub mod game;
+pub mod hash;
+pub mod headless;
+pub mod hud;
+pub mod input;
+pub mod level;
+pub mod loader;
+pub mod loading_screen;
+pub mod pathing;
+pub mod pathogen;
+pub mod placement;
+pub mod profile;
+pub mod render_gl;
+pub mod replay;
+pub mod resources;
+pub mod rng;
+pub mod save;
+pub mod settings;
+pub mod settings_menu;
+pub mod sim;
+pub mod storage;
+pub mod textured_square;
+pub mod tower;
+pub mod ui;
+pub mod world_renderer;
+
+pub trait Update {
+    fn update(&mut self);
+}
+pub trait Render {
+    fn render(&self, gl: &gl::Gl);
+}
@@ -0,0 +1,379 @@
+//! Background asset loading.
+//!
+//! Worker threads read and decode resources: images, fonts, levels and preprocessed shader
+//! sources. What needs the GL context (texture uploads, shader compilation) happens in
+//! `Loader::poll` on the main thread, a few assets per frame, so the window keeps pumping
+//! events and a loading screen can draw `progress`.
+//!
+//! Sounds aren't manifest assets: clips are resampled to the output device's rate, which
+//! `Asset::load` isn't given, so `audio::SoundBank::from_res` still decodes them on the main
+//! thread after loading.
+
+use crate::assets::{AssetKind, Assets, LoadedAssets};
+use crate::font::Font;
+use crate::level::Level;
+use crate::render_gl::{Program, ProgramSource, Texture, TextureFormat};
+use crate::resources::{self, Resources};
+use failure;
+use image::{self, GenericImageView};
+use std::collections::VecDeque;
+use std::mem;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+enum Decoded {
+    Image {
+        width: u32,
+        height: u32,
+        format: TextureFormat,
+        data: Vec<u8>,
+    },
+    Program(ProgramSource),
+    Font(Font),
+    Level(Level),
+}
+
+struct Message {
+    kind: AssetKind,
+    name: String,
+    result: Result<Decoded, resources::Error>,
+}
+
+/// An asset of the manifest that could not be loaded.
+#[derive(Debug)]
+pub struct Failed {
+    pub kind: AssetKind,
+    pub name: String,
+    pub error: resources::Error,
+}
+
+pub struct Loader {
+    queue: Arc<Mutex<Vec<(AssetKind, String)>>>,
+    results: mpsc::Receiver<Message>,
+    /// assets no worker has sent yet
+    outstanding: Vec<(AssetKind, String)>,
+    ready: VecDeque<Message>,
+    total: usize,
+    finished: usize,
+    loaded: LoadedAssets,
+    failed: Vec<Failed>,
+}
+
+impl Loader {
+    /// Starts decoding `pending` in order on `workers` threads, at least one.
+    pub fn start(
+        res: Arc<Resources>,
+        mut pending: Vec<(AssetKind, String)>,
+        workers: usize,
+    ) -> Loader {
+        let total = pending.len();
+        let outstanding = pending.clone();
+        pending.reverse();
+
+        let queue = Arc::new(Mutex::new(pending));
+        let (sender, results) = mpsc::channel();
+
+        for i in 0..workers.max(1).min(total.max(1)) {
+            let queue = queue.clone();
+            let sender = sender.clone();
+            let res = res.clone();
+
+            thread::Builder::new()
+                .name(format!("loader-{}", i))
+                .spawn(move || worker(&res, &queue, &sender))
+                .expect("expected to spawn loader thread");
+        }
+
+        Loader {
+            queue,
+            results,
+            outstanding,
+            ready: VecDeque::new(),
+            total,
+            finished: 0,
+            loaded: LoadedAssets::default(),
+            failed: Vec::new(),
+        }
+    }
+
+    /// Finalizes decoded assets until `budget` is used up. Always finalizes at least one
+    /// when one is ready. Returns false once everything is loaded or failed.
+    pub fn poll(&mut self, assets: &mut Assets, budget: Duration) -> bool {
+        let started = Instant::now();
+
+        loop {
+            match self.results.try_recv() {
+                Ok(message) => {
+                    if let Some(i) = self
+                        .outstanding
+                        .iter()
+                        .position(|(kind, name)| *kind == message.kind && *name == message.name)
+                    {
+                        self.outstanding.remove(i);
+                    }
+                    self.ready.push_back(message);
+                }
+                Err(mpsc::TryRecvError::Empty) => break,
+                // every worker is gone, one that panicked never sends what it was decoding
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    for (kind, name) in self.outstanding.drain(..) {
+                        self.finished += 1;
+                        let error = failed_to_load(
+                            &name,
+                            failure::err_msg("the loader thread stopped before sending it"),
+                        );
+                        self.failed.push(Failed { kind, name, error });
+                    }
+                    break;
+                }
+            }
+        }
+
+        while let Some(message) = self.ready.pop_front() {
+            self.finalize(assets, message);
+            if started.elapsed() >= budget {
+                break;
+            }
+        }
+
+        !self.is_done()
+    }
+
+    /// Fraction of the manifest loaded or failed, from 0.0 to 1.0.
+    pub fn progress(&self) -> f32 {
+        if self.total == 0 {
+            return 1.0;
+        }
+        self.finished as f32 / self.total as f32
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.finished == self.total
+    }
+
+    pub fn failed(&self) -> &[Failed] {
+        &self.failed
+    }
+
+    /// The assets loaded so far, and those that failed.
+    pub fn finish(mut self) -> (LoadedAssets, Vec<Failed>) {
+        (
+            mem::replace(&mut self.loaded, LoadedAssets::default()),
+            mem::replace(&mut self.failed, Vec::new()),
+        )
+    }
+
+    fn finalize(&mut self, assets: &mut Assets, message: Message) {
+        let Message { kind, name, result } = message;
+        self.finished += 1;
+
+        let result = result.and_then(|decoded| match decoded {
+            Decoded::Image {
+                width,
+                height,
+                format,
+                data,
+            } => {
+                let texture = match assets.get::<Texture>(&name) {
+                    Some(texture) => texture,
+                    None => {
+                        let texture = Texture::from_pixels(width, height, format, &data)
+                            .create(assets.gl())
+                            .map_err(|e| failed_to_load(&name, e))?;
+                        assets.insert(&name, texture)
+                    }
+                };
+                self.loaded.textures.push(texture);
+                Ok(())
+            }
+            Decoded::Program(source) => {
+                let program = match assets.get::<Program>(&name) {
+                    Some(program) => program,
+                    None => {
+                        let program = source
+                            .compile(assets.gl())
+                            .map_err(|e| failed_to_load(&name, e))?;
+                        assets.insert(&name, program)
+                    }
+                };
+                self.loaded.programs.push(program);
+                Ok(())
+            }
+            Decoded::Font(font) => {
+                let font = assets
+                    .get(&name)
+                    .unwrap_or_else(|| assets.insert(&name, font));
+                self.loaded.fonts.push(font);
+                Ok(())
+            }
+            Decoded::Level(level) => {
+                let level = assets
+                    .get(&name)
+                    .unwrap_or_else(|| assets.insert(&name, level));
+                self.loaded.levels.push(level);
+                Ok(())
+            }
+        });
+
+        if let Err(error) = result {
+            self.failed.push(Failed { kind, name, error });
+        }
+    }
+}
+
+impl Drop for Loader {
+    /// Lets the workers run out of work instead of decoding assets nobody will finalize.
+    fn drop(&mut self) {
+        let mut queue = self.queue.lock().unwrap_or_else(|p| p.into_inner());
+        queue.clear();
+    }
+}
+
+fn worker(
+    res: &Resources,
+    queue: &Mutex<Vec<(AssetKind, String)>>,
+    sender: &mpsc::Sender<Message>,
+) {
+    loop {
+        // a worker that panicked can't have left the queue half changed
+        let next = match queue.lock() {
+            Ok(mut queue) => queue.pop(),
+            Err(poisoned) => poisoned.into_inner().pop(),
+        };
+        let (kind, name) = match next {
+            Some(next) => next,
+            None => return,
+        };
+
+        let result = decode(res, kind, &name);
+        if sender.send(Message { kind, name, result }).is_err() {
+            return;
+        }
+    }
+}
+
+fn decode(res: &Resources, kind: AssetKind, name: &str) -> Result<Decoded, resources::Error> {
+    match kind {
+        AssetKind::Texture => {
+            let img = res.load_image(name)?;
+            let (width, height) = img.dimensions();
+            // the same choice `Asset for Texture` makes
+            let (format, data) = if resources::has_alpha(img.color()) {
+                (TextureFormat::Rgba8, img.to_rgba().into_raw())
+            } else {
+                (TextureFormat::Rgb8, img.to_rgb().into_raw())
+            };
+            Ok(Decoded::Image {
+                width,
+                height,
+                format,
+                data,
+            })
+        }
+        AssetKind::Program => ProgramSource::from_res(res, name)
+            .map(Decoded::Program)
+            .map_err(|e| failed_to_load(name, e)),
+        AssetKind::Font => Font::from_bytes(name, res.load_bytes(name)?)
+            .map(Decoded::Font)
+            .map_err(|e| failed_to_load(name, e)),
+        AssetKind::Level => Level::from_toml_str(name, &res.load_string(name)?)
+            .map(Decoded::Level)
+            .map_err(|e| failed_to_load(name, e)),
+    }
+}
+
+fn failed_to_load<E: Into<failure::Error>>(name: &str, error: E) -> resources::Error {
+    resources::Error::FailedToLoadAsset {
+        name: name.into(),
+        inner: error.into().compat(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::MemorySource;
+    use gl::recording::RecordingGl;
+    use image::png::PNGEncoder;
+    use image::ColorType;
+    use std::rc::Rc;
+
+    fn png() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        PNGEncoder::new(&mut bytes)
+            .encode(&[255; 16], 2, 2, ColorType::RGBA(8))
+            .unwrap();
+        bytes
+    }
+
+    fn assets(recording: &Rc<RecordingGl>, manifest: &str) -> Assets {
+        let res = Resources::new().with_source(
+            0,
+            MemorySource::new()
+                .with("manifest.toml", manifest)
+                .with("a.png", png())
+                .with("b.png", png())
+                .with("c.png", png())
+                .with("broken.toml", "not a level"),
+        );
+        Assets::new(&gl::Gl::from_api(recording.clone()), res)
+    }
+
+    #[test]
+    fn failed_assets_are_reported_one_by_one() {
+        let recording = Rc::new(RecordingGl::new());
+        let mut assets = assets(
+            &recording,
+            r#"
+                textures = ["a.png", "missing.png"]
+                levels = ["broken.toml"]
+            "#,
+        );
+
+        let mut loader = assets.preload_in_background("manifest.toml", 2).unwrap();
+        while loader.poll(&mut assets, Duration::from_secs(1)) {}
+        let (loaded, mut failed) = loader.finish();
+
+        assert_eq!(loaded.textures.len(), 1);
+        assert_eq!(loaded.textures[0].name(), "a.png");
+        failed.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(failed.len(), 2);
+        match &failed[0] {
+            Failed {
+                kind: AssetKind::Level,
+                error: resources::Error::FailedToLoadAsset { name, .. },
+                ..
+            } => assert_eq!(name, "broken.toml"),
+            other => panic!("expected the level to fail to parse, got {:?}", other),
+        }
+        match &failed[1] {
+            Failed {
+                kind: AssetKind::Texture,
+                error: resources::Error::ResourceNotFound { name },
+                ..
+            } => assert_eq!(name, "missing.png"),
+            other => panic!("expected the texture to be missing, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn poll_finalizes_one_asset_when_over_budget() {
+        let recording = Rc::new(RecordingGl::new());
+        let mut assets = assets(&recording, r#"textures = ["a.png", "b.png", "c.png"]"#);
+
+        let mut loader = assets.preload_in_background("manifest.toml", 1).unwrap();
+        // decoding is fast, give the worker time to send all three
+        thread::sleep(Duration::from_millis(200));
+
+        assert!(loader.poll(&mut assets, Duration::from_secs(0)));
+        assert_eq!(loader.progress(), 1.0 / 3.0);
+        assert!(loader.poll(&mut assets, Duration::from_secs(0)));
+        assert_eq!(loader.progress(), 2.0 / 3.0);
+
+        assert!(!loader.poll(&mut assets, Duration::from_secs(1)));
+        let (loaded, failed) = loader.finish();
+        assert_eq!(loaded.textures.len(), 3);
+        assert!(failed.is_empty());
+    }
+}
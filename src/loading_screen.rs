@@ -0,0 +1,41 @@
+use crate::render_gl::Viewport;
+use gl;
+use nalgebra as na;
+
+/// A progress bar drawn with scissored clears, so it needs no assets of its own.
+pub struct LoadingScreen {
+    background: na::Vector3<f32>,
+    bar: na::Vector3<f32>,
+}
+
+impl LoadingScreen {
+    pub fn new() -> LoadingScreen {
+        LoadingScreen {
+            background: na::Vector3::new(0.0, 0.0, 0.0),
+            bar: na::Vector3::new(0.8, 0.1, 0.15),
+        }
+    }
+
+    /// Draws the bar filled to `progress`, from 0.0 to 1.0.
+    pub fn render(&self, gl: &gl::Gl, viewport: &Viewport, progress: f32) {
+        let width = viewport.w / 2;
+        let height = (viewport.h / 40).max(4);
+        let x = viewport.x + (viewport.w - width) / 2;
+        let y = viewport.y + (viewport.h - height) / 2;
+        let filled = (width as f32 * progress.max(0.0).min(1.0)) as i32;
+
+        unsafe {
+            gl.ClearColor(self.background.x, self.background.y, self.background.z, 1.0);
+            gl.Clear(gl::COLOR_BUFFER_BIT);
+
+            gl.Enable(gl::SCISSOR_TEST);
+            gl.ClearColor(0.2, 0.2, 0.2, 1.0);
+            gl.Scissor(x, y, width, height);
+            gl.Clear(gl::COLOR_BUFFER_BIT);
+            gl.ClearColor(self.bar.x, self.bar.y, self.bar.z, 1.0);
+            gl.Scissor(x, y, filled, height);
+            gl.Clear(gl::COLOR_BUFFER_BIT);
+            gl.Disable(gl::SCISSOR_TEST);
+        }
+    }
+}
@@ -1,50 +1,153 @@
-#[macro_use]
-extern crate failure;
-#[macro_use]
-extern crate render_gl_derive;
-
-mod camera;
-mod debug;
-pub mod render_gl;
-pub mod resources;
-mod textured_square;
-
-use crate::resources::Resources;
 use failure::err_msg;
 use nalgebra as na;
 use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use std::path::Path;
-use std::time::{Duration, SystemTime};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use tow_def_game::assets::{Assets, Handle};
+use tow_def_game::resources::Resources;
+use tow_def_game::{
+    audio, camera, campaign, debug, display, font, game, headless, hud, input, level,
+    loading_screen, pathogen, placement, profile, render_gl, replay, rng, save, settings,
+    settings_menu, textured_square, tower, ui, world_renderer, Render, Update,
+};
 
-trait Update {
-    fn update(&mut self);
-}
-trait Render {
-    fn render(&self, gl: &gl::Gl, proj_matrix: &na::Matrix4<f32>);
+/// How the game was started from the command line.
+enum Mode {
+    /// with a window, playing back `replay` or recording to `record`
+    Windowed {
+        replay: Option<PathBuf>,
+        record: PathBuf,
+    },
+    Headless(headless::Config),
 }
 
 fn main() {
-    if let Err(e) = run() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match parse_args(&args) {
+        Ok(Mode::Headless(config)) => run_headless(&config),
+        Ok(Mode::Windowed { replay, record }) => run(replay, record),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
         println!("{}", debug::failure_to_string(e));
     }
 }
 
+/// `[--replay <file>] [--record <file>]` for the window, or `--headless <level>
+/// [--build <script>] [--seed <n>] [--ticks <n>] [--replay <file>]`.
+fn parse_args(args: &[String]) -> Result<Mode, failure::Error> {
+    const USAGE: &str = "usage: [--replay <file>] [--record <file>]\n       \
+                         --headless <level> [--build <script>] [--seed <n>] [--ticks <n>] \
+                         [--replay <file>]";
+
+    let mut args = args.iter();
+    let mut config = None;
+    let mut build = None;
+    let mut seed = None;
+    let mut max_ticks = None;
+    let mut replay = None;
+    let mut record = None;
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| err_msg(USAGE));
+        match arg.as_str() {
+            "--headless" => config = Some(headless::Config::new(value()?)),
+            "--build" => build = Some(value()?.clone()),
+            "--seed" => seed = Some(value()?.parse::<u64>()?),
+            "--ticks" => max_ticks = Some(value()?.parse::<u64>()?),
+            "--replay" => replay = Some(PathBuf::from(value()?)),
+            "--record" => record = Some(PathBuf::from(value()?)),
+            _ => return Err(err_msg(format!("unknown argument {}\n{}", arg, USAGE))),
+        }
+    }
+
+    match config {
+        Some(_) if record.is_some() => Err(err_msg(USAGE)),
+        Some(mut config) => {
+            config.build = build;
+            config.replay = replay;
+            if let Some(seed) = seed {
+                config.seed = seed;
+            }
+            if let Some(max_ticks) = max_ticks {
+                config.max_ticks = max_ticks;
+            }
+            Ok(Mode::Headless(config))
+        }
+        None if build.is_some() || seed.is_some() || max_ticks.is_some() => Err(err_msg(USAGE)),
+        None => Ok(Mode::Windowed {
+            replay,
+            record: record.unwrap_or_else(replay::default_path),
+        }),
+    }
+}
+
+/// Plays a level without opening a window and prints how it went.
+fn run_headless(config: &headless::Config) -> Result<(), failure::Error> {
+    let res = Resources::from_relative_exe_path(Path::new("res"))?;
+    let outcome = headless::run(&res, config)?;
+    print!("{}", outcome);
+    Ok(())
+}
+
+/// The window and what is fitted to its drawable size.
+struct Screen {
+    window: sdl2::video::Window,
+    viewprt: render_gl::Viewport,
+    cam: camera::Camera,
+    gl: gl::Gl,
+}
+
+/// Everything that draws a frame.
+struct Renderers {
+    clr_bffr: render_gl::ColorBuffer,
+    cam_uniforms: camera::CameraUniforms,
+    tex: textured_square::TexturedSquare,
+    world: world_renderer::WorldRenderer,
+    placement: placement::PlacementRenderer,
+    ui: ui::UiRenderer,
+}
+
+/// The level being played and what lasts as long as it does.
+struct Match {
+    level: Handle<level::Level>,
+    catalog: tower::TowerCatalog,
+    game: game::Game,
+    hud: hud::Hud,
+    stats: profile::MatchStats,
+    finished: bool,
+    placement: placement::PlacementController,
+    session: replay::Session,
+}
+
+/// Updates and frames since the title was last built, and the clock ticking once per update.
+struct Counters {
+    updt_cnt: i32,
+    fps_cnt: i32,
+    clk: u8,
+}
+
 /// Initialized variables and begins the game loop.
 /// It also calls the helper methods within the loop to keep the loop clean.
-fn run() -> Result<(), failure::Error> {
+fn run(replay_path: Option<PathBuf>, record_path: PathBuf) -> Result<(), failure::Error> {
     const TITLE: &str = "Protect Joe";
 
     // fps Calc and Game Clock
     const UPDATES: u8 = 60;
     const NANOS: f64 = 1_000_000_000.0 / UPDATES as f64;
+    // background loading, GL work per frame is capped so the loading screen stays responsive
+    const LOADER_THREADS: usize = 2;
+    const LOADING_BUDGET: Duration = Duration::from_millis(8);
+    // seconds to crossfade between menu and level music
+    const MUSIC_FADE: f32 = 2.0;
     let mut time_now: SystemTime;
     let mut timer = SystemTime::now();
     let mut lst_time = SystemTime::now();
     let mut delta: f64 = 0.0;
-    let mut clk: u8 = 0;
-    let mut fps_cnt = 0;
-    let mut updt_cnt = 0;
+    let mut counters = Counters {
+        updt_cnt: 0,
+        fps_cnt: 0,
+        clk: 0,
+    };
     let mut running = true;
 
     let res = Resources::from_relative_exe_path(Path::new("res")).unwrap();
@@ -52,59 +155,326 @@ fn run() -> Result<(), failure::Error> {
     let sdl = sdl2::init().map_err(err_msg)?;
     let vid_sub = sdl.video().map_err(err_msg)?;
 
+    // the settings file is rewritten only once something is changed in the game
+    let settings_path = match settings::default_path() {
+        Ok(path) => Some(path),
+        Err(e) => {
+            println!("{}", debug::failure_to_string(e.into()));
+            None
+        }
+    };
+    let mut settings = match &settings_path {
+        Some(path) => {
+            let (settings, warnings) = settings::Settings::load(path);
+            for warning in warnings {
+                println!("{}", warning);
+            }
+            settings
+        }
+        None => settings::Settings::default(),
+    };
+    // changes apply right away but are written once no slider is being dragged
+    let mut settings_unsaved = false;
+    let mut settings_menu = settings_menu::SettingsMenu::new(display::displays(&vid_sub));
+    let mut frame_interval = settings.frame_interval();
+    let mut lst_frame = Instant::now();
+
     let gl_attr = vid_sub.gl_attr();
 
     gl_attr.set_context_profile(sdl2::video::GLProfile::Core);
     gl_attr.set_context_version(4, 1);
+    if settings.display.msaa_samples > 0 {
+        gl_attr.set_multisample_buffers(1);
+        gl_attr.set_multisample_samples(settings.display.msaa_samples as u8);
+    }
 
-    let win_size: (i32, i32) = (1280, 720);
-
+    // opened windowed, then switched the same way the settings menu does
     let mut window = vid_sub
-        .window(TITLE, win_size.0 as u32, win_size.1 as u32)
+        .window(TITLE, settings.display.width, settings.display.height)
         .position_centered()
         .opengl()
         .resizable()
+        .allow_highdpi()
         .build()?;
+    if let Err(e) = display::apply(&mut window, &vid_sub, &settings.display) {
+        println!("{}", debug::failure_to_string(e.into()));
+    }
 
     let _gl_context = window.gl_create_context().map_err(err_msg)?;
     let gl = gl::Gl::load_with(|s| vid_sub.gl_get_proc_address(s) as *const std::os::raw::c_void);
-    let mut viewprt = render_gl::Viewport::for_window(win_size.0, win_size.1);
-    let clr_bffr = render_gl::ColorBuffer::new();
+    set_vsync(&vid_sub, settings.display.vsync);
+    // fullscreen modes may not get the size asked for, and high-DPI displays have more
+    // pixels than the window's size says
+    let win_size = window.drawable_size();
+    let mut screen = Screen {
+        window,
+        viewprt: render_gl::Viewport::for_window(win_size.0 as i32, win_size.1 as i32),
+        cam: camera::Camera::new(),
+        gl: gl.clone(),
+    };
+
+    screen.viewprt.set_used(&gl);
+    let mut pump = sdl.event_pump().map_err(err_msg)?;
+
+    let bindings = input::Bindings::from_res(&res, "input.toml")?;
+    let mut input = input::Input::new(settings.bindings(&bindings), sdl.game_controller().ok());
+
+    let mut audio = audio::Audio::new(&sdl);
+    audio.set_volumes(settings.audio.volumes());
+    resize(&mut screen, &mut audio);
+    let sounds = audio::SoundBank::from_res(&res, "audio/sounds.toml", audio.sample_rate())?;
+    if let Some(music) = sounds.get("menu_music") {
+        audio.play_music(music, 0.0);
+    }
+
+    let mut assets = Assets::new(&gl, res);
+    let mut loader = assets.preload_in_background("manifest.toml", LOADER_THREADS)?;
+    let loading_screen = loading_screen::LoadingScreen::new();
+    while loader.poll(&mut assets, LOADING_BUDGET) {
+        for event in pump.poll_iter() {
+            match event {
+                Event::Quit { .. } => return Ok(()),
+                Event::Window {
+                    win_event: sdl2::event::WindowEvent::SizeChanged(..),
+                    ..
+                }
+                | Event::Window {
+                    win_event: sdl2::event::WindowEvent::Moved(..),
+                    ..
+                } => resize(&mut screen, &mut audio),
+                _ => {}
+            }
+        }
+        loading_screen.render(&gl, &screen.viewprt, loader.progress());
+        screen.window.gl_swap_window();
+    }
+    let (_preloaded, failed) = loader.finish();
+    for failure in failed {
+        println!("{}", debug::failure_to_string(failure.error.into()));
+    }
+
+    // progress is kept when it can't be written, it just doesn't outlive the session
+    let campaign = campaign::Campaign::from_res(assets.res(), "campaign.toml")?;
+    let mut profile_path = match profile::default_path() {
+        Ok(path) => Some(path),
+        Err(e) => {
+            println!("{}", debug::failure_to_string(e.into()));
+            None
+        }
+    };
+    let mut profile = match &profile_path {
+        Some(path) => {
+            let recovered = profile::Profile::load_or_recover(path, &campaign);
+            for warning in &recovered.warnings {
+                println!("{}", warning);
+            }
+            if !recovered.writable {
+                profile_path = None;
+            }
+            recovered.profile
+        }
+        None => profile::Profile::new(&campaign),
+    };
 
-    let cam = camera::Camera::new();
+    // a replay brings its own level and seed, anything else is recorded
+    let (level_name, seed, session) = match replay_path {
+        Some(path) => {
+            let replay = replay::Replay::load(&path)?;
+            if let Some(warning) = replay.version_warning() {
+                println!("{}", warning);
+            }
+            (
+                replay.level.clone(),
+                replay.seed,
+                replay::Session::Playback(replay::Playback::new(replay)),
+            )
+        }
+        None => {
+            let seed = rng::RngService::seed_from_time();
+            (
+                "levels/arm.toml".to_string(),
+                seed,
+                replay::Session::Recording(replay::Recorder::new("levels/arm.toml", seed)),
+            )
+        }
+    };
 
-    let mut tex = textured_square::TexturedSquare::new(&res, &gl)?;
+    let level = assets.load::<level::Level>(&level_name)?;
+    let catalog = tower::TowerCatalog::from_res(assets.res(), "towers.toml")?;
+    let pathogens = pathogen::PathogenCatalog::from_res(assets.res(), "pathogens.toml")?;
+    let game = game::Game::new(&level, catalog.clone(), pathogens, seed)?;
+    let mut current = Match {
+        level,
+        catalog,
+        game,
+        hud: hud::Hud::new(),
+        stats: profile::MatchStats::new(),
+        finished: false,
+        placement: placement::PlacementController::new(),
+        session,
+    };
+
+    let theme = ui::Theme::from_res(assets.res(), "ui/theme.toml")?;
+    let font = assets.load::<font::Font>(&theme.font)?;
+    let mut ui = ui::Ui::new(theme, Box::new(font.clone()));
+    ui.set_scale(settings.ui.scale);
+
+    let mut renderers = Renderers {
+        clr_bffr: render_gl::ColorBuffer::new(),
+        cam_uniforms: camera::CameraUniforms::new(&gl),
+        tex: textured_square::TexturedSquare::new(&mut assets, &gl)?,
+        world: world_renderer::WorldRenderer::new(&mut assets, &gl, &current.catalog)?,
+        placement: placement::PlacementRenderer::new(&mut assets, &gl, &current.catalog)?,
+        ui: ui::UiRenderer::new(&gl, assets.res(), font)?,
+    };
+
+    audio.set_falloff(sounds.falloff());
+    if let Some(music) = sounds.get("level_music") {
+        audio.play_music(music, MUSIC_FADE);
+    }
 
     // let mut gameobjs: Vec<&mut dyn Update> = vec![&mut tex];
     // let drawables: Vec<&dyn Render> = vec![&tex];
 
     // set up shared state for window
-    viewprt.set_used(&gl);
-    clr_bffr.set_clear_color(&gl, na::Vector3::new(0.0, 0.0, 0.0));
-    let mut pump = sdl.event_pump().map_err(err_msg)?;
+    renderers
+        .clr_bffr
+        .set_clear_color(&gl, na::Vector3::new(0.0, 0.0, 0.0));
     // game loop
     while running {
         // fps and update timer goes here
         time_now = SystemTime::now();
         delta += time_now.duration_since(lst_time).unwrap().as_nanos() as f64 / NANOS;
         lst_time = time_now;
+        let mut updated = false;
         // capping updates to "UPDATES"
         while delta > 1.0 {
-            tex.update();
+            renderers.tex.update();
             running = handle_events(
                 // &mut gameobjs,
                 &mut pump,
-                &mut updt_cnt,
-                &mut clk,
+                &mut screen,
+                &mut input,
+                &mut ui,
+                &mut audio,
+                &mut counters,
                 UPDATES,
-                &mut viewprt,
-                &gl,
             );
-            // render(&mut window, &cam, &clr_bffr, &drawables, &mut fps_cnt, &gl);
-            render(&mut window, &cam, &clr_bffr, &tex, &mut fps_cnt, &gl);
+            let (commands, mut changed_settings) = build_ui(
+                &mut ui,
+                &screen.viewprt,
+                &mut current,
+                &mut settings_menu,
+                &settings,
+            );
+            running = running && handle_actions(&input, &ui, commands, &mut current);
+            move_camera(&input, &ui, &mut screen, &mut audio);
+            if input.pressed(input::Action::Settings)
+                || (input.pressed(input::Action::Cancel) && settings_menu.is_open())
+            {
+                settings_menu.toggle();
+            }
+            if input.pressed(input::Action::ToggleFullscreen) {
+                let mut changed = changed_settings.unwrap_or_else(|| settings.clone());
+                changed.display.mode = match changed.display.mode {
+                    settings::WindowMode::Windowed => settings::WindowMode::Borderless,
+                    _ => settings::WindowMode::Windowed,
+                };
+                changed_settings = Some(changed);
+            }
+            if let Some(changed) = changed_settings {
+                apply_settings(
+                    &settings,
+                    &changed,
+                    &mut screen,
+                    &vid_sub,
+                    &mut audio,
+                    &mut ui,
+                );
+                frame_interval = changed.frame_interval();
+                settings = changed;
+                settings_unsaved = true;
+            }
+            if settings_unsaved && !ui.is_dragging() {
+                settings_unsaved = false;
+                save_settings(&settings, settings_path.as_ref().map(PathBuf::as_path));
+            }
+            if input.pressed(input::Action::QuickSave) {
+                quick_save(&current.game, &current.level);
+            }
+            if input.pressed(input::Action::QuickLoad) {
+                match quick_load(&mut assets, &current.catalog, &current.game) {
+                    Ok((loaded_level, loaded_game)) => {
+                        current.level = loaded_level;
+                        current.game = loaded_game;
+                        current.hud = hud::Hud::new();
+                        current.stats = profile::MatchStats::new();
+                        current.finished = false;
+                        current.placement.cancel();
+                        current.session = replay::Session::Off;
+                    }
+                    Err(e) => println!("{}", debug::failure_to_string(e)),
+                }
+            }
+            current.session.update(&mut current.game, &current.level);
+            if let replay::Session::Recording(recorder) = &mut current.session {
+                if let Err(e) = recorder.save_if_due(&record_path) {
+                    println!("{}", debug::failure_to_string(e.into()));
+                }
+            }
+            for event in current.game.drain_events() {
+                current.hud.handle_event(&event);
+                current.stats.handle_event(&event);
+                play_event_sound(&mut audio, &sounds, &current.level, &event);
+            }
+            // watching a replay isn't playing the level
+            let playing_back = match current.session {
+                replay::Session::Playback(_) => true,
+                _ => false,
+            };
+            if current.game.is_over() && !current.finished && !playing_back {
+                current.finished = true;
+                finish_level(
+                    &mut profile,
+                    profile_path.as_ref().map(PathBuf::as_path),
+                    &campaign,
+                    &current.level.id,
+                    &current.stats,
+                );
+            }
             delta -= 1.0;
+            updated = true;
         } // uncapping updates and fps is below:
-        build_title_update_fps(&mut timer, &mut window, TITLE, &mut updt_cnt, &mut fps_cnt);
+          // frames are drawn independently of updates, at most once per frame interval
+        if frame_interval.map_or(true, |interval| lst_frame.elapsed() >= interval) {
+            lst_frame = Instant::now();
+            // render(&mut window, &cam, &clr_bffr, &drawables, &mut fps_cnt, &gl);
+            render(
+                &mut screen,
+                &mut renderers,
+                &current,
+                &ui,
+                &mut counters.fps_cnt,
+            );
+        } else if !updated {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        build_title_update_fps(&mut timer, &mut screen.window, TITLE, &mut counters);
+    }
+
+    if settings_unsaved {
+        save_settings(&settings, settings_path.as_ref().map(PathBuf::as_path));
+    }
+    match current.session {
+        replay::Session::Recording(recorder) => {
+            recorder.replay().save(&record_path)?;
+            println!("Replay saved to {}", record_path.display());
+        }
+        replay::Session::Playback(playback) => match playback.divergence() {
+            Some(divergence) => println!("Replay: {}", divergence),
+            None => println!("Replay: all {} checksums matched", playback.checked()),
+        },
+        replay::Session::Off => {}
     }
     Ok(())
 }
@@ -113,57 +483,320 @@ fn run() -> Result<(), failure::Error> {
 fn handle_events(
     // gameobjs: &mut Vec<&mut dyn Update>,
     pump: &mut sdl2::EventPump,
-    updt_cnt: &mut i32,
-    clk: &mut u8,
+    screen: &mut Screen,
+    input: &mut input::Input,
+    ui: &mut ui::Ui,
+    audio: &mut audio::Audio,
+    counters: &mut Counters,
     updates: u8,
-    viewprt: &mut render_gl::Viewport,
-    gl: &gl::Gl,
 ) -> bool {
+    input.begin_frame();
     for event in pump.poll_iter() {
-        println!("{:?}", event);
+        input.handle_event(&event);
+        ui.handle_event(&event);
         match event {
-            Event::Quit { .. }
-            | Event::KeyDown {
-                keycode: Some(Keycode::Escape),
-                ..
-            } => return false,
+            Event::Quit { .. } => return false,
+            // also sent when the window switches modes, unlike Resized. Moving to a display
+            // with another pixel ratio changes the drawable size but not the window's.
             Event::Window {
-                win_event: sdl2::event::WindowEvent::Resized(w, h),
+                win_event: sdl2::event::WindowEvent::SizeChanged(..),
                 ..
-            } => {
-                viewprt.update_size(w, h);
-                viewprt.set_used(&gl);
             }
+            | Event::Window {
+                win_event: sdl2::event::WindowEvent::Moved(..),
+                ..
+            } => resize(screen, audio),
             _ => {}
         }
     }
+    input.update_mouse_world(&screen.cam, &screen.viewprt);
     // for gameobj in gameobjs.iter() {
     //     gameobj.update();
     // }
     // tick the clock once
-    *clk += 1;
-    if *clk >= updates {
-        *clk = 0;
+    counters.clk += 1;
+    if counters.clk >= updates {
+        counters.clk = 0;
     }
-    *updt_cnt += 1;
+    counters.updt_cnt += 1;
     true
 }
 
+/// declares this frame's widgets, the tower shop picks what to place
+fn build_ui(
+    ui: &mut ui::Ui,
+    viewprt: &render_gl::Viewport,
+    current: &mut Match,
+    settings_menu: &mut settings_menu::SettingsMenu,
+    settings: &settings::Settings,
+) -> (Vec<game::Command>, Option<settings::Settings>) {
+    const SHOP_WIDTH: f32 = 220.0;
+
+    let Match {
+        hud,
+        catalog,
+        game,
+        placement,
+        ..
+    } = current;
+    // the UI is laid out in UI units, see ui::Ui::scale
+    ui.set_pixel_ratio(viewprt.pixel_ratio);
+    let (w, h) = (viewprt.w as f32 / ui.scale(), viewprt.h as f32 / ui.scale());
+    let gold = game.gold();
+    ui.begin_frame(ui::Rect::new(0.0, 0.0, w, h));
+    let commands = hud.build(ui, ui::Rect::new(0.0, 0.0, w - SHOP_WIDTH, h));
+    let shop = ui::Rect::new(w - SHOP_WIDTH, 0.0, SHOP_WIDTH, h);
+    ui.panel("shop", shop, |ui| {
+        for tower in catalog.iter() {
+            let text = format!("{} ({})", tower.name, tower.cost);
+            if ui.button_enabled(&text, gold >= tower.cost) {
+                placement.select(tower);
+            }
+            ui.tooltip(&format!("Range: {} tiles", tower.range));
+        }
+    });
+    let changed = settings_menu.build(ui, ui::Rect::new(0.0, 0.0, w, h), settings);
+    ui.end_frame();
+    (commands, changed)
+}
+
+/// fits the viewport, camera and audio listener to the window's drawable, which SDL sizes
+/// in pixels rather than window coordinates
+fn resize(screen: &mut Screen, audio: &mut audio::Audio) {
+    let (w, h) = screen.window.drawable_size();
+    screen
+        .viewprt
+        .update_drawable_size(w as i32, h as i32, screen.window.size().0 as i32);
+    screen.viewprt.set_used(&screen.gl);
+    screen.cam.set_aspect(w as f32 / h as f32);
+    audio.set_listener(audio::Listener::from_camera(&screen.cam));
+}
+
+/// applies the settings that take effect without a restart, `previous` is what was in
+/// effect until now
+fn apply_settings(
+    previous: &settings::Settings,
+    settings: &settings::Settings,
+    screen: &mut Screen,
+    vid_sub: &sdl2::VideoSubsystem,
+    audio: &mut audio::Audio,
+    ui: &mut ui::Ui,
+) {
+    if previous.window_changed(settings) {
+        if let Err(e) = display::apply(&mut screen.window, vid_sub, &settings.display) {
+            println!("{}", debug::failure_to_string(e.into()));
+        }
+        // SizeChanged follows, but this frame is already drawn at the new size
+        resize(screen, audio);
+    }
+    if previous.display.vsync != settings.display.vsync {
+        set_vsync(vid_sub, settings.display.vsync);
+    }
+    audio.set_volumes(settings.audio.volumes());
+    ui.set_scale(settings.ui.scale);
+}
+
+/// writes the settings, failing to doesn't end the game
+fn save_settings(settings: &settings::Settings, path: Option<&Path>) {
+    if let Some(path) = path {
+        if let Err(e) = settings.save(path) {
+            println!("{}", debug::failure_to_string(e.into()));
+        }
+    }
+}
+
+/// drivers that refuse a swap interval keep their own, that is not worth failing over
+fn set_vsync(vid_sub: &sdl2::VideoSubsystem, vsync: settings::Vsync) {
+    match display::set_vsync(vid_sub, vsync) {
+        Ok(set) if set != vsync => println!("Vsync {:?} isn't supported, using {:?}", vsync, set),
+        Ok(_) => {}
+        Err(e) => println!("Failed to set vsync: {}", e),
+    }
+}
+
+/// reacts to the player's actions, returns false once they quit
+fn handle_actions(
+    input: &input::Input,
+    ui: &ui::Ui,
+    mut commands: Vec<game::Command>,
+    current: &mut Match,
+) -> bool {
+    use tow_def_game::game::Command;
+    use tow_def_game::input::Action;
+    use tow_def_game::placement::PlacementEvent;
+
+    let Match {
+        level,
+        catalog,
+        game,
+        placement,
+        session,
+        ..
+    } = current;
+
+    // Space activates the focused widget rather than pausing
+    if input.pressed(Action::Pause) && !ui.wants_keyboard() {
+        commands.push(Command::TogglePause);
+    }
+    if input.pressed(Action::SpeedUp) {
+        commands.push(Command::CycleSpeed);
+    }
+    if input.pressed(Action::NextWave) {
+        commands.push(Command::SendNextWave);
+    }
+    for command in commands {
+        session.apply(game, level, command);
+    }
+
+    // clicks on the UI don't reach the map
+    if ui.wants_mouse() {
+        return !input.pressed(Action::Quit);
+    }
+
+    if !placement.is_placing() {
+        if input.pressed(Action::PlaceTower) {
+            if let Some(tower) = catalog.iter().next() {
+                placement.select(tower);
+            }
+        }
+        if input.pressed(Action::SellTower) {
+            match level.world_to_tile(&input.mouse_world()) {
+                Some(tile) if game.towers().is_occupied(tile) => {
+                    session.apply(game, level, Command::SellTower { tile });
+                }
+                _ => {}
+            }
+        }
+        return !input.pressed(Action::Quit);
+    }
+
+    if let Some(PlacementEvent::Placed { tile, tower, .. }) =
+        placement.update(input, level, game.towers(), game.world(), game.gold())
+    {
+        session.apply(game, level, Command::PlaceTower { tile, tower });
+    }
+    !input.pressed(Action::Quit)
+}
+
+/// drags the camera while `CameraPan` is held and zooms on `Zoom`, the wheel zooms in on
+/// what's under the mouse
+fn move_camera(input: &input::Input, ui: &ui::Ui, screen: &mut Screen, audio: &mut audio::Audio) {
+    use tow_def_game::input::Action;
+
+    // each wheel tick or update of a fully tilted stick shows this much more of the world
+    const ZOOM_STEP: f32 = 1.1;
+
+    let mouse = input.mouse_screen();
+    if input.held(Action::CameraPan) {
+        let from = screen
+            .cam
+            .screen_to_world(&(mouse - input.mouse_delta()), &screen.viewprt);
+        let to = screen.cam.screen_to_world(&mouse, &screen.viewprt);
+        screen.cam.target += na::Vector3::new(from.x - to.x, from.y - to.y, 0.0);
+    }
+    let zoom = input.value(Action::Zoom);
+    if zoom != 0.0 && !ui.wants_mouse() {
+        // the point under the mouse stays put
+        let before = screen.cam.screen_to_world(&mouse, &screen.viewprt);
+        screen.cam.zoom_by(ZOOM_STEP.powf(-zoom));
+        let after = screen.cam.screen_to_world(&mouse, &screen.viewprt);
+        screen.cam.target += na::Vector3::new(before.x - after.x, before.y - after.y, 0.0);
+    }
+    audio.set_listener(audio::Listener::from_camera(&screen.cam));
+}
+
+/// adds the finished match to the profile and writes it, failing to doesn't end the game
+fn finish_level(
+    profile: &mut profile::Profile,
+    path: Option<&Path>,
+    campaign: &campaign::Campaign,
+    level: &str,
+    stats: &profile::MatchStats,
+) {
+    for id in profile.finish_level(campaign, level, stats) {
+        if let Some(achievement) = campaign.achievement(&id) {
+            println!(
+                "Achievement unlocked: {} ({})",
+                achievement.name, achievement.description
+            );
+        }
+    }
+    if let Some(path) = path {
+        if let Err(e) = profile.save(path) {
+            println!("{}", debug::failure_to_string(e.into()));
+        }
+    }
+}
+
+/// writes the match to the quick save slot, failing to doesn't end the game
+fn quick_save(game: &game::Game, level: &level::Level) {
+    if game.is_over() {
+        println!("The match is over, there is nothing to save");
+        return;
+    }
+    let result = save::slot_path(save::QUICKSAVE)
+        .and_then(|path| game.to_save(level).save(&path).map(|_| path));
+    match result {
+        Ok(path) => println!("Saved to {}", path.display()),
+        Err(e) => println!("{}", debug::failure_to_string(e.into())),
+    }
+}
+
+/// the match in the quick save slot, on the level it was saved on
+fn quick_load(
+    assets: &mut Assets,
+    catalog: &tower::TowerCatalog,
+    game: &game::Game,
+) -> Result<(Handle<level::Level>, game::Game), failure::Error> {
+    let saved = save::SaveGame::load(&save::slot_path(save::QUICKSAVE)?)?;
+    let level = assets.load::<level::Level>(&saved.level)?;
+    let game = game::Game::from_save(&level, catalog.clone(), game.pathogens().clone(), saved)?;
+    Ok((level, game))
+}
+
+/// one-shot sound effects for what happened in the game
+fn play_event_sound(
+    audio: &mut audio::Audio,
+    sounds: &audio::SoundBank,
+    level: &level::Level,
+    event: &game::GameEvent,
+) {
+    let (sound, position) = match event {
+        game::GameEvent::HeartHit { .. } => ("heart_hit", level.tile_center(level.heart())),
+        game::GameEvent::TowerFired { tile } => ("tower_fire", level.tile_center(*tile)),
+        game::GameEvent::PathogenKilled { position, .. } => ("pathogen_death", *position),
+        _ => return,
+    };
+    if let Some(sound) = sounds.get(sound) {
+        audio.play_at(sound, &position);
+    }
+}
+
 /// renders the world to handle what happened in handle_events
 fn render(
-    window: &mut sdl2::video::Window,
-    cam: &camera::Camera,
-    clr_bffr: &render_gl::ColorBuffer,
-    tex: &textured_square::TexturedSquare,
+    screen: &mut Screen,
+    renderers: &mut Renderers,
+    current: &Match,
+    ui: &ui::Ui,
     fps_cnt: &mut i32,
-    gl: &gl::Gl,
 ) {
-    clr_bffr.clear(&gl);
+    let gl = &screen.gl;
+    renderers.clr_bffr.clear(gl);
+    renderers.cam_uniforms.update(&screen.cam);
     // for drawable in drawables.iter() {
-    //     drawable.render(&gl, &cam.get_p_matrix());
+    //     drawable.render(&gl);
     // }
-    tex.render(&gl, &cam.get_p_matrix());
-    window.gl_swap_window();
+    renderers.tex.render(gl);
+    renderers
+        .world
+        .render(&screen.cam, &current.level, &current.game);
+    renderers
+        .placement
+        .render(gl, &current.placement, &current.level);
+    renderers
+        .ui
+        .render(ui.draw_list(), &screen.viewprt, ui.scale());
+    screen.window.gl_swap_window();
     *fps_cnt += 1;
 }
 
@@ -177,7 +810,7 @@ fn render(
 // ) {
 //     clr_bffr.clear(&gl);
 //     for drawable in drawables.iter() {
-//         drawable.render(&gl, &cam.get_p_matrix());
+//         drawable.render(&gl);
 //     }
 //     window.gl_swap_window();
 //     *fps_cnt += 1;
@@ -188,8 +821,7 @@ fn build_title_update_fps(
     timer: &mut SystemTime,
     window: &mut sdl2::video::Window,
     title: &str,
-    updt_cnt: &mut i32,
-    fps_cnt: &mut i32,
+    counters: &mut Counters,
 ) {
     if SystemTime::now().duration_since(*timer).unwrap().as_secs() > 1 {
         *timer += Duration::new(1, 0);
@@ -197,12 +829,12 @@ fn build_title_update_fps(
             .set_title(
                 format!(
                     "{} | Updates: {} | FPS: {}",
-                    title, updt_cnt, fps_cnt
+                    title, counters.updt_cnt, counters.fps_cnt
                 )
                 .as_str(),
             )
             .unwrap();
-        *updt_cnt = 0;
-        *fps_cnt = 0;
+        counters.updt_cnt = 0;
+        counters.fps_cnt = 0;
     }
 }
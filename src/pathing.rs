@@ -0,0 +1,65 @@
+//! Which way pathogens walk: a flow field of step counts to Joe's heart, rebuilt whenever
+//! towers change which tiles can be entered.
+
+use crate::level::{Level, TileCoord};
+use std::collections::VecDeque;
+
+pub struct FlowField {
+    width: usize,
+    /// steps to the heart per tile, `None` where the heart can't be reached from
+    distances: Vec<Option<u32>>,
+}
+
+impl FlowField {
+    /// Tiles `blocked` returns true for can't be entered, e.g. because a tower stands there.
+    pub fn new<F>(level: &Level, blocked: F) -> FlowField
+    where
+        F: Fn(TileCoord) -> bool,
+    {
+        let width = level.width();
+        let mut distances = vec![None; width * level.height()];
+        let walkable = |coord: TileCoord| {
+            level.tile(coord).map_or(false, |t| t.is_walkable()) && !blocked(coord)
+        };
+
+        let mut open = VecDeque::new();
+        let heart = level.heart();
+        if walkable(heart) {
+            distances[heart.row * width + heart.col] = Some(0);
+            open.push_back((heart, 0));
+        }
+        while let Some((coord, distance)) = open.pop_front() {
+            for next in level.neighbours(coord) {
+                let index = next.row * width + next.col;
+                if distances[index].is_none() && walkable(next) {
+                    distances[index] = Some(distance + 1);
+                    open.push_back((next, distance + 1));
+                }
+            }
+        }
+
+        FlowField { width, distances }
+    }
+
+    pub fn distance(&self, coord: TileCoord) -> Option<u32> {
+        if coord.col >= self.width {
+            return None;
+        }
+        self.distances
+            .get(coord.row * self.width + coord.col)
+            .cloned()
+            .unwrap_or(None)
+    }
+
+    /// The neighbour one step closer to the heart. Ties go to the first neighbour in
+    /// `Level::neighbours` order, so every run walks the same way.
+    pub fn next_step(&self, level: &Level, coord: TileCoord) -> Option<TileCoord> {
+        let here = self.distance(coord);
+        level
+            .neighbours(coord)
+            .filter_map(|next| self.distance(next).map(|d| (next, d)))
+            .filter(|(_, d)| here.map_or(true, |here| *d < here))
+            .min_by_key(|(_, d)| *d)
+            .map(|(next, _)| next)
+    }
+}
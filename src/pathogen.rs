@@ -0,0 +1,86 @@
+use crate::resources::{self, Resources};
+use toml;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to load pathogens {}", name)]
+    ResourceLoad {
+        name: String,
+        #[cause]
+        inner: resources::Error,
+    },
+    #[fail(display = "Failed to parse pathogens {}", name)]
+    Parse {
+        name: String,
+        #[cause]
+        inner: toml::de::Error,
+    },
+    #[fail(display = "Pathogen {} is defined twice in {}", id, name)]
+    DuplicatePathogen { name: String, id: String },
+}
+
+/// A kind of invader the waves send at Joe's heart, see `res/pathogens.toml`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct PathogenDef {
+    pub id: String,
+    pub name: String,
+    pub health: f32,
+    /// in tiles per second
+    pub speed: f32,
+    /// gold for killing one
+    pub reward: u32,
+    /// heart health lost when one gets through
+    pub damage: u32,
+    /// RGBA tint it is drawn with
+    pub color: [u8; 4],
+}
+
+#[derive(Deserialize)]
+struct PathogensFile {
+    pathogens: Vec<PathogenDef>,
+}
+
+/// Every pathogen kind, in the order the file lists them.
+#[derive(Clone)]
+pub struct PathogenCatalog {
+    pathogens: Vec<PathogenDef>,
+}
+
+impl PathogenCatalog {
+    pub fn from_res(res: &Resources, name: &str) -> Result<PathogenCatalog, Error> {
+        let text = res.load_string(name).map_err(|e| Error::ResourceLoad {
+            name: name.into(),
+            inner: e,
+        })?;
+
+        PathogenCatalog::from_toml_str(name, &text)
+    }
+
+    pub fn from_toml_str(name: &str, text: &str) -> Result<PathogenCatalog, Error> {
+        let file: PathogensFile = toml::from_str(text).map_err(|e| Error::Parse {
+            name: name.into(),
+            inner: e,
+        })?;
+
+        for (i, pathogen) in file.pathogens.iter().enumerate() {
+            if file.pathogens[..i].iter().any(|p| p.id == pathogen.id) {
+                return Err(Error::DuplicatePathogen {
+                    name: name.into(),
+                    id: pathogen.id.clone(),
+                });
+            }
+        }
+
+        Ok(PathogenCatalog {
+            pathogens: file.pathogens,
+        })
+    }
+
+    pub fn get(&self, id: &str) -> Option<&PathogenDef> {
+        self.pathogens.iter().find(|p| p.id == id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &PathogenDef> {
+        self.pathogens.iter()
+    }
+}
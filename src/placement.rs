@@ -0,0 +1,442 @@
+//! Placing towers: the mouse is snapped to the level grid, a ghost of the selected tower
+//! follows it together with its range, and confirming asks the game to build it there.
+
+use crate::assets::{Assets, Handle};
+use crate::camera::CameraUniforms;
+use crate::input::{Action, Input};
+use crate::level::{Level, TileCoord};
+use crate::pathing::FlowField;
+use crate::render_gl::{buffer, data, Program, Texture};
+use crate::sim::World;
+use crate::tower::{TowerCatalog, TowerDef, Towers};
+use failure;
+use gl;
+use nalgebra as na;
+use std::collections::HashMap;
+
+/// Why the tile under the mouse can or can't take the selected tower.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Validity {
+    Valid,
+    OutsideMap,
+    NotBuildable,
+    Occupied,
+    BlocksPath,
+    TooExpensive,
+    UnknownTower,
+}
+
+impl Validity {
+    pub fn is_valid(self) -> bool {
+        self == Validity::Valid
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum PlacementEvent {
+    /// the player confirmed a valid tile, the game still has the last word
+    Placed {
+        tile: TileCoord,
+        tower: String,
+        cost: u32,
+    },
+    Cancelled,
+}
+
+pub struct PlacementController {
+    selected: Option<TowerDef>,
+    hover: Option<TileCoord>,
+    ghost_position: na::Point2<f32>,
+    validity: Validity,
+}
+
+impl PlacementController {
+    pub fn new() -> PlacementController {
+        PlacementController {
+            selected: None,
+            hover: None,
+            ghost_position: na::Point2::origin(),
+            validity: Validity::OutsideMap,
+        }
+    }
+
+    pub fn select(&mut self, tower: &TowerDef) {
+        self.selected = Some(tower.clone());
+    }
+
+    pub fn cancel(&mut self) {
+        self.selected = None;
+        self.hover = None;
+    }
+
+    pub fn is_placing(&self) -> bool {
+        self.selected.is_some()
+    }
+
+    pub fn selected(&self) -> Option<&TowerDef> {
+        self.selected.as_ref()
+    }
+
+    /// The tile under the mouse, `None` outside the map.
+    pub fn hover(&self) -> Option<TileCoord> {
+        self.hover
+    }
+
+    pub fn validity(&self) -> Validity {
+        self.validity
+    }
+
+    /// Follows the mouse, and on `PlaceTower` reports the selected tower if the tile is
+    /// valid for `gold`. `Cancel` drops the selection. Call after `Input::update_mouse_world`.
+    pub fn update(
+        &mut self,
+        input: &Input,
+        level: &Level,
+        towers: &Towers,
+        world: &World,
+        gold: u32,
+    ) -> Option<PlacementEvent> {
+        let tower = match &self.selected {
+            Some(tower) => tower.clone(),
+            None => return None,
+        };
+
+        if input.pressed(Action::Cancel) {
+            self.cancel();
+            return Some(PlacementEvent::Cancelled);
+        }
+
+        let mouse = input.mouse_world();
+        self.hover = level.world_to_tile(&mouse);
+        self.ghost_position = match self.hover {
+            Some(tile) => level.tile_center(tile),
+            None => mouse,
+        };
+        self.validity = match self.hover {
+            Some(tile) => check_tile(level, towers, world, gold, &tower, tile),
+            None => Validity::OutsideMap,
+        };
+
+        match self.hover {
+            Some(tile) if self.validity.is_valid() && input.pressed(Action::PlaceTower) => {
+                self.cancel();
+                Some(PlacementEvent::Placed {
+                    tile,
+                    tower: tower.id,
+                    cost: tower.cost,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Whether `tower` can go on `tile` right now, the same check `Game::place_tower` makes.
+pub fn check_tile(
+    level: &Level,
+    towers: &Towers,
+    world: &World,
+    gold: u32,
+    tower: &TowerDef,
+    tile: TileCoord,
+) -> Validity {
+    match flow_with_tower(level, towers, world, gold, tower, tile) {
+        Ok(_) => Validity::Valid,
+        Err(validity) => validity,
+    }
+}
+
+/// `check_tile`, with the flow field pathogens follow once the tower stands on a valid tile.
+pub(crate) fn flow_with_tower(
+    level: &Level,
+    towers: &Towers,
+    world: &World,
+    gold: u32,
+    tower: &TowerDef,
+    tile: TileCoord,
+) -> Result<FlowField, Validity> {
+    match level.tile(tile) {
+        None => return Err(Validity::OutsideMap),
+        Some(t) if !t.is_buildable() => return Err(Validity::NotBuildable),
+        _ => {}
+    }
+    if towers.is_occupied(tile) {
+        return Err(Validity::Occupied);
+    }
+    let blocked = |coord| coord == tile || towers.is_occupied(coord);
+    if !level.path_exists(blocked) {
+        return Err(Validity::BlocksPath);
+    }
+    // pathogens already on their way mustn't be walled in either
+    let flow = FlowField::new(level, blocked);
+    if !world.all_reach(&flow) {
+        return Err(Validity::BlocksPath);
+    }
+    if gold < tower.cost {
+        return Err(Validity::TooExpensive);
+    }
+    Ok(flow)
+}
+
+#[derive(VertexAttribPointers, Copy, Clone, Debug)]
+#[repr(C, packed)]
+struct Vertex {
+    #[location = "0"]
+    pos: data::f32_f32_f32,
+    #[location = "1"]
+    tc: data::f16_f16,
+}
+
+const VALID_TINT: (f32, f32, f32, f32) = (1.0, 1.0, 1.0, 0.6);
+const INVALID_TINT: (f32, f32, f32, f32) = (1.0, 0.25, 0.25, 0.6);
+const RANGE_RIM: f32 = 0.04;
+
+/// Draws the ghost and range circle of a `PlacementController`.
+pub struct PlacementRenderer {
+    ghost: Handle<Program>,
+    range: Handle<Program>,
+    textures: HashMap<String, Handle<Texture>>,
+    _vbo: buffer::ArrayBuffer,
+    _ibo: buffer::ElementArrayBuffer,
+    vao: buffer::VertexArray,
+}
+
+impl PlacementRenderer {
+    /// Loads the ghost texture of every tower in `catalog` up front.
+    pub fn new(
+        assets: &mut Assets,
+        gl: &gl::Gl,
+        catalog: &TowerCatalog,
+    ) -> Result<PlacementRenderer, failure::Error> {
+        let ghost = assets.load::<Program>("shaders/ghost")?;
+        ghost.require_uniforms(&["Model", "TexFace", "Tint"])?;
+        ghost.bind_uniform_block("Camera", CameraUniforms::BINDING)?;
+
+        let range = assets.load::<Program>("shaders/range")?;
+        range.require_uniforms(&["Model", "Tint", "Rim"])?;
+        range.bind_uniform_block("Camera", CameraUniforms::BINDING)?;
+
+        let mut textures = HashMap::new();
+        for tower in catalog.iter() {
+            textures.insert(tower.id.clone(), assets.load::<Texture>(&tower.texture)?);
+        }
+
+        let vertices: Vec<Vertex> = vec![
+            Vertex {
+                pos: (-0.5, -0.5, 0.0).into(),
+                tc: (0.0, 1.0).into(),
+            }, // bottom left
+            Vertex {
+                pos: (0.5, -0.5, 0.0).into(),
+                tc: (1.0, 1.0).into(),
+            }, // bottom right
+            Vertex {
+                pos: (0.5, 0.5, 0.0).into(),
+                tc: (1.0, 0.0).into(),
+            }, // top right
+            Vertex {
+                pos: (-0.5, 0.5, 0.0).into(),
+                tc: (0.0, 0.0).into(),
+            }, // top left
+        ];
+        let indices: Vec<gl::types::GLuint> = vec![0, 1, 2, 2, 3, 0];
+
+        let vbo = buffer::ArrayBuffer::new(gl);
+        vbo.bind();
+        vbo.static_draw_data(&vertices);
+
+        let ibo = buffer::ElementArrayBuffer::new(gl);
+        ibo.bind();
+        ibo.static_draw_data(&indices);
+
+        let vao = buffer::VertexArray::new(gl);
+        vao.bind();
+        vbo.bind();
+        ibo.bind();
+        Vertex::vertex_attrib_pointers(gl);
+        vao.unbind();
+
+        Ok(PlacementRenderer {
+            ghost,
+            range,
+            textures,
+            _vbo: vbo,
+            _ibo: ibo,
+            vao,
+        })
+    }
+
+    pub fn render(&self, gl: &gl::Gl, controller: &PlacementController, level: &Level) {
+        let tower = match controller.selected() {
+            Some(tower) => tower,
+            None => return,
+        };
+
+        let tint = if controller.validity().is_valid() {
+            VALID_TINT
+        } else {
+            INVALID_TINT
+        };
+        let tint = na::Vector4::new(tint.0, tint.1, tint.2, tint.3);
+        let position = controller.ghost_position;
+        let model = |size: f32| {
+            na::Translation3::new(position.x, position.y, 0.0).to_homogeneous()
+                * na::Matrix4::new_scaling(size)
+        };
+
+        unsafe {
+            gl.Enable(gl::BLEND);
+            gl.BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        }
+        self.vao.bind();
+
+        self.range.set_used();
+        let _ = self
+            .range
+            .set_uniform("Model", &model(tower.range * 2.0 * level.tile_size));
+        let _ = self.range.set_uniform("Tint", &tint);
+        let _ = self.range.set_uniform("Rim", &RANGE_RIM);
+        self.draw_quad(gl);
+
+        if let Some(texture) = self.textures.get(&tower.id) {
+            self.ghost.set_used();
+            texture.bind_at(0);
+            let _ = self.ghost.set_uniform("Model", &model(level.tile_size));
+            let _ = self.ghost.set_uniform("TexFace", &0);
+            let _ = self.ghost.set_uniform("Tint", &tint);
+            self.draw_quad(gl);
+        }
+
+        self.vao.unbind();
+        unsafe {
+            gl.Disable(gl::BLEND);
+        }
+    }
+
+    fn draw_quad(&self, gl: &gl::Gl) {
+        unsafe {
+            gl.DrawElements(gl::TRIANGLES, 6, gl::UNSIGNED_INT, std::ptr::null());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::Enemy;
+    use crate::tower::PlacedTower;
+
+    // a corridor from the spawn to the heart with a dead end below it
+    const LEVEL: &str = r#"
+        name = "Test"
+        starting_gold = 100
+        heart_health = 10
+        map = """
+######
+S....H
+##.###
+##.###
+######
+"""
+    "#;
+
+    const TOWERS: &str = r#"
+        [[towers]]
+        id = "macrophage"
+        name = "Macrophage"
+        cost = 50
+        range = 2.5
+        damage = 14.0
+        fire_interval = 0.8
+        projectile_speed = 6.0
+        texture = "textures/test.png"
+    "#;
+
+    struct Setup {
+        level: Level,
+        tower: TowerDef,
+        towers: Towers,
+        world: World,
+    }
+
+    impl Setup {
+        fn new() -> Setup {
+            let level = Level::from_toml_str("test", LEVEL).unwrap();
+            let catalog = TowerCatalog::from_toml_str("test", TOWERS).unwrap();
+            let world = World::new(&level);
+            Setup {
+                tower: catalog.get("macrophage").unwrap().clone(),
+                towers: Towers::new(),
+                world,
+                level,
+            }
+        }
+
+        fn check(&self, gold: u32, col: usize, row: usize) -> Validity {
+            let tile = TileCoord::new(col, row);
+            check_tile(
+                &self.level,
+                &self.towers,
+                &self.world,
+                gold,
+                &self.tower,
+                tile,
+            )
+        }
+    }
+
+    #[test]
+    fn free_buildable_tile_is_valid() {
+        assert_eq!(Setup::new().check(50, 2, 3), Validity::Valid);
+    }
+
+    #[test]
+    fn tiles_off_the_map_are_outside() {
+        let setup = Setup::new();
+        assert_eq!(setup.check(50, 6, 1), Validity::OutsideMap);
+        assert_eq!(setup.check(50, 0, 5), Validity::OutsideMap);
+        assert_eq!(setup.check(50, 0, 0), Validity::NotBuildable);
+    }
+
+    #[test]
+    fn tiles_with_a_tower_are_occupied() {
+        let mut setup = Setup::new();
+        let placed = PlacedTower::new(&setup.tower);
+        setup.towers.place(TileCoord::new(2, 3), placed);
+        assert_eq!(setup.check(50, 2, 3), Validity::Occupied);
+    }
+
+    #[test]
+    fn walling_off_the_heart_blocks_the_path() {
+        assert_eq!(Setup::new().check(50, 3, 1), Validity::BlocksPath);
+    }
+
+    #[test]
+    fn walling_in_a_pathogen_blocks_the_path() {
+        let mut setup = Setup::new();
+        assert_eq!(setup.check(50, 2, 2), Validity::Valid);
+
+        let tile = TileCoord::new(2, 3);
+        setup.world.enemies.push(Enemy {
+            id: 0,
+            pathogen: "virus".into(),
+            health: 10.0,
+            max_health: 10.0,
+            speed: 1.0,
+            reward: 1,
+            damage: 1,
+            position: setup.level.tile_center(tile),
+            tile,
+            target: None,
+        });
+        assert_eq!(setup.check(50, 2, 2), Validity::BlocksPath);
+    }
+
+    #[test]
+    fn too_little_gold_is_too_expensive() {
+        let setup = Setup::new();
+        assert_eq!(setup.check(49, 2, 3), Validity::TooExpensive);
+        // where the tower can't go at all, that is the reason given
+        assert_eq!(setup.check(49, 3, 1), Validity::BlocksPath);
+    }
+}
@@ -0,0 +1,541 @@
+//! The player's progress across matches: unlocked levels, best stars and score per level,
+//! lifetime statistics and achievements.
+//!
+//! The profile is `profile.toml` in `storage::user_dir`. It carries a checksum of its
+//! contents, so a damaged file is noticed instead of silently resetting progress. Before a
+//! good profile is replaced it is copied to `profile.toml.bak`, which is what a damaged
+//! profile is recovered from.
+
+use crate::campaign::{Campaign, Goal};
+use crate::game::GameEvent;
+use crate::hash::StateHasher;
+use crate::rng::hex_u64;
+use crate::storage;
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+use std::fs;
+use std::hash::Hasher;
+use std::io;
+use std::path::{Path, PathBuf};
+use toml;
+
+/// Format version written by this build.
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to access profile {}", path)]
+    Io {
+        path: String,
+        #[cause]
+        inner: io::Error,
+    },
+    #[fail(display = "Failed to parse profile {}", path)]
+    Parse {
+        path: String,
+        #[cause]
+        inner: toml::de::Error,
+    },
+    #[fail(display = "Failed to write profile {}", path)]
+    Serialize {
+        path: String,
+        #[cause]
+        inner: toml::ser::Error,
+    },
+    #[fail(display = "Profile {} is damaged, its checksum doesn't match", path)]
+    Corrupt { path: String },
+    #[fail(
+        display = "Profile {} is from a newer version of the game (format {}, this game reads up to {})",
+        path, version, supported
+    )]
+    TooNew {
+        path: String,
+        version: u32,
+        supported: u32,
+    },
+    #[fail(display = "Failed to find the profile directory")]
+    Storage {
+        #[cause]
+        inner: storage::Error,
+    },
+}
+
+/// The player's best at one level.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct LevelRecord {
+    pub best_stars: u32,
+    pub best_score: u32,
+    pub played: u32,
+    pub won: u32,
+}
+
+/// Totals over every finished match.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    pub levels_played: u64,
+    pub levels_won: u64,
+    /// by pathogen id
+    #[serde(default)]
+    pub kills: BTreeMap<String, u64>,
+    /// by tower id
+    #[serde(default)]
+    pub towers_built: BTreeMap<String, u64>,
+}
+
+impl Stats {
+    pub fn total_kills(&self) -> u64 {
+        self.kills.values().sum()
+    }
+
+    pub fn total_towers_built(&self) -> u64 {
+        self.towers_built.values().sum()
+    }
+}
+
+/// What happened in one match, collected from its events while it is played.
+#[derive(Clone, Debug, Default)]
+pub struct MatchStats {
+    pub won: bool,
+    pub heart_health: u32,
+    pub max_heart_health: u32,
+    pub score: u32,
+    pub kills: BTreeMap<String, u64>,
+    pub towers_built: BTreeMap<String, u64>,
+}
+
+impl MatchStats {
+    pub fn new() -> MatchStats {
+        MatchStats::default()
+    }
+
+    pub fn handle_event(&mut self, event: &GameEvent) {
+        match event {
+            GameEvent::HeartHealthChanged { health, max } => {
+                self.heart_health = *health;
+                self.max_heart_health = *max;
+            }
+            GameEvent::ScoreChanged { score } => self.score = *score,
+            GameEvent::PathogenKilled { pathogen, .. } => {
+                *self.kills.entry(pathogen.clone()).or_insert(0) += 1;
+            }
+            GameEvent::TowerPlaced { tower, .. } => {
+                *self.towers_built.entry(tower.clone()).or_insert(0) += 1;
+            }
+            GameEvent::LevelWon => self.won = true,
+            _ => {}
+        }
+    }
+}
+
+/// Plain values come before tables, TOML can't have them after.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    /// resource names of the levels the player may start
+    #[serde(default)]
+    pub unlocked: BTreeSet<String>,
+    /// ids of the achievements earned
+    #[serde(default)]
+    pub achievements: BTreeSet<String>,
+    #[serde(default)]
+    pub stats: Stats,
+    /// by level resource name
+    #[serde(default)]
+    pub levels: BTreeMap<String, LevelRecord>,
+}
+
+/// The file around a profile. The checksum covers the profile as this game writes it, so
+/// anything changed outside the game fails the check as well.
+#[derive(Serialize, Deserialize)]
+struct ProfileFile {
+    version: u32,
+    #[serde(with = "hex_u64")]
+    checksum: u64,
+    profile: Profile,
+}
+
+impl Profile {
+    /// A fresh profile with the first level of `campaign` unlocked.
+    pub fn new(campaign: &Campaign) -> Profile {
+        let mut profile = Profile::default();
+        profile.unlocked.insert(campaign.first().level.clone());
+        profile
+    }
+
+    pub fn is_unlocked(&self, level: &str) -> bool {
+        self.unlocked.contains(level)
+    }
+
+    pub fn record(&self, level: &str) -> Option<&LevelRecord> {
+        self.levels.get(level)
+    }
+
+    /// Best stars of every level added up.
+    pub fn total_stars(&self) -> u32 {
+        self.levels.values().map(|r| r.best_stars).sum()
+    }
+
+    /// Adds a finished match of `level`: keeps its stars and score if they are the best
+    /// yet, unlocks the next level if it was won and updates the statistics. Returns the
+    /// ids of achievements earned by it.
+    pub fn finish_level(
+        &mut self,
+        campaign: &Campaign,
+        level: &str,
+        result: &MatchStats,
+    ) -> Vec<String> {
+        let stars = match campaign.get(level) {
+            Some(campaign_level) if result.won => {
+                campaign_level.stars_for(result.heart_health, result.max_heart_health)
+            }
+            _ => 0,
+        };
+
+        let record = self.levels.entry(level.to_string()).or_default();
+        record.played += 1;
+        if result.won {
+            record.won += 1;
+        }
+        record.best_stars = record.best_stars.max(stars);
+        record.best_score = record.best_score.max(result.score);
+
+        self.stats.levels_played += 1;
+        if result.won {
+            self.stats.levels_won += 1;
+            if let Some(next) = campaign.next_after(level) {
+                self.unlocked.insert(next.level.clone());
+            }
+        }
+        for (pathogen, count) in &result.kills {
+            *self.stats.kills.entry(pathogen.clone()).or_insert(0) += count;
+        }
+        for (tower, count) in &result.towers_built {
+            *self.stats.towers_built.entry(tower.clone()).or_insert(0) += count;
+        }
+
+        let earned: Vec<String> = campaign
+            .achievements()
+            .filter(|a| !self.achievements.contains(&a.id) && self.reached(&a.goal))
+            .map(|a| a.id.clone())
+            .collect();
+        self.achievements.extend(earned.iter().cloned());
+        earned
+    }
+
+    fn reached(&self, goal: &Goal) -> bool {
+        let stats = &self.stats;
+        match goal {
+            Goal::Kills {
+                pathogen: Some(pathogen),
+                count,
+            } => stats.kills.get(pathogen).cloned().unwrap_or(0) >= *count,
+            Goal::Kills {
+                pathogen: None,
+                count,
+            } => stats.total_kills() >= *count,
+            Goal::TowersBuilt {
+                tower: Some(tower),
+                count,
+            } => stats.towers_built.get(tower).cloned().unwrap_or(0) >= *count,
+            Goal::TowersBuilt { tower: None, count } => stats.total_towers_built() >= *count,
+            Goal::LevelsWon { count } => stats.levels_won >= *count,
+            Goal::Stars { count } => self.total_stars() >= *count,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Profile, Error> {
+        let text = fs::read_to_string(path).map_err(|e| Error::Io {
+            path: path.display().to_string(),
+            inner: e,
+        })?;
+
+        Profile::from_toml_str(&path.display().to_string(), &text)
+    }
+
+    /// Reads a profile, checking its version and checksum.
+    pub fn from_toml_str(path: &str, text: &str) -> Result<Profile, Error> {
+        let parse_error = |e| Error::Parse {
+            path: path.into(),
+            inner: e,
+        };
+        let table: toml::value::Table = toml::from_str(text).map_err(parse_error)?;
+
+        // checked before the rest, a newer format may not read as this one
+        if let Some(version) = table.get("version").and_then(toml::Value::as_integer) {
+            if version > i64::from(VERSION) {
+                return Err(Error::TooNew {
+                    path: path.into(),
+                    version: u32::try_from(version).unwrap_or(u32::MAX),
+                    supported: VERSION,
+                });
+            }
+        }
+        let file: ProfileFile = toml::Value::Table(table).try_into().map_err(parse_error)?;
+        match checksum(&file.profile) {
+            Ok(checksum) if checksum == file.checksum => Ok(file.profile),
+            _ => Err(Error::Corrupt { path: path.into() }),
+        }
+    }
+
+    pub fn to_toml_string(&self, path: &str) -> Result<String, Error> {
+        let serialize_error = |e| Error::Serialize {
+            path: path.into(),
+            inner: e,
+        };
+        let file = ProfileFile {
+            version: VERSION,
+            checksum: checksum(self).map_err(serialize_error)?,
+            profile: self.clone(),
+        };
+
+        toml::to_string(&file).map_err(serialize_error)
+    }
+
+    /// Writes the profile atomically. A profile already at `path` that reads fine is kept
+    /// as the backup first, a damaged one never replaces the backup.
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let io_error = |e| Error::Io {
+            path: path.display().to_string(),
+            inner: e,
+        };
+        let text = self.to_toml_string(&path.display().to_string())?;
+
+        if Profile::load(path).is_ok() {
+            let backup = fs::read(path).map_err(io_error)?;
+            storage::write_atomic(&backup_path(path), &backup).map_err(io_error)?;
+        }
+        storage::write_atomic(path, text.as_bytes()).map_err(io_error)
+    }
+
+    /// The profile at `path` or, if that is damaged, its backup. A damaged profile is
+    /// moved aside to the first free one of `<name>.corrupt`, `<name>.corrupt.1` and so on,
+    /// so earlier damaged profiles are kept as well. Without a usable
+    /// profile a new one is started. A profile from a newer version of the game isn't
+    /// damaged, it is left alone and the new one must not be saved over it.
+    pub fn load_or_recover(path: &Path, campaign: &Campaign) -> Recovered {
+        let mut warnings = Vec::new();
+        let mut writable = true;
+
+        let profile = match Profile::load(path) {
+            Ok(profile) => Some(profile),
+            Err(Error::Io { ref inner, .. }) if inner.kind() == io::ErrorKind::NotFound => None,
+            Err(e @ Error::TooNew { .. }) => {
+                warnings.push(format!("{}, progress made now won't be saved", e));
+                writable = false;
+                None
+            }
+            Err(e) => {
+                warnings.push(e.to_string());
+                if let Err(e) = fs::rename(path, corrupt_path(path)) {
+                    warnings.push(format!("Failed to move the damaged profile aside: {}", e));
+                }
+
+                match Profile::load(&backup_path(path)) {
+                    Ok(profile) => {
+                        warnings.push("Restored the profile from its backup".into());
+                        Some(profile)
+                    }
+                    Err(e) => {
+                        warnings.push(format!("No usable backup: {}", e));
+                        None
+                    }
+                }
+            }
+        };
+
+        let mut profile = profile.unwrap_or_else(|| Profile::new(campaign));
+        // levels added to the campaign since don't lock out the start
+        profile.unlocked.insert(campaign.first().level.clone());
+        Recovered {
+            profile,
+            warnings,
+            writable,
+        }
+    }
+}
+
+/// What `Profile::load_or_recover` found.
+#[derive(Clone, Debug)]
+pub struct Recovered {
+    pub profile: Profile,
+    /// what went wrong, for the player to see
+    pub warnings: Vec<String>,
+    /// false when saving `profile` would overwrite progress this game can't read
+    pub writable: bool,
+}
+
+/// Where the player's profile lives.
+pub fn default_path() -> Result<PathBuf, Error> {
+    let dir = storage::user_dir().map_err(|e| Error::Storage { inner: e })?;
+    Ok(dir.join("profile.toml"))
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    let mut backup = path.as_os_str().to_os_string();
+    backup.push(".bak");
+    PathBuf::from(backup)
+}
+
+/// The first of `<path>.corrupt`, `<path>.corrupt.1`, ... that doesn't exist yet.
+fn corrupt_path(path: &Path) -> PathBuf {
+    let mut corrupt = path.as_os_str().to_os_string();
+    corrupt.push(".corrupt");
+    let corrupt = PathBuf::from(corrupt);
+
+    let mut candidate = corrupt.clone();
+    let mut n = 1;
+    while candidate.exists() {
+        let mut numbered = corrupt.as_os_str().to_os_string();
+        numbered.push(format!(".{}", n));
+        candidate = PathBuf::from(numbered);
+        n += 1;
+    }
+    candidate
+}
+
+/// Hash of the profile as TOML.
+fn checksum(profile: &Profile) -> Result<u64, toml::ser::Error> {
+    let text = toml::to_string(profile)?;
+    let mut hasher = StateHasher::new();
+    hasher.write(text.as_bytes());
+    Ok(hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn campaign() -> Campaign {
+        Campaign::from_toml_str("campaign.toml", include_str!("../res/campaign.toml")).unwrap()
+    }
+
+    const TWO_LEVELS: &str = r#"
+        [[levels]]
+        level = "a"
+
+        [[levels]]
+        level = "b"
+
+        [[achievements]]
+        id = "first_blood"
+        name = "First Blood"
+        description = "Kill a pathogen"
+        goal = { type = "Kills", count = 1 }
+
+        [[achievements]]
+        id = "winner"
+        name = "Winner"
+        description = "Win a level"
+        goal = { type = "LevelsWon", count = 1 }
+    "#;
+
+    fn finished(won: bool, heart_health: u32, score: u32) -> MatchStats {
+        MatchStats {
+            won,
+            heart_health,
+            max_heart_health: 10,
+            score,
+            ..MatchStats::default()
+        }
+    }
+
+    #[test]
+    fn winning_unlocks_the_next_level() {
+        let campaign = Campaign::from_toml_str("campaign.toml", TWO_LEVELS).unwrap();
+        let mut profile = Profile::new(&campaign);
+        assert!(profile.is_unlocked("a") && !profile.is_unlocked("b"));
+
+        profile.finish_level(&campaign, "a", &finished(false, 0, 10));
+        assert!(!profile.is_unlocked("b"));
+
+        profile.finish_level(&campaign, "a", &finished(true, 1, 10));
+        assert!(profile.is_unlocked("b"));
+        // winning the last level unlocks nothing more
+        profile.finish_level(&campaign, "b", &finished(true, 1, 10));
+        assert_eq!(profile.unlocked.len(), 2);
+    }
+
+    #[test]
+    fn best_stars_and_score_are_kept() {
+        let campaign = Campaign::from_toml_str("campaign.toml", TWO_LEVELS).unwrap();
+        let mut profile = Profile::new(&campaign);
+
+        profile.finish_level(&campaign, "a", &finished(true, 10, 100));
+        profile.finish_level(&campaign, "a", &finished(true, 5, 500));
+        profile.finish_level(&campaign, "a", &finished(false, 0, 50));
+
+        let record = profile.record("a").unwrap();
+        assert_eq!((record.best_stars, record.best_score), (3, 500));
+        assert_eq!((record.played, record.won), (3, 2));
+        assert_eq!(profile.total_stars(), 3);
+    }
+
+    #[test]
+    fn achievements_are_earned_once() {
+        let campaign = Campaign::from_toml_str("campaign.toml", TWO_LEVELS).unwrap();
+        let mut profile = Profile::new(&campaign);
+        let mut stats = finished(false, 0, 0);
+        stats.kills.insert("virus".into(), 2);
+
+        assert_eq!(
+            profile.finish_level(&campaign, "a", &stats),
+            ["first_blood"]
+        );
+        stats.won = true;
+        assert_eq!(profile.finish_level(&campaign, "a", &stats), ["winner"]);
+        assert!(profile.finish_level(&campaign, "a", &stats).is_empty());
+        assert_eq!(profile.achievements.len(), 2);
+        assert_eq!(profile.stats.kills["virus"], 6);
+    }
+
+    #[test]
+    fn newer_profile_is_left_alone() {
+        let dir = env::temp_dir().join("tow_def_game_newer_profile");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("profile.toml");
+        let text = "version = 2\nchecksum = \"0\"\n\n[profile]\nfuture = true\n";
+        fs::write(&path, text).unwrap();
+
+        let recovered = Profile::load_or_recover(&path, &campaign());
+        assert!(!recovered.writable);
+        assert_eq!(recovered.warnings.len(), 1);
+        assert_eq!(fs::read_to_string(&path).unwrap(), text);
+        assert!(!dir.join("profile.toml.corrupt").exists());
+    }
+
+    #[test]
+    fn damaged_profile_is_recovered_from_its_backup() {
+        let dir = env::temp_dir().join("tow_def_game_damaged_profile");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("profile.toml");
+        let campaign = campaign();
+
+        let mut profile = Profile::new(&campaign);
+        profile.stats.levels_played = 3;
+        profile.save(&path).unwrap();
+        profile.stats.levels_played = 4;
+        profile.save(&path).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        fs::write(
+            &path,
+            text.replace("levels_played = 4", "levels_played = 40"),
+        )
+        .unwrap();
+
+        let recovered = Profile::load_or_recover(&path, &campaign);
+        assert!(recovered.writable);
+        assert_eq!(recovered.profile.stats.levels_played, 3);
+        assert!(dir.join("profile.toml.corrupt").exists());
+
+        // a second damaged profile doesn't replace the first one moved aside
+        fs::write(&path, "damaged").unwrap();
+        Profile::load_or_recover(&path, &campaign);
+        assert!(dir.join("profile.toml.corrupt").exists());
+        assert_eq!(
+            fs::read_to_string(dir.join("profile.toml.corrupt.1")).unwrap(),
+            "damaged"
+        );
+    }
+}
@@ -14,6 +14,11 @@ impl BufferType for BufferTypeElementArray {
     const BUFFER_TYPE: gl::types::GLuint = gl::ELEMENT_ARRAY_BUFFER;
 }
 
+pub struct BufferTypeUniform;
+impl BufferType for BufferTypeUniform {
+    const BUFFER_TYPE: gl::types::GLuint = gl::UNIFORM_BUFFER;
+}
+
 pub struct Buffer<B>
 where
     B: BufferType,
@@ -160,6 +165,17 @@ where
 
 pub type ArrayBuffer = Buffer<BufferTypeArray>;
 pub type ElementArrayBuffer = Buffer<BufferTypeElementArray>;
+pub type UniformBuffer = Buffer<BufferTypeUniform>;
+
+impl UniformBuffer {
+    /// Binds the whole buffer to uniform block binding point `binding`,
+    /// see `Program::bind_uniform_block`.
+    pub fn bind_base(&self, binding: u32) {
+        unsafe {
+            self.gl.BindBufferBase(gl::UNIFORM_BUFFER, binding, self.vbo);
+        }
+    }
+}
 
 pub struct VertexArray {
     gl: gl::Gl,
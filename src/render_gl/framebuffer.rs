@@ -0,0 +1,139 @@
+use gl;
+use image;
+use std::os::raw;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Framebuffer is incomplete, status 0x{:x}", status)]
+    Incomplete { status: gl::types::GLenum },
+}
+
+/// An offscreen RGBA8 render target with a depth/stencil attachment.
+pub struct Framebuffer {
+    gl: gl::Gl,
+    fbo: gl::types::GLuint,
+    color: gl::types::GLuint,
+    depth_stencil: gl::types::GLuint,
+    width: i32,
+    height: i32,
+}
+
+impl Framebuffer {
+    pub fn new(gl: &gl::Gl, width: i32, height: i32) -> Result<Framebuffer, Error> {
+        let mut fbo: gl::types::GLuint = 0;
+        let mut renderbuffers: [gl::types::GLuint; 2] = [0; 2];
+        unsafe {
+            gl.GenFramebuffers(1, &mut fbo);
+            gl.GenRenderbuffers(2, renderbuffers.as_mut_ptr());
+        }
+
+        let mut framebuffer = Framebuffer {
+            gl: gl.clone(),
+            fbo,
+            color: renderbuffers[0],
+            depth_stencil: renderbuffers[1],
+            width: 0,
+            height: 0,
+        };
+
+        framebuffer.resize(width, height)?;
+
+        Ok(framebuffer)
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    /// Reallocates the attachments, needed whenever the size of whatever this mirrors changes.
+    pub fn resize(&mut self, width: i32, height: i32) -> Result<(), Error> {
+        let gl = &self.gl;
+
+        unsafe {
+            gl.BindRenderbuffer(gl::RENDERBUFFER, self.color);
+            gl.RenderbufferStorage(gl::RENDERBUFFER, gl::RGBA8, width, height);
+            gl.BindRenderbuffer(gl::RENDERBUFFER, self.depth_stencil);
+            gl.RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH24_STENCIL8, width, height);
+            gl.BindRenderbuffer(gl::RENDERBUFFER, 0);
+
+            gl.BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
+            gl.FramebufferRenderbuffer(
+                gl::FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                gl::RENDERBUFFER,
+                self.color,
+            );
+            gl.FramebufferRenderbuffer(
+                gl::FRAMEBUFFER,
+                gl::DEPTH_STENCIL_ATTACHMENT,
+                gl::RENDERBUFFER,
+                self.depth_stencil,
+            );
+        }
+
+        let status = unsafe { gl.CheckFramebufferStatus(gl::FRAMEBUFFER) };
+        unsafe {
+            gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+
+        if status != gl::FRAMEBUFFER_COMPLETE {
+            return Err(Error::Incomplete { status });
+        }
+
+        self.width = width;
+        self.height = height;
+        Ok(())
+    }
+
+    pub fn bind(&self) {
+        unsafe {
+            self.gl.BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
+        }
+    }
+
+    pub fn unbind(&self) {
+        unsafe {
+            self.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+    }
+
+    /// Reads the color attachment back, flipped so that row 0 is the top of the image.
+    pub fn read_pixels(&self) -> image::RgbaImage {
+        let mut pixels: Vec<u8> = vec![0; (self.width * self.height * 4) as usize];
+
+        self.bind();
+        unsafe {
+            self.gl.PixelStorei(gl::PACK_ALIGNMENT, 1);
+            self.gl.ReadPixels(
+                0,
+                0,
+                self.width,
+                self.height,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                pixels.as_mut_ptr() as *mut raw::c_void,
+            );
+        }
+        self.unbind();
+
+        let img = image::RgbaImage::from_raw(self.width as u32, self.height as u32, pixels)
+            .expect("expected pixel buffer to match framebuffer size");
+
+        // GL rows start at the bottom
+        image::imageops::flip_vertical(&img)
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        let renderbuffers = [self.color, self.depth_stencil];
+        unsafe {
+            self.gl.DeleteRenderbuffers(2, renderbuffers.as_ptr());
+            self.gl.DeleteFramebuffers(1, &self.fbo);
+        }
+    }
+}
@@ -0,0 +1,276 @@
+//! Offscreen rendering for screenshot tests.
+//!
+//! The GL context lives on a hidden SDL window and everything is drawn into a `Framebuffer`,
+//! so nothing depends on the window ever being mapped. On machines without a GPU, run with
+//! `LIBGL_ALWAYS_SOFTWARE=1` to get Mesa's llvmpipe, and either `SDL_VIDEODRIVER=offscreen`
+//! (SDL 2.0.16+, EGL) or under `xvfb-run` when no display server is available.
+
+use crate::render_gl::{framebuffer::Framebuffer, Viewport};
+use failure::err_msg;
+use gl;
+use image;
+use std::env;
+use std::path::Path;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(
+        display = "Image is {}x{} but golden image {} is {}x{}",
+        width, height, name, golden_width, golden_height
+    )]
+    SizeMismatch {
+        name: String,
+        width: u32,
+        height: u32,
+        golden_width: u32,
+        golden_height: u32,
+    },
+    #[fail(
+        display = "{} of {} pixels differ from golden image {} (max channel difference {}), actual image written to {}",
+        mismatched, total, name, max_difference, actual_path
+    )]
+    GoldenMismatch {
+        name: String,
+        mismatched: usize,
+        total: usize,
+        max_difference: u8,
+        actual_path: String,
+    },
+    #[fail(
+        display = "Golden image {} doesn't exist, run with UPDATE_GOLDEN=1 to create it",
+        name
+    )]
+    MissingGolden { name: String },
+}
+
+pub struct HeadlessContext {
+    gl: gl::Gl,
+    framebuffer: Framebuffer,
+    viewport: Viewport,
+    _gl_context: sdl2::video::GLContext,
+    _window: sdl2::video::Window,
+    _vid_sub: sdl2::VideoSubsystem,
+    _sdl: sdl2::Sdl,
+}
+
+impl HeadlessContext {
+    /// Creates a GL 4.1 core context on a hidden window, with a `width` x `height` framebuffer
+    /// bound as the render target.
+    pub fn new(width: i32, height: i32) -> Result<HeadlessContext, failure::Error> {
+        let sdl = sdl2::init().map_err(err_msg)?;
+        let vid_sub = sdl.video().map_err(err_msg)?;
+
+        let gl_attr = vid_sub.gl_attr();
+        gl_attr.set_context_profile(sdl2::video::GLProfile::Core);
+        gl_attr.set_context_version(4, 1);
+
+        let window = vid_sub
+            .window("headless", width as u32, height as u32)
+            .opengl()
+            .hidden()
+            .build()?;
+
+        let gl_context = window.gl_create_context().map_err(err_msg)?;
+        let gl =
+            gl::Gl::load_with(|s| vid_sub.gl_get_proc_address(s) as *const std::os::raw::c_void);
+
+        let framebuffer = Framebuffer::new(&gl, width, height)?;
+        let viewport = Viewport::for_window(width, height);
+
+        Ok(HeadlessContext {
+            gl,
+            framebuffer,
+            viewport,
+            _gl_context: gl_context,
+            _window: window,
+            _vid_sub: vid_sub,
+            _sdl: sdl,
+        })
+    }
+
+    pub fn gl(&self) -> &gl::Gl {
+        &self.gl
+    }
+
+    /// Calls `render_frame` with the frame index `frames` times, then reads the result back.
+    pub fn render_frames<F>(&self, frames: u32, mut render_frame: F) -> image::RgbaImage
+    where
+        F: FnMut(&gl::Gl, u32),
+    {
+        self.framebuffer.bind();
+        self.viewport.set_used(&self.gl);
+
+        for frame in 0..frames {
+            render_frame(&self.gl, frame);
+        }
+
+        unsafe {
+            self.gl.Finish();
+        }
+        self.framebuffer.read_pixels()
+    }
+}
+
+/// Result of comparing two images channel by channel.
+#[derive(Clone, Debug)]
+pub struct ImageDiff {
+    pub mismatched: usize,
+    pub total: usize,
+    pub max_difference: u8,
+}
+
+/// Counts the pixels where any channel differs by more than `tolerance`.
+/// Both images must be the same size.
+pub fn compare_images(
+    actual: &image::RgbaImage,
+    expected: &image::RgbaImage,
+    tolerance: u8,
+) -> ImageDiff {
+    let mut diff = ImageDiff {
+        mismatched: 0,
+        total: (actual.width() * actual.height()) as usize,
+        max_difference: 0,
+    };
+
+    for (a, e) in actual.pixels().zip(expected.pixels()) {
+        let difference = a
+            .data
+            .iter()
+            .zip(e.data.iter())
+            .map(|(a, e)| (i16::from(*a) - i16::from(*e)).abs() as u8)
+            .max()
+            .unwrap_or(0);
+
+        diff.max_difference = diff.max_difference.max(difference);
+        if difference > tolerance {
+            diff.mismatched += 1;
+        }
+    }
+
+    diff
+}
+
+/// Compares `actual` with the PNG at `golden_path`, allowing `max_mismatched` pixels to be off
+/// by more than `tolerance`. When `UPDATE_GOLDEN` is set, `actual` is written as the new golden
+/// image instead, a golden image that doesn't exist otherwise fails. On mismatch the actual
+/// image is written next to the golden one as `<name>.actual.png`.
+pub fn assert_matches_golden(
+    actual: &image::RgbaImage,
+    golden_path: &Path,
+    tolerance: u8,
+    max_mismatched: usize,
+) -> Result<(), failure::Error> {
+    if env::var("UPDATE_GOLDEN").is_ok() {
+        actual.save(golden_path)?;
+        return Ok(());
+    }
+
+    let name = golden_path.display().to_string();
+    if !golden_path.exists() {
+        return Err(Error::MissingGolden { name }.into());
+    }
+    let expected = image::open(golden_path)?.to_rgba();
+
+    if expected.dimensions() != actual.dimensions() {
+        return Err(Error::SizeMismatch {
+            name,
+            width: actual.width(),
+            height: actual.height(),
+            golden_width: expected.width(),
+            golden_height: expected.height(),
+        }
+        .into());
+    }
+
+    let diff = compare_images(actual, &expected, tolerance);
+    if diff.mismatched <= max_mismatched {
+        return Ok(());
+    }
+
+    let actual_path = golden_path.with_extension("actual.png");
+    actual.save(&actual_path)?;
+
+    Err(Error::GoldenMismatch {
+        name,
+        mismatched: diff.mismatched,
+        total: diff.total,
+        max_difference: diff.max_difference,
+        actual_path: actual_path.display().to_string(),
+    }
+    .into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn golden(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("golden")
+            .join(name)
+    }
+
+    /// Blue with a red bottom left quadrant, what `quadrants` draws.
+    fn quadrants_image(size: u32) -> image::RgbaImage {
+        image::RgbaImage::from_fn(size, size, |x, y| {
+            if x < size / 2 && y >= size / 2 {
+                image::Rgba([255, 0, 0, 255])
+            } else {
+                image::Rgba([0, 0, 255, 255])
+            }
+        })
+    }
+
+    #[test]
+    fn compare_images_counts_pixels_beyond_tolerance() {
+        let expected = quadrants_image(4);
+        let mut actual = expected.clone();
+        actual.put_pixel(0, 0, image::Rgba([0, 0, 250, 255]));
+        actual.put_pixel(1, 0, image::Rgba([0, 20, 255, 255]));
+
+        let diff = compare_images(&actual, &expected, 5);
+        assert_eq!(diff.mismatched, 1);
+        assert_eq!(diff.total, 16);
+        assert_eq!(diff.max_difference, 20);
+    }
+
+    #[test]
+    fn missing_golden_fails_without_writing_it() {
+        let path = env::temp_dir().join("tow_def_game_missing_golden.png");
+        let _ = std::fs::remove_file(&path);
+
+        let error = assert_matches_golden(&quadrants_image(4), &path, 0, 0).unwrap_err();
+        match error.downcast::<Error>() {
+            Ok(Error::MissingGolden { .. }) => {}
+            other => panic!("expected MissingGolden, got {:?}", other),
+        }
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn golden_fixture_matches_its_reference() {
+        assert_matches_golden(&quadrants_image(64), &golden("quadrants.png"), 0, 0).unwrap();
+    }
+
+    /// Needs a GL context, e.g. `LIBGL_ALWAYS_SOFTWARE=1 xvfb-run cargo test -- --ignored`.
+    #[test]
+    #[ignore]
+    fn quadrants_render_matches_golden() {
+        let context = HeadlessContext::new(64, 64).unwrap();
+        let img = context.render_frames(1, |gl, _| unsafe {
+            gl.Disable(gl::SCISSOR_TEST);
+            gl.ClearColor(0.0, 0.0, 1.0, 1.0);
+            gl.Clear(gl::COLOR_BUFFER_BIT);
+            // GL rows start at the bottom, so this is the bottom left quadrant of the image
+            gl.Enable(gl::SCISSOR_TEST);
+            gl.Scissor(0, 0, 32, 32);
+            gl.ClearColor(1.0, 0.0, 0.0, 1.0);
+            gl.Clear(gl::COLOR_BUFFER_BIT);
+            gl.Disable(gl::SCISSOR_TEST);
+        });
+
+        assert_matches_golden(&img, &golden("quadrants.png"), 0, 0).unwrap();
+    }
+}
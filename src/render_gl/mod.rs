@@ -1,13 +1,28 @@
 pub mod buffer;
 mod color_buffer;
 pub mod data;
+pub mod framebuffer;
+pub mod headless;
 mod shader;
+pub mod sprite_batch;
+pub mod text;
 pub mod texture;
+pub mod uniform;
 mod viewport;
 
 pub use self::{
     color_buffer::ColorBuffer,
-    shader::{Error, Program, Shader},
-    texture::{Texture, TextureLoadBuilder, TextureLoadOptions},
+    framebuffer::Framebuffer,
+    shader::{
+        ActiveVariable, Error, PreprocessedSource, Program, ProgramSource, Shader, ShaderDefines,
+    },
+    sprite_batch::{Quad, SpriteBatch, SpriteMode},
+    text::TextRenderer,
+    texture::{
+        MagFilter, MinFilter, Sampler, Texture, TextureArray, TextureArrayLoadBuilder,
+        TextureArrayLoadOptions, TextureFormat, TextureLoadBuilder, TextureLoadOptions,
+        TextureSource, Wrap,
+    },
+    uniform::{Std140, Std140Writer, Uniform, UniformValue},
     viewport::Viewport,
 };
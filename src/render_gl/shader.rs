@@ -1,8 +1,11 @@
+use crate::render_gl::uniform::Uniform;
 use crate::resources::{self, Resources};
 use gl;
 use nalgebra as na;
 use std;
+use std::collections::HashMap;
 use std::ffi::{CStr, CString};
+use std::fmt::Write;
 
 #[derive(Debug, Fail)]
 pub enum Error {
@@ -18,31 +21,68 @@ pub enum Error {
     CompileError { name: String, message: String },
     #[fail(display = "Failed to link program {}: {}", name, message)]
     LinkError { name: String, message: String },
+    #[fail(display = "Include cycle in shader {}: {}", name, chain)]
+    IncludeCycle { name: String, chain: String },
+    #[fail(display = "Malformed #include in {} at line {}", name, line)]
+    MalformedInclude { name: String, line: usize },
+    #[fail(display = "Preprocessed shader {} contains a nul byte", name)]
+    SourceContainsNil { name: String },
+    #[fail(display = "Program {} has no active uniform(s) {}", program, names)]
+    MissingUniforms { program: String, names: String },
+    #[fail(
+        display = "Uniform {} of program {} is {}, not {}",
+        name, program, found, expected
+    )]
+    UniformTypeMismatch {
+        program: String,
+        name: String,
+        expected: &'static str,
+        found: &'static str,
+    },
+    #[fail(
+        display = "Uniform {} of program {} holds {} values, got {}",
+        name, program, size, len
+    )]
+    UniformArrayTooLong {
+        program: String,
+        name: String,
+        len: usize,
+        size: i32,
+    },
+    #[fail(display = "Program {} has no active uniform block {}", program, name)]
+    MissingUniformBlock { program: String, name: String },
+}
+
+/// An active uniform or vertex attribute as reported by the linker.
+/// `size` is the array length, 1 for non-array variables.
+#[derive(Clone, Debug)]
+pub struct ActiveVariable {
+    pub name: String,
+    pub gl_type: gl::types::GLenum,
+    pub size: i32,
+    pub location: i32,
 }
 
 pub struct Program {
     gl: gl::Gl,
     id: gl::types::GLuint,
+    name: String,
+    uniforms: HashMap<String, ActiveVariable>,
+    attributes: HashMap<String, ActiveVariable>,
 }
 
 impl Program {
     pub fn from_res(gl: &gl::Gl, res: &Resources, name: &str) -> Result<Program, Error> {
-        const POSSIBLE_EXT: [&str; 2] = [".vert", ".frag"];
-
-        let resource_names = POSSIBLE_EXT
-            .iter()
-            .map(|file_extension| format!("{}{}", name, file_extension))
-            .collect::<Vec<String>>();
-
-        let shaders = resource_names
-            .iter()
-            .map(|resource_name| Shader::from_res(gl, res, resource_name))
-            .collect::<Result<Vec<Shader>, Error>>()?;
+        Program::from_res_with_defines(gl, res, name, &ShaderDefines::new())
+    }
 
-        Program::from_shaders(gl, &shaders[..]).map_err(|message| Error::LinkError {
-            name: name.into(),
-            message,
-        })
+    pub fn from_res_with_defines(
+        gl: &gl::Gl,
+        res: &Resources,
+        name: &str,
+        defines: &ShaderDefines,
+    ) -> Result<Program, Error> {
+        ProgramSource::from_res_with_defines(res, name, defines)?.compile(gl)
     }
 
     pub fn from_shaders(gl: &gl::Gl, shaders: &[Shader]) -> Result<Program, String> {
@@ -92,6 +132,9 @@ impl Program {
         Ok(Program {
             gl: gl.clone(),
             id: program_id,
+            name: String::from("<unnamed>"),
+            uniforms: reflect_active_variables(gl, program_id, VariableKind::Uniform),
+            attributes: reflect_active_variables(gl, program_id, VariableKind::Attribute),
         })
     }
 
@@ -99,6 +142,125 @@ impl Program {
         self.id
     }
 
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn uniforms(&self) -> impl Iterator<Item = &ActiveVariable> {
+        self.uniforms.values()
+    }
+
+    pub fn attributes(&self) -> impl Iterator<Item = &ActiveVariable> {
+        self.attributes.values()
+    }
+
+    /// Array uniforms can be looked up either as `Lights` or `Lights[0]`.
+    pub fn uniform(&self, name: &str) -> Option<&ActiveVariable> {
+        self.uniforms.get(name)
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&ActiveVariable> {
+        self.attributes.get(name)
+    }
+
+    /// Reports every name in `names` the linker did not keep. Only checked in debug builds,
+    /// release builds always succeed and writes to missing uniforms go nowhere.
+    pub fn require_uniforms(&self, names: &[&str]) -> Result<(), Error> {
+        if !cfg!(debug_assertions) {
+            return Ok(());
+        }
+
+        let missing = names
+            .iter()
+            .filter(|name| !self.uniforms.contains_key(**name))
+            .cloned()
+            .collect::<Vec<&str>>();
+
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::MissingUniforms {
+                program: self.name.clone(),
+                names: missing.join(", "),
+            })
+        }
+    }
+
+    /// Location of uniform `name`, checked to be of `gl_type` (e.g. `gl::FLOAT_MAT4`).
+    /// A missing uniform is an error in debug builds and location -1 (ignored by GL) otherwise.
+    pub fn typed_uniform_location(
+        &self,
+        name: &str,
+        gl_type: gl::types::GLenum,
+    ) -> Result<i32, Error> {
+        match self.uniforms.get(name) {
+            Some(uniform) if uniform.gl_type == gl_type => Ok(uniform.location),
+            Some(uniform) => Err(Error::UniformTypeMismatch {
+                program: self.name.clone(),
+                name: name.into(),
+                expected: gl_type_name(gl_type),
+                found: gl_type_name(uniform.gl_type),
+            }),
+            None => self.require_uniforms(&[name]).map(|_| -1),
+        }
+    }
+
+    /// Writes `value` to uniform `name` after checking that the GLSL declaration matches.
+    /// The program must be in use.
+    pub fn set_uniform<U: Uniform + ?Sized>(&self, name: &str, value: &U) -> Result<(), Error> {
+        let uniform = match self.uniforms.get(name) {
+            Some(uniform) => uniform,
+            None => return self.require_uniforms(&[name]),
+        };
+
+        if !value.accepts(uniform.gl_type) {
+            return Err(Error::UniformTypeMismatch {
+                program: self.name.clone(),
+                name: name.into(),
+                expected: gl_type_name(value.gl_type()),
+                found: gl_type_name(uniform.gl_type),
+            });
+        }
+        if value.count() > uniform.size as usize {
+            return Err(Error::UniformArrayTooLong {
+                program: self.name.clone(),
+                name: name.into(),
+                len: value.count(),
+                size: uniform.size,
+            });
+        }
+
+        unsafe {
+            value.upload(&self.gl, uniform.location);
+        }
+        Ok(())
+    }
+
+    /// Unchecked write to a location obtained from `typed_uniform_location`.
+    pub fn set_uniform_at<U: Uniform + ?Sized>(&self, location: i32, value: &U) {
+        unsafe {
+            value.upload(&self.gl, location);
+        }
+    }
+
+    /// Points uniform block `name` at the `UniformBuffer` bound to `binding`.
+    pub fn bind_uniform_block(&self, name: &str, binding: u32) -> Result<(), Error> {
+        let cname = CString::new(name).expect("expected uniform block name to have no nul bytes");
+        let index = unsafe { self.gl.GetUniformBlockIndex(self.id, cname.as_ptr()) };
+
+        if index == gl::INVALID_INDEX {
+            return Err(Error::MissingUniformBlock {
+                program: self.name.clone(),
+                name: name.into(),
+            });
+        }
+
+        unsafe {
+            self.gl.UniformBlockBinding(self.id, index, binding);
+        }
+        Ok(())
+    }
+
     pub fn set_used(&self) {
         unsafe {
             self.gl.UseProgram(self.id);
@@ -159,23 +321,30 @@ pub struct Shader {
 
 impl Shader {
     pub fn from_res(gl: &gl::Gl, res: &Resources, name: &str) -> Result<Shader, Error> {
-        const POSSIBLE_EXT: [(&str, gl::types::GLenum); 2] =
-            [(".vert", gl::VERTEX_SHADER), (".frag", gl::FRAGMENT_SHADER)];
-
-        let shader_kind = POSSIBLE_EXT
-            .iter()
-            .find(|&&(file_extension, _)| name.ends_with(file_extension))
-            .map(|&(_, kind)| kind)
-            .ok_or_else(|| Error::CanNotDetermineShaderTypeForResource { name: name.into() })?;
+        Shader::from_res_with_defines(gl, res, name, &ShaderDefines::new())
+    }
 
-        let source = res.load_cstring(name).map_err(|e| Error::ResourceLoad {
-            name: name.into(),
-            inner: e,
-        })?;
+    pub fn from_res_with_defines(
+        gl: &gl::Gl,
+        res: &Resources,
+        name: &str,
+        defines: &ShaderDefines,
+    ) -> Result<Shader, Error> {
+        let shader_kind = shader_kind(name)?;
+        let source = preprocess(res, name, defines)?;
+
+        Shader::from_preprocessed(gl, name, &source, shader_kind)
+    }
 
-        Shader::from_source(gl, &source, shader_kind).map_err(|message| Error::CompileError {
+    pub fn from_preprocessed(
+        gl: &gl::Gl,
+        name: &str,
+        source: &PreprocessedSource,
+        kind: gl::types::GLenum,
+    ) -> Result<Shader, Error> {
+        Shader::from_source(gl, source.as_cstr(), kind).map_err(|message| Error::CompileError {
             name: name.into(),
-            message,
+            message: source.map_log(&message),
         })
     }
 
@@ -209,6 +378,420 @@ impl Drop for Shader {
     }
 }
 
+fn shader_kind(name: &str) -> Result<gl::types::GLenum, Error> {
+    const POSSIBLE_EXT: [(&str, gl::types::GLenum); 2] =
+        [(".vert", gl::VERTEX_SHADER), (".frag", gl::FRAGMENT_SHADER)];
+
+    POSSIBLE_EXT
+        .iter()
+        .find(|&&(file_extension, _)| name.ends_with(file_extension))
+        .map(|&(_, kind)| kind)
+        .ok_or_else(|| Error::CanNotDetermineShaderTypeForResource { name: name.into() })
+}
+
+/// The preprocessed stages of a program. Building this only reads resources, so it can
+/// happen off the GL thread; `compile` then needs the context.
+pub struct ProgramSource {
+    name: String,
+    stages: Vec<(String, gl::types::GLenum, PreprocessedSource)>,
+}
+
+impl ProgramSource {
+    pub fn from_res(res: &Resources, name: &str) -> Result<ProgramSource, Error> {
+        ProgramSource::from_res_with_defines(res, name, &ShaderDefines::new())
+    }
+
+    pub fn from_res_with_defines(
+        res: &Resources,
+        name: &str,
+        defines: &ShaderDefines,
+    ) -> Result<ProgramSource, Error> {
+        const POSSIBLE_EXT: [&str; 2] = [".vert", ".frag"];
+
+        let stages = POSSIBLE_EXT
+            .iter()
+            .map(|file_extension| {
+                let resource_name = format!("{}{}", name, file_extension);
+                let kind = shader_kind(&resource_name)?;
+                let source = preprocess(res, &resource_name, defines)?;
+                Ok((resource_name, kind, source))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Ok(ProgramSource {
+            name: name.into(),
+            stages,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn compile(&self, gl: &gl::Gl) -> Result<Program, Error> {
+        let shaders = self
+            .stages
+            .iter()
+            .map(|(resource_name, kind, source)| {
+                Shader::from_preprocessed(gl, resource_name, source, *kind)
+            })
+            .collect::<Result<Vec<Shader>, Error>>()?;
+
+        let mut program =
+            Program::from_shaders(gl, &shaders[..]).map_err(|message| Error::LinkError {
+                name: self.name.clone(),
+                message,
+            })?;
+        program.name = self.name.clone();
+
+        Ok(program)
+    }
+}
+
+#[derive(Copy, Clone)]
+enum VariableKind {
+    Uniform,
+    Attribute,
+}
+
+fn reflect_active_variables(
+    gl: &gl::Gl,
+    program_id: gl::types::GLuint,
+    kind: VariableKind,
+) -> HashMap<String, ActiveVariable> {
+    let (count_param, max_len_param) = match kind {
+        VariableKind::Uniform => (gl::ACTIVE_UNIFORMS, gl::ACTIVE_UNIFORM_MAX_LENGTH),
+        VariableKind::Attribute => (gl::ACTIVE_ATTRIBUTES, gl::ACTIVE_ATTRIBUTE_MAX_LENGTH),
+    };
+
+    let mut count: gl::types::GLint = 0;
+    let mut max_len: gl::types::GLint = 0;
+    unsafe {
+        gl.GetProgramiv(program_id, count_param, &mut count);
+        gl.GetProgramiv(program_id, max_len_param, &mut max_len);
+    }
+
+    let mut variables = HashMap::new();
+
+    for index in 0..count as gl::types::GLuint {
+        let mut buffer: Vec<u8> = vec![0; max_len.max(1) as usize];
+        let mut len: gl::types::GLsizei = 0;
+        let mut size: gl::types::GLint = 0;
+        let mut gl_type: gl::types::GLenum = 0;
+
+        let name_ptr = buffer.as_mut_ptr() as *mut gl::types::GLchar;
+        unsafe {
+            match kind {
+                VariableKind::Uniform => gl.GetActiveUniform(
+                    program_id,
+                    index,
+                    max_len,
+                    &mut len,
+                    &mut size,
+                    &mut gl_type,
+                    name_ptr,
+                ),
+                VariableKind::Attribute => gl.GetActiveAttrib(
+                    program_id,
+                    index,
+                    max_len,
+                    &mut len,
+                    &mut size,
+                    &mut gl_type,
+                    name_ptr,
+                ),
+            }
+        }
+
+        buffer.truncate(len as usize);
+        let cname = unsafe { CString::from_vec_unchecked(buffer) };
+        let location = unsafe {
+            match kind {
+                VariableKind::Uniform => gl.GetUniformLocation(program_id, cname.as_ptr()),
+                VariableKind::Attribute => gl.GetAttribLocation(program_id, cname.as_ptr()),
+            }
+        };
+        let name = cname.to_string_lossy().into_owned();
+
+        let variable = ActiveVariable {
+            name: name.clone(),
+            gl_type,
+            size,
+            location,
+        };
+
+        // arrays are reported as `name[0]`, make them reachable by their plain name too
+        if name.ends_with("[0]") {
+            variables.insert(name[..name.len() - 3].to_string(), variable.clone());
+        }
+        variables.insert(name, variable);
+    }
+
+    variables
+}
+
+/// GLSL spelling of a uniform/attribute type, for error messages.
+pub fn gl_type_name(gl_type: gl::types::GLenum) -> &'static str {
+    match gl_type {
+        gl::FLOAT => "float",
+        gl::FLOAT_VEC2 => "vec2",
+        gl::FLOAT_VEC3 => "vec3",
+        gl::FLOAT_VEC4 => "vec4",
+        gl::INT => "int",
+        gl::INT_VEC2 => "ivec2",
+        gl::INT_VEC3 => "ivec3",
+        gl::INT_VEC4 => "ivec4",
+        gl::UNSIGNED_INT => "uint",
+        gl::UNSIGNED_INT_VEC2 => "uvec2",
+        gl::UNSIGNED_INT_VEC3 => "uvec3",
+        gl::UNSIGNED_INT_VEC4 => "uvec4",
+        gl::BOOL => "bool",
+        gl::BOOL_VEC2 => "bvec2",
+        gl::BOOL_VEC3 => "bvec3",
+        gl::BOOL_VEC4 => "bvec4",
+        gl::FLOAT_MAT2 => "mat2",
+        gl::FLOAT_MAT3 => "mat3",
+        gl::FLOAT_MAT4 => "mat4",
+        gl::SAMPLER_2D => "sampler2D",
+        gl::SAMPLER_2D_ARRAY => "sampler2DArray",
+        gl::SAMPLER_CUBE => "samplerCube",
+        _ => "<unknown type>",
+    }
+}
+
+/// `#define`s injected right after the `#version` line of every shader built with them.
+/// A define without a value is a plain feature toggle (`#define USE_FOG`).
+#[derive(Clone, Debug, Default)]
+pub struct ShaderDefines {
+    defines: Vec<(String, Option<String>)>,
+}
+
+impl ShaderDefines {
+    pub fn new() -> ShaderDefines {
+        ShaderDefines {
+            defines: Vec::new(),
+        }
+    }
+
+    pub fn with_flag(mut self, name: &str) -> Self {
+        self.set(name, None);
+        self
+    }
+
+    pub fn with_value<T: ToString>(mut self, name: &str, value: T) -> Self {
+        self.set(name, Some(value.to_string()));
+        self
+    }
+
+    fn set(&mut self, name: &str, value: Option<String>) {
+        match self.defines.iter_mut().find(|(n, _)| n == name) {
+            Some(define) => define.1 = value,
+            None => self.defines.push((name.into(), value)),
+        }
+    }
+}
+
+/// Where a line of the preprocessed output came from.
+#[derive(Clone, Debug)]
+struct SourceLine {
+    file: String,
+    line: usize,
+}
+
+/// Shader source with includes resolved and defines injected, plus enough bookkeeping
+/// to point compiler messages back at the file the offending line was written in.
+pub struct PreprocessedSource {
+    source: CString,
+    lines: Vec<SourceLine>,
+}
+
+impl PreprocessedSource {
+    pub fn as_cstr(&self) -> &CStr {
+        &self.source
+    }
+
+    /// Rewrites `0:LINE` / `0(LINE)` locations in a driver info log to `file:line`.
+    /// Mesa, NVIDIA and AMD all use one of these two forms for the first source string.
+    pub fn map_log(&self, log: &str) -> String {
+        let mut result = String::with_capacity(log.len());
+
+        for log_line in log.lines() {
+            match find_log_location(log_line) {
+                Some((start, end, line)) => match self.lines.get(line.wrapping_sub(1)) {
+                    Some(origin) => {
+                        result.push_str(&log_line[..start]);
+                        let _ = write!(&mut result, "{}:{}", origin.file, origin.line);
+                        result.push_str(&log_line[end..]);
+                    }
+                    None => result.push_str(log_line),
+                },
+                None => result.push_str(log_line),
+            }
+            result.push('\n');
+        }
+
+        result
+    }
+}
+
+/// Loads `name` through `res`, resolving `#include "path"` directives relative to the
+/// including file and injecting `defines` after the `#version` line.
+pub fn preprocess(
+    res: &Resources,
+    name: &str,
+    defines: &ShaderDefines,
+) -> Result<PreprocessedSource, Error> {
+    let mut output = String::new();
+    let mut lines = Vec::new();
+    let mut stack = Vec::new();
+    let mut once = Vec::new();
+
+    include_file(res, name, &mut stack, &mut once, &mut output, &mut lines)?;
+
+    // defines go after #version, which must stay the first statement of the shader
+    let insert_at = output
+        .lines()
+        .position(|text| text.trim_start().starts_with("#version"))
+        .map(|i| i + 1)
+        .unwrap_or(0);
+
+    let mut define_text = String::new();
+    let mut define_lines = Vec::new();
+    for (i, (define, value)) in defines.defines.iter().enumerate() {
+        match value {
+            Some(value) => {
+                let _ = writeln!(&mut define_text, "#define {} {}", define, value);
+            }
+            None => {
+                let _ = writeln!(&mut define_text, "#define {}", define);
+            }
+        }
+        define_lines.push(SourceLine {
+            file: "<defines>".into(),
+            line: i + 1,
+        });
+    }
+
+    let byte_offset: usize = output.lines().take(insert_at).map(|l| l.len() + 1).sum();
+    output.insert_str(byte_offset, &define_text);
+    lines.splice(insert_at..insert_at, define_lines);
+
+    let source =
+        CString::new(output).map_err(|_| Error::SourceContainsNil { name: name.into() })?;
+
+    Ok(PreprocessedSource { source, lines })
+}
+
+fn include_file(
+    res: &Resources,
+    name: &str,
+    stack: &mut Vec<String>,
+    once: &mut Vec<String>,
+    output: &mut String,
+    lines: &mut Vec<SourceLine>,
+) -> Result<(), Error> {
+    if stack.iter().any(|n| n == name) {
+        stack.push(name.into());
+        return Err(Error::IncludeCycle {
+            name: stack[0].clone(),
+            chain: stack.join(" -> "),
+        });
+    }
+    if once.iter().any(|n| n == name) {
+        return Ok(());
+    }
+
+    let text = res
+        .load_cstring(name)
+        .map_err(|e| Error::ResourceLoad {
+            name: name.into(),
+            inner: e,
+        })?
+        .to_string_lossy()
+        .into_owned();
+
+    stack.push(name.into());
+
+    for (i, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+
+        if trimmed.starts_with("#include") {
+            let included = parse_include(trimmed).ok_or_else(|| Error::MalformedInclude {
+                name: name.into(),
+                line: i + 1,
+            })?;
+            let included = resolve_include(name, included);
+            include_file(res, &included, stack, once, output, lines)?;
+            continue;
+        }
+
+        if trimmed == "#pragma once" {
+            once.push(name.into());
+            continue;
+        }
+
+        output.push_str(line);
+        output.push('\n');
+        lines.push(SourceLine {
+            file: name.into(),
+            line: i + 1,
+        });
+    }
+
+    stack.pop();
+    Ok(())
+}
+
+fn parse_include(line: &str) -> Option<&str> {
+    let rest = line["#include".len()..].trim();
+    if rest.len() < 2 || !rest.starts_with('"') || !rest.ends_with('"') {
+        return None;
+    }
+    Some(&rest[1..rest.len() - 1])
+}
+
+/// Include paths are relative to the directory of the file that includes them.
+fn resolve_include(including: &str, included: &str) -> String {
+    match including.rfind('/') {
+        Some(i) => format!("{}/{}", &including[..i], included),
+        None => included.into(),
+    }
+}
+
+/// Finds a `0:LINE` or `0(LINE)` location and returns its byte range and line number.
+fn find_log_location(log_line: &str) -> Option<(usize, usize, usize)> {
+    let bytes = log_line.as_bytes();
+
+    for start in 0..bytes.len() {
+        if bytes[start] != b'0' || (start > 0 && bytes[start - 1].is_ascii_digit()) {
+            continue;
+        }
+        let (open, close) = match bytes.get(start + 1) {
+            Some(b':') => (start + 2, None),
+            Some(b'(') => (start + 2, Some(b')')),
+            _ => continue,
+        };
+        let digits = bytes[open..]
+            .iter()
+            .take_while(|b| b.is_ascii_digit())
+            .count();
+        if digits == 0 {
+            continue;
+        }
+        let mut end = open + digits;
+        if let Some(close) = close {
+            if bytes.get(end) != Some(&close) {
+                continue;
+            }
+            end += 1;
+        }
+        let line = log_line[open..open + digits].parse().ok()?;
+        return Some((start, end, line));
+    }
+
+    None
+}
+
 fn shader_from_source(
     gl: &gl::Gl,
     source: &CStr,
@@ -256,3 +839,130 @@ fn create_whitespace_cstring_with_len(len: usize) -> CString {
     // convert buffer to CString
     unsafe { CString::from_vec_unchecked(buffer) }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::MemorySource;
+
+    fn res(files: &[(&str, &str)]) -> Resources {
+        let source = files
+            .iter()
+            .fold(MemorySource::new(), |source, (name, text)| {
+                source.with(name, *text)
+            });
+        Resources::new().with_source(0, source)
+    }
+
+    fn text(source: &PreprocessedSource) -> &str {
+        source.as_cstr().to_str().unwrap()
+    }
+
+    #[test]
+    fn nested_includes_resolve_relative_to_the_including_file() {
+        let res = res(&[
+            (
+                "shaders/main.frag",
+                "#version 330 core\n#include \"lib/light.glsl\"\nvoid main() {}\n",
+            ),
+            (
+                "shaders/lib/light.glsl",
+                "#include \"math.glsl\"\nfloat light;\n",
+            ),
+            ("shaders/lib/math.glsl", "float pi;\n"),
+        ]);
+
+        let source = preprocess(&res, "shaders/main.frag", &ShaderDefines::new()).unwrap();
+        assert_eq!(
+            text(&source),
+            "#version 330 core\nfloat pi;\nfloat light;\nvoid main() {}\n"
+        );
+    }
+
+    #[test]
+    fn pragma_once_includes_a_file_only_the_first_time() {
+        let res = res(&[
+            (
+                "shaders/main.frag",
+                "#include \"common.glsl\"\n#include \"util.glsl\"\n#include \"common.glsl\"\n",
+            ),
+            (
+                "shaders/util.glsl",
+                "#include \"common.glsl\"\nfloat util;\n",
+            ),
+            ("shaders/common.glsl", "#pragma once\nfloat common;\n"),
+        ]);
+
+        let source = preprocess(&res, "shaders/main.frag", &ShaderDefines::new()).unwrap();
+        assert_eq!(text(&source), "float common;\nfloat util;\n");
+    }
+
+    #[test]
+    fn include_cycle_is_an_error() {
+        let res = res(&[
+            ("shaders/main.frag", "#include \"a.glsl\"\n"),
+            ("shaders/a.glsl", "#include \"b.glsl\"\n"),
+            ("shaders/b.glsl", "#pragma once\n#include \"a.glsl\"\n"),
+        ]);
+
+        match preprocess(&res, "shaders/main.frag", &ShaderDefines::new()) {
+            Err(Error::IncludeCycle { name, chain }) => {
+                assert_eq!(name, "shaders/main.frag");
+                assert_eq!(
+                    chain,
+                    "shaders/main.frag -> shaders/a.glsl -> shaders/b.glsl -> shaders/a.glsl"
+                );
+            }
+            Err(e) => panic!("expected an include cycle, got {}", e),
+            Ok(_) => panic!("expected an include cycle"),
+        }
+    }
+
+    #[test]
+    fn malformed_include_reports_its_line() {
+        let res = res(&[("shaders/main.frag", "float a;\n#include <common.glsl>\n")]);
+
+        match preprocess(&res, "shaders/main.frag", &ShaderDefines::new()) {
+            Err(Error::MalformedInclude { name, line }) => {
+                assert_eq!(name, "shaders/main.frag");
+                assert_eq!(line, 2);
+            }
+            Err(e) => panic!("expected a malformed include, got {}", e),
+            Ok(_) => panic!("expected a malformed include"),
+        }
+    }
+
+    #[test]
+    fn map_log_points_at_the_original_file_and_line() {
+        let res = res(&[
+            (
+                "shaders/main.frag",
+                "#version 330 core\n#include \"lib/fog.glsl\"\nvoid main() {\n    oops;\n}\n",
+            ),
+            ("shaders/lib/fog.glsl", "#pragma once\n\nfloat fog;\n"),
+        ]);
+        let defines = ShaderDefines::new()
+            .with_flag("USE_FOG")
+            .with_value("LIGHTS", 4);
+
+        let source = preprocess(&res, "shaders/main.frag", &defines).unwrap();
+        assert_eq!(
+            text(&source),
+            "#version 330 core\n#define USE_FOG\n#define LIGHTS 4\n\nfloat fog;\n\
+             void main() {\n    oops;\n}\n"
+        );
+
+        // Mesa style, NVIDIA style, a define, and a line past the end left alone
+        let log = "0:7(5): error: `oops' undeclared\n\
+                   0(5) : warning C7022: unused fog\n\
+                   ERROR: 0:3: 'LIGHTS' : redefinition\n\
+                   0:99: error: past the end";
+        assert_eq!(
+            source.map_log(log),
+            "shaders/main.frag:4(5): error: `oops' undeclared\n\
+             shaders/lib/fog.glsl:3 : warning C7022: unused fog\n\
+             ERROR: <defines>:2: 'LIGHTS' : redefinition\n\
+             0:99: error: past the end\n"
+        );
+    }
+}
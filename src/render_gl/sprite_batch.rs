@@ -0,0 +1,389 @@
+use crate::render_gl::{buffer, data, Program, Texture, TextureArray};
+use crate::resources::Resources;
+use failure;
+use gl;
+use nalgebra as na;
+
+#[derive(VertexAttribPointers, Copy, Clone, Debug)]
+#[repr(C, packed)]
+struct Vertex {
+    #[location = "0"]
+    pos: data::f32_f32,
+    #[location = "1"]
+    uv: data::f32_f32,
+    #[location = "2"]
+    tint: data::u8_u8_u8_u8_float,
+    /// layer of a `TextureArray`, 0 for plain textures
+    #[location = "3"]
+    layer: data::f32_,
+}
+
+/// How a sprite's texture is sampled.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SpriteMode {
+    /// texel times tint
+    Color,
+    /// the red channel is coverage for the tint colour, for R8 textures like the glyph cache
+    Mask,
+}
+
+/// Texture units of the two samplers in `shaders/sprite.frag`.
+const TEXTURE_UNIT: u32 = 0;
+const ARRAY_UNIT: u32 = 1;
+
+/// What a run of quads is drawn from, a new draw call starts when it changes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Source {
+    Texture(gl::types::GLuint, SpriteMode),
+    /// a `TextureArray`, each quad picks its layer
+    Layers(gl::types::GLuint),
+}
+
+/// One textured, tinted rectangle. `uv` is `[u0, v0, u1, v1]`, top left to bottom right.
+#[derive(Copy, Clone, Debug)]
+pub struct Quad {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+    pub uv: [f32; 4],
+    pub tint: [u8; 4],
+}
+
+/// Collects quads and draws them with as few draw calls as possible: a new call is only
+/// needed when the texture or `SpriteMode` changes, or `MAX_SPRITES` is reached. Quads
+/// drawn from different layers of one `TextureArray` share a draw call.
+pub struct SpriteBatch {
+    gl: gl::Gl,
+    program: Program,
+    vertices: Vec<Vertex>,
+    current: Option<Source>,
+    draw_calls: usize,
+    vbo: buffer::ArrayBuffer,
+    _ibo: buffer::ElementArrayBuffer,
+    vao: buffer::VertexArray,
+}
+
+impl SpriteBatch {
+    pub const MAX_SPRITES: usize = 4096;
+
+    pub fn new(gl: &gl::Gl, res: &Resources) -> Result<SpriteBatch, failure::Error> {
+        let program = Program::from_res(gl, res, "shaders/sprite")?;
+        program.require_uniforms(&["Projection", "TexFace", "TexLayers", "Mask", "Layered"])?;
+
+        let indices = (0..SpriteBatch::MAX_SPRITES as u32)
+            .flat_map(|i| {
+                let v = i * 4;
+                vec![v, v + 1, v + 2, v + 2, v + 3, v]
+            })
+            .collect::<Vec<gl::types::GLuint>>();
+
+        let vbo = buffer::ArrayBuffer::new(gl);
+        let ibo = buffer::ElementArrayBuffer::new(gl);
+        let vao = buffer::VertexArray::new(gl);
+
+        vao.bind();
+        vbo.bind();
+        ibo.bind();
+        ibo.static_draw_data(&indices);
+        Vertex::vertex_attrib_pointers(gl);
+        vao.unbind();
+
+        Ok(SpriteBatch {
+            gl: gl.clone(),
+            program,
+            vertices: Vec::with_capacity(SpriteBatch::MAX_SPRITES * 4),
+            current: None,
+            draw_calls: 0,
+            vbo,
+            _ibo: ibo,
+            vao,
+        })
+    }
+
+    /// Pixel coordinates with the origin at the top left, y pointing down.
+    pub fn screen_projection(width: f32, height: f32) -> na::Matrix4<f32> {
+        na::Orthographic3::new(0.0, width, height, 0.0, -1.0, 1.0).into_inner()
+    }
+
+    /// Starts a batch, with blending on until `end`.
+    pub fn begin(&mut self, projection: &na::Matrix4<f32>) {
+        self.vertices.clear();
+        self.current = None;
+        self.draw_calls = 0;
+
+        self.program.set_used();
+        let _ = self.program.set_uniform("Projection", projection);
+        let _ = self.program.set_uniform("TexFace", &(TEXTURE_UNIT as i32));
+        let _ = self.program.set_uniform("TexLayers", &(ARRAY_UNIT as i32));
+        unsafe {
+            self.gl.Enable(gl::BLEND);
+            self.gl.BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        }
+    }
+
+    pub fn draw(&mut self, texture: &Texture, mode: SpriteMode, quad: &Quad) {
+        self.push(Source::Texture(texture.id(), mode), 0.0, quad);
+    }
+
+    /// Draws layer `layer` of `array`, tinted like `SpriteMode::Color`.
+    pub fn draw_layer(&mut self, array: &TextureArray, layer: u32, quad: &Quad) {
+        debug_assert!(layer < array.layers(), "expected a layer of the array");
+        self.push(Source::Layers(array.id()), layer as f32, quad);
+    }
+
+    fn push(&mut self, source: Source, layer: f32, quad: &Quad) {
+        if self.current != Some(source) || self.vertices.len() == SpriteBatch::MAX_SPRITES * 4 {
+            self.flush();
+            self.current = Some(source);
+        }
+
+        let [u0, v0, u1, v1] = quad.uv;
+        let tint = (quad.tint[0], quad.tint[1], quad.tint[2], quad.tint[3]);
+        let (x0, y0, x1, y1) = (quad.x, quad.y, quad.x + quad.w, quad.y + quad.h);
+
+        for &(x, y, u, v) in &[
+            (x0, y1, u0, v1), // bottom left
+            (x1, y1, u1, v1), // bottom right
+            (x1, y0, u1, v0), // top right
+            (x0, y0, u0, v0), // top left
+        ] {
+            self.vertices.push(Vertex {
+                pos: (x, y).into(),
+                uv: (u, v).into(),
+                tint: tint.into(),
+                layer: layer.into(),
+            });
+        }
+    }
+
+    /// Draws everything queued so far.
+    pub fn flush(&mut self) {
+        let source = match self.current {
+            Some(current) if !self.vertices.is_empty() => current,
+            _ => return,
+        };
+        let (target, unit, texture, mask) = match source {
+            Source::Texture(texture, mode) => (
+                gl::TEXTURE_2D,
+                TEXTURE_UNIT,
+                texture,
+                mode == SpriteMode::Mask,
+            ),
+            Source::Layers(array) => (gl::TEXTURE_2D_ARRAY, ARRAY_UNIT, array, false),
+        };
+
+        let _ = self.program.set_uniform("Mask", &mask);
+        let _ = self
+            .program
+            .set_uniform("Layered", &(target == gl::TEXTURE_2D_ARRAY));
+        self.vbo.bind();
+        self.vbo.dynamic_draw_data(&self.vertices);
+        self.vbo.unbind();
+
+        self.vao.bind();
+        unsafe {
+            self.gl.ActiveTexture(gl::TEXTURE0 + unit);
+            self.gl.BindTexture(target, texture);
+            self.gl.DrawElements(
+                gl::TRIANGLES,
+                (self.vertices.len() / 4 * 6) as i32,
+                gl::UNSIGNED_INT,
+                std::ptr::null(),
+            );
+            self.gl.BindTexture(target, 0);
+        }
+        self.vao.unbind();
+
+        self.vertices.clear();
+        self.draw_calls += 1;
+    }
+
+    pub fn end(&mut self) {
+        self.flush();
+        unsafe {
+            self.gl.Disable(gl::BLEND);
+        }
+    }
+
+    /// Draw calls issued since `begin`.
+    pub fn draw_calls(&self) -> usize {
+        self.draw_calls
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render_gl::{TextureFormat, TextureLoadOptions};
+    use gl::recording::{Call, RecordingGl};
+    use std::path::Path;
+    use std::rc::Rc;
+
+    const QUAD: Quad = Quad {
+        x: 0.0,
+        y: 0.0,
+        w: 16.0,
+        h: 16.0,
+        uv: [0.0, 0.0, 1.0, 1.0],
+        tint: [255; 4],
+    };
+
+    fn recording() -> Rc<RecordingGl> {
+        Rc::new(
+            RecordingGl::new()
+                .with_active_uniform("Projection", gl::FLOAT_MAT4, 1)
+                .with_active_uniform("TexFace", gl::SAMPLER_2D, 1)
+                .with_active_uniform("TexLayers", gl::SAMPLER_2D_ARRAY, 1)
+                .with_active_uniform("Mask", gl::BOOL, 1)
+                .with_active_uniform("Layered", gl::BOOL, 1),
+        )
+    }
+
+    fn res() -> Resources {
+        Resources::from_directory(&Path::new(env!("CARGO_MANIFEST_DIR")).join("res"))
+    }
+
+    fn texture(gl: &gl::Gl, res: &Resources) -> Texture {
+        Texture::from_res(
+            TextureLoadOptions::from_res_rgba("textures/test.png"),
+            gl,
+            res,
+        )
+        .unwrap()
+    }
+
+    fn named<'a>(calls: &'a [Call], name: &str) -> Vec<&'a Call> {
+        calls.iter().filter(|call| call.name == name).collect()
+    }
+
+    #[test]
+    fn flush_uploads_and_draws_the_queued_quads() {
+        let recording = recording();
+        let gl = gl::Gl::from_api(recording.clone());
+        let res = res();
+        let mut batch = SpriteBatch::new(&gl, &res).unwrap();
+        let texture = texture(&gl, &res);
+
+        batch.begin(&SpriteBatch::screen_projection(640.0, 480.0));
+        batch.draw(&texture, SpriteMode::Color, &QUAD);
+        batch.draw(&texture, SpriteMode::Color, &QUAD);
+        recording.clear_calls();
+        batch.flush();
+
+        let calls = recording.calls();
+        let names = calls.iter().map(|call| call.name).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                "Uniform1iv",
+                "Uniform1iv",
+                "BindBuffer",
+                "BufferData",
+                "BindBuffer",
+                "BindVertexArray",
+                "ActiveTexture",
+                "BindTexture",
+                "DrawElements",
+                "BindTexture",
+                "BindVertexArray",
+            ]
+        );
+        let vertex_bytes = 2 * 4 * std::mem::size_of::<Vertex>();
+        assert_eq!(
+            named(&calls, "BufferData")[0].args[1],
+            vertex_bytes.to_string()
+        );
+        assert_eq!(named(&calls, "DrawElements")[0].args[1], "12");
+        assert_eq!(
+            named(&calls, "BindTexture")[0].args[1],
+            texture.id().to_string()
+        );
+
+        // nothing queued, nothing drawn
+        recording.clear_calls();
+        batch.flush();
+        assert!(recording.calls().is_empty());
+        assert_eq!(batch.draw_calls(), 1);
+    }
+
+    #[test]
+    fn changing_texture_or_mode_starts_a_new_draw_call() {
+        let recording = recording();
+        let gl = gl::Gl::from_api(recording.clone());
+        let res = res();
+        let mut batch = SpriteBatch::new(&gl, &res).unwrap();
+        let first = texture(&gl, &res);
+        let second = texture(&gl, &res);
+
+        batch.begin(&SpriteBatch::screen_projection(640.0, 480.0));
+        recording.clear_calls();
+        batch.draw(&first, SpriteMode::Color, &QUAD);
+        batch.draw(&first, SpriteMode::Color, &QUAD);
+        batch.draw(&second, SpriteMode::Color, &QUAD);
+        batch.draw(&second, SpriteMode::Mask, &QUAD);
+        batch.end();
+
+        let calls = recording.calls();
+        let counts = named(&calls, "DrawElements")
+            .iter()
+            .map(|call| call.args[1].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(counts, vec!["12", "6", "6"]);
+        assert_eq!(batch.draw_calls(), 3);
+        assert_eq!(calls.last().unwrap().name, "Disable");
+    }
+
+    #[test]
+    fn layers_of_one_array_share_a_draw_call() {
+        let recording = recording();
+        let gl = gl::Gl::from_api(recording.clone());
+        let res = res();
+        let mut batch = SpriteBatch::new(&gl, &res).unwrap();
+        let texture = texture(&gl, &res);
+        let names = ["textures/test.png", "textures/test.png"];
+        let variants = TextureArray::from_res(&names, TextureFormat::Rgba8)
+            .load(&gl, &res)
+            .unwrap();
+
+        batch.begin(&SpriteBatch::screen_projection(640.0, 480.0));
+        batch.draw_layer(&variants, 0, &QUAD);
+        batch.draw_layer(&variants, 1, &QUAD);
+        let layers = batch
+            .vertices
+            .iter()
+            .map(|vertex| vertex.layer.d0)
+            .collect::<Vec<_>>();
+        assert_eq!(layers, vec![0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 1.0]);
+
+        recording.clear_calls();
+        batch.draw(&texture, SpriteMode::Color, &QUAD);
+        let calls = recording.calls();
+        assert_eq!(named(&calls, "DrawElements").len(), 1);
+        assert_eq!(named(&calls, "DrawElements")[0].args[1], "12");
+        let texture_unit = (gl::TEXTURE0 + ARRAY_UNIT).to_string();
+        assert_eq!(named(&calls, "ActiveTexture")[0].args[0], texture_unit);
+        let bind = &named(&calls, "BindTexture")[0].args;
+        assert_eq!(bind[0], gl::TEXTURE_2D_ARRAY.to_string());
+        assert_eq!(bind[1], variants.id().to_string());
+
+        batch.end();
+        assert_eq!(batch.draw_calls(), 2);
+    }
+
+    #[test]
+    fn dropping_deletes_every_object() {
+        let recording = recording();
+        let gl = gl::Gl::from_api(recording.clone());
+        let res = res();
+        let batch = SpriteBatch::new(&gl, &res).unwrap();
+        let texture = texture(&gl, &res);
+        assert!(!recording.live_objects().is_empty());
+
+        drop(batch);
+        drop(texture);
+
+        assert_eq!(recording.live_objects(), vec![]);
+        assert_eq!(recording.invalid_deletes(), vec![]);
+    }
+}
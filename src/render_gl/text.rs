@@ -0,0 +1,205 @@
+use crate::assets::Handle;
+use crate::font::Font;
+use crate::render_gl::sprite_batch::{Quad, SpriteBatch, SpriteMode};
+use crate::render_gl::{Texture, TextureFormat};
+use failure;
+use gl;
+use rusttype::{self, GlyphId};
+use std::collections::HashMap;
+
+/// Side of the square glyph cache texture in pixels.
+const ATLAS_SIZE: u32 = 1024;
+/// Side of the solid block at the atlas origin that `white_uv` points at.
+const WHITE_SIZE: u32 = 4;
+/// Empty pixels around each glyph so linear filtering doesn't bleed neighbours in.
+const GLYPH_PADDING: u32 = 1;
+
+#[derive(Copy, Clone, Debug)]
+struct CachedGlyph {
+    uv: [f32; 4],
+    /// bounding box relative to the pen position on the baseline
+    offset: (f32, f32),
+    size: (f32, f32),
+}
+
+/// Draws single lines of text through a `SpriteBatch`.
+///
+/// Glyphs are rasterized with rusttype on first use into one R8 coverage texture, packed
+/// in shelves. When the texture is full, the batch is flushed and the cache starts over.
+/// The texture also holds a solid block, so untextured rectangles drawn with `white_uv`
+/// batch together with text.
+pub struct TextRenderer {
+    font: Handle<Font>,
+    atlas: Texture,
+    /// `None` for glyphs without an outline, like spaces
+    glyphs: HashMap<(GlyphId, u32), Option<CachedGlyph>>,
+    shelf_x: u32,
+    shelf_y: u32,
+    shelf_height: u32,
+}
+
+impl TextRenderer {
+    pub fn new(gl: &gl::Gl, font: Handle<Font>) -> Result<TextRenderer, failure::Error> {
+        let empty = vec![0; (ATLAS_SIZE * ATLAS_SIZE) as usize];
+        let atlas =
+            Texture::from_pixels(ATLAS_SIZE, ATLAS_SIZE, TextureFormat::R8, &empty).create(gl)?;
+
+        let white = vec![255; (WHITE_SIZE * WHITE_SIZE) as usize];
+        atlas.write_region(0, 0, WHITE_SIZE, WHITE_SIZE, TextureFormat::R8, &white)?;
+
+        let mut text = TextRenderer {
+            font,
+            atlas,
+            glyphs: HashMap::new(),
+            shelf_x: 0,
+            shelf_y: 0,
+            shelf_height: 0,
+        };
+        text.reset_shelves();
+        Ok(text)
+    }
+
+    pub fn font(&self) -> &Handle<Font> {
+        &self.font
+    }
+
+    pub fn atlas(&self) -> &Texture {
+        &self.atlas
+    }
+
+    /// Texture coordinates of the solid block, for drawing plain rectangles with the atlas
+    /// in `SpriteMode::Mask`.
+    pub fn white_uv(&self) -> [f32; 4] {
+        let center = (WHITE_SIZE as f32 / 2.0) / ATLAS_SIZE as f32;
+        [center, center, center, center]
+    }
+
+    /// Draws `text` with the top of its line at `x`, `y` in the batch's coordinates, which
+    /// are `scale` pixels per unit. Glyphs are rasterized at `size * scale` pixels so they
+    /// stay sharp when the batch is scaled up. Returns the width of the drawn text.
+    pub fn draw(
+        &mut self,
+        batch: &mut SpriteBatch,
+        text: &str,
+        x: f32,
+        y: f32,
+        size: f32,
+        scale: f32,
+        color: [u8; 4],
+    ) -> f32 {
+        // laid out in pixels, so glyphs land on whole pixels, then back into batch units
+        let font = self.font.clone();
+        let pixel_size = size * scale;
+        let (ascent, _) = font.line_metrics(pixel_size);
+        let baseline = (y * scale + ascent).round();
+        let mut width = 0.0;
+
+        for (glyph, pen_x) in font.layout_line(text, pixel_size) {
+            width = pen_x + glyph.h_metrics().advance_width;
+            let cached = match self.glyph(batch, &glyph, pixel_size) {
+                Some(cached) => cached,
+                None => continue,
+            };
+
+            batch.draw(
+                &self.atlas,
+                SpriteMode::Mask,
+                &Quad {
+                    x: ((x * scale + pen_x).round() + cached.offset.0) / scale,
+                    y: (baseline + cached.offset.1) / scale,
+                    w: cached.size.0 / scale,
+                    h: cached.size.1 / scale,
+                    uv: cached.uv,
+                    tint: color,
+                },
+            );
+        }
+
+        width / scale
+    }
+
+    fn glyph(
+        &mut self,
+        batch: &mut SpriteBatch,
+        glyph: &rusttype::ScaledGlyph<'_>,
+        size: f32,
+    ) -> Option<CachedGlyph> {
+        let key = (glyph.id(), size.round() as u32);
+        if let Some(cached) = self.glyphs.get(&key) {
+            return *cached;
+        }
+
+        let positioned = glyph.clone().positioned(rusttype::point(0.0, 0.0));
+        let cached = match positioned.pixel_bounding_box() {
+            Some(bounds) => {
+                let width = bounds.width() as u32;
+                let height = bounds.height() as u32;
+                let mut coverage = vec![0; (width * height) as usize];
+                positioned.draw(|x, y, v| {
+                    coverage[(y * width + x) as usize] = (v * 255.0) as u8;
+                });
+
+                let (x, y) = match self.allocate(width, height) {
+                    Some(position) => position,
+                    None => {
+                        // quads already queued still point at the old glyphs
+                        batch.flush();
+                        self.glyphs.clear();
+                        self.reset_shelves();
+                        self.allocate(width, height)?
+                    }
+                };
+                if let Err(e) =
+                    self.atlas
+                        .write_region(x, y, width, height, TextureFormat::R8, &coverage)
+                {
+                    println!("Failed to cache glyph: {}", e);
+                    return None;
+                }
+
+                let atlas = ATLAS_SIZE as f32;
+                Some(CachedGlyph {
+                    uv: [
+                        x as f32 / atlas,
+                        y as f32 / atlas,
+                        (x + width) as f32 / atlas,
+                        (y + height) as f32 / atlas,
+                    ],
+                    offset: (bounds.min.x as f32, bounds.min.y as f32),
+                    size: (width as f32, height as f32),
+                })
+            }
+            None => None,
+        };
+
+        self.glyphs.insert(key, cached);
+        cached
+    }
+
+    /// Finds room for a `width` x `height` glyph, `None` once the atlas is full.
+    fn allocate(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        let padded_width = width + GLYPH_PADDING;
+        let padded_height = height + GLYPH_PADDING;
+
+        if self.shelf_x + padded_width > ATLAS_SIZE {
+            self.shelf_y += self.shelf_height;
+            self.shelf_x = 0;
+            self.shelf_height = 0;
+        }
+        if self.shelf_x + padded_width > ATLAS_SIZE || self.shelf_y + padded_height > ATLAS_SIZE {
+            return None;
+        }
+
+        let position = (self.shelf_x, self.shelf_y);
+        self.shelf_x += padded_width;
+        self.shelf_height = self.shelf_height.max(padded_height);
+        Some(position)
+    }
+
+    /// The first shelf starts right of the solid block.
+    fn reset_shelves(&mut self) {
+        self.shelf_x = WHITE_SIZE + GLYPH_PADDING;
+        self.shelf_y = 0;
+        self.shelf_height = WHITE_SIZE + GLYPH_PADDING;
+    }
+}
@@ -1,29 +1,295 @@
-use crate::resources::Resources;
+use crate::resources::{self, Resources};
 use failure;
 use gl;
 use std::os::raw;
 
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Texture size {}x{} is invalid", width, height)]
+    InvalidSize { width: u32, height: u32 },
+    #[fail(
+        display = "Pixel buffer for {}x{} {:?} texture holds {} bytes, expected {}",
+        width, height, format, len, expected
+    )]
+    PixelBufferSize {
+        width: u32,
+        height: u32,
+        format: TextureFormat,
+        len: usize,
+        expected: usize,
+    },
+    #[fail(display = "{:?} textures have no sRGB internal format", format)]
+    NoSrgbFormat { format: TextureFormat },
+    #[fail(
+        display = "Min filter {:?} samples mipmaps, but none are generated",
+        filter
+    )]
+    MipmapFilterWithoutMipmaps { filter: MinFilter },
+    #[fail(display = "Anisotropy must be at least 1.0, got {}", anisotropy)]
+    InvalidAnisotropy { anisotropy: f32 },
+    #[fail(display = "Texture from resource {} needs resources to load", name)]
+    ResourcesRequired { name: String },
+    #[fail(display = "Texture array needs at least one layer")]
+    NoLayers,
+    #[fail(
+        display = "Texture array has {} layers, the driver supports at most {}",
+        layers, max_layers
+    )]
+    TooManyLayers { layers: usize, max_layers: usize },
+    #[fail(
+        display = "Layer {} ({}) is {}x{}, but layer 0 ({}) is {}x{}",
+        layer, name, width, height, first, expected_width, expected_height
+    )]
+    LayerSizeMismatch {
+        name: String,
+        layer: usize,
+        width: u32,
+        height: u32,
+        first: String,
+        expected_width: u32,
+        expected_height: u32,
+    },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TextureFormat {
+    /// single channel, for masks and glyph caches
+    R8,
+    Rgb8,
+    Rgba8,
+}
+
+impl TextureFormat {
+    pub fn channels(self) -> usize {
+        match self {
+            TextureFormat::R8 => 1,
+            TextureFormat::Rgb8 => 3,
+            TextureFormat::Rgba8 => 4,
+        }
+    }
+
+    fn gl_format(self) -> gl::types::GLenum {
+        match self {
+            TextureFormat::R8 => gl::RED,
+            TextureFormat::Rgb8 => gl::RGB,
+            TextureFormat::Rgba8 => gl::RGBA,
+        }
+    }
+
+    fn gl_internal_format(self, srgb: bool) -> Result<gl::types::GLenum, Error> {
+        match (self, srgb) {
+            (TextureFormat::R8, false) => Ok(gl::R8),
+            (TextureFormat::Rgb8, false) => Ok(gl::RGB8),
+            (TextureFormat::Rgba8, false) => Ok(gl::RGBA8),
+            (TextureFormat::Rgb8, true) => Ok(gl::SRGB8),
+            (TextureFormat::Rgba8, true) => Ok(gl::SRGB8_ALPHA8),
+            (format, true) => Err(Error::NoSrgbFormat { format }),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MinFilter {
+    Nearest,
+    Linear,
+    NearestMipmapNearest,
+    LinearMipmapNearest,
+    NearestMipmapLinear,
+    LinearMipmapLinear,
+}
+
+impl MinFilter {
+    fn uses_mipmaps(self) -> bool {
+        match self {
+            MinFilter::Nearest | MinFilter::Linear => false,
+            _ => true,
+        }
+    }
+
+    fn gl_enum(self) -> gl::types::GLenum {
+        match self {
+            MinFilter::Nearest => gl::NEAREST,
+            MinFilter::Linear => gl::LINEAR,
+            MinFilter::NearestMipmapNearest => gl::NEAREST_MIPMAP_NEAREST,
+            MinFilter::LinearMipmapNearest => gl::LINEAR_MIPMAP_NEAREST,
+            MinFilter::NearestMipmapLinear => gl::NEAREST_MIPMAP_LINEAR,
+            MinFilter::LinearMipmapLinear => gl::LINEAR_MIPMAP_LINEAR,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MagFilter {
+    Nearest,
+    Linear,
+}
+
+impl MagFilter {
+    fn gl_enum(self) -> gl::types::GLenum {
+        match self {
+            MagFilter::Nearest => gl::NEAREST,
+            MagFilter::Linear => gl::LINEAR,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Wrap {
+    ClampToEdge,
+    Repeat,
+    MirroredRepeat,
+}
+
+impl Wrap {
+    fn gl_enum(self) -> gl::types::GLenum {
+        match self {
+            Wrap::ClampToEdge => gl::CLAMP_TO_EDGE,
+            Wrap::Repeat => gl::REPEAT,
+            Wrap::MirroredRepeat => gl::MIRRORED_REPEAT,
+        }
+    }
+}
+
+/// How a texture is sampled.
+#[derive(Copy, Clone, Debug)]
+pub struct Sampler {
+    pub min_filter: MinFilter,
+    pub mag_filter: MagFilter,
+    pub wrap_s: Wrap,
+    pub wrap_t: Wrap,
+    /// max anisotropy, clamped to what the driver supports. `None` leaves it off.
+    pub anisotropy: Option<f32>,
+}
+
+impl Default for Sampler {
+    fn default() -> Sampler {
+        Sampler {
+            min_filter: MinFilter::Linear,
+            mag_filter: MagFilter::Linear,
+            wrap_s: Wrap::ClampToEdge,
+            wrap_t: Wrap::ClampToEdge,
+            anisotropy: None,
+        }
+    }
+}
+
+impl Sampler {
+    fn validate(&self, gen_mipmaps: bool) -> Result<(), Error> {
+        if self.min_filter.uses_mipmaps() && !gen_mipmaps {
+            return Err(Error::MipmapFilterWithoutMipmaps {
+                filter: self.min_filter,
+            });
+        }
+        if let Some(anisotropy) = self.anisotropy {
+            if anisotropy.is_nan() || anisotropy < 1.0 {
+                return Err(Error::InvalidAnisotropy { anisotropy });
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets filters, wrap modes and anisotropy of the texture bound to `target`.
+    unsafe fn apply(&self, gl: &gl::Gl, target: gl::types::GLenum) {
+        gl.TexParameteri(
+            target,
+            gl::TEXTURE_MIN_FILTER,
+            self.min_filter.gl_enum() as gl::types::GLint,
+        );
+        gl.TexParameteri(
+            target,
+            gl::TEXTURE_MAG_FILTER,
+            self.mag_filter.gl_enum() as gl::types::GLint,
+        );
+        gl.TexParameteri(
+            target,
+            gl::TEXTURE_WRAP_S,
+            self.wrap_s.gl_enum() as gl::types::GLint,
+        );
+        gl.TexParameteri(
+            target,
+            gl::TEXTURE_WRAP_T,
+            self.wrap_t.gl_enum() as gl::types::GLint,
+        );
+
+        if let Some(anisotropy) = self.anisotropy {
+            let mut max_anisotropy: gl::types::GLfloat = 0.0;
+            gl.GetFloatv(gl::MAX_TEXTURE_MAX_ANISOTROPY, &mut max_anisotropy);
+            // 0.0 when the driver lacks the extension, there is nothing to set then
+            if max_anisotropy >= 1.0 {
+                gl.TexParameterf(
+                    target,
+                    gl::TEXTURE_MAX_ANISOTROPY,
+                    anisotropy.min(max_anisotropy),
+                );
+            }
+        }
+    }
+}
+
+/// Where the texels come from.
+#[derive(Copy, Clone, Debug)]
+pub enum TextureSource<'a> {
+    Resource(&'a str),
+    Pixels {
+        width: u32,
+        height: u32,
+        data: &'a [u8],
+    },
+}
+
 pub struct TextureLoadOptions<'a> {
-    resource_name: &'a str,
-    format: gl::types::GLenum,
+    pub source: TextureSource<'a>,
+    pub format: TextureFormat,
+    /// store as sRGB so sampling returns linear values, for colour art that is blended
+    pub srgb: bool,
     pub gen_mipmaps: bool,
+    pub sampler: Sampler,
 }
 
 impl<'a> TextureLoadOptions<'a> {
-    pub fn from_res_rgb(resource_name: &str) -> TextureLoadOptions {
+    pub fn new(source: TextureSource<'a>, format: TextureFormat) -> TextureLoadOptions<'a> {
         TextureLoadOptions {
-            resource_name,
-            format: gl::RGB,
+            source,
+            format,
+            srgb: false,
             gen_mipmaps: false,
+            sampler: Sampler::default(),
         }
     }
 
+    pub fn from_res_r8(resource_name: &str) -> TextureLoadOptions {
+        TextureLoadOptions::new(TextureSource::Resource(resource_name), TextureFormat::R8)
+    }
+
+    pub fn from_res_rgb(resource_name: &str) -> TextureLoadOptions {
+        TextureLoadOptions::new(TextureSource::Resource(resource_name), TextureFormat::Rgb8)
+    }
+
     pub fn from_res_rgba(resource_name: &str) -> TextureLoadOptions {
-        TextureLoadOptions {
-            resource_name,
-            format: gl::RGBA,
-            gen_mipmaps: false,
-        }
+        TextureLoadOptions::new(TextureSource::Resource(resource_name), TextureFormat::Rgba8)
+    }
+
+    /// `data` is tightly packed rows of `format` texels, top row first.
+    pub fn from_pixels(
+        width: u32,
+        height: u32,
+        format: TextureFormat,
+        data: &[u8],
+    ) -> TextureLoadOptions {
+        TextureLoadOptions::new(
+            TextureSource::Pixels {
+                width,
+                height,
+                data,
+            },
+            format,
+        )
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        self.sampler.validate(self.gen_mipmaps)?;
+        self.format.gl_internal_format(self.srgb)?;
+        Ok(())
     }
 }
 
@@ -36,10 +302,44 @@ impl<'a> TextureLoadBuilder<'a> {
         Texture::from_res(self.options, gl, res)
     }
 
+    /// Creates a texture from a `TextureSource::Pixels` source, no resources needed.
+    pub fn create(self, gl: &gl::Gl) -> Result<Texture, failure::Error> {
+        let texture = Texture::new(gl);
+        texture.upload(&self.options, None)?;
+        Ok(texture)
+    }
+
     pub fn with_gen_mipmaps(mut self) -> Self {
         self.options.gen_mipmaps = true;
         self
     }
+
+    pub fn with_srgb(mut self) -> Self {
+        self.options.srgb = true;
+        self
+    }
+
+    pub fn with_filter(mut self, min_filter: MinFilter, mag_filter: MagFilter) -> Self {
+        self.options.sampler.min_filter = min_filter;
+        self.options.sampler.mag_filter = mag_filter;
+        self
+    }
+
+    /// Nearest filtering both ways, for pixel art.
+    pub fn with_nearest_filter(self) -> Self {
+        self.with_filter(MinFilter::Nearest, MagFilter::Nearest)
+    }
+
+    pub fn with_wrap(mut self, wrap_s: Wrap, wrap_t: Wrap) -> Self {
+        self.options.sampler.wrap_s = wrap_s;
+        self.options.sampler.wrap_t = wrap_t;
+        self
+    }
+
+    pub fn with_anisotropy(mut self, anisotropy: f32) -> Self {
+        self.options.sampler.anisotropy = Some(anisotropy);
+        self
+    }
 }
 
 pub struct Texture {
@@ -48,6 +348,12 @@ pub struct Texture {
 }
 
 impl Texture {
+    pub fn from_res_r8(resource_name: &str) -> TextureLoadBuilder {
+        TextureLoadBuilder {
+            options: TextureLoadOptions::from_res_r8(resource_name),
+        }
+    }
+
     pub fn from_res_rgb(resource_name: &str) -> TextureLoadBuilder {
         TextureLoadBuilder {
             options: TextureLoadOptions::from_res_rgb(resource_name),
@@ -60,21 +366,23 @@ impl Texture {
         }
     }
 
+    pub fn from_pixels(
+        width: u32,
+        height: u32,
+        format: TextureFormat,
+        data: &[u8],
+    ) -> TextureLoadBuilder {
+        TextureLoadBuilder {
+            options: TextureLoadOptions::from_pixels(width, height, format, data),
+        }
+    }
+
     pub fn from_res<'a>(
         options: TextureLoadOptions<'a>,
         gl: &gl::Gl,
         res: &Resources,
     ) -> Result<Texture, failure::Error> {
-        let mut obj: gl::types::GLuint = 0;
-        unsafe {
-            gl.GenTextures(1, &mut obj);
-        }
-
-        let texture = Texture {
-            gl: gl.clone(),
-            obj,
-        };
-
+        let texture = Texture::new(gl);
         texture.update(options, res)?;
 
         Ok(texture)
@@ -85,91 +393,99 @@ impl Texture {
         options: TextureLoadOptions<'a>,
         res: &Resources,
     ) -> Result<(), failure::Error> {
-        let gl = &self.gl;
+        self.upload(&options, Some(res))
+    }
 
+    fn new(gl: &gl::Gl) -> Texture {
+        let mut obj: gl::types::GLuint = 0;
         unsafe {
-            gl.BindTexture(gl::TEXTURE_2D, self.obj);
+            gl.GenTextures(1, &mut obj);
         }
 
-        // https://www.khronos.org/opengl/wiki/Common_Mistakes
-
-        match options.format {
-            gl::RGB => {
-                let img = res.load_rgb_image(options.resource_name)?;
-
-                if options.gen_mipmaps {
-                    unsafe {
-                        gl.TexImage2D(
-                            gl::TEXTURE_2D,
-                            0,
-                            gl::RGB8 as gl::types::GLint,
-                            img.width() as i32,
-                            img.height() as i32,
-                            0,
-                            gl::RGB,
-                            gl::UNSIGNED_BYTE,
-                            img.as_ptr() as *const raw::c_void,
-                        );
-                        gl.GenerateMipmap(gl::TEXTURE_2D);
-                    }
-                } else {
-                    unsafe {
-                        gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_BASE_LEVEL, 0);
-                        gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAX_LEVEL, 0);
-                        gl.TexImage2D(
-                            gl::TEXTURE_2D,
-                            0,
-                            gl::RGB8 as gl::types::GLint,
-                            img.width() as i32,
-                            img.height() as i32,
-                            0,
-                            gl::RGB,
-                            gl::UNSIGNED_BYTE,
-                            img.as_ptr() as *const raw::c_void,
-                        );
-                    }
-                }
+        Texture {
+            gl: gl.clone(),
+            obj,
+        }
+    }
+
+    fn upload(
+        &self,
+        options: &TextureLoadOptions,
+        res: Option<&Resources>,
+    ) -> Result<(), failure::Error> {
+        options.validate()?;
+
+        match options.source {
+            TextureSource::Pixels {
+                width,
+                height,
+                data,
+            } => self.upload_pixels(options, width, height, data),
+            TextureSource::Resource(name) => {
+                let res = res.ok_or_else(|| Error::ResourcesRequired { name: name.into() })?;
+                let (width, height, data) = load_pixels(res, name, options.format)?;
+                self.upload_pixels(options, width, height, &data)
             }
-            gl::RGBA => {
-                let img = res.load_rgba_image(options.resource_name)?;
-
-                if options.gen_mipmaps {
-                    unsafe {
-                        gl.TexImage2D(
-                            gl::TEXTURE_2D,
-                            0,
-                            gl::RGBA8 as gl::types::GLint,
-                            img.width() as i32,
-                            img.height() as i32,
-                            0,
-                            gl::RGBA,
-                            gl::UNSIGNED_BYTE,
-                            img.as_ptr() as *const raw::c_void,
-                        );
-                        gl.GenerateMipmap(gl::TEXTURE_2D);
-                    }
-                } else {
-                    unsafe {
-                        gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_BASE_LEVEL, 0);
-                        gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAX_LEVEL, 0);
-                        gl.TexImage2D(
-                            gl::TEXTURE_2D,
-                            0,
-                            gl::RGBA8 as gl::types::GLint,
-                            img.width() as i32,
-                            img.height() as i32,
-                            0,
-                            gl::RGBA,
-                            gl::UNSIGNED_BYTE,
-                            img.as_ptr() as *const raw::c_void,
-                        );
-                    }
-                }
+        }
+    }
+
+    fn upload_pixels(
+        &self,
+        options: &TextureLoadOptions,
+        width: u32,
+        height: u32,
+        data: &[u8],
+    ) -> Result<(), failure::Error> {
+        if width == 0
+            || height == 0
+            || width > i32::max_value() as u32
+            || height > i32::max_value() as u32
+        {
+            return Err(Error::InvalidSize { width, height }.into());
+        }
+        let expected = width as usize * height as usize * options.format.channels();
+        if data.len() != expected {
+            return Err(Error::PixelBufferSize {
+                width,
+                height,
+                format: options.format,
+                len: data.len(),
+                expected,
             }
-            _ => unreachable!("Only RGB or RGBA images can be constructed"),
+            .into());
         }
+        let internal_format = options.format.gl_internal_format(options.srgb)?;
+
+        let gl = &self.gl;
 
         unsafe {
+            gl.BindTexture(gl::TEXTURE_2D, self.obj);
+
+            // https://www.khronos.org/opengl/wiki/Common_Mistakes
+            if !options.gen_mipmaps {
+                gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_BASE_LEVEL, 0);
+                gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAX_LEVEL, 0);
+            }
+            options.sampler.apply(gl, gl::TEXTURE_2D);
+
+            // rows of RGB and R8 images are not 4 byte aligned
+            gl.PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            gl.TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                internal_format as gl::types::GLint,
+                width as i32,
+                height as i32,
+                0,
+                options.format.gl_format(),
+                gl::UNSIGNED_BYTE,
+                data.as_ptr() as *const raw::c_void,
+            );
+            gl.PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+
+            if options.gen_mipmaps {
+                gl.GenerateMipmap(gl::TEXTURE_2D);
+            }
             gl.BindTexture(gl::TEXTURE_2D, 0);
         }
 
@@ -194,6 +510,53 @@ impl Texture {
         }
         self.bind();
     }
+
+    pub fn id(&self) -> gl::types::GLuint {
+        self.obj
+    }
+
+    /// Overwrites a `width` x `height` block of level 0 at `x`, `y`, e.g. a glyph cache entry.
+    /// The region must lie within the texture, which GL checks.
+    pub fn write_region(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        format: TextureFormat,
+        data: &[u8],
+    ) -> Result<(), Error> {
+        let expected = width as usize * height as usize * format.channels();
+        if data.len() != expected {
+            return Err(Error::PixelBufferSize {
+                width,
+                height,
+                format,
+                len: data.len(),
+                expected,
+            });
+        }
+
+        unsafe {
+            self.gl.BindTexture(gl::TEXTURE_2D, self.obj);
+            self.gl.PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            self.gl.TexSubImage2D(
+                gl::TEXTURE_2D,
+                0,
+                x as i32,
+                y as i32,
+                width as i32,
+                height as i32,
+                format.gl_format(),
+                gl::UNSIGNED_BYTE,
+                data.as_ptr() as *const raw::c_void,
+            );
+            self.gl.PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+            self.gl.BindTexture(gl::TEXTURE_2D, 0);
+        }
+
+        Ok(())
+    }
 }
 
 impl Drop for Texture {
@@ -202,9 +565,286 @@ impl Drop for Texture {
     }
 }
 
+/// Decodes resource `name` into tightly packed `format` texels.
+fn load_pixels(
+    res: &Resources,
+    name: &str,
+    format: TextureFormat,
+) -> Result<(u32, u32, Vec<u8>), resources::Error> {
+    match format {
+        TextureFormat::R8 => {
+            let img = res.load_image(name)?.to_luma();
+            Ok((img.width(), img.height(), img.into_raw()))
+        }
+        TextureFormat::Rgb8 => {
+            let img = res.load_rgb_image(name)?;
+            Ok((img.width(), img.height(), img.into_raw()))
+        }
+        TextureFormat::Rgba8 => {
+            let img = res.load_rgba_image(name)?;
+            Ok((img.width(), img.height(), img.into_raw()))
+        }
+    }
+}
+
+pub struct TextureArrayLoadOptions<'a> {
+    /// one layer per resource, in order
+    pub resource_names: &'a [&'a str],
+    pub format: TextureFormat,
+    pub srgb: bool,
+    pub gen_mipmaps: bool,
+    pub sampler: Sampler,
+}
+
+impl<'a> TextureArrayLoadOptions<'a> {
+    pub fn new(
+        resource_names: &'a [&'a str],
+        format: TextureFormat,
+    ) -> TextureArrayLoadOptions<'a> {
+        TextureArrayLoadOptions {
+            resource_names,
+            format,
+            srgb: false,
+            gen_mipmaps: false,
+            sampler: Sampler::default(),
+        }
+    }
+}
+
+pub struct TextureArrayLoadBuilder<'a> {
+    options: TextureArrayLoadOptions<'a>,
+}
+
+impl<'a> TextureArrayLoadBuilder<'a> {
+    pub fn load(self, gl: &gl::Gl, res: &Resources) -> Result<TextureArray, failure::Error> {
+        TextureArray::from_options(self.options, gl, res)
+    }
+
+    pub fn with_gen_mipmaps(mut self) -> Self {
+        self.options.gen_mipmaps = true;
+        self
+    }
+
+    pub fn with_srgb(mut self) -> Self {
+        self.options.srgb = true;
+        self
+    }
+
+    pub fn with_filter(mut self, min_filter: MinFilter, mag_filter: MagFilter) -> Self {
+        self.options.sampler.min_filter = min_filter;
+        self.options.sampler.mag_filter = mag_filter;
+        self
+    }
+
+    /// Nearest filtering both ways, for pixel art.
+    pub fn with_nearest_filter(self) -> Self {
+        self.with_filter(MinFilter::Nearest, MagFilter::Nearest)
+    }
+
+    pub fn with_wrap(mut self, wrap_s: Wrap, wrap_t: Wrap) -> Self {
+        self.options.sampler.wrap_s = wrap_s;
+        self.options.sampler.wrap_t = wrap_t;
+        self
+    }
+
+    pub fn with_anisotropy(mut self, anisotropy: f32) -> Self {
+        self.options.sampler.anisotropy = Some(anisotropy);
+        self
+    }
+}
+
+/// A `GL_TEXTURE_2D_ARRAY` of same-sized images, e.g. the variants of one pathogen.
+/// Everything drawn from it can share one bind and pick its layer per vertex or instance,
+/// sampled with a `sampler2DArray` in the shader.
+pub struct TextureArray {
+    gl: gl::Gl,
+    obj: gl::types::GLuint,
+    width: u32,
+    height: u32,
+    layers: u32,
+}
+
+impl TextureArray {
+    pub fn from_res<'a>(
+        resource_names: &'a [&'a str],
+        format: TextureFormat,
+    ) -> TextureArrayLoadBuilder<'a> {
+        TextureArrayLoadBuilder {
+            options: TextureArrayLoadOptions::new(resource_names, format),
+        }
+    }
+
+    pub fn from_options(
+        options: TextureArrayLoadOptions,
+        gl: &gl::Gl,
+        res: &Resources,
+    ) -> Result<TextureArray, failure::Error> {
+        options.sampler.validate(options.gen_mipmaps)?;
+        let internal_format = options.format.gl_internal_format(options.srgb)?;
+
+        let layers = options.resource_names.len();
+        if layers == 0 {
+            return Err(Error::NoLayers.into());
+        }
+        let mut max_layers: gl::types::GLint = 0;
+        unsafe {
+            gl.GetIntegerv(gl::MAX_ARRAY_TEXTURE_LAYERS, &mut max_layers);
+        }
+        if layers > max_layers as usize {
+            return Err(Error::TooManyLayers {
+                layers,
+                max_layers: max_layers as usize,
+            }
+            .into());
+        }
+
+        let first_name = options.resource_names[0];
+        let (width, height, first) = load_pixels(res, first_name, options.format)?;
+        if width == 0 || height == 0 {
+            return Err(Error::InvalidSize { width, height }.into());
+        }
+
+        let mut obj: gl::types::GLuint = 0;
+        unsafe {
+            gl.GenTextures(1, &mut obj);
+        }
+        let array = TextureArray {
+            gl: gl.clone(),
+            obj,
+            width,
+            height,
+            layers: layers as u32,
+        };
+
+        unsafe {
+            gl.BindTexture(gl::TEXTURE_2D_ARRAY, obj);
+            if !options.gen_mipmaps {
+                gl.TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_BASE_LEVEL, 0);
+                gl.TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_MAX_LEVEL, 0);
+            }
+            options.sampler.apply(gl, gl::TEXTURE_2D_ARRAY);
+
+            gl.TexImage3D(
+                gl::TEXTURE_2D_ARRAY,
+                0,
+                internal_format as gl::types::GLint,
+                width as i32,
+                height as i32,
+                layers as i32,
+                0,
+                options.format.gl_format(),
+                gl::UNSIGNED_BYTE,
+                std::ptr::null(),
+            );
+        }
+
+        let result = array.upload_layers(&options, res, first);
+
+        unsafe {
+            if result.is_ok() && options.gen_mipmaps {
+                gl.GenerateMipmap(gl::TEXTURE_2D_ARRAY);
+            }
+            gl.BindTexture(gl::TEXTURE_2D_ARRAY, 0);
+        }
+
+        result.map(|_| array)
+    }
+
+    /// Expects the array to be bound. Stops at the first layer that fails to load.
+    fn upload_layers(
+        &self,
+        options: &TextureArrayLoadOptions,
+        res: &Resources,
+        first: Vec<u8>,
+    ) -> Result<(), failure::Error> {
+        let first_name = options.resource_names[0];
+        let mut pixels = first;
+
+        for (layer, name) in options.resource_names.iter().enumerate() {
+            if layer > 0 {
+                let (width, height, data) = load_pixels(res, name, options.format)?;
+                if (width, height) != (self.width, self.height) {
+                    return Err(Error::LayerSizeMismatch {
+                        name: (*name).into(),
+                        layer,
+                        width,
+                        height,
+                        first: first_name.into(),
+                        expected_width: self.width,
+                        expected_height: self.height,
+                    }
+                    .into());
+                }
+                pixels = data;
+            }
+
+            unsafe {
+                self.gl.PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+                self.gl.TexSubImage3D(
+                    gl::TEXTURE_2D_ARRAY,
+                    0,
+                    0,
+                    0,
+                    layer as i32,
+                    self.width as i32,
+                    self.height as i32,
+                    1,
+                    options.format.gl_format(),
+                    gl::UNSIGNED_BYTE,
+                    pixels.as_ptr() as *const raw::c_void,
+                );
+                self.gl.PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn layers(&self) -> u32 {
+        self.layers
+    }
+
+    pub fn id(&self) -> gl::types::GLuint {
+        self.obj
+    }
+
+    pub fn bind(&self) {
+        unsafe {
+            self.gl.BindTexture(gl::TEXTURE_2D_ARRAY, self.obj);
+        }
+    }
+
+    pub fn unbind(&self) {
+        unsafe {
+            self.gl.BindTexture(gl::TEXTURE_2D_ARRAY, 0);
+        }
+    }
+
+    pub fn bind_at(&self, index: u32) {
+        unsafe {
+            self.gl.ActiveTexture(gl::TEXTURE0 + index);
+        }
+        self.bind();
+    }
+}
+
+impl Drop for TextureArray {
+    fn drop(&mut self) {
+        unsafe { self.gl.DeleteTextures(1, &mut self.obj) };
+    }
+}
+
 // My first NON RUSTY attempt, following my man nercury now
 
-// use crate::resources::Resources;
+// use crate::resources::{self, Resources};
 // use gl::*;
 // use std::string::String;
 
@@ -272,3 +912,112 @@ impl Drop for Texture {
 //         unsafe { self.gl.DeleteTextures(1, &mut self.obj) };
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::MemorySource;
+    use gl::recording::RecordingGl;
+    use image::png::PNGEncoder;
+    use image::ColorType;
+    use std::rc::Rc;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let pixels = vec![255; (width * height * 4) as usize];
+        PNGEncoder::new(&mut bytes)
+            .encode(&pixels, width, height, ColorType::RGBA(8))
+            .unwrap();
+        bytes
+    }
+
+    fn res() -> Resources {
+        Resources::new().with_source(
+            0,
+            MemorySource::new()
+                .with("small.png", png(4, 4))
+                .with("other.png", png(4, 4))
+                .with("large.png", png(8, 4)),
+        )
+    }
+
+    fn load_array(recording: &Rc<RecordingGl>, names: &[&str]) -> Result<TextureArray, Error> {
+        let gl = gl::Gl::from_api(recording.clone());
+        TextureArray::from_res(names, TextureFormat::Rgba8)
+            .load(&gl, &res())
+            .map_err(|e| e.downcast::<Error>().unwrap())
+    }
+
+    #[test]
+    fn array_holds_one_layer_per_resource() {
+        let recording = Rc::new(RecordingGl::new());
+        let array = load_array(&recording, &["small.png", "other.png"]).unwrap();
+
+        assert_eq!((array.width(), array.height(), array.layers()), (4, 4, 2));
+        let uploads = recording
+            .calls()
+            .into_iter()
+            .filter(|call| call.name == "TexSubImage3D")
+            .map(|call| call.args[4].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(uploads, vec!["0", "1"]);
+
+        drop(array);
+        assert_eq!(recording.live_objects(), vec![]);
+    }
+
+    #[test]
+    fn array_without_layers_is_an_error() {
+        let recording = Rc::new(RecordingGl::new());
+        match load_array(&recording, &[]) {
+            Err(Error::NoLayers) => {}
+            Err(e) => panic!("expected NoLayers, got {}", e),
+            Ok(_) => panic!("expected NoLayers"),
+        }
+        assert_eq!(recording.live_objects(), vec![]);
+    }
+
+    #[test]
+    fn array_past_the_driver_limit_is_an_error() {
+        let recording = Rc::new(RecordingGl::new());
+        // the recording backend allows 256 layers
+        let names = vec!["small.png"; 257];
+        match load_array(&recording, &names) {
+            Err(Error::TooManyLayers { layers, max_layers }) => {
+                assert_eq!((layers, max_layers), (257, 256));
+            }
+            Err(e) => panic!("expected TooManyLayers, got {}", e),
+            Ok(_) => panic!("expected TooManyLayers"),
+        }
+        assert_eq!(recording.live_objects(), vec![]);
+    }
+
+    #[test]
+    fn layers_of_different_sizes_are_an_error() {
+        let recording = Rc::new(RecordingGl::new());
+        match load_array(&recording, &["small.png", "other.png", "large.png"]) {
+            Err(Error::LayerSizeMismatch {
+                name,
+                layer,
+                width,
+                height,
+                first,
+                expected_width,
+                expected_height,
+            }) => {
+                assert_eq!(
+                    (name.as_str(), layer, width, height),
+                    ("large.png", 2, 8, 4)
+                );
+                assert_eq!(
+                    (first.as_str(), expected_width, expected_height),
+                    ("small.png", 4, 4)
+                );
+            }
+            Err(e) => panic!("expected LayerSizeMismatch, got {}", e),
+            Ok(_) => panic!("expected LayerSizeMismatch"),
+        }
+        // the half built array is deleted again
+        assert_eq!(recording.live_objects(), vec![]);
+    }
+}
@@ -0,0 +1,461 @@
+use gl;
+use nalgebra as na;
+
+/// A single value that can be written to a uniform of `GL_TYPE`.
+/// Arrays of these are written in one call through the `[T]` impl of `Uniform`.
+pub trait UniformValue: Copy {
+    const GL_TYPE: gl::types::GLenum;
+
+    /// Whether a uniform declared as `gl_type` can take this value.
+    fn accepts(gl_type: gl::types::GLenum) -> bool {
+        gl_type == Self::GL_TYPE
+    }
+
+    unsafe fn upload(gl: &gl::Gl, location: i32, values: &[Self]);
+}
+
+/// Anything `Program::set_uniform` can write: a single `UniformValue` or a slice of them.
+pub trait Uniform {
+    fn gl_type(&self) -> gl::types::GLenum;
+    fn accepts(&self, gl_type: gl::types::GLenum) -> bool;
+    /// Array elements written, 1 for a single value.
+    fn count(&self) -> usize;
+    unsafe fn upload(&self, gl: &gl::Gl, location: i32);
+}
+
+impl<T: UniformValue> Uniform for T {
+    fn gl_type(&self) -> gl::types::GLenum {
+        T::GL_TYPE
+    }
+
+    fn accepts(&self, gl_type: gl::types::GLenum) -> bool {
+        T::accepts(gl_type)
+    }
+
+    fn count(&self) -> usize {
+        1
+    }
+
+    unsafe fn upload(&self, gl: &gl::Gl, location: i32) {
+        T::upload(gl, location, ::std::slice::from_ref(self));
+    }
+}
+
+impl<T: UniformValue> Uniform for [T] {
+    fn gl_type(&self) -> gl::types::GLenum {
+        T::GL_TYPE
+    }
+
+    fn accepts(&self, gl_type: gl::types::GLenum) -> bool {
+        T::accepts(gl_type)
+    }
+
+    fn count(&self) -> usize {
+        self.len()
+    }
+
+    unsafe fn upload(&self, gl: &gl::Gl, location: i32) {
+        T::upload(gl, location, self);
+    }
+}
+
+impl UniformValue for f32 {
+    const GL_TYPE: gl::types::GLenum = gl::FLOAT;
+
+    unsafe fn upload(gl: &gl::Gl, location: i32, values: &[Self]) {
+        gl.Uniform1fv(location, values.len() as i32, values.as_ptr());
+    }
+}
+
+impl UniformValue for i32 {
+    const GL_TYPE: gl::types::GLenum = gl::INT;
+
+    /// Samplers are set through their texture unit index.
+    fn accepts(gl_type: gl::types::GLenum) -> bool {
+        match gl_type {
+            gl::INT
+            | gl::SAMPLER_2D
+            | gl::SAMPLER_2D_ARRAY
+            | gl::SAMPLER_CUBE
+            | gl::INT_SAMPLER_2D
+            | gl::UNSIGNED_INT_SAMPLER_2D => true,
+            _ => false,
+        }
+    }
+
+    unsafe fn upload(gl: &gl::Gl, location: i32, values: &[Self]) {
+        gl.Uniform1iv(location, values.len() as i32, values.as_ptr());
+    }
+}
+
+impl UniformValue for u32 {
+    const GL_TYPE: gl::types::GLenum = gl::UNSIGNED_INT;
+
+    unsafe fn upload(gl: &gl::Gl, location: i32, values: &[Self]) {
+        gl.Uniform1uiv(location, values.len() as i32, values.as_ptr());
+    }
+}
+
+impl UniformValue for bool {
+    const GL_TYPE: gl::types::GLenum = gl::BOOL;
+
+    unsafe fn upload(gl: &gl::Gl, location: i32, values: &[Self]) {
+        // GL has no bool upload, bools are set as ints
+        let values = values.iter().map(|v| *v as i32).collect::<Vec<i32>>();
+        gl.Uniform1iv(location, values.len() as i32, values.as_ptr());
+    }
+}
+
+impl UniformValue for na::Vector2<f32> {
+    const GL_TYPE: gl::types::GLenum = gl::FLOAT_VEC2;
+
+    unsafe fn upload(gl: &gl::Gl, location: i32, values: &[Self]) {
+        gl.Uniform2fv(location, values.len() as i32, values.as_ptr() as *const f32);
+    }
+}
+
+impl UniformValue for na::Vector3<f32> {
+    const GL_TYPE: gl::types::GLenum = gl::FLOAT_VEC3;
+
+    unsafe fn upload(gl: &gl::Gl, location: i32, values: &[Self]) {
+        gl.Uniform3fv(location, values.len() as i32, values.as_ptr() as *const f32);
+    }
+}
+
+impl UniformValue for na::Vector4<f32> {
+    const GL_TYPE: gl::types::GLenum = gl::FLOAT_VEC4;
+
+    unsafe fn upload(gl: &gl::Gl, location: i32, values: &[Self]) {
+        gl.Uniform4fv(location, values.len() as i32, values.as_ptr() as *const f32);
+    }
+}
+
+// nalgebra matrices are column major, same as GL, so no transpose
+
+impl UniformValue for na::Matrix2<f32> {
+    const GL_TYPE: gl::types::GLenum = gl::FLOAT_MAT2;
+
+    unsafe fn upload(gl: &gl::Gl, location: i32, values: &[Self]) {
+        gl.UniformMatrix2fv(
+            location,
+            values.len() as i32,
+            gl::FALSE,
+            values.as_ptr() as *const f32,
+        );
+    }
+}
+
+impl UniformValue for na::Matrix3<f32> {
+    const GL_TYPE: gl::types::GLenum = gl::FLOAT_MAT3;
+
+    unsafe fn upload(gl: &gl::Gl, location: i32, values: &[Self]) {
+        gl.UniformMatrix3fv(
+            location,
+            values.len() as i32,
+            gl::FALSE,
+            values.as_ptr() as *const f32,
+        );
+    }
+}
+
+impl UniformValue for na::Matrix4<f32> {
+    const GL_TYPE: gl::types::GLenum = gl::FLOAT_MAT4;
+
+    unsafe fn upload(gl: &gl::Gl, location: i32, values: &[Self]) {
+        gl.UniformMatrix4fv(
+            location,
+            values.len() as i32,
+            gl::FALSE,
+            values.as_ptr() as *const f32,
+        );
+    }
+}
+
+// -----------------------------------------
+
+/// A value with a std140 layout, see section 7.6.2.2 of the GL 4.5 spec.
+pub trait Std140 {
+    /// Base alignment in bytes.
+    const ALIGN: usize;
+
+    fn write_std140(&self, out: &mut Vec<u8>);
+}
+
+fn write_f32s(out: &mut Vec<u8>, values: &[f32]) {
+    for v in values {
+        out.extend_from_slice(&v.to_ne_bytes());
+    }
+}
+
+impl Std140 for f32 {
+    const ALIGN: usize = 4;
+
+    fn write_std140(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_ne_bytes());
+    }
+}
+
+impl Std140 for i32 {
+    const ALIGN: usize = 4;
+
+    fn write_std140(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_ne_bytes());
+    }
+}
+
+impl Std140 for u32 {
+    const ALIGN: usize = 4;
+
+    fn write_std140(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_ne_bytes());
+    }
+}
+
+impl Std140 for bool {
+    const ALIGN: usize = 4;
+
+    fn write_std140(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&(*self as u32).to_ne_bytes());
+    }
+}
+
+impl Std140 for na::Vector2<f32> {
+    const ALIGN: usize = 8;
+
+    fn write_std140(&self, out: &mut Vec<u8>) {
+        write_f32s(out, self.as_slice());
+    }
+}
+
+impl Std140 for na::Vector3<f32> {
+    const ALIGN: usize = 16;
+
+    fn write_std140(&self, out: &mut Vec<u8>) {
+        write_f32s(out, self.as_slice());
+    }
+}
+
+impl Std140 for na::Vector4<f32> {
+    const ALIGN: usize = 16;
+
+    fn write_std140(&self, out: &mut Vec<u8>) {
+        write_f32s(out, self.as_slice());
+    }
+}
+
+/// Matrices are laid out as arrays of column vectors, each padded to a vec4.
+macro_rules! impl_std140_matrix {
+    ($matrix:ty, $rows:expr) => {
+        impl Std140 for $matrix {
+            const ALIGN: usize = 16;
+
+            fn write_std140(&self, out: &mut Vec<u8>) {
+                for column in self.as_slice().chunks($rows) {
+                    write_f32s(out, column);
+                    write_f32s(out, &[0.0; 4][$rows..]);
+                }
+            }
+        }
+    };
+}
+
+impl_std140_matrix!(na::Matrix2<f32>, 2);
+impl_std140_matrix!(na::Matrix3<f32>, 3);
+impl_std140_matrix!(na::Matrix4<f32>, 4);
+
+/// Builds the bytes of a std140 uniform block, member by member in declaration order.
+///
+/// ```ignore
+/// let mut block = Std140Writer::new();
+/// block.push(&projection).push(&view);
+/// ubo.dynamic_draw_data(block.as_bytes());
+/// ```
+#[derive(Default)]
+pub struct Std140Writer {
+    data: Vec<u8>,
+}
+
+impl Std140Writer {
+    pub fn new() -> Std140Writer {
+        Std140Writer { data: Vec::new() }
+    }
+
+    pub fn push<T: Std140>(&mut self, value: &T) -> &mut Self {
+        self.align_to(T::ALIGN);
+        value.write_std140(&mut self.data);
+        self
+    }
+
+    /// Array elements are aligned and strided to a multiple of 16 bytes.
+    pub fn push_array<T: Std140>(&mut self, values: &[T]) -> &mut Self {
+        let align = round_up(T::ALIGN, 16);
+        for value in values {
+            self.align_to(align);
+            value.write_std140(&mut self.data);
+        }
+        self.align_to(align);
+        self
+    }
+
+    /// A struct member: `members` pushes its fields, and the struct is aligned and padded
+    /// to a vec4 like an array element.
+    pub fn push_struct(&mut self, members: impl FnOnce(&mut Std140Writer)) -> &mut Self {
+        self.align_to(16);
+        members(self);
+        self.align_to(16);
+        self
+    }
+
+    /// The block size itself is rounded up to a vec4.
+    pub fn as_bytes(&mut self) -> &[u8] {
+        self.align_to(16);
+        &self.data
+    }
+
+    pub fn clear(&mut self) {
+        self.data.clear();
+    }
+
+    fn align_to(&mut self, align: usize) {
+        let len = round_up(self.data.len(), align);
+        self.data.resize(len, 0);
+    }
+}
+
+fn round_up(value: usize, align: usize) -> usize {
+    (value + align - 1) / align * align
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn f32_at(bytes: &[u8], offset: usize) -> f32 {
+        let mut word = [0; 4];
+        word.copy_from_slice(&bytes[offset..offset + 4]);
+        f32::from_ne_bytes(word)
+    }
+
+    fn i32_at(bytes: &[u8], offset: usize) -> i32 {
+        let mut word = [0; 4];
+        word.copy_from_slice(&bytes[offset..offset + 4]);
+        i32::from_ne_bytes(word)
+    }
+
+    #[test]
+    fn vec3_is_aligned_to_16_and_a_scalar_fits_after_it() {
+        let mut block = Std140Writer::new();
+        block
+            .push(&1.0f32)
+            .push(&na::Vector3::new(2.0, 3.0, 4.0))
+            .push(&5.0f32);
+        let bytes = block.as_bytes();
+
+        assert_eq!(bytes.len(), 32);
+        assert_eq!(f32_at(bytes, 0), 1.0);
+        assert_eq!(f32_at(bytes, 16), 2.0);
+        assert_eq!(f32_at(bytes, 24), 4.0);
+        assert_eq!(f32_at(bytes, 28), 5.0);
+    }
+
+    #[test]
+    fn mat3_columns_are_padded_to_vec4() {
+        let mut block = Std140Writer::new();
+        block.push(&na::Matrix3::new(
+            1.0, 4.0, 7.0, //
+            2.0, 5.0, 8.0, //
+            3.0, 6.0, 9.0,
+        ));
+        let bytes = block.as_bytes();
+
+        assert_eq!(bytes.len(), 48);
+        for column in 0..3 {
+            for row in 0..3 {
+                let expected = (column * 3 + row + 1) as f32;
+                assert_eq!(f32_at(bytes, column * 16 + row * 4), expected);
+            }
+            assert_eq!(f32_at(bytes, column * 16 + 12), 0.0);
+        }
+    }
+
+    #[test]
+    fn array_stride_is_rounded_up_to_16() {
+        let mut block = Std140Writer::new();
+        block
+            .push_array(&[1.0f32, 2.0])
+            .push_array(&[na::Vector2::new(3.0, 4.0), na::Vector2::new(5.0, 6.0)])
+            .push(&7.0f32);
+        let bytes = block.as_bytes();
+
+        assert_eq!(f32_at(bytes, 0), 1.0);
+        assert_eq!(f32_at(bytes, 16), 2.0);
+        assert_eq!(f32_at(bytes, 32), 3.0);
+        assert_eq!(f32_at(bytes, 48), 5.0);
+        // the member after an array starts at the next vec4
+        assert_eq!(f32_at(bytes, 64), 7.0);
+        assert_eq!(bytes.len(), 80);
+    }
+
+    /// The example block of the `ARB_uniform_buffer_object` spec, with `bvec2 e` as a
+    /// `vec2` and without the members whose types `Std140` doesn't cover:
+    ///
+    /// ```glsl
+    /// layout(std140) uniform Example {
+    ///     float a;            // offset 0
+    ///     vec2 b;             // 8
+    ///     vec3 c;             // 16
+    ///     struct {
+    ///         int d;          // 32
+    ///         vec2 e;         // 40
+    ///     } f;
+    ///     float g;            // 48
+    ///     float h[2];         // 64, 80
+    ///     mat3 n[2];          // 96, 144
+    /// };                      // 192 bytes
+    /// ```
+    #[test]
+    fn matches_the_spec_example() {
+        let mut block = Std140Writer::new();
+        block
+            .push(&1.0f32)
+            .push(&na::Vector2::new(2.0, 2.5))
+            .push(&na::Vector3::new(3.0, 3.5, 3.75))
+            .push_struct(|f| {
+                f.push(&4i32).push(&na::Vector2::new(5.0, 5.5));
+            })
+            .push(&6.0f32)
+            .push_array(&[7.0f32, 8.0])
+            .push_array(&[
+                na::Matrix3::from_element(9.0),
+                na::Matrix3::from_element(10.0),
+            ]);
+        let bytes = block.as_bytes();
+
+        assert_eq!(f32_at(bytes, 0), 1.0);
+        assert_eq!(f32_at(bytes, 8), 2.0);
+        assert_eq!(f32_at(bytes, 16), 3.0);
+        assert_eq!(i32_at(bytes, 32), 4);
+        assert_eq!(f32_at(bytes, 40), 5.0);
+        assert_eq!(f32_at(bytes, 48), 6.0);
+        assert_eq!(f32_at(bytes, 64), 7.0);
+        assert_eq!(f32_at(bytes, 80), 8.0);
+        assert_eq!(f32_at(bytes, 96), 9.0);
+        assert_eq!(f32_at(bytes, 144), 10.0);
+        assert_eq!(bytes.len(), 192);
+    }
+
+    #[test]
+    fn struct_is_padded_to_vec4_at_its_end() {
+        let mut block = Std140Writer::new();
+        block
+            .push_struct(|s| {
+                s.push(&1.0f32);
+            })
+            .push(&2.0f32);
+        let bytes = block.as_bytes();
+
+        assert_eq!(f32_at(bytes, 0), 1.0);
+        assert_eq!(f32_at(bytes, 16), 2.0);
+        assert_eq!(bytes.len(), 32);
+    }
+}
@@ -1,15 +1,25 @@
 use gl;
 
+/// The area drawn to, in drawable pixels. On high-DPI displays a window has more of them
+/// than window coordinates, which SDL reports window sizes and the mouse in.
 pub struct Viewport {
     pub x: i32,
     pub y: i32,
     pub w: i32,
     pub h: i32,
+    /// drawable pixels per window coordinate
+    pub pixel_ratio: f32,
 }
 
 impl Viewport {
     pub fn for_window(w: i32, h: i32) -> Viewport {
-        Viewport { x: 0, y: 0, w, h }
+        Viewport {
+            x: 0,
+            y: 0,
+            w,
+            h,
+            pixel_ratio: 1.0,
+        }
     }
 
     pub fn update_size(&mut self, w: i32, h: i32) {
@@ -17,6 +27,15 @@ impl Viewport {
         self.h = h;
     }
 
+    /// Sizes the viewport to a window's drawable of `w` x `h` pixels, `window_w` window
+    /// coordinates wide.
+    pub fn update_drawable_size(&mut self, w: i32, h: i32, window_w: i32) {
+        self.update_size(w, h);
+        if w > 0 && window_w > 0 {
+            self.pixel_ratio = w as f32 / window_w as f32;
+        }
+    }
+
     pub fn set_used(&self, gl: &gl::Gl) {
         unsafe {
             gl.Viewport(self.x, self.y, self.w, self.h);
@@ -0,0 +1,418 @@
+//! Recording and playing back matches.
+//!
+//! A replay holds what a match needs to play out again: the level, the seed, the game
+//! version and every command that changes the simulation, stamped with the tick it was
+//! applied before. Every `CHECKSUM_INTERVAL` ticks a checksum of the match state is stored
+//! too, so playback can tell the first tick at which it disagrees with the recording.
+
+use crate::game::{Command, Game};
+use crate::hash::StateHasher;
+use crate::level::{Level, TileCoord};
+use crate::rng::{self, hex_u64};
+use crate::storage;
+use std::fmt;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use toml;
+
+/// Version of the game that recorded a replay, playback warns when it differs.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Ticks between two state checksums, one per simulated second.
+pub const CHECKSUM_INTERVAL: u64 = 60;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to access replay {}", path)]
+    Io {
+        path: String,
+        #[cause]
+        inner: io::Error,
+    },
+    #[fail(display = "Failed to parse replay {}", path)]
+    Parse {
+        path: String,
+        #[cause]
+        inner: toml::de::Error,
+    },
+    #[fail(display = "Failed to write replay {}", path)]
+    Serialize {
+        path: String,
+        #[cause]
+        inner: toml::ser::Error,
+    },
+}
+
+/// A command applied right before tick `tick + 1`, when `tick` ticks had run.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Action {
+    pub tick: u64,
+    pub command: Command,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Checksum {
+    pub tick: u64,
+    #[serde(with = "hex_u64")]
+    pub value: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: String,
+    /// resource name of the level, e.g. `levels/arm.toml`
+    pub level: String,
+    #[serde(with = "hex_u64")]
+    pub seed: u64,
+    /// ticks run when recording stopped
+    pub ticks: u64,
+    #[serde(default)]
+    pub actions: Vec<Action>,
+    #[serde(default)]
+    pub checksums: Vec<Checksum>,
+}
+
+impl Replay {
+    pub fn new(level: &str, seed: u64) -> Replay {
+        Replay {
+            version: VERSION.into(),
+            level: level.into(),
+            seed,
+            ticks: 0,
+            actions: Vec::new(),
+            checksums: Vec::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Replay, Error> {
+        let text = fs::read_to_string(path).map_err(|e| Error::Io {
+            path: path.display().to_string(),
+            inner: e,
+        })?;
+
+        toml::from_str(&text).map_err(|e| Error::Parse {
+            path: path.display().to_string(),
+            inner: e,
+        })
+    }
+
+    /// A warning if this replay was recorded by another version of the game, which may
+    /// play it out differently.
+    pub fn version_warning(&self) -> Option<String> {
+        if self.version == VERSION {
+            None
+        } else {
+            Some(format!(
+                "Replay was recorded with version {}, this is version {}",
+                self.version, VERSION
+            ))
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let text = toml::to_string(self).map_err(|e| Error::Serialize {
+            path: path.display().to_string(),
+            inner: e,
+        })?;
+
+        storage::write_atomic(path, text.as_bytes()).map_err(|e| Error::Io {
+            path: path.display().to_string(),
+            inner: e,
+        })
+    }
+}
+
+/// Where `--record` writes to when no file is given, in the working directory.
+pub fn default_path() -> PathBuf {
+    PathBuf::from("last_replay.toml")
+}
+
+/// A hash of everything the simulation depends on. Cosmetic randomness and game speed are
+/// left out, they may differ between recording and playback.
+pub fn checksum(game: &Game) -> u64 {
+    let mut hasher = StateHasher::new();
+    let h = &mut hasher;
+
+    game.ticks().hash(h);
+    game.gold().hash(h);
+    game.heart_health().hash(h);
+    (game.waves().started() as u64).hash(h);
+    game.waves().countdown().map(f32::to_bits).hash(h);
+    game.rng().peek(rng::GAMEPLAY).hash(h);
+
+    for (tile, tower) in game.towers().iter() {
+        hash_tile(Some(tile), h);
+        tower.tower.hash(h);
+        tower.cooldown.hash(h);
+        tower.kills.hash(h);
+    }
+
+    let world = game.world();
+    for enemy in &world.enemies {
+        enemy.id.hash(h);
+        enemy.health.to_bits().hash(h);
+        enemy.position.x.to_bits().hash(h);
+        enemy.position.y.to_bits().hash(h);
+        hash_tile(enemy.target, h);
+    }
+    for projectile in &world.projectiles {
+        projectile.target.hash(h);
+        projectile.position.x.to_bits().hash(h);
+        projectile.position.y.to_bits().hash(h);
+    }
+    for spawn in &world.spawns {
+        spawn.tick.hash(h);
+        spawn.pathogen.hash(h);
+    }
+
+    hasher.finish()
+}
+
+/// `usize` hashes differently on 32 and 64 bit platforms.
+fn hash_tile(tile: Option<TileCoord>, h: &mut StateHasher) {
+    tile.map(|t| (t.col as u64, t.row as u64)).hash(h);
+}
+
+/// Collects a replay while the match is played.
+pub struct Recorder {
+    replay: Replay,
+    /// a checksum was added since the last `save_if_due`
+    unsaved: bool,
+}
+
+impl Recorder {
+    pub fn new(level: &str, seed: u64) -> Recorder {
+        Recorder {
+            replay: Replay::new(level, seed),
+            unsaved: false,
+        }
+    }
+
+    /// Notes `command` if it changes the simulation, call right before applying it.
+    pub fn record(&mut self, game: &Game, command: &Command) {
+        if command.affects_simulation() {
+            self.replay.actions.push(Action {
+                tick: game.ticks(),
+                command: command.clone(),
+            });
+        }
+    }
+
+    /// Call after every tick.
+    pub fn after_tick(&mut self, game: &Game) {
+        // a match that is over doesn't tick anymore
+        if game.ticks() == self.replay.ticks {
+            return;
+        }
+        self.replay.ticks = game.ticks();
+        if game.ticks() % CHECKSUM_INTERVAL == 0 {
+            self.replay.checksums.push(Checksum {
+                tick: game.ticks(),
+                value: checksum(game),
+            });
+            self.unsaved = true;
+        }
+    }
+
+    /// Writes the replay to `path` if a checksum was added since the last write, so a
+    /// crash loses at most `CHECKSUM_INTERVAL` ticks of it. Call after every update.
+    pub fn save_if_due(&mut self, path: &Path) -> Result<(), Error> {
+        if !self.unsaved {
+            return Ok(());
+        }
+        self.unsaved = false;
+        self.replay.save(path)
+    }
+
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+}
+
+/// The first tick at which playback disagreed with the recording.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    pub tick: u64,
+    pub expected: u64,
+    pub actual: u64,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "playback diverged at tick {}: checksum {:016x}, recorded {:016x}",
+            self.tick, self.actual, self.expected
+        )
+    }
+}
+
+/// Feeds a replay's commands to a match at the ticks they were recorded at.
+pub struct Playback {
+    replay: Replay,
+    next_action: usize,
+    next_checksum: usize,
+    checked: usize,
+    divergence: Option<Divergence>,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Playback {
+        Playback {
+            replay,
+            next_action: 0,
+            next_checksum: 0,
+            checked: 0,
+            divergence: None,
+        }
+    }
+
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    /// Applies the commands due before the next tick.
+    pub fn before_tick(&mut self, game: &mut Game, level: &Level) {
+        while let Some(action) = self.replay.actions.get(self.next_action) {
+            if action.tick > game.ticks() {
+                break;
+            }
+            game.apply(level, action.command.clone());
+            self.next_action += 1;
+        }
+    }
+
+    /// Compares the state with the recording, call after every tick.
+    pub fn after_tick(&mut self, game: &Game) {
+        while let Some(recorded) = self.replay.checksums.get(self.next_checksum) {
+            if recorded.tick > game.ticks() {
+                break;
+            }
+            self.next_checksum += 1;
+            if recorded.tick < game.ticks() {
+                continue;
+            }
+
+            self.checked += 1;
+            let actual = checksum(game);
+            if actual != recorded.value && self.divergence.is_none() {
+                self.divergence = Some(Divergence {
+                    tick: recorded.tick,
+                    expected: recorded.value,
+                    actual,
+                });
+            }
+        }
+    }
+
+    /// True once the match got as far as the recording did.
+    pub fn is_finished(&self, game: &Game) -> bool {
+        game.ticks() >= self.replay.ticks
+    }
+
+    /// Checksums compared so far.
+    pub fn checked(&self) -> usize {
+        self.checked
+    }
+
+    pub fn divergence(&self) -> Option<Divergence> {
+        self.divergence
+    }
+}
+
+/// A match that is either recorded or played back, whichever it is the commands and ticks
+/// of the match go through here.
+pub enum Session {
+    Recording(Recorder),
+    Playback(Playback),
+    /// neither, for a match continued from a save: it didn't start from its seed
+    Off,
+}
+
+impl Session {
+    /// During playback only commands that leave the simulation alone, like pausing, are
+    /// taken from the player.
+    pub fn apply(&mut self, game: &mut Game, level: &Level, command: Command) {
+        match self {
+            Session::Recording(recorder) => {
+                recorder.record(game, &command);
+                game.apply(level, command);
+            }
+            Session::Playback(_) if command.affects_simulation() => {}
+            Session::Playback(_) | Session::Off => game.apply(level, command),
+        }
+    }
+
+    /// Runs one tick, with the recorded commands due before it during playback.
+    pub fn tick(&mut self, game: &mut Game, level: &Level) {
+        match self {
+            Session::Recording(recorder) => {
+                game.tick(level);
+                recorder.after_tick(game);
+            }
+            Session::Playback(playback) => {
+                if playback.is_finished(game) {
+                    return;
+                }
+                playback.before_tick(game, level);
+                game.tick(level);
+                playback.after_tick(game);
+            }
+            Session::Off => game.tick(level),
+        }
+    }
+
+    /// Runs this frame's ticks, see `Game::frame_ticks`.
+    pub fn update(&mut self, game: &mut Game, level: &Level) {
+        for _ in 0..game.frame_ticks() {
+            self.tick(game, level);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pathogen::PathogenCatalog;
+    use crate::resources::Resources;
+    use crate::tower::TowerCatalog;
+    use std::env;
+
+    #[test]
+    fn recording_is_saved_once_per_checksum() {
+        let res = Resources::from_directory(&Path::new(env!("CARGO_MANIFEST_DIR")).join("res"));
+        let level = Level::from_res(&res, "levels/arm.toml").unwrap();
+        let catalog = TowerCatalog::from_res(&res, "towers.toml").unwrap();
+        let pathogens = PathogenCatalog::from_res(&res, "pathogens.toml").unwrap();
+        let mut game = Game::new(&level, catalog, pathogens, 3).unwrap();
+
+        let dir = env::temp_dir().join("tow_def_game_replay_flush");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("replay.toml");
+
+        let mut session = Session::Recording(Recorder::new("levels/arm.toml", 3));
+        let save = |session: &mut Session| match session {
+            Session::Recording(recorder) => recorder.save_if_due(&path).unwrap(),
+            _ => unreachable!(),
+        };
+        for _ in 0..CHECKSUM_INTERVAL - 1 {
+            session.tick(&mut game, &level);
+            save(&mut session);
+        }
+        assert!(!path.exists());
+
+        session.tick(&mut game, &level);
+        save(&mut session);
+        let saved = Replay::load(&path).unwrap();
+        assert_eq!(saved.ticks, CHECKSUM_INTERVAL);
+        assert_eq!(saved.checksums.len(), 1);
+
+        // nothing new to save until the next checksum
+        fs::remove_file(&path).unwrap();
+        session.tick(&mut game, &level);
+        save(&mut session);
+        assert!(!path.exists());
+    }
+}
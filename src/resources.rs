@@ -1,7 +1,10 @@
+use crate::archive::{self, Archive};
+use failure;
 use image;
+use std::collections::HashMap;
 use std::ffi;
 use std::fs;
-use std::io::{self, Read};
+use std::io;
 use std::path::{Path, PathBuf};
 
 #[derive(Debug, Fail)]
@@ -16,6 +19,18 @@ pub enum Error {
     FailedToLoadImage(#[cause] image::ImageError),
     #[fail(display = "Image {} is not RGBA", name)]
     ImageIsNotRgba { name: String },
+    #[fail(display = "Resource {} not found", name)]
+    ResourceNotFound { name: String },
+    #[fail(display = "Resource {} is not valid UTF-8", name)]
+    FileIsNotUtf8 { name: String },
+    #[fail(display = "Failed to read resource archive")]
+    Archive(#[cause] archive::Error),
+    #[fail(display = "Failed to load asset {}", name)]
+    FailedToLoadAsset {
+        name: String,
+        #[cause]
+        inner: failure::Compat<failure::Error>,
+    },
 }
 
 impl From<io::Error> for Error {
@@ -30,31 +45,181 @@ impl From<image::ImageError> for Error {
     }
 }
 
-pub struct Resources {
+/// Somewhere resources can be read from.
+pub trait Source: Send + Sync {
+    /// `None` when this source has no resource `resource_name`.
+    fn read(&self, resource_name: &str) -> Option<Result<Vec<u8>, Error>>;
+}
+
+/// Loose files under a root directory.
+pub struct DirectorySource {
     root_path: PathBuf,
 }
 
+impl DirectorySource {
+    pub fn new(root_path: &Path) -> DirectorySource {
+        DirectorySource {
+            root_path: root_path.into(),
+        }
+    }
+}
+
+impl Source for DirectorySource {
+    fn read(&self, resource_name: &str) -> Option<Result<Vec<u8>, Error>> {
+        match fs::read(resource_name_to_path(&self.root_path, resource_name)) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => None,
+            result => Some(result.map_err(Error::from)),
+        }
+    }
+}
+
+impl Source for Archive {
+    fn read(&self, resource_name: &str) -> Option<Result<Vec<u8>, Error>> {
+        Archive::read(self, resource_name).map(|result| result.map_err(Error::Archive))
+    }
+}
+
+/// Resources compiled into the executable, see `embed_resources!`.
+pub struct EmbeddedSource {
+    files: &'static [(&'static str, &'static [u8])],
+}
+
+impl EmbeddedSource {
+    pub fn new(files: &'static [(&'static str, &'static [u8])]) -> EmbeddedSource {
+        EmbeddedSource { files }
+    }
+}
+
+impl Source for EmbeddedSource {
+    fn read(&self, resource_name: &str) -> Option<Result<Vec<u8>, Error>> {
+        self.files
+            .iter()
+            .find(|(name, _)| *name == resource_name)
+            .map(|(_, data)| Ok(data.to_vec()))
+    }
+}
+
+/// Builds an `EmbeddedSource` from paths relative to `res/`:
+/// `embed_resources!["shaders/tex.vert", "shaders/tex.frag"]`.
+#[macro_export]
+macro_rules! embed_resources {
+    ($($name:expr),* $(,)?) => {
+        $crate::resources::EmbeddedSource::new(&[
+            $(($name, include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/res/", $name)) as &[u8]),)*
+        ])
+    };
+}
+
+/// Resources held in memory, for tests and tools.
+#[derive(Default)]
+pub struct MemorySource {
+    files: HashMap<String, Vec<u8>>,
+}
+
+impl MemorySource {
+    pub fn new() -> MemorySource {
+        MemorySource::default()
+    }
+
+    pub fn with(mut self, resource_name: &str, data: impl Into<Vec<u8>>) -> Self {
+        self.insert(resource_name, data);
+        self
+    }
+
+    pub fn insert(&mut self, resource_name: &str, data: impl Into<Vec<u8>>) {
+        self.files.insert(resource_name.into(), data.into());
+    }
+}
+
+impl Source for MemorySource {
+    fn read(&self, resource_name: &str) -> Option<Result<Vec<u8>, Error>> {
+        self.files.get(resource_name).map(|data| Ok(data.clone()))
+    }
+}
+
+/// Priorities used by `from_relative_exe_path`, higher shadows lower.
+pub const PRIORITY_ARCHIVE: i32 = 0;
+pub const PRIORITY_DIRECTORY: i32 = 10;
+pub const PRIORITY_MODS: i32 = 20;
+
+/// A stack of sources. Each resource is read from the highest priority source that has it,
+/// so a mod directory can shadow single files of the base game.
+#[derive(Default)]
+pub struct Resources {
+    sources: Vec<(i32, Box<dyn Source>)>,
+}
+
 impl Resources {
+    pub fn new() -> Resources {
+        Resources::default()
+    }
+
+    /// Loose files in `<exe dir>/<rel_path>` over `<exe dir>/<rel_path>.pak` if it exists,
+    /// with `<exe dir>/mods` over both.
     pub fn from_relative_exe_path(rel_path: &Path) -> Result<Resources, Error> {
         let exe_file_name = ::std::env::current_exe().map_err(|_| Error::FailedToGetExePath)?;
 
         let exe_path = exe_file_name.parent().ok_or(Error::FailedToGetExePath)?;
 
-        Ok(Resources {
-            root_path: exe_path.join(rel_path),
-        })
+        let root_path = exe_path.join(rel_path);
+        let archive_path = root_path.with_extension("pak");
+        let mods_path = exe_path.join("mods");
+
+        let mut resources = Resources::from_directory(&root_path);
+        if archive_path.is_file() {
+            let archive = Archive::open(&archive_path).map_err(Error::Archive)?;
+            resources.add_source(PRIORITY_ARCHIVE, archive);
+        }
+        if mods_path.is_dir() {
+            resources.add_source(PRIORITY_MODS, DirectorySource::new(&mods_path));
+        }
+
+        Ok(resources)
     }
 
     pub fn from_exe_path() -> Result<Resources, Error> {
         Resources::from_relative_exe_path(Path::new(""))
     }
 
-    pub fn load_cstring(&self, resource_name: &str) -> Result<ffi::CString, Error> {
-        let mut file = fs::File::open(resource_name_to_path(&self.root_path, resource_name))?;
+    pub fn from_directory(root_path: &Path) -> Resources {
+        Resources::new().with_source(PRIORITY_DIRECTORY, DirectorySource::new(root_path))
+    }
+
+    pub fn with_source<S: Source + 'static>(mut self, priority: i32, source: S) -> Self {
+        self.add_source(priority, source);
+        self
+    }
+
+    /// Sources with equal priority are searched in the order they were added.
+    pub fn add_source<S: Source + 'static>(&mut self, priority: i32, source: S) {
+        let index = self
+            .sources
+            .iter()
+            .position(|(p, _)| *p < priority)
+            .unwrap_or_else(|| self.sources.len());
+        self.sources.insert(index, (priority, Box::new(source)));
+    }
+
+    pub fn load_bytes(&self, resource_name: &str) -> Result<Vec<u8>, Error> {
+        self.sources
+            .iter()
+            .filter_map(|(_, source)| source.read(resource_name))
+            .next()
+            .unwrap_or_else(|| {
+                Err(Error::ResourceNotFound {
+                    name: resource_name.into(),
+                })
+            })
+    }
 
-        // allocate buffer of the same size as file
-        let mut buffer: Vec<u8> = Vec::with_capacity(file.metadata()?.len() as usize + 1);
-        file.read_to_end(&mut buffer)?;
+    pub fn load_string(&self, resource_name: &str) -> Result<String, Error> {
+        String::from_utf8(self.load_bytes(resource_name)?).map_err(|_| Error::FileIsNotUtf8 {
+            name: resource_name.into(),
+        })
+    }
+
+    pub fn load_cstring(&self, resource_name: &str) -> Result<ffi::CString, Error> {
+        let buffer = self.load_bytes(resource_name)?;
 
         // check for nul byte
         if buffer.iter().find(|i| **i == 0).is_some() {
@@ -64,16 +229,20 @@ impl Resources {
         Ok(unsafe { ffi::CString::from_vec_unchecked(buffer) })
     }
 
+    pub fn load_image(&self, resource_name: &str) -> Result<image::DynamicImage, Error> {
+        Ok(image::load_from_memory(&self.load_bytes(resource_name)?)?)
+    }
+
     pub fn load_rgb_image(&self, resource_name: &str) -> Result<image::RgbImage, Error> {
-        let img = image::open(resource_name_to_path(&self.root_path, resource_name))?;
+        let img = image::load_from_memory(&self.load_bytes(resource_name)?)?;
 
         Ok(img.to_rgb())
     }
 
     pub fn load_rgba_image(&self, resource_name: &str) -> Result<image::RgbaImage, Error> {
-        let img = image::open(resource_name_to_path(&self.root_path, resource_name))?;
+        let img = image::load_from_memory(&self.load_bytes(resource_name)?)?;
 
-        if let image::ColorType::RGBA(_) = img.color() {
+        if has_alpha(img.color()) {
             Ok(img.to_rgba())
         } else {
             Err(Error::ImageIsNotRgba {
@@ -83,6 +252,14 @@ impl Resources {
     }
 }
 
+/// True for images with an alpha channel, they are loaded as RGBA and all others as RGB.
+pub fn has_alpha(color: image::ColorType) -> bool {
+    match color {
+        image::ColorType::RGBA(_) | image::ColorType::BGRA(_) | image::ColorType::GrayA(_) => true,
+        _ => false,
+    }
+}
+
 fn resource_name_to_path(root_dir: &Path, location: &str) -> PathBuf {
     let mut path: PathBuf = root_dir.into();
 
@@ -92,3 +269,68 @@ fn resource_name_to_path(root_dir: &Path, location: &str) -> PathBuf {
 
     path
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let pixels = vec![255u8; (width * height * 4) as usize];
+        let mut bytes = Vec::new();
+        image::png::PNGEncoder::new(&mut bytes)
+            .encode(&pixels, width, height, image::ColorType::RGBA(8))
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn higher_priority_shadows_lower() {
+        let res = Resources::new()
+            .with_source(PRIORITY_ARCHIVE, MemorySource::new().with("a.txt", "base"))
+            .with_source(PRIORITY_MODS, MemorySource::new().with("a.txt", "mod"));
+
+        assert_eq!(res.load_string("a.txt").unwrap(), "mod");
+    }
+
+    #[test]
+    fn missing_names_fall_through() {
+        let res = Resources::new()
+            .with_source(
+                PRIORITY_ARCHIVE,
+                MemorySource::new()
+                    .with("a.txt", "base")
+                    .with("b.txt", "base"),
+            )
+            .with_source(PRIORITY_MODS, MemorySource::new().with("a.txt", "mod"));
+
+        assert_eq!(res.load_string("b.txt").unwrap(), "base");
+        match res.load_bytes("c.txt") {
+            Err(Error::ResourceNotFound { name }) => assert_eq!(name, "c.txt"),
+            other => panic!("expected a missing resource, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn typed_loads_read_from_any_source() {
+        let res = Resources::new().with_source(
+            PRIORITY_DIRECTORY,
+            MemorySource::new()
+                .with("shader.vert", "void main() {}")
+                .with("nul.vert", vec![b'a', 0, b'b'])
+                .with("sprite.png", png(2, 3)),
+        );
+
+        assert_eq!(
+            res.load_cstring("shader.vert").unwrap().as_bytes(),
+            b"void main() {}"
+        );
+        match res.load_cstring("nul.vert") {
+            Err(Error::FileContainsNil) => {}
+            other => panic!("expected a nul byte error, got {:?}", other),
+        }
+        assert_eq!(
+            res.load_rgba_image("sprite.png").unwrap().dimensions(),
+            (2, 3)
+        );
+    }
+}
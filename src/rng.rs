@@ -0,0 +1,191 @@
+//! Seeded randomness for a match.
+//!
+//! One seed per match feeds independent named streams, so drawing numbers for cosmetic
+//! effects never changes what the simulation draws. Generators are PCG32 (pcg-random.org):
+//! small, fast and with a state that serializes into saves and replays.
+
+use crate::hash::StateHasher;
+use serde::{Deserialize, Deserializer, Serializer};
+use std::collections::BTreeMap;
+use std::hash::Hasher;
+
+const MULTIPLIER: u64 = 6_364_136_223_846_793_005;
+
+/// Everything the simulation draws: critical hits, spawn jitter, loot.
+pub const GAMEPLAY: &str = "gameplay";
+/// Draws that only change what is shown or heard, like particle spread.
+pub const COSMETIC: &str = "cosmetic";
+
+/// A PCG32 generator. Streams with a different `stream` id give unrelated sequences for the
+/// same seed.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Rng {
+    #[serde(with = "hex_u64")]
+    state: u64,
+    #[serde(with = "hex_u64")]
+    increment: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64, stream: u64) -> Rng {
+        let mut rng = Rng {
+            state: 0,
+            increment: (stream << 1) | 1,
+        };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(MULTIPLIER).wrapping_add(self.increment);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        xorshifted.rotate_right((old >> 59) as u32)
+    }
+
+    /// Uniform in `[0.0, 1.0)`.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1u32 << 24) as f32
+    }
+
+    /// Uniform in `[low, high)`, `low` if the range is empty.
+    pub fn range_u32(&mut self, low: u32, high: u32) -> u32 {
+        if high <= low {
+            return low;
+        }
+
+        // rejecting the few values above the last whole multiple of `bound` keeps it unbiased
+        let bound = high - low;
+        let threshold = bound.wrapping_neg() % bound;
+        loop {
+            let r = self.next_u32();
+            if r >= threshold {
+                return low + r % bound;
+            }
+        }
+    }
+
+    /// Uniform in `[low, high)`.
+    pub fn range_f32(&mut self, low: f32, high: f32) -> f32 {
+        low + (high - low) * self.next_f32()
+    }
+
+    /// True with probability `p`.
+    pub fn chance(&mut self, p: f32) -> bool {
+        p > 0.0 && self.next_f32() < p
+    }
+}
+
+/// The random number generators of a match, one per stream name, all derived from `seed`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RngService {
+    #[serde(with = "hex_u64")]
+    seed: u64,
+    /// ordered, so the serialized state doesn't depend on when streams were first used
+    streams: BTreeMap<String, Rng>,
+}
+
+impl RngService {
+    pub fn new(seed: u64) -> RngService {
+        RngService {
+            seed,
+            streams: BTreeMap::new(),
+        }
+    }
+
+    /// A seed that differs between runs, for matches nobody asked to reproduce.
+    pub fn seed_from_time() -> u64 {
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0)
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The stream called `name`, started on first use. A stream draws the same numbers no
+    /// matter how much the others were used.
+    pub fn stream(&mut self, name: &str) -> &mut Rng {
+        let seed = self.seed;
+        self.streams
+            .entry(name.to_string())
+            .or_insert_with(|| Rng::new(seed, stream_id(name)))
+    }
+
+    /// The stream called `name` if it was used yet, without starting it.
+    pub fn peek(&self, name: &str) -> Option<&Rng> {
+        self.streams.get(name)
+    }
+
+    pub fn gameplay(&mut self) -> &mut Rng {
+        self.stream(GAMEPLAY)
+    }
+
+    pub fn cosmetic(&mut self) -> &mut Rng {
+        self.stream(COSMETIC)
+    }
+}
+
+/// Hash of the stream name, the same on every run and platform.
+fn stream_id(name: &str) -> u64 {
+    let mut hasher = StateHasher::new();
+    hasher.write(name.as_bytes());
+    hasher.finish()
+}
+
+/// `u64`s as hex strings: TOML integers are signed 64 bit and can't hold every state.
+pub mod hex_u64 {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{:016x}", value))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        u64::from_str_radix(&text, 16).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_the_pcg32_reference_output() {
+        // pcg32-demo from pcg-c-basic, seeded with `pcg32_srandom(42, 54)`
+        let mut rng = Rng::new(42, 54);
+        let outputs: Vec<u32> = (0..6).map(|_| rng.next_u32()).collect();
+
+        assert_eq!(
+            outputs,
+            [
+                0xa15c_02b7,
+                0x7b47_f409,
+                0xba1d_3330,
+                0x83d2_f293,
+                0xbfa4_784b,
+                0xcbed_606e
+            ]
+        );
+    }
+
+    #[test]
+    fn cosmetic_draws_leave_gameplay_alone() {
+        let mut quiet = RngService::new(7);
+        let mut noisy = RngService::new(7);
+
+        for _ in 0..10 {
+            noisy.cosmetic().next_u32();
+            assert_eq!(quiet.gameplay().next_u32(), noisy.gameplay().next_u32());
+            noisy.cosmetic().next_f32();
+        }
+        assert_ne!(quiet.peek(COSMETIC), noisy.peek(COSMETIC));
+    }
+}
@@ -0,0 +1,363 @@
+//! Saving a match mid-level and picking it up again.
+//!
+//! Saves are TOML files in `saves/` under `storage::user_dir`. Each starts with a format
+//! `version`: older saves are migrated forward one version at a time before they are read,
+//! saves from a newer game or too old to migrate are rejected.
+
+use crate::level::TileCoord;
+use crate::rng::RngService;
+use crate::storage;
+use std::convert::TryFrom;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use toml;
+
+/// Format version written by this build.
+pub const VERSION: u32 = 1;
+/// The oldest format version that can still be migrated to `VERSION`.
+pub const OLDEST_VERSION: u32 = 1;
+/// The slot quick save and quick load use.
+pub const QUICKSAVE: &str = "quicksave";
+
+/// Turns a save table of one version into one of the next, or says why it can't.
+type Migration = fn(&mut toml::value::Table) -> Result<(), String>;
+
+/// Turns a save of version `OLDEST_VERSION + i` into one of the next version. Bump
+/// `VERSION` and add an entry here whenever the format changes.
+const MIGRATIONS: &[Migration] = &[];
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to access save {}", path)]
+    Io {
+        path: String,
+        #[cause]
+        inner: io::Error,
+    },
+    #[fail(display = "Failed to parse save {}", path)]
+    Parse {
+        path: String,
+        #[cause]
+        inner: toml::de::Error,
+    },
+    #[fail(display = "Failed to write save {}", path)]
+    Serialize {
+        path: String,
+        #[cause]
+        inner: toml::ser::Error,
+    },
+    #[fail(display = "Save {} has no format version", path)]
+    MissingVersion { path: String },
+    #[fail(
+        display = "Save {} is from a newer version of the game (format {}, this game reads up to {})",
+        path, version, supported
+    )]
+    TooNew {
+        path: String,
+        version: u32,
+        supported: u32,
+    },
+    #[fail(
+        display = "Save {} is too old to load (format {}, the oldest this game reads is {})",
+        path, version, oldest
+    )]
+    TooOld {
+        path: String,
+        version: u32,
+        oldest: u32,
+    },
+    #[fail(
+        display = "Failed to migrate save {} from format {}: {}",
+        path, version, message
+    )]
+    Migration {
+        path: String,
+        version: u32,
+        message: String,
+    },
+    #[fail(display = "Save is for level {}, not {}", saved, level)]
+    WrongLevel { saved: String, level: String },
+    #[fail(display = "Save doesn't fit the game: {}", message)]
+    Invalid { message: String },
+    #[fail(display = "Failed to find the save directory")]
+    Storage {
+        #[cause]
+        inner: storage::Error,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SavedTower {
+    pub tower: String,
+    /// gold paid, for the sell refund
+    pub cost: u32,
+    pub cooldown: u32,
+    pub kills: u32,
+    pub tile: TileCoord,
+}
+
+/// A pathogen on its way, its speed, reward and damage come from the pathogen catalog.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SavedEnemy {
+    pub id: u32,
+    pub pathogen: String,
+    pub health: f32,
+    pub position: [f32; 2],
+    /// the last tile center reached
+    pub tile: TileCoord,
+    /// the tile center it walks to
+    #[serde(default)]
+    pub target: Option<TileCoord>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SavedProjectile {
+    pub target: u32,
+    pub position: [f32; 2],
+    pub speed: f32,
+    pub damage: f32,
+    /// the tower that fired it
+    pub tower: TileCoord,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SavedSpawn {
+    pub tick: u64,
+    pub pathogen: String,
+}
+
+/// Everything needed to continue a match, see `Game::to_save` and `Game::from_save`.
+///
+/// Plain values come before tables and arrays of tables, TOML can't have them after.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SaveGame {
+    pub version: u32,
+    /// version of the game that wrote it, for bug reports
+    pub game_version: String,
+    /// resource name of the level, e.g. `levels/arm.toml`
+    pub level: String,
+    pub ticks: u64,
+    pub gold: u32,
+    pub heart_health: u32,
+    pub score: u32,
+    pub speed: u32,
+    /// waves started so far
+    pub waves_started: usize,
+    /// seconds until the next wave
+    pub wave_countdown: f32,
+    pub next_enemy_id: u32,
+    pub spawn_counter: usize,
+    pub rng: RngService,
+    #[serde(default)]
+    pub towers: Vec<SavedTower>,
+    #[serde(default)]
+    pub enemies: Vec<SavedEnemy>,
+    #[serde(default)]
+    pub projectiles: Vec<SavedProjectile>,
+    /// pathogens of started waves still waiting to enter the map
+    #[serde(default)]
+    pub spawns: Vec<SavedSpawn>,
+}
+
+impl SaveGame {
+    pub fn load(path: &Path) -> Result<SaveGame, Error> {
+        let text = fs::read_to_string(path).map_err(|e| Error::Io {
+            path: path.display().to_string(),
+            inner: e,
+        })?;
+
+        SaveGame::from_toml_str(&path.display().to_string(), &text)
+    }
+
+    /// Reads a save of any supported version, migrating it to `VERSION`.
+    pub fn from_toml_str(path: &str, text: &str) -> Result<SaveGame, Error> {
+        let parse_error = |e| Error::Parse {
+            path: path.into(),
+            inner: e,
+        };
+        let mut table: toml::value::Table = toml::from_str(text).map_err(parse_error)?;
+
+        let version = match table.get("version").and_then(|v| v.as_integer()) {
+            // past u32 it's still newer than anything this game reads
+            Some(version) if version >= 0 => u32::try_from(version).unwrap_or(u32::MAX),
+            _ => return Err(Error::MissingVersion { path: path.into() }),
+        };
+        if version > VERSION {
+            return Err(Error::TooNew {
+                path: path.into(),
+                version,
+                supported: VERSION,
+            });
+        }
+        if version < OLDEST_VERSION {
+            return Err(Error::TooOld {
+                path: path.into(),
+                version,
+                oldest: OLDEST_VERSION,
+            });
+        }
+
+        migrate(&mut table, version, OLDEST_VERSION, MIGRATIONS).map_err(
+            |(version, message)| Error::Migration {
+                path: path.into(),
+                version,
+                message,
+            },
+        )?;
+
+        toml::Value::Table(table).try_into().map_err(parse_error)
+    }
+
+    /// Writes the save atomically, an older save at `path` stays intact if that fails.
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let text = toml::to_string(self).map_err(|e| Error::Serialize {
+            path: path.display().to_string(),
+            inner: e,
+        })?;
+
+        storage::write_atomic(path, text.as_bytes()).map_err(|e| Error::Io {
+            path: path.display().to_string(),
+            inner: e,
+        })
+    }
+}
+
+/// Migrates `table` of `version` one version at a time, `migrations[i]` turning version
+/// `oldest + i` into the next. Fails with the version that couldn't be migrated.
+fn migrate(
+    table: &mut toml::value::Table,
+    version: u32,
+    oldest: u32,
+    migrations: &[Migration],
+) -> Result<(), (u32, String)> {
+    for from in version..oldest + migrations.len() as u32 {
+        migrations[(from - oldest) as usize](table).map_err(|message| (from, message))?;
+        table.insert("version".into(), toml::Value::Integer(i64::from(from + 1)));
+    }
+    Ok(())
+}
+
+/// Where the save called `slot` lives.
+pub fn slot_path(slot: &str) -> Result<PathBuf, Error> {
+    let dir = storage::user_dir().map_err(|e| Error::Storage { inner: e })?;
+    Ok(dir.join("saves").join(format!("{}.toml", slot)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Game;
+    use crate::level::Level;
+    use crate::pathogen::PathogenCatalog;
+    use crate::resources::Resources;
+    use crate::tower::TowerCatalog;
+
+    const LEVEL: &str = "levels/arm.toml";
+
+    fn load() -> (Level, TowerCatalog, PathogenCatalog) {
+        let res = Resources::from_directory(&Path::new(env!("CARGO_MANIFEST_DIR")).join("res"));
+        (
+            Level::from_res(&res, LEVEL).unwrap(),
+            TowerCatalog::from_res(&res, "towers.toml").unwrap(),
+            PathogenCatalog::from_res(&res, "pathogens.toml").unwrap(),
+        )
+    }
+
+    #[test]
+    fn every_old_version_has_a_migration() {
+        assert_eq!(MIGRATIONS.len(), (VERSION - OLDEST_VERSION) as usize);
+    }
+
+    #[test]
+    fn migrations_run_in_order_from_the_save_version() {
+        fn rename_gold(table: &mut toml::value::Table) -> Result<(), String> {
+            let gold = table.remove("money").ok_or("no money")?;
+            table.insert("gold".into(), gold);
+            Ok(())
+        }
+        fn double_gold(table: &mut toml::value::Table) -> Result<(), String> {
+            let gold = table
+                .get("gold")
+                .and_then(|v| v.as_integer())
+                .ok_or("no gold")?;
+            table.insert("gold".into(), toml::Value::Integer(gold * 2));
+            Ok(())
+        }
+        let migrations: &[Migration] = &[rename_gold, double_gold];
+
+        let mut table: toml::value::Table = toml::from_str("version = 3\nmoney = 5").unwrap();
+        migrate(&mut table, 3, 3, migrations).unwrap();
+        assert_eq!(table["version"].as_integer(), Some(5));
+        assert_eq!(table["gold"].as_integer(), Some(10));
+
+        // a save that is already past the first migration skips it
+        let mut table: toml::value::Table = toml::from_str("version = 4\ngold = 5").unwrap();
+        migrate(&mut table, 4, 3, migrations).unwrap();
+        assert_eq!(table["gold"].as_integer(), Some(10));
+
+        let mut table: toml::value::Table = toml::from_str("version = 3\ngold = 5").unwrap();
+        assert_eq!(
+            migrate(&mut table, 3, 3, migrations),
+            Err((3, "no money".to_string()))
+        );
+    }
+
+    #[test]
+    fn newer_version_is_too_new() {
+        let text = format!("version = {}\n", VERSION + 1);
+        match SaveGame::from_toml_str("save.toml", &text) {
+            Err(Error::TooNew { version, .. }) => assert_eq!(version, VERSION + 1),
+            other => panic!("expected TooNew, got {:?}", other),
+        }
+
+        // would wrap around to version 1 if truncated
+        let text = format!("version = {}\n", (1i64 << 32) + 1);
+        match SaveGame::from_toml_str("save.toml", &text) {
+            Err(Error::TooNew { .. }) => {}
+            other => panic!("expected TooNew, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn missing_or_invalid_version_is_rejected() {
+        for text in &[
+            "level = \"levels/arm.toml\"\n",
+            "version = -1\n",
+            "version = \"1\"\n",
+        ] {
+            match SaveGame::from_toml_str("save.toml", text) {
+                Err(Error::MissingVersion { .. }) => {}
+                other => panic!("expected MissingVersion for {:?}, got {:?}", text, other),
+            }
+        }
+    }
+
+    #[test]
+    fn match_survives_a_round_trip_through_a_save() {
+        let (level, catalog, pathogens) = load();
+        let mut game = Game::new(&level, catalog.clone(), pathogens.clone(), 5).unwrap();
+        for &(col, row) in &[(7, 2), (13, 2)] {
+            game.place_tower(&level, TileCoord::new(col, row), "macrophage")
+                .unwrap();
+        }
+        while game.world().enemies.is_empty() || game.world().projectiles.is_empty() {
+            game.tick(&level);
+        }
+
+        let saved = game.to_save(&level);
+        let text = toml::to_string(&saved).unwrap();
+        let read = SaveGame::from_toml_str("save.toml", &text).unwrap();
+        assert_eq!(read, saved);
+
+        let mut loaded = Game::from_save(&level, catalog, pathogens, read).unwrap();
+        assert_eq!(loaded.to_save(&level), saved);
+
+        // and it plays on the same
+        for _ in 0..600 {
+            game.tick(&level);
+            loaded.tick(&level);
+        }
+        assert_eq!(loaded.to_save(&level), game.to_save(&level));
+    }
+}
@@ -0,0 +1,531 @@
+//! Player settings: window, graphics, audio, UI scale and key bindings.
+//!
+//! They are kept in `settings.toml` in `storage::user_dir`. Every key is optional, missing
+//! ones and whole sections that fail to parse fall back to their defaults, and values out
+//! of range are replaced. Each such fix is reported as a warning rather than an error, a
+//! broken settings file never keeps the game from starting.
+
+use crate::audio::Volumes;
+use crate::input::{Action, Binding, Bindings};
+use crate::storage;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use toml;
+
+/// MSAA sample counts the settings accept, 0 turns multisampling off.
+pub const MSAA_SAMPLES: [u32; 5] = [0, 2, 4, 8, 16];
+/// Frame rate caps the settings menu cycles through, 0 is uncapped.
+pub const FPS_CAPS: [u32; 5] = [30, 60, 120, 144, 0];
+pub const MIN_FPS_CAP: u32 = 15;
+pub const MIN_UI_SCALE: f32 = 0.5;
+pub const MAX_UI_SCALE: f32 = 3.0;
+/// Smallest window the HUD and shop still fit in.
+pub const MIN_RESOLUTION: (u32, u32) = (800, 450);
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to access settings {}", path)]
+    Io {
+        path: String,
+        #[cause]
+        inner: io::Error,
+    },
+    #[fail(display = "Failed to write settings {}", path)]
+    Serialize {
+        path: String,
+        #[cause]
+        inner: toml::ser::Error,
+    },
+    #[fail(display = "Failed to find the settings directory")]
+    Storage {
+        #[cause]
+        inner: storage::Error,
+    },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WindowMode {
+    Windowed,
+    /// a window covering the whole display, at the desktop's resolution
+    Borderless,
+    /// the display switches to `width` x `height` at `refresh_rate`
+    Fullscreen,
+}
+
+impl WindowMode {
+    /// The next mode, for cycling through them in the settings menu.
+    pub fn next(self) -> WindowMode {
+        match self {
+            WindowMode::Windowed => WindowMode::Borderless,
+            WindowMode::Borderless => WindowMode::Fullscreen,
+            WindowMode::Fullscreen => WindowMode::Windowed,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Vsync {
+    Off,
+    On,
+    /// waits for the display unless a frame is late, then swaps right away
+    Adaptive,
+}
+
+impl Vsync {
+    /// The next setting, for cycling through them in the settings menu.
+    pub fn next(self) -> Vsync {
+        match self {
+            Vsync::Off => Vsync::On,
+            Vsync::On => Vsync::Adaptive,
+            Vsync::Adaptive => Vsync::Off,
+        }
+    }
+}
+
+/// Also reads `true` and `false`, vsync used to be either on or off.
+fn deserialize_vsync<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vsync, D::Error> {
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum VsyncSetting {
+        Enabled(bool),
+        Vsync(Vsync),
+    }
+    Ok(match VsyncSetting::deserialize(deserializer)? {
+        VsyncSetting::Enabled(true) => Vsync::On,
+        VsyncSetting::Enabled(false) => Vsync::Off,
+        VsyncSetting::Vsync(vsync) => vsync,
+    })
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Display {
+    /// window size in windowed mode, display mode in fullscreen
+    pub width: u32,
+    pub height: u32,
+    /// refresh rate in fullscreen, 0 for the display's own
+    pub refresh_rate: u32,
+    pub mode: WindowMode,
+    /// index of the display to open on, the first one if it isn't connected
+    pub monitor: u32,
+    #[serde(deserialize_with = "deserialize_vsync")]
+    pub vsync: Vsync,
+    pub msaa_samples: u32,
+    /// frames drawn per second at most, 0 for no limit
+    pub fps_cap: u32,
+}
+
+impl Default for Display {
+    fn default() -> Display {
+        Display {
+            width: 1280,
+            height: 720,
+            refresh_rate: 0,
+            mode: WindowMode::Windowed,
+            monitor: 0,
+            vsync: Vsync::On,
+            msaa_samples: 0,
+            fps_cap: 60,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Audio {
+    pub master: f32,
+    pub music: f32,
+    pub sfx: f32,
+}
+
+impl Default for Audio {
+    fn default() -> Audio {
+        Audio {
+            master: 1.0,
+            music: 1.0,
+            sfx: 1.0,
+        }
+    }
+}
+
+impl Audio {
+    pub fn volumes(&self) -> Volumes {
+        Volumes {
+            master: self.master,
+            music: self.music,
+            sfx: self.sfx,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Interface {
+    /// window coordinates per UI unit, high-DPI displays scale it further, see
+    /// `ui::Ui::scale`
+    pub scale: f32,
+}
+
+impl Default for Interface {
+    fn default() -> Interface {
+        Interface { scale: 1.0 }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Settings {
+    pub display: Display,
+    pub audio: Audio,
+    pub ui: Interface,
+    /// replaces the bindings of `res/input.toml` for the actions listed, in the same
+    /// format
+    pub bindings: BTreeMap<String, Vec<String>>,
+}
+
+impl Settings {
+    /// The settings at `path` with every problem fixed, and what was fixed. Defaults if
+    /// there is no file yet.
+    pub fn load(path: &Path) -> (Settings, Vec<String>) {
+        match fs::read_to_string(path) {
+            Ok(text) => Settings::from_toml_str(&path.display().to_string(), &text),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => (Settings::default(), Vec::new()),
+            Err(e) => (
+                Settings::default(),
+                vec![format!(
+                    "Failed to read settings {}, using defaults: {}",
+                    path.display(),
+                    e
+                )],
+            ),
+        }
+    }
+
+    /// Reads each section on its own, so a mistake in one only resets that one.
+    pub fn from_toml_str(path: &str, text: &str) -> (Settings, Vec<String>) {
+        let mut settings = Settings::default();
+        let mut warnings = Vec::new();
+
+        let mut table: toml::value::Table = match toml::from_str(text) {
+            Ok(table) => table,
+            Err(e) => {
+                warnings.push(format!(
+                    "Failed to parse settings {}, using defaults: {}",
+                    path, e
+                ));
+                return (settings, warnings);
+            }
+        };
+
+        fn section<T: serde::de::DeserializeOwned + serde::Serialize>(
+            table: &mut toml::value::Table,
+            name: &str,
+            value: &mut T,
+            warnings: &mut Vec<String>,
+        ) {
+            if let Some(section) = table.remove(name) {
+                let keys: Vec<String> = match &section {
+                    toml::Value::Table(section) => section.keys().cloned().collect(),
+                    _ => Vec::new(),
+                };
+                match section.try_into::<T>() {
+                    Ok(parsed) => {
+                        // serde skips keys it doesn't know, writing it back drops them
+                        if let Ok(toml::Value::Table(known)) = toml::Value::try_from(&parsed) {
+                            for key in keys.iter().filter(|key| !known.contains_key(*key)) {
+                                warnings.push(format!("Unknown setting {}.{} ignored", name, key));
+                            }
+                        }
+                        *value = parsed;
+                    }
+                    Err(e) => {
+                        warnings.push(format!("Settings [{}] reset to defaults: {}", name, e))
+                    }
+                }
+            }
+        }
+        section(&mut table, "display", &mut settings.display, &mut warnings);
+        section(&mut table, "audio", &mut settings.audio, &mut warnings);
+        section(&mut table, "ui", &mut settings.ui, &mut warnings);
+        section(
+            &mut table,
+            "bindings",
+            &mut settings.bindings,
+            &mut warnings,
+        );
+        for name in table.keys() {
+            warnings.push(format!("Unknown setting {} ignored", name));
+        }
+
+        warnings.extend(settings.validate());
+        (settings, warnings)
+    }
+
+    /// Replaces values out of range with the nearest valid or default one, returns what
+    /// was replaced.
+    pub fn validate(&mut self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        let defaults = Settings::default();
+
+        let display = &mut self.display;
+        if display.width < MIN_RESOLUTION.0 || display.height < MIN_RESOLUTION.1 {
+            warnings.push(format!(
+                "Resolution {}x{} is below {}x{}, using {}x{}",
+                display.width,
+                display.height,
+                MIN_RESOLUTION.0,
+                MIN_RESOLUTION.1,
+                defaults.display.width,
+                defaults.display.height
+            ));
+            display.width = defaults.display.width;
+            display.height = defaults.display.height;
+        }
+        if !MSAA_SAMPLES.contains(&display.msaa_samples) {
+            let samples = MSAA_SAMPLES
+                .iter()
+                .cloned()
+                .filter(|s| *s <= display.msaa_samples)
+                .last()
+                .unwrap_or(0);
+            warnings.push(format!(
+                "{} MSAA samples aren't supported, using {}",
+                display.msaa_samples, samples
+            ));
+            display.msaa_samples = samples;
+        }
+        if display.fps_cap != 0 && display.fps_cap < MIN_FPS_CAP {
+            warnings.push(format!(
+                "FPS cap {} is below {}, using {}",
+                display.fps_cap, MIN_FPS_CAP, MIN_FPS_CAP
+            ));
+            display.fps_cap = MIN_FPS_CAP;
+        }
+
+        let audio = &mut self.audio;
+        validate_volume("master", &mut audio.master, &mut warnings);
+        validate_volume("music", &mut audio.music, &mut warnings);
+        validate_volume("sfx", &mut audio.sfx, &mut warnings);
+
+        let scale = self.ui.scale;
+        if scale.is_nan() || scale < MIN_UI_SCALE || scale > MAX_UI_SCALE {
+            let clamped = if scale.is_nan() {
+                defaults.ui.scale
+            } else {
+                scale.max(MIN_UI_SCALE).min(MAX_UI_SCALE)
+            };
+            warnings.push(format!(
+                "UI scale {} is outside {} to {}, using {}",
+                scale, MIN_UI_SCALE, MAX_UI_SCALE, clamped
+            ));
+            self.ui.scale = clamped;
+        }
+
+        self.bindings.retain(|action, _| {
+            let known = Action::from_name(action).is_some();
+            if !known {
+                warnings.push(format!("Bindings for unknown action {} ignored", action));
+            }
+            known
+        });
+        for (action, texts) in &mut self.bindings {
+            texts.retain(|text| {
+                let known = Binding::parse(text).is_some();
+                if !known {
+                    warnings.push(format!("Unknown binding {} for {} ignored", text, action));
+                }
+                known
+            });
+        }
+
+        warnings
+    }
+
+    /// `defaults` with the actions listed in `bindings` rebound. Call `validate` first,
+    /// bindings it didn't accept are skipped.
+    pub fn bindings(&self, defaults: &Bindings) -> Bindings {
+        let mut bindings = defaults.clone();
+        for (name, texts) in &self.bindings {
+            let action = match Action::from_name(name) {
+                Some(action) => action,
+                None => continue,
+            };
+            bindings.clear(action);
+            for binding in texts.iter().filter_map(|text| Binding::parse(text)) {
+                bindings.bind(action, binding);
+            }
+        }
+        bindings
+    }
+
+    /// Time between two frames at most, `None` without a cap.
+    pub fn frame_interval(&self) -> Option<Duration> {
+        match self.display.fps_cap {
+            0 => None,
+            fps => Some(Duration::from_secs(1) / fps),
+        }
+    }
+
+    /// True if going from `self` to `other` only takes effect after a restart, the MSAA
+    /// samples are fixed when the GL context is created.
+    pub fn needs_restart(&self, other: &Settings) -> bool {
+        self.display.msaa_samples != other.display.msaa_samples
+    }
+
+    /// True if going from `self` to `other` moves or resizes the window, see
+    /// `display::apply`.
+    pub fn window_changed(&self, other: &Settings) -> bool {
+        let (a, b) = (&self.display, &other.display);
+        a.width != b.width
+            || a.height != b.height
+            || a.refresh_rate != b.refresh_rate
+            || a.mode != b.mode
+            || a.monitor != b.monitor
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let text = toml::to_string(self).map_err(|e| Error::Serialize {
+            path: path.display().to_string(),
+            inner: e,
+        })?;
+
+        storage::write_atomic(path, text.as_bytes()).map_err(|e| Error::Io {
+            path: path.display().to_string(),
+            inner: e,
+        })
+    }
+}
+
+fn validate_volume(name: &str, volume: &mut f32, warnings: &mut Vec<String>) {
+    if volume.is_nan() {
+        warnings.push(format!("Volume {} isn't a number, using 1", name));
+        *volume = 1.0;
+    } else if *volume < 0.0 || *volume > 1.0 {
+        let clamped = volume.max(0.0).min(1.0);
+        warnings.push(format!(
+            "Volume {} {} is outside 0 to 1, using {}",
+            name, volume, clamped
+        ));
+        *volume = clamped;
+    }
+}
+
+/// Where the player's settings live.
+pub fn default_path() -> Result<PathBuf, Error> {
+    let dir = storage::user_dir().map_err(|e| Error::Storage { inner: e })?;
+    Ok(dir.join("settings.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sdl2::keyboard::Keycode;
+    use sdl2::mouse::MouseButton;
+
+    #[test]
+    fn broken_section_resets_only_itself() {
+        let text = r#"
+            [display]
+            width = "wide"
+
+            [audio]
+            music = 0.25
+        "#;
+        let (settings, warnings) = Settings::from_toml_str("settings.toml", text);
+
+        assert_eq!(settings.display, Display::default());
+        assert_eq!(settings.audio.music, 0.25);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("Settings [display] reset to defaults"));
+    }
+
+    #[test]
+    fn unknown_keys_are_warned_about() {
+        let text = r#"
+            colour = "blue"
+
+            [ui]
+            scale = 1.5
+            font = "big"
+        "#;
+        let (settings, warnings) = Settings::from_toml_str("settings.toml", text);
+
+        assert_eq!(settings.ui.scale, 1.5);
+        assert_eq!(
+            warnings,
+            [
+                "Unknown setting ui.font ignored",
+                "Unknown setting colour ignored"
+            ]
+        );
+    }
+
+    #[test]
+    fn values_out_of_range_are_clamped() {
+        let text = r#"
+            [display]
+            msaa_samples = 6
+
+            [audio]
+            master = 1.5
+            sfx = -0.5
+
+            [ui]
+            scale = 10.0
+        "#;
+        let (settings, warnings) = Settings::from_toml_str("settings.toml", text);
+
+        assert_eq!(settings.display.msaa_samples, 4);
+        assert_eq!((settings.audio.master, settings.audio.sfx), (1.0, 0.0));
+        assert_eq!(settings.ui.scale, MAX_UI_SCALE);
+        assert_eq!(warnings.len(), 4);
+    }
+
+    #[test]
+    fn vsync_reads_the_old_booleans() {
+        for &(text, vsync) in &[
+            ("vsync = true", Vsync::On),
+            ("vsync = false", Vsync::Off),
+            ("vsync = \"Adaptive\"", Vsync::Adaptive),
+        ] {
+            let text = format!("[display]\n{}\n", text);
+            let (settings, warnings) = Settings::from_toml_str("settings.toml", &text);
+            assert_eq!(settings.display.vsync, vsync);
+            assert!(warnings.is_empty());
+        }
+    }
+
+    #[test]
+    fn bindings_replace_only_the_listed_actions() {
+        let defaults = Bindings::from_toml_str(
+            "input.toml",
+            r#"
+                [bindings]
+                PlaceTower = ["Mouse:Left", "Key:P"]
+                Cancel = ["Mouse:Right"]
+            "#,
+        )
+        .unwrap();
+        let text = r#"
+            [bindings]
+            PlaceTower = ["Key:B", "Key:Nope"]
+            Dance = ["Key:D"]
+        "#;
+        let (settings, warnings) = Settings::from_toml_str("settings.toml", text);
+        let bindings = settings.bindings(&defaults);
+
+        assert_eq!(
+            bindings.bindings(Action::PlaceTower),
+            &[Binding::Key(Keycode::B)]
+        );
+        assert_eq!(
+            bindings.bindings(Action::Cancel),
+            &[Binding::Mouse(MouseButton::Right)]
+        );
+        assert_eq!(warnings.len(), 2);
+    }
+}
@@ -0,0 +1,161 @@
+//! The in-game settings panel, opened with the `Settings` action.
+//!
+//! It edits a copy of the settings and hands back every change, the caller applies what it
+//! can right away and saves them. Key bindings are only changed in the settings file.
+
+use crate::display::{DisplayInfo, Mode};
+use crate::settings::{Settings, WindowMode, FPS_CAPS, MAX_UI_SCALE, MIN_UI_SCALE, MSAA_SAMPLES};
+use crate::ui::{Rect, Ui};
+
+const PANEL_WIDTH: f32 = 320.0;
+const UI_SCALE_STEP: f32 = 0.25;
+/// Window sizes the menu cycles through in windowed mode, any other can be set in the file.
+const RESOLUTIONS: [(u32, u32); 5] = [
+    (1280, 720),
+    (1366, 768),
+    (1600, 900),
+    (1920, 1080),
+    (2560, 1440),
+];
+
+#[derive(Clone, Debug, Default)]
+pub struct SettingsMenu {
+    open: bool,
+    /// changes were made that only apply after a restart
+    restart_needed: bool,
+    /// see `display::displays`
+    displays: Vec<DisplayInfo>,
+}
+
+impl SettingsMenu {
+    pub fn new(displays: Vec<DisplayInfo>) -> SettingsMenu {
+        SettingsMenu {
+            displays,
+            ..SettingsMenu::default()
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+    }
+
+    /// Declares the panel in the middle of `screen` while open. Returns the new settings
+    /// when the player changed one, `current` is what is in effect now.
+    pub fn build(&mut self, ui: &mut Ui, screen: Rect, current: &Settings) -> Option<Settings> {
+        if !self.open {
+            return None;
+        }
+
+        let mut settings = current.clone();
+        let height = (ui.button_height() + ui.theme().spacing) * 13.0 + ui.theme().padding * 2.0;
+        let rect = Rect::new(
+            screen.x + (screen.w - PANEL_WIDTH) / 2.0,
+            screen.y + (screen.h - height) / 2.0,
+            PANEL_WIDTH,
+            height.min(screen.h),
+        );
+
+        ui.panel("settings", rect, |ui| {
+            ui.label("Settings");
+
+            let display = &mut settings.display;
+            if ui.button(&format!("Window: {:?}", display.mode)) {
+                display.mode = display.mode.next();
+            }
+            let name = self
+                .displays
+                .get(display.monitor as usize)
+                .map_or("not connected", |d| d.name.as_str());
+            if ui.button_enabled(
+                &format!("Display: {} ({})", display.monitor + 1, name),
+                self.displays.len() > 1,
+            ) {
+                display.monitor = (display.monitor + 1) % self.displays.len() as u32;
+            }
+            // exclusive fullscreen picks from the modes the display has
+            let modes = self
+                .displays
+                .get(display.monitor as usize)
+                .map_or(&[][..], |d| d.modes.as_slice());
+            match display.mode {
+                WindowMode::Borderless => {
+                    ui.button_enabled("Resolution: desktop", false);
+                }
+                WindowMode::Fullscreen if !modes.is_empty() => {
+                    let current = Mode {
+                        width: display.width,
+                        height: display.height,
+                        refresh_rate: display.refresh_rate,
+                    };
+                    if ui.button(&format!("Resolution: {}", current)) {
+                        let mode = next(modes, current);
+                        display.width = mode.width;
+                        display.height = mode.height;
+                        display.refresh_rate = mode.refresh_rate;
+                    }
+                }
+                _ => {
+                    if ui.button(&format!("Resolution: {}x{}", display.width, display.height)) {
+                        let (width, height) = next(&RESOLUTIONS, (display.width, display.height));
+                        display.width = width;
+                        display.height = height;
+                    }
+                }
+            }
+            if ui.button(&format!("Vsync: {:?}", display.vsync)) {
+                display.vsync = display.vsync.next();
+            }
+            let fps = match display.fps_cap {
+                0 => "unlimited".to_string(),
+                fps => fps.to_string(),
+            };
+            if ui.button(&format!("FPS cap: {}", fps)) {
+                display.fps_cap = next(&FPS_CAPS, display.fps_cap);
+            }
+            if ui.button(&format!("MSAA: {}x", display.msaa_samples)) {
+                display.msaa_samples = next(&MSAA_SAMPLES, display.msaa_samples);
+            }
+
+            let audio = &mut settings.audio;
+            ui.slider("Master volume", &mut audio.master, 0.0, 1.0);
+            ui.slider("Music volume", &mut audio.music, 0.0, 1.0);
+            ui.slider("Effects volume", &mut audio.sfx, 0.0, 1.0);
+
+            let scale = &mut settings.ui.scale;
+            ui.row(|ui| {
+                ui.label(&format!("UI scale: {:.2}x", scale));
+                if ui.button_enabled("-", *scale > MIN_UI_SCALE) {
+                    *scale = (*scale - UI_SCALE_STEP).max(MIN_UI_SCALE);
+                }
+                if ui.button_enabled("+", *scale < MAX_UI_SCALE) {
+                    *scale = (*scale + UI_SCALE_STEP).min(MAX_UI_SCALE);
+                }
+            });
+
+            if self.restart_needed {
+                ui.label("Some changes apply after a restart");
+            }
+            if ui.button("Close") {
+                self.open = false;
+            }
+        });
+
+        if settings == *current {
+            return None;
+        }
+        if current.needs_restart(&settings) {
+            self.restart_needed = true;
+        }
+        Some(settings)
+    }
+}
+
+/// The entry after `current` in `options`, the first one if `current` isn't listed.
+fn next<T: Copy + PartialEq>(options: &[T], current: T) -> T {
+    let index = options.iter().position(|o| *o == current);
+    options[index.map_or(0, |i| (i + 1) % options.len())]
+}
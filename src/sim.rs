@@ -0,0 +1,318 @@
+//! Pathogens, projectiles and spawning, advanced in fixed ticks.
+//!
+//! Nothing here reads the clock or touches SDL or GL, and everything is iterated in a fixed
+//! order, so the same level and inputs always play out the same way.
+
+use crate::level::{Level, TileCoord, Wave};
+use crate::pathing::FlowField;
+use crate::pathogen::{PathogenCatalog, PathogenDef};
+use crate::rng::Rng;
+use crate::tower::{TowerCatalog, Towers};
+use nalgebra as na;
+use std::cmp::Ordering;
+
+/// Simulation steps per simulated second.
+pub const TICKS_PER_SECOND: u32 = 60;
+/// Simulated seconds per tick.
+pub const TICK: f32 = 1.0 / TICKS_PER_SECOND as f32;
+
+/// Whole ticks closest to `seconds`.
+pub fn seconds_to_ticks(seconds: f32) -> u64 {
+    (seconds.max(0.0) * TICKS_PER_SECOND as f32).round() as u64
+}
+
+#[derive(Clone, Debug)]
+pub struct Enemy {
+    /// unique within a match, in spawn order
+    pub id: u32,
+    pub pathogen: String,
+    pub health: f32,
+    pub max_health: f32,
+    /// world units per second
+    pub speed: f32,
+    pub reward: u32,
+    pub damage: u32,
+    pub position: na::Point2<f32>,
+    /// the last tile center reached
+    pub tile: TileCoord,
+    /// the tile center it walks to, `None` when it has nowhere to go
+    pub target: Option<TileCoord>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Projectile {
+    /// the tower that fired it
+    pub tower: TileCoord,
+    /// the enemy it homes in on
+    pub target: u32,
+    pub position: na::Point2<f32>,
+    /// world units per second
+    pub speed: f32,
+    pub damage: f32,
+}
+
+/// An enemy waiting for its tick to enter the map.
+#[derive(Clone, Debug)]
+pub struct PendingSpawn {
+    pub tick: u64,
+    pub pathogen: String,
+}
+
+/// A shot that hit its target.
+pub struct Hit {
+    pub tower: TileCoord,
+    pub enemy: u32,
+    pub damage: f32,
+}
+
+/// Everything on the map that moves.
+pub struct World {
+    pub enemies: Vec<Enemy>,
+    pub projectiles: Vec<Projectile>,
+    /// sorted by tick, ties in the order they were queued
+    pub spawns: Vec<PendingSpawn>,
+    pub next_enemy_id: u32,
+    /// spawn tiles are used in turn
+    pub spawn_counter: usize,
+    pub flow: FlowField,
+}
+
+impl World {
+    pub fn new(level: &Level) -> World {
+        World {
+            enemies: Vec::new(),
+            projectiles: Vec::new(),
+            spawns: Vec::new(),
+            next_enemy_id: 0,
+            spawn_counter: 0,
+            flow: FlowField::new(level, |_| false),
+        }
+    }
+
+    /// Whether every enemy on the map could still reach the heart along `flow`.
+    pub fn all_reach(&self, flow: &FlowField) -> bool {
+        self.enemies.iter().all(|enemy| {
+            enemy.target.map_or(false, |t| flow.distance(t).is_some())
+                || flow.distance(enemy.tile).is_some()
+        })
+    }
+
+    /// Switches to a new flow field, enemies whose next tile can't be entered anymore
+    /// turn around.
+    pub fn set_flow(&mut self, level: &Level, flow: FlowField) {
+        self.flow = flow;
+        let flow = &self.flow;
+        for enemy in &mut self.enemies {
+            let blocked = enemy.target.map_or(true, |t| flow.distance(t).is_none());
+            if blocked {
+                enemy.target = flow.next_step(level, enemy.tile);
+            }
+        }
+    }
+
+    /// Queues the groups of `wave`, counting from `now`.
+    pub fn queue_wave(&mut self, wave: &Wave, now: u64, rng: &mut Rng) {
+        for group in &wave.groups {
+            for i in 0..group.count {
+                let jitter = rng.range_f32(0.0, group.jitter.max(0.0));
+                self.spawns.push(PendingSpawn {
+                    tick: now + seconds_to_ticks(group.delay + i as f32 * group.interval + jitter),
+                    pathogen: group.pathogen.clone(),
+                });
+            }
+        }
+        // stable, so spawns due in the same tick keep their queue order
+        self.spawns.sort_by_key(|s| s.tick);
+    }
+
+    /// Brings in every enemy due by `now`, returns their ids.
+    pub fn spawn_due(&mut self, level: &Level, pathogens: &PathogenCatalog, now: u64) -> Vec<u32> {
+        let due = self.spawns.iter().take_while(|s| s.tick <= now).count();
+        let mut spawned = Vec::with_capacity(due);
+
+        for spawn in self.spawns.drain(..due).collect::<Vec<_>>() {
+            let def = match pathogens.get(&spawn.pathogen) {
+                Some(def) => def,
+                None => continue,
+            };
+            let tile = level.spawns()[self.spawn_counter % level.spawns().len()];
+            self.spawn_counter += 1;
+
+            let enemy = self.spawn(level, def, tile);
+            spawned.push(enemy);
+        }
+
+        spawned
+    }
+
+    fn spawn(&mut self, level: &Level, def: &PathogenDef, tile: TileCoord) -> u32 {
+        let id = self.next_enemy_id;
+        self.next_enemy_id += 1;
+
+        self.enemies.push(Enemy {
+            id,
+            pathogen: def.id.clone(),
+            health: def.health,
+            max_health: def.health,
+            speed: def.speed * level.tile_size,
+            reward: def.reward,
+            damage: def.damage,
+            position: level.tile_center(tile),
+            tile,
+            target: self.flow.next_step(level, tile),
+        });
+        id
+    }
+
+    /// Walks every enemy one tick along the flow field, returns those that reached the
+    /// heart.
+    pub fn move_enemies(&mut self, level: &Level) -> Vec<Enemy> {
+        let heart = level.heart();
+        let flow = &self.flow;
+        let mut breached = Vec::new();
+
+        for enemy in &mut self.enemies {
+            let mut step = enemy.speed * TICK;
+            while let Some(target) = enemy.target {
+                let goal = level.tile_center(target);
+                let to_goal = goal - enemy.position;
+                let distance = to_goal.norm();
+                if distance > step {
+                    enemy.position += to_goal / distance * step;
+                    break;
+                }
+
+                enemy.position = goal;
+                enemy.tile = target;
+                step -= distance;
+                if target == heart {
+                    breached.push(enemy.id);
+                    break;
+                }
+                enemy.target = flow.next_step(level, target);
+            }
+        }
+
+        let (arrived, walking) = self
+            .enemies
+            .drain(..)
+            .partition(|enemy| breached.contains(&enemy.id));
+        self.enemies = walking;
+        arrived
+    }
+
+    /// Fires every tower that is ready and has an enemy in range, returns the towers that
+    /// fired. Towers shoot at the enemy closest to the heart.
+    pub fn fire_towers(
+        &mut self,
+        level: &Level,
+        catalog: &TowerCatalog,
+        towers: &mut Towers,
+        rng: &mut Rng,
+    ) -> Vec<TileCoord> {
+        let mut fired = Vec::new();
+
+        for (tile, placed) in towers.iter_mut() {
+            if placed.cooldown > 0 {
+                placed.cooldown -= 1;
+                continue;
+            }
+            let def = match catalog.get(&placed.tower) {
+                Some(def) => def,
+                None => continue,
+            };
+
+            let position = level.tile_center(tile);
+            let range = def.range * level.tile_size;
+            let target = self
+                .enemies
+                .iter()
+                .filter(|e| (e.position - position).norm() <= range)
+                .min_by(|a, b| {
+                    self.distance_left(level, a)
+                        .partial_cmp(&self.distance_left(level, b))
+                        .unwrap_or(Ordering::Equal)
+                        .then(a.id.cmp(&b.id))
+                })
+                .map(|e| e.id);
+
+            if let Some(target) = target {
+                let damage = if rng.chance(def.critical_chance) {
+                    def.damage * def.critical_multiplier
+                } else {
+                    def.damage
+                };
+                self.projectiles.push(Projectile {
+                    tower: tile,
+                    target,
+                    position,
+                    speed: def.projectile_speed * level.tile_size,
+                    damage,
+                });
+                placed.cooldown = seconds_to_ticks(def.fire_interval).max(1) as u32 - 1;
+                fired.push(tile);
+            }
+        }
+
+        fired
+    }
+
+    /// Moves projectiles towards their targets. Those whose target is gone fizzle.
+    pub fn move_projectiles(&mut self) -> Vec<Hit> {
+        let mut hits = Vec::new();
+        let mut flying = Vec::with_capacity(self.projectiles.len());
+
+        for mut projectile in self.projectiles.drain(..) {
+            let target = match self.enemies.iter().find(|e| e.id == projectile.target) {
+                Some(target) => target,
+                None => continue,
+            };
+
+            let to_target = target.position - projectile.position;
+            let distance = to_target.norm();
+            let step = projectile.speed * TICK;
+            if distance <= step {
+                hits.push(Hit {
+                    tower: projectile.tower,
+                    enemy: projectile.target,
+                    damage: projectile.damage,
+                });
+            } else {
+                projectile.position += to_target / distance * step;
+                flying.push(projectile);
+            }
+        }
+
+        self.projectiles = flying;
+        hits
+    }
+
+    /// Takes `damage` from an enemy, returns it if that killed it.
+    pub fn damage_enemy(&mut self, id: u32, damage: f32) -> Option<Enemy> {
+        let index = self.enemies.iter().position(|e| e.id == id)?;
+        let enemy = &mut self.enemies[index];
+        enemy.health -= damage;
+        if enemy.health <= 0.0 {
+            Some(self.enemies.remove(index))
+        } else {
+            None
+        }
+    }
+
+    /// True once no enemy is on the map or waiting to spawn.
+    pub fn is_clear(&self) -> bool {
+        self.enemies.is_empty() && self.spawns.is_empty()
+    }
+
+    /// How far an enemy still has to go, in tiles, for picking targets.
+    fn distance_left(&self, level: &Level, enemy: &Enemy) -> f32 {
+        match enemy.target {
+            Some(target) => {
+                let steps = self.flow.distance(target).unwrap_or(u32::max_value());
+                let to_target = (level.tile_center(target) - enemy.position).norm();
+                steps as f32 + to_target / level.tile_size
+            }
+            None => std::f32::MAX,
+        }
+    }
+}
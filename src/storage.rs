@@ -0,0 +1,52 @@
+//! Files that belong to the player rather than the game: saves, the profile and settings.
+//!
+//! They live in SDL's per-user preference directory, e.g. `~/.local/share/Protect Joe` on
+//! Linux or `%APPDATA%\Protect Joe` on Windows, and are always replaced atomically so a
+//! crash mid-write never leaves half a file behind.
+
+use sdl2::filesystem::{self, PrefPathError};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const ORGANIZATION: &str = "";
+const APPLICATION: &str = "Protect Joe";
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to find the user data directory")]
+    UserDir {
+        #[cause]
+        inner: PrefPathError,
+    },
+}
+
+/// The per-user directory, created if it doesn't exist yet.
+pub fn user_dir() -> Result<PathBuf, Error> {
+    filesystem::pref_path(ORGANIZATION, APPLICATION)
+        .map(PathBuf::from)
+        .map_err(|e| Error::UserDir { inner: e })
+}
+
+/// Replaces `path` with `contents`: they are written and flushed to a temporary file next
+/// to it first, which is then renamed over `path`. Readers see the old or the new file,
+/// never a mix.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".tmp");
+    let temp = path.with_file_name(temp_name);
+
+    {
+        let mut file = fs::File::create(&temp)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+    }
+    fs::rename(&temp, path).map_err(|e| {
+        let _ = fs::remove_file(&temp);
+        e
+    })
+}
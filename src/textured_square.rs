@@ -1,5 +1,6 @@
+use crate::assets::{Assets, Handle};
+use crate::camera::CameraUniforms;
 use crate::render_gl::{self, buffer, data};
-use crate::resources::Resources;
 use crate::Render;
 use crate::Update;
 
@@ -16,26 +17,26 @@ struct Vertex {
 }
 
 pub struct TexturedSquare {
-    program: render_gl::Program,
-    texture: render_gl::Texture,
+    program: Handle<render_gl::Program>,
+    texture: Handle<render_gl::Texture>,
     position: na::Vector3<f32>,
-    program_model_location: Option<i32>,
-    program_projection_location: Option<i32>,
-    tex_face_location: Option<i32>,
+    program_model_location: i32,
+    tex_face_location: i32,
     _vbo: buffer::ArrayBuffer,
     _ibo: buffer::ElementArrayBuffer,
     index_count: i32,
     vao: buffer::VertexArray,
 }
 impl TexturedSquare {
-    pub fn new(res: &Resources, gl: &gl::Gl) -> Result<TexturedSquare, failure::Error> {
-        // set up shader program
-        let texture = render_gl::Texture::from_res_rgb("textures/test.png").load(gl, res)?;
-        let program = render_gl::Program::from_res(gl, res, "shaders/tex")?;
+    pub fn new(assets: &mut Assets, gl: &gl::Gl) -> Result<TexturedSquare, failure::Error> {
+        // set up shader program, shared with every other square
+        let texture = assets.load::<render_gl::Texture>("textures/test.png")?;
+        let program = assets.load::<render_gl::Program>("shaders/tex")?;
 
-        let program_model_location = program.get_uniform_location("Model");
-        let program_projection_location = program.get_uniform_location("Projection");
-        let tex_face_location = program.get_uniform_location("TexFace");
+        program.require_uniforms(&["Model", "TexFace"])?;
+        program.bind_uniform_block("Camera", CameraUniforms::BINDING)?;
+        let program_model_location = program.typed_uniform_location("Model", gl::FLOAT_MAT4)?;
+        let tex_face_location = program.typed_uniform_location("TexFace", gl::SAMPLER_2D)?;
 
         // set up vertex buffer object
         let vertices: Vec<Vertex> = vec![
@@ -80,7 +81,6 @@ impl TexturedSquare {
             program,
             texture,
             program_model_location,
-            program_projection_location,
             tex_face_location,
             position: na::Vector3::new(0.0, 0.0, 0.0),
             _vbo: vbo,
@@ -103,28 +103,17 @@ impl Update for TexturedSquare {
     }
 }
 impl Render for TexturedSquare {
-    fn render(&self, gl: &gl::Gl, proj_matrix: &na::Matrix4<f32>) {
+    fn render(&self, gl: &gl::Gl) {
         // set shader
         self.program.set_used();
 
-        if let Some(loc) = self.program_model_location {
-            self.program.set_uniform_matrix_4fv(
-                loc,
-                &na::geometry::Translation3::new(
-                    self.position[0],
-                    self.position[1],
-                    self.position[2],
-                )
+        self.program.set_uniform_at(
+            self.program_model_location,
+            &na::geometry::Translation3::new(self.position[0], self.position[1], self.position[2])
                 .to_homogeneous(),
-            );
-        }
-        if let Some(loc) = self.program_projection_location {
-            self.program.set_uniform_matrix_4fv(loc, proj_matrix);
-        }
-        if let Some(loc) = self.tex_face_location {
-            self.texture.bind_at(0);
-            self.program.set_uniform_1i(loc, 0);
-        }
+        );
+        self.texture.bind_at(0);
+        self.program.set_uniform_at(self.tex_face_location, &0);
         self.vao.bind();
 
         unsafe {
@@ -137,3 +126,66 @@ impl Render for TexturedSquare {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::Resources;
+    use gl::recording::RecordingGl;
+    use std::path::Path;
+    use std::rc::Rc;
+
+    fn recording() -> Rc<RecordingGl> {
+        Rc::new(
+            RecordingGl::new()
+                .with_active_uniform("Model", gl::FLOAT_MAT4, 1)
+                .with_active_uniform("TexFace", gl::SAMPLER_2D, 1),
+        )
+    }
+
+    fn assets(gl: &gl::Gl) -> Assets {
+        let res = Resources::from_directory(&Path::new(env!("CARGO_MANIFEST_DIR")).join("res"));
+        Assets::new(gl, res)
+    }
+
+    #[test]
+    fn render_binds_everything_before_drawing() {
+        let recording = recording();
+        let gl = gl::Gl::from_api(recording.clone());
+        let mut assets = assets(&gl);
+        let square = TexturedSquare::new(&mut assets, &gl).unwrap();
+
+        recording.clear_calls();
+        square.render(&gl);
+
+        assert_eq!(
+            recording.call_names(),
+            vec![
+                "UseProgram",
+                "UniformMatrix4fv",
+                "ActiveTexture",
+                "BindTexture",
+                "Uniform1iv",
+                "BindVertexArray",
+                "DrawElements",
+            ]
+        );
+        let draw = recording.calls().pop().unwrap();
+        assert_eq!(draw.args[1], "6");
+    }
+
+    #[test]
+    fn dropping_deletes_every_object() {
+        let recording = recording();
+        let gl = gl::Gl::from_api(recording.clone());
+        let mut assets = assets(&gl);
+        let square = TexturedSquare::new(&mut assets, &gl).unwrap();
+        assert!(!recording.live_objects().is_empty());
+
+        drop(square);
+        drop(assets);
+
+        assert_eq!(recording.live_objects(), vec![]);
+        assert_eq!(recording.invalid_deletes(), vec![]);
+    }
+}
@@ -0,0 +1,160 @@
+use crate::level::TileCoord;
+use crate::resources::{self, Resources};
+use std::collections::BTreeMap;
+use toml;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to load towers {}", name)]
+    ResourceLoad {
+        name: String,
+        #[cause]
+        inner: resources::Error,
+    },
+    #[fail(display = "Failed to parse towers {}", name)]
+    Parse {
+        name: String,
+        #[cause]
+        inner: toml::de::Error,
+    },
+    #[fail(display = "Tower {} is defined twice in {}", id, name)]
+    DuplicateTower { name: String, id: String },
+}
+
+/// A kind of immune cell the player can build, see `res/towers.toml`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TowerDef {
+    pub id: String,
+    pub name: String,
+    pub cost: u32,
+    /// in tiles
+    pub range: f32,
+    /// health taken from a pathogen per hit
+    pub damage: f32,
+    /// seconds between two shots
+    pub fire_interval: f32,
+    /// in tiles per second
+    pub projectile_speed: f32,
+    /// probability of a shot dealing `critical_multiplier` times the damage
+    #[serde(default)]
+    pub critical_chance: f32,
+    #[serde(default = "default_critical_multiplier")]
+    pub critical_multiplier: f32,
+    pub texture: String,
+}
+
+fn default_critical_multiplier() -> f32 {
+    2.0
+}
+
+#[derive(Deserialize)]
+struct TowersFile {
+    towers: Vec<TowerDef>,
+}
+
+/// Every tower kind, in the order the file lists them.
+#[derive(Clone)]
+pub struct TowerCatalog {
+    towers: Vec<TowerDef>,
+}
+
+impl TowerCatalog {
+    pub fn from_res(res: &Resources, name: &str) -> Result<TowerCatalog, Error> {
+        let text = res.load_string(name).map_err(|e| Error::ResourceLoad {
+            name: name.into(),
+            inner: e,
+        })?;
+
+        TowerCatalog::from_toml_str(name, &text)
+    }
+
+    pub fn from_toml_str(name: &str, text: &str) -> Result<TowerCatalog, Error> {
+        let file: TowersFile = toml::from_str(text).map_err(|e| Error::Parse {
+            name: name.into(),
+            inner: e,
+        })?;
+
+        for (i, tower) in file.towers.iter().enumerate() {
+            if file.towers[..i].iter().any(|t| t.id == tower.id) {
+                return Err(Error::DuplicateTower {
+                    name: name.into(),
+                    id: tower.id.clone(),
+                });
+            }
+        }
+
+        Ok(TowerCatalog {
+            towers: file.towers,
+        })
+    }
+
+    pub fn get(&self, id: &str) -> Option<&TowerDef> {
+        self.towers.iter().find(|t| t.id == id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &TowerDef> {
+        self.towers.iter()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PlacedTower {
+    pub tower: String,
+    /// gold paid, for the sell refund
+    pub cost: u32,
+    /// simulation ticks until it can fire again
+    pub cooldown: u32,
+    pub kills: u32,
+}
+
+impl PlacedTower {
+    pub fn new(tower: &TowerDef) -> PlacedTower {
+        PlacedTower {
+            tower: tower.id.clone(),
+            cost: tower.cost,
+            cooldown: 0,
+            kills: 0,
+        }
+    }
+}
+
+/// Towers standing on the level grid, at most one per tile.
+#[derive(Default)]
+pub struct Towers {
+    placed: BTreeMap<TileCoord, PlacedTower>,
+}
+
+impl Towers {
+    pub fn new() -> Towers {
+        Towers::default()
+    }
+
+    pub fn is_occupied(&self, tile: TileCoord) -> bool {
+        self.placed.contains_key(&tile)
+    }
+
+    pub fn get(&self, tile: TileCoord) -> Option<&PlacedTower> {
+        self.placed.get(&tile)
+    }
+
+    pub fn get_mut(&mut self, tile: TileCoord) -> Option<&mut PlacedTower> {
+        self.placed.get_mut(&tile)
+    }
+
+    pub fn place(&mut self, tile: TileCoord, tower: PlacedTower) {
+        self.placed.insert(tile, tower);
+    }
+
+    pub fn remove(&mut self, tile: TileCoord) -> Option<PlacedTower> {
+        self.placed.remove(&tile)
+    }
+
+    /// Ordered by tile, the same way every run.
+    pub fn iter(&self) -> impl Iterator<Item = (TileCoord, &PlacedTower)> {
+        self.placed.iter().map(|(tile, tower)| (*tile, tower))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (TileCoord, &mut PlacedTower)> {
+        self.placed.iter_mut().map(|(tile, tower)| (*tile, tower))
+    }
+}
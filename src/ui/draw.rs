@@ -0,0 +1,94 @@
+use crate::assets::Handle;
+use crate::font::Font;
+use crate::ui::layout::Rect;
+use crate::ui::theme::Color;
+
+/// Text sizes the UI lays out with, so layout works the same with or without a GL context.
+pub trait TextMeasure {
+    /// Width and line height in pixels of `text` on a single line.
+    fn measure(&self, text: &str, size: f32) -> (f32, f32);
+}
+
+impl TextMeasure for Font {
+    fn measure(&self, text: &str, size: f32) -> (f32, f32) {
+        Font::measure(self, text, size)
+    }
+}
+
+impl<T: TextMeasure> TextMeasure for Handle<T> {
+    fn measure(&self, text: &str, size: f32) -> (f32, f32) {
+        (**self).measure(text, size)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DrawCommand {
+    Rect {
+        rect: Rect,
+        color: Color,
+    },
+    /// one line of text, `x`, `y` is the top left of the line
+    Text {
+        x: f32,
+        y: f32,
+        text: String,
+        size: f32,
+        color: Color,
+    },
+}
+
+/// What a UI frame draws, back to front.
+#[derive(Clone, Debug, Default)]
+pub struct DrawList {
+    commands: Vec<DrawCommand>,
+}
+
+impl DrawList {
+    pub fn new() -> DrawList {
+        DrawList::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.commands.clear();
+    }
+
+    pub fn rect(&mut self, rect: Rect, color: Color) {
+        if rect.w > 0.0 && rect.h > 0.0 && color[3] > 0 {
+            self.commands.push(DrawCommand::Rect { rect, color });
+        }
+    }
+
+    /// Four `width` thick edges just inside `rect`.
+    pub fn outline(&mut self, rect: Rect, width: f32, color: Color) {
+        self.rect(Rect::new(rect.x, rect.y, rect.w, width), color);
+        self.rect(
+            Rect::new(rect.x, rect.bottom() - width, rect.w, width),
+            color,
+        );
+        self.rect(Rect::new(rect.x, rect.y, width, rect.h), color);
+        self.rect(
+            Rect::new(rect.right() - width, rect.y, width, rect.h),
+            color,
+        );
+    }
+
+    pub fn text(&mut self, x: f32, y: f32, text: &str, size: f32, color: Color) {
+        if !text.is_empty() {
+            self.commands.push(DrawCommand::Text {
+                x,
+                y,
+                text: text.into(),
+                size,
+                color,
+            });
+        }
+    }
+
+    pub fn append(&mut self, other: &mut DrawList) {
+        self.commands.append(&mut other.commands);
+    }
+
+    pub fn commands(&self) -> &[DrawCommand] {
+        &self.commands
+    }
+}
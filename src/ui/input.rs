@@ -0,0 +1,97 @@
+use sdl2::event::Event;
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::mouse::MouseButton;
+
+/// Keys the UI reacts to when a widget has keyboard focus.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum UiKey {
+    /// Tab, or Shift-Tab when `backwards`
+    Tab {
+        backwards: bool,
+    },
+    /// Enter or Space
+    Activate,
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+/// Mouse and keyboard state as the UI sees it, independent of the game's `input::Bindings`.
+#[derive(Clone, Debug, Default)]
+pub struct UiInput {
+    pub mouse: (f32, f32),
+    pub mouse_down: bool,
+    /// true only in the frame the left button went down
+    pub mouse_pressed: bool,
+    /// true only in the frame the left button went up
+    pub mouse_released: bool,
+    /// wheel ticks this frame, positive away from the user
+    pub wheel: f32,
+    /// keys pressed this frame, in order
+    pub keys: Vec<UiKey>,
+}
+
+impl UiInput {
+    pub fn new() -> UiInput {
+        UiInput::default()
+    }
+
+    /// Forgets last frame's presses, releases, wheel ticks and keys.
+    pub fn begin_frame(&mut self) {
+        self.mouse_pressed = false;
+        self.mouse_released = false;
+        self.wheel = 0.0;
+        self.keys.clear();
+    }
+
+    pub fn handle_event(&mut self, event: &Event) {
+        match *event {
+            Event::MouseMotion { x, y, .. } => self.mouse = (x as f32, y as f32),
+            Event::MouseButtonDown {
+                mouse_btn: MouseButton::Left,
+                x,
+                y,
+                ..
+            } => {
+                self.mouse = (x as f32, y as f32);
+                self.mouse_down = true;
+                self.mouse_pressed = true;
+            }
+            Event::MouseButtonUp {
+                mouse_btn: MouseButton::Left,
+                x,
+                y,
+                ..
+            } => {
+                self.mouse = (x as f32, y as f32);
+                self.mouse_down = false;
+                self.mouse_released = true;
+            }
+            Event::MouseWheel { y, .. } => self.wheel += y as f32,
+            Event::KeyDown {
+                keycode: Some(keycode),
+                keymod,
+                ..
+            } => {
+                let key = match keycode {
+                    Keycode::Tab => Some(UiKey::Tab {
+                        backwards: keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD),
+                    }),
+                    Keycode::Return | Keycode::KpEnter | Keycode::Space => Some(UiKey::Activate),
+                    Keycode::Left => Some(UiKey::Left),
+                    Keycode::Right => Some(UiKey::Right),
+                    Keycode::Up => Some(UiKey::Up),
+                    Keycode::Down => Some(UiKey::Down),
+                    _ => None,
+                };
+                self.keys.extend(key);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn key_pressed(&self, key: UiKey) -> bool {
+        self.keys.contains(&key)
+    }
+}
@@ -0,0 +1,148 @@
+/// An axis aligned rectangle in window pixels, origin at the top left, y pointing down.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+impl Rect {
+    pub fn new(x: f32, y: f32, w: f32, h: f32) -> Rect {
+        Rect { x, y, w, h }
+    }
+
+    pub fn right(&self) -> f32 {
+        self.x + self.w
+    }
+
+    pub fn bottom(&self) -> f32 {
+        self.y + self.h
+    }
+
+    /// Left and top edges are inside, right and bottom edges are not.
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        x >= self.x && x < self.right() && y >= self.y && y < self.bottom()
+    }
+
+    /// Shrunk by `amount` on every side, never below zero size.
+    pub fn inset(&self, amount: f32) -> Rect {
+        Rect {
+            x: self.x + amount,
+            y: self.y + amount,
+            w: (self.w - amount * 2.0).max(0.0),
+            h: (self.h - amount * 2.0).max(0.0),
+        }
+    }
+
+    /// The left `fraction` of the rectangle, clamped to 0.0..=1.0.
+    pub fn left_fraction(&self, fraction: f32) -> Rect {
+        Rect {
+            w: self.w * fraction.max(0.0).min(1.0),
+            ..*self
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// widgets stacked top to bottom, each as wide as the layout
+    Vertical,
+    /// widgets left to right, each as tall as the layout
+    Horizontal,
+}
+
+/// Hands out space for widgets one after another.
+#[derive(Copy, Clone, Debug)]
+pub struct Layout {
+    rect: Rect,
+    direction: Direction,
+    spacing: f32,
+    cursor: f32,
+}
+
+impl Layout {
+    pub fn new(rect: Rect, direction: Direction, spacing: f32) -> Layout {
+        Layout {
+            rect,
+            direction,
+            spacing,
+            cursor: 0.0,
+        }
+    }
+
+    pub fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// Takes the next `extent` pixels along the layout direction. Space past the end of the
+    /// layout is still handed out, overflowing widgets are the caller's problem.
+    pub fn allocate(&mut self, extent: f32) -> Rect {
+        let offset = self.cursor;
+        self.cursor += extent + self.spacing;
+
+        match self.direction {
+            Direction::Vertical => {
+                Rect::new(self.rect.x, self.rect.y + offset, self.rect.w, extent)
+            }
+            Direction::Horizontal => {
+                Rect::new(self.rect.x + offset, self.rect.y, extent, self.rect.h)
+            }
+        }
+    }
+
+    /// Pixels left along the layout direction.
+    pub fn remaining(&self) -> f32 {
+        let length = match self.direction {
+            Direction::Vertical => self.rect.h,
+            Direction::Horizontal => self.rect.w,
+        };
+        (length - self.cursor).max(0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contains_includes_only_the_top_left_edges() {
+        let rect = Rect::new(10.0, 20.0, 30.0, 40.0);
+        assert!(rect.contains(10.0, 20.0));
+        assert!(rect.contains(39.9, 59.9));
+        assert!(!rect.contains(40.0, 30.0));
+        assert!(!rect.contains(20.0, 60.0));
+        assert!(!rect.contains(9.9, 30.0));
+        assert!(!rect.contains(20.0, 19.9));
+        assert!(!Rect::new(0.0, 0.0, 0.0, 0.0).contains(0.0, 0.0));
+    }
+
+    #[test]
+    fn vertical_layout_stacks_full_width_rows() {
+        let mut layout = Layout::new(Rect::new(10.0, 20.0, 100.0, 50.0), Direction::Vertical, 4.0);
+
+        assert_eq!(layout.allocate(16.0), Rect::new(10.0, 20.0, 100.0, 16.0));
+        assert_eq!(layout.allocate(8.0), Rect::new(10.0, 40.0, 100.0, 8.0));
+        assert_eq!(layout.remaining(), 50.0 - 32.0);
+        // overflowing space is still handed out
+        assert_eq!(layout.allocate(40.0), Rect::new(10.0, 52.0, 100.0, 40.0));
+        assert_eq!(layout.remaining(), 0.0);
+    }
+
+    #[test]
+    fn horizontal_layout_places_full_height_columns() {
+        let mut layout = Layout::new(
+            Rect::new(10.0, 20.0, 100.0, 24.0),
+            Direction::Horizontal,
+            2.0,
+        );
+
+        assert_eq!(layout.allocate(30.0), Rect::new(10.0, 20.0, 30.0, 24.0));
+        assert_eq!(layout.allocate(12.0), Rect::new(42.0, 20.0, 12.0, 24.0));
+        assert_eq!(layout.remaining(), 100.0 - 46.0);
+    }
+}
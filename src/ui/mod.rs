@@ -0,0 +1,795 @@
+//! Immediate-mode UI for the HUD, menus and tower panels.
+//!
+//! Widgets are declared every frame by calling methods on `Ui` between `begin_frame` and
+//! `end_frame`; the call that declares a button also reports whether it was clicked. `Ui`
+//! handles hover, click and keyboard focus and records a `DrawList`, without touching GL,
+//! so layout and hit-testing work headless. `UiRenderer` draws the list.
+
+pub mod draw;
+pub mod input;
+pub mod layout;
+mod renderer;
+pub mod theme;
+
+pub use self::{
+    draw::{DrawCommand, DrawList, TextMeasure},
+    input::{UiInput, UiKey},
+    layout::{Direction, Layout, Rect},
+    renderer::UiRenderer,
+    theme::{Color, Theme},
+};
+
+use sdl2::event::Event;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+/// Identifies a widget across frames: its label hashed together with the `push_id` stack.
+pub type WidgetId = u64;
+
+/// Distance of a tooltip from the mouse pointer.
+const TOOLTIP_OFFSET: (f32, f32) = (14.0, 18.0);
+
+/// How a widget reacted to this frame's input.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Response {
+    pub hovered: bool,
+    /// the mouse went down on the widget and is still down
+    pub held: bool,
+    /// released over the widget after pressing it, or activated with the keyboard
+    pub clicked: bool,
+    pub focused: bool,
+}
+
+pub struct Ui {
+    theme: Theme,
+    measure: Box<dyn TextMeasure>,
+    input: UiInput,
+    draw: DrawList,
+    overlay: DrawList,
+    layouts: Vec<Layout>,
+    ids: Vec<u64>,
+    hot: Option<WidgetId>,
+    active: Option<WidgetId>,
+    focused: Option<WidgetId>,
+    focus_order: Vec<WidgetId>,
+    last_focus_order: Vec<WidgetId>,
+    panels: Vec<Rect>,
+    last_rect: Rect,
+    scroll: HashMap<WidgetId, usize>,
+    /// window coordinates per UI unit, as the player set it
+    scale: f32,
+    /// drawable pixels per window coordinate, see `Viewport::pixel_ratio`
+    pixel_ratio: f32,
+}
+
+impl Ui {
+    pub fn new(theme: Theme, measure: Box<dyn TextMeasure>) -> Ui {
+        Ui {
+            theme,
+            measure,
+            input: UiInput::new(),
+            draw: DrawList::new(),
+            overlay: DrawList::new(),
+            layouts: Vec::new(),
+            ids: Vec::new(),
+            hot: None,
+            active: None,
+            focused: None,
+            focus_order: Vec::new(),
+            last_focus_order: Vec::new(),
+            panels: Vec::new(),
+            last_rect: Rect::default(),
+            scroll: HashMap::new(),
+            scale: 1.0,
+            pixel_ratio: 1.0,
+        }
+    }
+
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    pub fn input(&self) -> &UiInput {
+        &self.input
+    }
+
+    /// Drawable pixels per UI unit, the player's scale times the pixel ratio. Widgets, the
+    /// screen passed to `begin_frame` and the theme's sizes are all in UI units,
+    /// `UiRenderer` scales them up.
+    pub fn scale(&self) -> f32 {
+        self.scale * self.pixel_ratio
+    }
+
+    /// The player's scale, see `settings::Interface`.
+    pub fn set_scale(&mut self, scale: f32) {
+        if scale > 0.0 {
+            self.scale = scale;
+        }
+    }
+
+    /// Keeps the UI the same size in window coordinates on high-DPI displays.
+    pub fn set_pixel_ratio(&mut self, pixel_ratio: f32) {
+        if pixel_ratio > 0.0 {
+            self.pixel_ratio = pixel_ratio;
+        }
+    }
+
+    /// Feed every event of the frame before `begin_frame`.
+    pub fn handle_event(&mut self, event: &Event) {
+        self.input.handle_event(event);
+    }
+
+    /// Starts declaring widgets, laid out top to bottom in `screen` unless inside a panel.
+    pub fn begin_frame(&mut self, screen: Rect) {
+        self.draw.clear();
+        self.overlay.clear();
+        self.layouts.clear();
+        self.layouts.push(Layout::new(
+            screen.inset(self.theme.padding),
+            Direction::Vertical,
+            self.theme.spacing,
+        ));
+        self.ids.clear();
+        self.hot = None;
+        self.focus_order.clear();
+        self.panels.clear();
+        self.last_rect = Rect::default();
+
+        // focus moves along last frame's widgets, this frame's aren't declared yet
+        for key in self.input.keys.clone() {
+            if let UiKey::Tab { backwards } = key {
+                self.focused = next_focus(&self.last_focus_order, self.focused, backwards);
+            }
+        }
+    }
+
+    pub fn end_frame(&mut self) {
+        if let Some(focused) = self.focused {
+            if !self.focus_order.contains(&focused) {
+                self.focused = None;
+            }
+        }
+        if self.input.mouse_pressed && self.hot.is_none() {
+            self.focused = None;
+        }
+        if !self.input.mouse_down {
+            self.active = None;
+        }
+
+        std::mem::swap(&mut self.focus_order, &mut self.last_focus_order);
+        self.draw.append(&mut self.overlay);
+        // events of the next frame arrive before its begin_frame
+        self.input.begin_frame();
+    }
+
+    /// What the last finished frame draws.
+    pub fn draw_list(&self) -> &DrawList {
+        &self.draw
+    }
+
+    /// True while the mouse is over a panel or dragging a widget, so the game should ignore
+    /// clicks. Valid after the frame's widgets are declared.
+    pub fn wants_mouse(&self) -> bool {
+        let (x, y) = self.mouse();
+        self.active.is_some() || self.panels.iter().any(|panel| panel.contains(x, y))
+    }
+
+    /// True while the mouse is held down on a widget, e.g. a slider being dragged. Valid
+    /// after `end_frame`.
+    pub fn is_dragging(&self) -> bool {
+        self.active.is_some()
+    }
+
+    /// True while a widget has keyboard focus, so Enter and Space belong to the UI.
+    pub fn wants_keyboard(&self) -> bool {
+        self.focused.is_some()
+    }
+
+    pub fn push_id(&mut self, id: &str) {
+        let id = self.id(id);
+        self.ids.push(id);
+    }
+
+    pub fn pop_id(&mut self) {
+        self.ids.pop();
+    }
+
+    /// Widgets declared in `f` get ids unique to `id`, e.g. one tower panel per tower.
+    pub fn with_id<R>(&mut self, id: &str, f: impl FnOnce(&mut Ui) -> R) -> R {
+        self.push_id(id);
+        let result = f(self);
+        self.pop_id();
+        result
+    }
+
+    /// A filled box at `rect` with its widgets laid out top to bottom inside. The game
+    /// doesn't get mouse input over panels, see `wants_mouse`.
+    pub fn panel<R>(&mut self, id: &str, rect: Rect, f: impl FnOnce(&mut Ui) -> R) -> R {
+        self.panels.push(rect);
+        self.draw.rect(rect, self.theme.colors.panel);
+
+        self.push_id(id);
+        self.layouts.push(Layout::new(
+            rect.inset(self.theme.padding),
+            Direction::Vertical,
+            self.theme.spacing,
+        ));
+        let result = f(self);
+        self.layouts.pop();
+        self.pop_id();
+        result
+    }
+
+    /// Widgets declared in `f` are laid out left to right, each as tall as a button.
+    pub fn row<R>(&mut self, f: impl FnOnce(&mut Ui) -> R) -> R {
+        let height = self.button_height();
+        let rect = self.allocate(0.0, height);
+        self.layouts
+            .push(Layout::new(rect, Direction::Horizontal, self.theme.spacing));
+        let result = f(self);
+        self.layouts.pop();
+        result
+    }
+
+    /// Empty space along the current layout.
+    pub fn space(&mut self, pixels: f32) {
+        self.allocate(pixels, pixels);
+    }
+
+    pub fn label(&mut self, text: &str) {
+        let (width, height) = self.measure.measure(text, self.theme.font_size);
+        let rect = self.allocate(width, height);
+        self.last_rect = rect;
+        self.draw.text(
+            rect.x,
+            rect.y,
+            text,
+            self.theme.font_size,
+            self.theme.colors.text,
+        );
+    }
+
+    /// Returns true when clicked.
+    pub fn button(&mut self, text: &str) -> bool {
+        self.button_enabled(text, true)
+    }
+
+    /// A disabled button is greyed out, can't be clicked and is skipped by Tab.
+    pub fn button_enabled(&mut self, text: &str, enabled: bool) -> bool {
+        let (text_width, _) = self.measure.measure(text, self.theme.font_size);
+        let padding = self.theme.padding;
+        let rect = self.allocate(text_width + padding * 2.0, self.button_height());
+        self.last_rect = rect;
+
+        let (response, fill, text_color) = if enabled {
+            let id = self.id(text);
+            let response = self.interact(id, rect);
+            (
+                response,
+                self.widget_color(&response),
+                self.theme.colors.text,
+            )
+        } else {
+            (
+                Response::default(),
+                self.theme.colors.widget_active,
+                self.theme.colors.text_disabled,
+            )
+        };
+
+        self.draw.rect(rect, fill);
+        self.draw_focus(rect, &response);
+        self.centered_text(rect, text, text_color);
+        response.clicked
+    }
+
+    /// Drags `value` between `min` and `max`; Left and Right step it when focused.
+    /// Returns true when the value changed.
+    pub fn slider(&mut self, text: &str, value: &mut f32, min: f32, max: f32) -> bool {
+        let rect = self.allocate(0.0, self.button_height());
+        self.last_rect = rect;
+        let id = self.id(text);
+        let response = self.interact(id, rect);
+
+        let old = *value;
+        if response.held && rect.w > 0.0 {
+            let fraction = (self.mouse().0 - rect.x) / rect.w;
+            *value = min + fraction.max(0.0).min(1.0) * (max - min);
+        }
+        if response.focused {
+            let step = (max - min) / 20.0;
+            if self.input.key_pressed(UiKey::Left) {
+                *value -= step;
+            }
+            if self.input.key_pressed(UiKey::Right) {
+                *value += step;
+            }
+        }
+        *value = value.max(min.min(max)).min(max.max(min));
+
+        let fraction = if max != min {
+            (*value - min) / (max - min)
+        } else {
+            0.0
+        };
+        self.draw.rect(rect, self.widget_color(&response));
+        self.draw
+            .rect(rect.left_fraction(fraction), self.theme.colors.accent);
+        self.draw_focus(rect, &response);
+        self.centered_text(
+            rect,
+            &format!("{}: {:.2}", text, value),
+            self.theme.colors.text,
+        );
+
+        *value != old
+    }
+
+    /// A scrolling list showing `rows` of `items` at a time. Clicking picks an item, Up and
+    /// Down move the selection when focused. Returns true when the selection changed.
+    pub fn list<S: AsRef<str>>(
+        &mut self,
+        id: &str,
+        items: &[S],
+        selected: &mut Option<usize>,
+        rows: usize,
+    ) -> bool {
+        let font_size = self.theme.font_size;
+        let padding = self.theme.padding;
+        let row_height = self.line_height() + padding;
+        let rows = rows.max(1);
+        let rect = self.allocate(0.0, row_height * rows as f32);
+        self.last_rect = rect;
+        let id = self.id(id);
+        let response = self.interact(id, rect);
+
+        let max_scroll = items.len().saturating_sub(rows);
+        let mut scroll = self.scroll.get(&id).cloned().unwrap_or(0).min(max_scroll);
+        if response.hovered && self.input.wheel != 0.0 {
+            let ticks = self.input.wheel.abs().ceil() as usize;
+            scroll = if self.input.wheel > 0.0 {
+                scroll.saturating_sub(ticks)
+            } else {
+                (scroll + ticks).min(max_scroll)
+            };
+        }
+
+        let old = *selected;
+        if response.hovered && self.input.mouse_pressed {
+            let row = ((self.mouse().1 - rect.y) / row_height) as usize + scroll;
+            if row < items.len() {
+                *selected = Some(row);
+            }
+        }
+        if response.focused && !items.is_empty() {
+            if self.input.key_pressed(UiKey::Up) {
+                *selected = Some(selected.map_or(0, |s| s.saturating_sub(1)));
+            }
+            if self.input.key_pressed(UiKey::Down) {
+                *selected = Some(selected.map_or(0, |s| (s + 1).min(items.len() - 1)));
+            }
+        }
+        if *selected != old {
+            // keep the selection in view
+            if let Some(s) = *selected {
+                if s < scroll {
+                    scroll = s;
+                } else if s >= scroll + rows {
+                    scroll = s + 1 - rows;
+                }
+            }
+        }
+        self.scroll.insert(id, scroll);
+
+        self.draw.rect(rect, self.theme.colors.widget);
+        for (i, item) in items.iter().enumerate().skip(scroll).take(rows) {
+            let row = Rect::new(
+                rect.x,
+                rect.y + (i - scroll) as f32 * row_height,
+                rect.w,
+                row_height,
+            );
+            if *selected == Some(i) {
+                self.draw.rect(row, self.theme.colors.selection);
+            }
+            self.draw.text(
+                row.x + padding,
+                row.y + padding / 2.0,
+                item.as_ref(),
+                font_size,
+                self.theme.colors.text,
+            );
+        }
+        self.draw_focus(rect, &response);
+
+        *selected != old
+    }
+
+    /// A bar filled to `fraction` with `text` on top, e.g. a health bar.
+    pub fn progress_bar(&mut self, text: &str, fraction: f32, color: Color) {
+        let rect = self.allocate(0.0, self.button_height());
+        self.last_rect = rect;
+        self.draw.rect(rect, self.theme.colors.widget_active);
+        self.draw.rect(rect.left_fraction(fraction), color);
+        self.centered_text(rect, text, self.theme.colors.text);
+    }
+
+    /// Shows `text` next to the mouse while it's over the previous widget.
+    pub fn tooltip(&mut self, text: &str) {
+        let (x, y) = self.mouse();
+        if text.is_empty() || self.active.is_some() || !self.last_rect.contains(x, y) {
+            return;
+        }
+
+        let padding = self.theme.padding;
+        let (width, height) = self.measure.measure(text, self.theme.font_size);
+        let rect = Rect::new(
+            x + TOOLTIP_OFFSET.0,
+            y + TOOLTIP_OFFSET.1,
+            width + padding * 2.0,
+            height + padding * 2.0,
+        );
+        self.overlay.rect(rect, self.theme.colors.tooltip);
+        self.overlay.text(
+            rect.x + padding,
+            rect.y + padding,
+            text,
+            self.theme.font_size,
+            self.theme.colors.text,
+        );
+    }
+
+    pub fn line_height(&self) -> f32 {
+        self.measure.measure("", self.theme.font_size).1
+    }
+
+    pub fn button_height(&self) -> f32 {
+        self.line_height() + self.theme.padding * 2.0
+    }
+
+    /// Space for a `width` x `height` widget from the innermost layout, which decides
+    /// the extent across its direction.
+    pub fn allocate(&mut self, width: f32, height: f32) -> Rect {
+        let layout = self
+            .layouts
+            .last_mut()
+            .expect("expected begin_frame before declaring widgets");
+        match layout.direction() {
+            Direction::Vertical => layout.allocate(height),
+            Direction::Horizontal => layout.allocate(width),
+        }
+    }
+
+    /// The mouse in UI units. SDL reports it in window coordinates, so the pixel ratio
+    /// doesn't apply.
+    fn mouse(&self) -> (f32, f32) {
+        let (x, y) = self.input.mouse;
+        (x / self.scale, y / self.scale)
+    }
+
+    fn id(&self, label: &str) -> WidgetId {
+        let mut hasher = DefaultHasher::new();
+        self.ids.last().hash(&mut hasher);
+        label.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Hover, click and focus of a widget at `rect`. While another widget holds the mouse,
+    /// nothing else is hovered.
+    fn interact(&mut self, id: WidgetId, rect: Rect) -> Response {
+        self.focus_order.push(id);
+
+        let (x, y) = self.mouse();
+        let hovered = rect.contains(x, y) && self.active.map_or(true, |active| active == id);
+        if hovered {
+            self.hot = Some(id);
+            if self.input.mouse_pressed {
+                self.active = Some(id);
+                self.focused = Some(id);
+            }
+        }
+
+        let active = self.active == Some(id);
+        let focused = self.focused == Some(id);
+        Response {
+            hovered,
+            held: active && self.input.mouse_down,
+            clicked: (active && hovered && self.input.mouse_released)
+                || (focused && self.input.key_pressed(UiKey::Activate)),
+            focused,
+        }
+    }
+
+    fn widget_color(&self, response: &Response) -> Color {
+        if response.held {
+            self.theme.colors.widget_active
+        } else if response.hovered {
+            self.theme.colors.widget_hot
+        } else {
+            self.theme.colors.widget
+        }
+    }
+
+    fn draw_focus(&mut self, rect: Rect, response: &Response) {
+        if response.focused {
+            self.draw
+                .outline(rect, self.theme.focus_width, self.theme.colors.focus);
+        }
+    }
+
+    fn centered_text(&mut self, rect: Rect, text: &str, color: Color) {
+        let (width, height) = self.measure.measure(text, self.theme.font_size);
+        self.draw.text(
+            (rect.x + (rect.w - width) / 2.0).round(),
+            (rect.y + (rect.h - height) / 2.0).round(),
+            text,
+            self.theme.font_size,
+            color,
+        );
+    }
+}
+
+/// The widget after (or before) `focused` in `order`, wrapping around.
+fn next_focus(order: &[WidgetId], focused: Option<WidgetId>, backwards: bool) -> Option<WidgetId> {
+    if order.is_empty() {
+        return None;
+    }
+
+    let len = order.len();
+    let index = match focused.and_then(|f| order.iter().position(|id| *id == f)) {
+        Some(i) if backwards => (i + len - 1) % len,
+        Some(i) => (i + 1) % len,
+        None if backwards => len - 1,
+        None => 0,
+    };
+    Some(order[index])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::Resources;
+    use std::path::Path;
+
+    const SCREEN: Rect = Rect {
+        x: 0.0,
+        y: 0.0,
+        w: 400.0,
+        h: 300.0,
+    };
+
+    /// Every character is 8 pixels wide, every line 16 pixels high.
+    struct FixedWidth;
+
+    impl TextMeasure for FixedWidth {
+        fn measure(&self, text: &str, _size: f32) -> (f32, f32) {
+            (text.chars().count() as f32 * 8.0, 16.0)
+        }
+    }
+
+    fn ui() -> Ui {
+        let res = Resources::from_directory(&Path::new(env!("CARGO_MANIFEST_DIR")).join("res"));
+        Ui::new(
+            Theme::from_res(&res, "ui/theme.toml").unwrap(),
+            Box::new(FixedWidth),
+        )
+    }
+
+    /// One frame: `input` is this frame's events, `widgets` declares the widgets.
+    fn frame<R>(
+        ui: &mut Ui,
+        input: impl FnOnce(&mut UiInput),
+        widgets: impl FnOnce(&mut Ui) -> R,
+    ) -> R {
+        input(&mut ui.input);
+        ui.begin_frame(SCREEN);
+        let result = widgets(ui);
+        ui.end_frame();
+        result
+    }
+
+    fn move_to(x: f32, y: f32) -> impl FnOnce(&mut UiInput) {
+        move |input: &mut UiInput| input.mouse = (x, y)
+    }
+
+    fn press(x: f32, y: f32) -> impl FnOnce(&mut UiInput) {
+        move |input: &mut UiInput| {
+            input.mouse = (x, y);
+            input.mouse_down = true;
+            input.mouse_pressed = true;
+        }
+    }
+
+    fn release(x: f32, y: f32) -> impl FnOnce(&mut UiInput) {
+        move |input: &mut UiInput| {
+            input.mouse = (x, y);
+            input.mouse_down = false;
+            input.mouse_released = true;
+        }
+    }
+
+    fn key(key: UiKey) -> impl FnOnce(&mut UiInput) {
+        move |input: &mut UiInput| input.keys.push(key)
+    }
+
+    /// Declares buttons "A", "B" and "C" and returns which were clicked.
+    fn three_buttons(ui: &mut Ui) -> Vec<&'static str> {
+        ["A", "B", "C"]
+            .iter()
+            .cloned()
+            .filter(|text| ui.button(text))
+            .collect()
+    }
+
+    #[test]
+    fn widgets_stack_inside_the_padded_screen() {
+        let mut ui = ui();
+        let (padding, spacing) = (ui.theme().padding, ui.theme().spacing);
+        let height = 16.0 + padding * 2.0;
+
+        let rects = frame(
+            &mut ui,
+            |_| {},
+            |ui| {
+                ui.button("first");
+                let first = ui.last_rect;
+                ui.label("second");
+                (first, ui.last_rect)
+            },
+        );
+
+        assert_eq!(
+            rects.0,
+            Rect::new(padding, padding, SCREEN.w - padding * 2.0, height)
+        );
+        assert_eq!(
+            rects.1,
+            Rect::new(
+                padding,
+                padding + height + spacing,
+                SCREEN.w - padding * 2.0,
+                16.0
+            )
+        );
+    }
+
+    #[test]
+    fn row_places_buttons_side_by_side() {
+        let mut ui = ui();
+        let (padding, spacing) = (ui.theme().padding, ui.theme().spacing);
+
+        let rects = frame(
+            &mut ui,
+            |_| {},
+            |ui| {
+                ui.row(|ui| {
+                    ui.button("OK");
+                    let ok = ui.last_rect;
+                    ui.button("Cancel");
+                    (ok, ui.last_rect)
+                })
+            },
+        );
+
+        let height = 16.0 + padding * 2.0;
+        let ok_width = 2.0 * 8.0 + padding * 2.0;
+        assert_eq!(rects.0, Rect::new(padding, padding, ok_width, height));
+        assert_eq!(
+            rects.1,
+            Rect::new(
+                padding + ok_width + spacing,
+                padding,
+                6.0 * 8.0 + padding * 2.0,
+                height
+            )
+        );
+    }
+
+    #[test]
+    fn click_needs_press_and_release_over_the_same_widget() {
+        let mut ui = ui();
+        let over_a = (20.0, ui.theme().padding + 1.0);
+        let over_b = (
+            20.0,
+            ui.theme().padding + ui.button_height() + ui.theme().spacing + 1.0,
+        );
+
+        assert!(frame(&mut ui, press(over_a.0, over_a.1), three_buttons).is_empty());
+        assert!(ui.is_dragging());
+        assert_eq!(
+            frame(&mut ui, release(over_a.0, over_a.1), three_buttons),
+            vec!["A"]
+        );
+        assert!(!ui.is_dragging());
+
+        // pressed on A, released on B
+        frame(&mut ui, press(over_a.0, over_a.1), three_buttons);
+        let hovered_b = frame(&mut ui, move_to(over_b.0, over_b.1), |ui| {
+            three_buttons(ui);
+            ui.hot
+        });
+        assert_eq!(hovered_b, None, "B can't be hot while A holds the mouse");
+        assert!(frame(&mut ui, release(over_b.0, over_b.1), three_buttons).is_empty());
+    }
+
+    #[test]
+    fn tab_cycles_focus_and_activate_clicks_the_focused_widget() {
+        let mut ui = ui();
+        let tab = UiKey::Tab { backwards: false };
+        let back = UiKey::Tab { backwards: true };
+
+        frame(&mut ui, |_| {}, three_buttons);
+        assert!(!ui.wants_keyboard());
+
+        frame(&mut ui, key(tab), three_buttons);
+        frame(&mut ui, key(tab), three_buttons);
+        assert_eq!(
+            frame(&mut ui, key(UiKey::Activate), three_buttons),
+            vec!["B"]
+        );
+
+        frame(&mut ui, key(back), three_buttons);
+        frame(&mut ui, key(back), three_buttons);
+        assert_eq!(
+            frame(&mut ui, key(UiKey::Activate), three_buttons),
+            vec!["C"]
+        );
+        assert!(ui.wants_keyboard());
+
+        // clicking empty space drops focus
+        frame(&mut ui, press(20.0, SCREEN.h - 1.0), three_buttons);
+        assert!(!ui.wants_keyboard());
+    }
+
+    #[test]
+    fn next_focus_wraps_around() {
+        let order = [1, 2, 3];
+        assert_eq!(next_focus(&order, None, false), Some(1));
+        assert_eq!(next_focus(&order, None, true), Some(3));
+        assert_eq!(next_focus(&order, Some(3), false), Some(1));
+        assert_eq!(next_focus(&order, Some(1), true), Some(3));
+        assert_eq!(next_focus(&order, Some(2), false), Some(3));
+        // a widget that went away starts over
+        assert_eq!(next_focus(&order, Some(9), false), Some(1));
+        assert_eq!(next_focus(&[], Some(1), false), None);
+    }
+
+    #[test]
+    fn wants_mouse_over_panels_and_while_dragging() {
+        let mut ui = ui();
+        let panel = Rect::new(200.0, 100.0, 150.0, 150.0);
+        let mut volume = 0.5;
+        let widgets = |ui: &mut Ui, volume: &mut f32| {
+            ui.slider("Volume", volume, 0.0, 1.0);
+            ui.panel("tower", panel, |ui| ui.label("Tower"));
+            ui.wants_mouse()
+        };
+
+        assert!(frame(&mut ui, move_to(250.0, 150.0), |ui| widgets(
+            ui,
+            &mut volume
+        )));
+        assert!(!frame(&mut ui, move_to(100.0, 150.0), |ui| widgets(
+            ui,
+            &mut volume
+        )));
+
+        // dragging the slider out of it keeps the mouse
+        let over_slider = ui.theme().padding + 1.0;
+        frame(&mut ui, press(100.0, over_slider), |ui| {
+            widgets(ui, &mut volume)
+        });
+        assert!(frame(&mut ui, move_to(100.0, 150.0), |ui| widgets(
+            ui,
+            &mut volume
+        )));
+        frame(&mut ui, release(100.0, 150.0), |ui| {
+            widgets(ui, &mut volume)
+        });
+        assert!(!frame(&mut ui, move_to(100.0, 150.0), |ui| widgets(
+            ui,
+            &mut volume
+        )));
+    }
+}
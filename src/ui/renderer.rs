@@ -0,0 +1,63 @@
+use crate::assets::Handle;
+use crate::font::Font;
+use crate::render_gl::{Quad, SpriteBatch, SpriteMode, TextRenderer, Viewport};
+use crate::resources::Resources;
+use crate::ui::draw::{DrawCommand, DrawList};
+use failure;
+use gl;
+
+/// Draws a `Ui`'s `DrawList` on top of the frame. Rectangles use the solid block of the
+/// glyph atlas, so a whole list usually takes a single draw call.
+pub struct UiRenderer {
+    batch: SpriteBatch,
+    text: TextRenderer,
+}
+
+impl UiRenderer {
+    pub fn new(
+        gl: &gl::Gl,
+        res: &Resources,
+        font: Handle<Font>,
+    ) -> Result<UiRenderer, failure::Error> {
+        Ok(UiRenderer {
+            batch: SpriteBatch::new(gl, res)?,
+            text: TextRenderer::new(gl, font)?,
+        })
+    }
+
+    /// Draws `list` at `scale` pixels per UI unit, see `Ui::scale`.
+    pub fn render(&mut self, list: &DrawList, viewport: &Viewport, scale: f32) {
+        let projection =
+            SpriteBatch::screen_projection(viewport.w as f32 / scale, viewport.h as f32 / scale);
+        let white = self.text.white_uv();
+
+        self.batch.begin(&projection);
+        for command in list.commands() {
+            match command {
+                DrawCommand::Rect { rect, color } => self.batch.draw(
+                    self.text.atlas(),
+                    SpriteMode::Mask,
+                    &Quad {
+                        x: rect.x,
+                        y: rect.y,
+                        w: rect.w,
+                        h: rect.h,
+                        uv: white,
+                        tint: *color,
+                    },
+                ),
+                DrawCommand::Text {
+                    x,
+                    y,
+                    text,
+                    size,
+                    color,
+                } => {
+                    self.text
+                        .draw(&mut self.batch, text, *x, *y, *size, scale, *color);
+                }
+            }
+        }
+        self.batch.end();
+    }
+}
@@ -0,0 +1,69 @@
+use crate::resources::{self, Resources};
+use toml;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to load theme {}", name)]
+    ResourceLoad {
+        name: String,
+        #[cause]
+        inner: resources::Error,
+    },
+    #[fail(display = "Failed to parse theme {}", name)]
+    Parse {
+        name: String,
+        #[cause]
+        inner: toml::de::Error,
+    },
+}
+
+/// RGBA, 0 to 255.
+pub type Color = [u8; 4];
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Colors {
+    pub text: Color,
+    pub text_disabled: Color,
+    pub panel: Color,
+    pub widget: Color,
+    pub widget_hot: Color,
+    pub widget_active: Color,
+    pub focus: Color,
+    pub accent: Color,
+    pub selection: Color,
+    pub tooltip: Color,
+}
+
+/// Fonts, colours and spacing of every widget, see `res/ui/theme.toml`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Theme {
+    /// font resource, loaded through `Assets`
+    pub font: String,
+    /// pixels per em
+    pub font_size: f32,
+    /// space between a widget's border and its content
+    pub padding: f32,
+    /// space between neighbouring widgets
+    pub spacing: f32,
+    /// thickness of the keyboard focus outline
+    pub focus_width: f32,
+    pub colors: Colors,
+}
+
+impl Theme {
+    pub fn from_res(res: &Resources, name: &str) -> Result<Theme, Error> {
+        let text = res.load_string(name).map_err(|e| Error::ResourceLoad {
+            name: name.into(),
+            inner: e,
+        })?;
+
+        Theme::from_toml_str(name, &text)
+    }
+
+    pub fn from_toml_str(name: &str, text: &str) -> Result<Theme, Error> {
+        toml::from_str(text).map_err(|e| Error::Parse {
+            name: name.into(),
+            inner: e,
+        })
+    }
+}
@@ -0,0 +1,144 @@
+//! Draws the match on the level grid: walls, path, towers, pathogens with their health and
+//! the projectiles in flight, all in one sprite batch.
+
+use crate::assets::{Assets, Handle};
+use crate::camera::Camera;
+use crate::game::Game;
+use crate::level::{Level, Tile, TileCoord};
+use crate::render_gl::{Quad, SpriteBatch, SpriteMode, Texture, TextureFormat};
+use crate::tower::TowerCatalog;
+use failure;
+use gl;
+use nalgebra as na;
+use std::collections::HashMap;
+
+/// Textures are stored top row first, the world's y axis points up.
+const WORLD_UV: [f32; 4] = [0.0, 1.0, 1.0, 0.0];
+const WALL_COLOR: [u8; 4] = [60, 20, 30, 255];
+const PATH_COLOR: [u8; 4] = [110, 40, 50, 255];
+const SPAWN_COLOR: [u8; 4] = [70, 70, 90, 255];
+const HEART_COLOR: [u8; 4] = [200, 40, 60, 255];
+const HEALTH_BACK: [u8; 4] = [40, 0, 0, 200];
+const HEALTH_FRONT: [u8; 4] = [90, 230, 90, 255];
+const PROJECTILE_COLOR: [u8; 4] = [255, 255, 220, 255];
+/// in tiles
+const PATHOGEN_SIZE: f32 = 0.5;
+const PROJECTILE_SIZE: f32 = 0.12;
+const HEALTH_BAR_HEIGHT: f32 = 0.08;
+
+pub struct WorldRenderer {
+    batch: SpriteBatch,
+    white: Texture,
+    towers: HashMap<String, Handle<Texture>>,
+}
+
+impl WorldRenderer {
+    /// Loads the texture of every tower in `catalog` up front.
+    pub fn new(
+        assets: &mut Assets,
+        gl: &gl::Gl,
+        catalog: &TowerCatalog,
+    ) -> Result<WorldRenderer, failure::Error> {
+        let batch = SpriteBatch::new(gl, assets.res())?;
+        let white = Texture::from_pixels(1, 1, TextureFormat::Rgba8, &[255; 4]).create(gl)?;
+
+        let mut towers = HashMap::new();
+        for tower in catalog.iter() {
+            towers.insert(tower.id.clone(), assets.load::<Texture>(&tower.texture)?);
+        }
+
+        Ok(WorldRenderer {
+            batch,
+            white,
+            towers,
+        })
+    }
+
+    pub fn render(&mut self, cam: &Camera, level: &Level, game: &Game) {
+        let size = level.tile_size;
+        self.batch.begin(&(cam.get_p_matrix() * cam.get_v_matrix()));
+
+        for row in 0..level.height() {
+            for col in 0..level.width() {
+                let coord = TileCoord::new(col, row);
+                let color = match level.tile(coord) {
+                    Some(Tile::Blocked) => WALL_COLOR,
+                    Some(Tile::Path) => PATH_COLOR,
+                    Some(Tile::Spawn) => SPAWN_COLOR,
+                    Some(Tile::Heart) => HEART_COLOR,
+                    _ => continue,
+                };
+                let center = level.tile_center(coord);
+                self.rect(&center, size, size, color);
+            }
+        }
+
+        for (tile, placed) in game.towers().iter() {
+            if let Some(texture) = self.towers.get(&placed.tower) {
+                let center = level.tile_center(tile);
+                self.batch
+                    .draw(texture, SpriteMode::Color, &centered(&center, size, size));
+            }
+        }
+
+        let pathogens = game.pathogens();
+        for enemy in &game.world().enemies {
+            let color = pathogens
+                .get(&enemy.pathogen)
+                .map(|p| p.color)
+                .unwrap_or([255; 4]);
+            self.rect(
+                &enemy.position,
+                PATHOGEN_SIZE * size,
+                PATHOGEN_SIZE * size,
+                color,
+            );
+
+            // health bar just above the pathogen, emptying from the right
+            let width = PATHOGEN_SIZE * size;
+            let fraction = (enemy.health / enemy.max_health).max(0.0).min(1.0);
+            let bar = na::Point2::new(
+                enemy.position.x,
+                enemy.position.y + (PATHOGEN_SIZE / 2.0 + HEALTH_BAR_HEIGHT) * size,
+            );
+            self.rect(&bar, width, HEALTH_BAR_HEIGHT * size, HEALTH_BACK);
+            let front = na::Point2::new(bar.x - width * (1.0 - fraction) / 2.0, bar.y);
+            self.rect(
+                &front,
+                width * fraction,
+                HEALTH_BAR_HEIGHT * size,
+                HEALTH_FRONT,
+            );
+        }
+
+        for projectile in &game.world().projectiles {
+            self.rect(
+                &projectile.position,
+                PROJECTILE_SIZE * size,
+                PROJECTILE_SIZE * size,
+                PROJECTILE_COLOR,
+            );
+        }
+
+        self.batch.end();
+    }
+
+    fn rect(&mut self, center: &na::Point2<f32>, w: f32, h: f32, tint: [u8; 4]) {
+        let quad = Quad {
+            tint,
+            ..centered(center, w, h)
+        };
+        self.batch.draw(&self.white, SpriteMode::Color, &quad);
+    }
+}
+
+fn centered(center: &na::Point2<f32>, w: f32, h: f32) -> Quad {
+    Quad {
+        x: center.x - w / 2.0,
+        y: center.y - h / 2.0,
+        w,
+        h,
+        uv: WORLD_UV,
+        tint: [255; 4],
+    }
+}